@@ -0,0 +1,643 @@
+/*
+ * looking at a world without changing anything
+ *
+ * `brdb_optimize analyze <world.brdb>` reads the world and prints what's in it:
+ * a table of every grid (bricks, components, chunks, engine, frozen), which entities and components there are,
+ * how many lights cast shadows, how much is frozen, and where the file's size goes.
+ * it also gives every chunk a "lag score" (see [lag_score] in the config),
+ * and lists the worst chunks with their position so admins can go and have a look,
+ * the biggest physics grids, and the entities with the most components attached
+ * (their own, for physics grids, and joints connected to them), the candidates for freezing and welding.
+ * logic networks that are more complex than [logic] allows get flagged too (see logic.rs),
+ * and so do grids jointed together in a loop (see joints.rs).
+ * with --census, only a full list of one kind of thing gets printed instead.
+ * with --heatmap and --density, the main grid's lights and bricks get drawn into images too (see heatmap.rs).
+ * with --json, everything is printed as json instead, for hosting panels and scripts.
+ * fields only ever get added to that, and `schema` goes up when anything else changes.
+ * nothing gets written (other worlds than World/0 are still mounted into a
+ * temporary file to read them, see worlds.rs)
+ */
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+use brdb::{AsBrdbValue, BrReader, Brdb, BrdbComponent, ChunkIndex, IntoReader, Vector3f, pending::BrPendingFs};
+use serde::{Serialize, Serializer};
+
+use crate::{
+    blobs,
+    cli::{Args, Census},
+    config::{Config, LagScoreConfig},
+    grids::{self, ENGINE_COMPONENTS},
+    heatmap,
+    joints::{self, JointLoop},
+    logic::{Graph, LogicStats},
+    owners,
+    physics::{self, PhysicsCost},
+    revisions,
+    rules::LIGHT_COMPONENTS,
+    welds::CHUNK_SIZE,
+    worlds,
+};
+
+// the version of the json output
+const SCHEMA: u32 = 1;
+
+/*
+ * what's in one chunk of the world that costs performance.
+ * physics grids move around, so everything in them is counted in the chunk their entity is in
+ */
+#[derive(Default, Serialize)]
+pub struct ChunkLoad {
+    #[serde(serialize_with = "chunk_index")]
+    pub chunk: ChunkIndex,
+    pub bricks: usize,
+    pub components: usize,
+    pub wires: usize,
+    pub lights: usize,
+    pub shadow_lights: usize,
+    pub unfrozen_entities: usize,
+    pub score: f64,
+}
+
+impl ChunkLoad {
+    // the center of the chunk, in world units
+    pub fn center(&self) -> (i32, i32, i32) {
+        let c = self.chunk;
+        let center = |v: i16| v as i32 * CHUNK_SIZE + CHUNK_SIZE / 2;
+        (center(c.x), center(c.y), center(c.z))
+    }
+}
+
+// everything found in one grid
+#[derive(Serialize)]
+pub struct GridStats {
+    pub grid: usize,
+    pub root: bool,
+    pub bricks: usize,
+    pub components: usize,
+    pub chunks: usize,
+    // the chunk with the most bricks, and how many it has
+    pub heaviest_chunk: Option<(String, usize)>,
+    pub engine: bool,
+    // components by type
+    pub component_types: BTreeMap<String, usize>,
+    // whether the grid's entity is frozen, None for root grids (they never move)
+    pub frozen: Option<bool>,
+}
+
+// how many entities of one type there are
+#[derive(Default, Serialize)]
+pub struct EntityCount {
+    pub frozen: usize,
+    pub unfrozen: usize,
+}
+
+// an entity with components attached to it
+#[derive(Serialize)]
+pub struct AttachedEntity {
+    pub id: usize,
+    pub entity_type: String,
+    pub components: usize,
+}
+
+// what one person (or nobody, for public things) has in the world
+#[derive(Default, Serialize)]
+pub struct OwnerStats {
+    pub name: String,
+    pub lights: usize,
+    pub shadow_lights: usize,
+    pub engines: usize,
+    pub weights: usize,
+    pub unfrozen_entities: usize,
+}
+
+// everything found in one world
+#[derive(Default, Serialize)]
+pub struct WorldStats {
+    pub world: String,
+    // root grids first, then dynamic grids
+    pub grids: Vec<GridStats>,
+    // entities by type
+    pub entities: BTreeMap<String, EntityCount>,
+    // components by type
+    pub components: BTreeMap<String, usize>,
+    pub lights: usize,
+    pub shadow_lights: usize,
+    pub frozen: usize,
+    pub unfrozen: usize,
+    // chunks whose components or wires couldn't be read, and so aren't counted
+    pub unreadable_chunks: usize,
+    // every chunk with something in it, worst lag score first
+    pub chunks: Vec<ChunkLoad>,
+    // entities with any components attached, most first
+    pub attached: Vec<AttachedEntity>,
+    // estimated physics cost, see physics.rs
+    pub physics: PhysicsCost,
+    // how complex the wired together bricks are, see logic.rs
+    pub logic: LogicStats,
+    // grids jointed together in a circle
+    pub joint_loops: Vec<JointLoop>,
+    // by owner index, 0 being public
+    pub owners: BTreeMap<u32, OwnerStats>,
+    // lights in the main grid's chunks seen from above, (chunk x, chunk y) -> (lights, shadow casting lights)
+    #[serde(skip)]
+    pub light_map: BTreeMap<(i16, i16), (usize, usize)>,
+    // bricks in the main grid's chunk columns, (chunk x, chunk y) -> bricks
+    #[serde(skip)]
+    pub brick_map: BTreeMap<(i16, i16), usize>,
+}
+
+// everything found in the whole file
+#[derive(Serialize)]
+pub struct Analysis {
+    pub schema: u32,
+    pub worlds: Vec<WorldStats>,
+    pub revisions: usize,
+    pub file_size: u64,
+    pub sizes: blobs::Sizes,
+    // what the latest version takes up, by folder
+    pub folders: BTreeMap<String, u64>,
+}
+
+pub fn run(src: PathBuf, args: &Args, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    if !src.exists() {
+        println!("world file {:?} does not exist", src);
+        process::exit(1);
+    }
+
+    if !args.json {
+        println!("Reading file {:?}", src);
+    }
+    let analysis = analyze(&src, config)?;
+    match args.census {
+        _ if args.json => println!("{}", serde_json::to_string_pretty(&analysis)?),
+        Some(Census::Components) => print_component_census(&analysis),
+        Some(Census::Entities) => print_entity_census(&analysis),
+        Some(Census::Owners) => print_owner_census(&analysis),
+        None => print(&analysis, args, config),
+    }
+
+    for stats in &analysis.worlds {
+        if let Some(path) = &args.heatmap {
+            let path = image_path(path, &stats.world);
+            let written = heatmap::lights(&path, &stats.light_map)?;
+            if !args.json && written {
+                println!("light heatmap of world {} written to {:?}", stats.world, path);
+            } else if !args.json {
+                println!("world {} has no lights on the main grid, no heatmap written", stats.world);
+            }
+        }
+        if let Some(path) = &args.density {
+            let path = image_path(path, &stats.world);
+            let written = heatmap::density(&path, &stats.brick_map)?;
+            if !args.json && written {
+                println!("brick density map of world {} written to {:?}", stats.world, path);
+            } else if !args.json {
+                println!("world {} has no bricks on the main grid, no density map written", stats.world);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// every world gets its own image, next to the one of the first world
+fn image_path(path: &Path, world: &str) -> PathBuf {
+    if world == "0" {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap().to_string_lossy();
+    path.with_file_name(format!("{stem}.world{world}.png"))
+}
+
+// gathers the statistics of every world in the file
+pub fn analyze(src: &Path, config: &Config) -> Result<Analysis, Box<dyn std::error::Error>> {
+    let db = Brdb::open(src)?.into_reader();
+    let source = db.to_pending()?;
+
+    let mut stats = vec![];
+    for world in worlds::indices(&source) {
+        stats.push(worlds::read_world(&db, &source, &world, src, |world_db| {
+            analyze_world(world_db, &source, &world, config)
+        })?);
+    }
+
+    Ok(Analysis {
+        schema: SCHEMA,
+        worlds: stats,
+        revisions: revisions::list(src)?.len(),
+        file_size: fs::metadata(src)?.len(),
+        sizes: blobs::sizes(src)?,
+        folders: blobs::folder_sizes(src)?,
+    })
+}
+
+fn analyze_world(
+    db: &BrReader<Brdb>,
+    source: &BrPendingFs,
+    world: &str,
+    config: &Config,
+) -> Result<WorldStats, Box<dyn std::error::Error>> {
+    let mut stats = WorldStats { world: world.to_string(), ..Default::default() };
+    let mut load: HashMap<ChunkIndex, ChunkLoad> = HashMap::new();
+
+    // names for everyone in the owner table, bricks and entities count owners from 1
+    let names: Vec<String> = match db.owners_soa() {
+        Ok(table) => (0..table.user_ids.len())
+            .map(|i| {
+                let user = table.user_names.get(i).cloned().unwrap_or_default();
+                match table.display_names.get(i) {
+                    Some(display) if *display != user => format!("{display} ({user})"),
+                    _ => user,
+                }
+            })
+            .collect(),
+        Err(_) => vec![],
+    };
+
+    // every entity's frozen state and chunk, to tell which dynamic grids are frozen and where they are
+    let mut frozen = HashMap::new();
+    let mut located = HashMap::new();
+    let mut types = HashMap::new();
+    // components attached to every entity
+    let mut attached: HashMap<usize, usize> = HashMap::new();
+    for chunk in db.entity_chunk_index()? {
+        for entity in db.entity_chunk(chunk)? {
+            let ent_type = entity.data.get_schema_struct().map_or("unknown".to_string(), |s| s.0.to_string());
+            let count = stats.entities.entry(ent_type.clone()).or_default();
+            let chunk = world_chunk(&entity.location);
+            if let Some(id) = entity.id {
+                frozen.insert(id, entity.frozen);
+                located.insert(id, chunk);
+                types.insert(id, ent_type.clone());
+            }
+            if entity.frozen {
+                stats.frozen += 1;
+                count.frozen += 1;
+            } else {
+                stats.unfrozen += 1;
+                count.unfrozen += 1;
+                owner_stats(&mut stats.owners, &names, entity.owner_index.unwrap_or(0)).unfrozen_entities += 1;
+                load_of(&mut load, chunk).unfrozen_entities += 1;
+            }
+        }
+    }
+
+    let mut logic = Graph::default();
+    let grids = grids::find(db, source, world)?;
+    for grid in grids.all() {
+        let root = grids.is_root(grid);
+        let mut grid_stats = GridStats {
+            grid,
+            root,
+            bricks: 0,
+            components: 0,
+            chunks: 0,
+            heaviest_chunk: None,
+            engine: false,
+            component_types: BTreeMap::new(),
+            frozen: if root { None } else { frozen.get(&grid).copied() },
+        };
+
+        for chunk in db.brick_chunk_index(grid)? {
+            grid_stats.bricks += chunk.num_bricks as usize;
+            grid_stats.components += chunk.num_components as usize;
+            grid_stats.chunks += 1;
+            if grid_stats.heaviest_chunk.as_ref().is_none_or(|(_, bricks)| chunk.num_bricks as usize > *bricks) {
+                grid_stats.heaviest_chunk = Some((chunk.to_string(), chunk.num_bricks as usize));
+            }
+
+            if root {
+                *stats.brick_map.entry((chunk.x, chunk.y)).or_default() += chunk.num_bricks as usize;
+            } else {
+                *attached.entry(grid).or_default() += chunk.num_components as usize;
+            }
+
+            // a physics grid whose entity is gone can't be placed anywhere
+            let world_chunk = if root { Some(*chunk) } else { located.get(&grid).copied() };
+            let mut chunk_load = world_chunk.map(|c| load_of(&mut load, c));
+            if let Some(chunk_load) = chunk_load.as_mut() {
+                chunk_load.bricks += chunk.num_bricks as usize;
+                chunk_load.components += chunk.num_components as usize;
+                chunk_load.wires += chunk.num_wires as usize;
+            }
+
+            if chunk.num_wires > 0 {
+                match db.wire_chunk_soa(grid, *chunk) {
+                    Ok(soa) => logic.add_chunk(grid, *chunk, &soa),
+                    Err(_) => stats.unreadable_chunks += 1,
+                }
+            }
+
+            if chunk.num_components == 0 {
+                continue;
+            }
+
+            let Ok((soa, components)) = db.component_chunk(grid, *chunk) else {
+                stats.unreadable_chunks += 1;
+                continue;
+            };
+            let component_owners = owners::of_components(db, grid, *chunk, &soa).unwrap_or_default();
+            for entity in &soa.joint_entity_references {
+                *attached.entry(*entity as usize).or_default() += 1;
+            }
+            for (i, component) in components.iter().enumerate() {
+                let name = component.get_name();
+                let owner_index = component_owners.get(i).copied().unwrap_or(0);
+                if ENGINE_COMPONENTS.contains(&name) {
+                    owner_stats(&mut stats.owners, &names, owner_index).engines += 1;
+                }
+                if name == "BrickComponentData_WeightBrick" {
+                    owner_stats(&mut stats.owners, &names, owner_index).weights += 1;
+                }
+                *stats.components.entry(name.to_string()).or_default() += 1;
+                *grid_stats.component_types.entry(name.to_string()).or_default() += 1;
+                grid_stats.engine |= ENGINE_COMPONENTS.contains(&name);
+                if LIGHT_COMPONENTS.contains(&name) {
+                    let shadows = component.prop("bCastShadows").and_then(|v| v.as_brdb_bool()).is_ok_and(|s| s);
+                    stats.lights += 1;
+                    stats.shadow_lights += shadows as usize;
+                    if let Some(chunk_load) = chunk_load.as_mut() {
+                        chunk_load.lights += 1;
+                        chunk_load.shadow_lights += shadows as usize;
+                    }
+                    let owner = owner_stats(&mut stats.owners, &names, owner_index);
+                    owner.lights += 1;
+                    owner.shadow_lights += shadows as usize;
+                    if root {
+                        let spot = stats.light_map.entry((chunk.x, chunk.y)).or_default();
+                        spot.0 += 1;
+                        spot.1 += shadows as usize;
+                    }
+                }
+            }
+        }
+        stats.grids.push(grid_stats);
+    }
+
+    stats.physics = physics::estimate(db, &grids, &config.physics_cost)?;
+    stats.logic = logic.stats(&config.logic);
+    stats.joint_loops = joints::loops(db, &grids)?.loops;
+
+    stats.attached = attached.into_iter()
+        .filter(|(_, components)| *components > 0)
+        .map(|(id, components)| AttachedEntity {
+            id,
+            entity_type: types.get(&id).cloned().unwrap_or_else(|| "unknown".to_string()),
+            components,
+        })
+        .collect();
+    stats.attached.sort_by_key(|e| (std::cmp::Reverse(e.components), e.id));
+
+    stats.chunks = load.into_values().map(|mut chunk| {
+        chunk.score = lag_score(&chunk, &config.lag_score);
+        chunk
+    }).collect();
+    stats.chunks.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    Ok(stats)
+}
+
+// the stats of an owner, made on first use
+fn owner_stats<'a>(owners: &'a mut BTreeMap<u32, OwnerStats>, names: &[String], index: u32) -> &'a mut OwnerStats {
+    owners.entry(index).or_insert_with(|| OwnerStats {
+        name: match index {
+            0 => "public".to_string(),
+            i => names.get(i as usize - 1).cloned().unwrap_or_else(|| format!("owner #{i}")),
+        },
+        ..Default::default()
+    })
+}
+
+// chunks go into the json as {"x": .., "y": .., "z": ..}
+fn chunk_index<S: Serializer>(chunk: &ChunkIndex, serializer: S) -> Result<S::Ok, S::Error> {
+    BTreeMap::from([("x", chunk.x), ("y", chunk.y), ("z", chunk.z)]).serialize(serializer)
+}
+
+// the chunk of the world a position is in, which is also the entity chunk an entity there belongs in
+pub fn world_chunk(location: &Vector3f) -> ChunkIndex {
+    let chunk = |v: f32| (v / CHUNK_SIZE as f32).floor() as i16;
+    ChunkIndex { x: chunk(location.x), y: chunk(location.y), z: chunk(location.z) }
+}
+
+fn load_of(load: &mut HashMap<ChunkIndex, ChunkLoad>, chunk: ChunkIndex) -> &mut ChunkLoad {
+    load.entry(chunk).or_insert_with(|| ChunkLoad { chunk, ..Default::default() })
+}
+
+// how much a chunk is expected to cost, the higher the worse
+pub fn lag_score(chunk: &ChunkLoad, weights: &LagScoreConfig) -> f64 {
+    chunk.shadow_lights as f64 * weights.shadow_lights
+        + chunk.lights as f64 * weights.lights
+        + chunk.unfrozen_entities as f64 * weights.unfrozen_entities
+        + chunk.wires as f64 * weights.wires
+        + chunk.components as f64 * weights.components
+        + chunk.bricks as f64 * weights.bricks
+}
+
+fn print(analysis: &Analysis, args: &Args, config: &Config) {
+    for stats in &analysis.worlds {
+        println!("---SEP---");
+        println!("world {}:", stats.world);
+
+        println!();
+        print_grids(&stats.grids);
+
+        let top = args.top.unwrap_or(10);
+        let mut largest: Vec<&GridStats> = stats.grids.iter().filter(|g| !g.root).collect();
+        largest.sort_by_key(|g| std::cmp::Reverse(g.bricks));
+        if !largest.is_empty() {
+            println!();
+            println!("largest physics grids:");
+            for grid in largest.iter().take(top) {
+                println!("  {:>10}  grid {} ({} components)", grid.bricks, grid.grid, grid.components);
+            }
+        }
+        if !stats.attached.is_empty() {
+            println!();
+            println!("entities with the most components attached:");
+            for entity in stats.attached.iter().take(top) {
+                println!("  {:>10}  {} {}", entity.components, entity.entity_type, entity.id);
+            }
+        }
+
+        println!();
+        println!("entities ({} frozen, {} not frozen):", stats.frozen, stats.unfrozen);
+        print_counts(&stats.entities.iter().map(|(name, count)| (name.clone(), count.frozen + count.unfrozen)).collect());
+
+        println!();
+        println!("components:");
+        print_counts(&stats.components);
+        println!();
+        println!("{} lights, {} of them cast shadows", stats.lights, stats.shadow_lights);
+        let physics = &stats.physics;
+        println!(
+            "estimated physics cost: {:.0} ({} unfrozen entities, {} joints and {} bricks on unfrozen physics grids)",
+            physics.score, physics.unfrozen_entities, physics.joints, physics.dynamic_bricks,
+        );
+        if stats.unreadable_chunks > 0 {
+            println!("[WARNING] {} chunks couldn't be read, their components and wires aren't counted", stats.unreadable_chunks);
+        }
+
+        let logic = &stats.logic;
+        println!(
+            "logic: {} bricks wired together by {} wires in {} networks, longest chain of {} bricks, {} loops",
+            logic.bricks, logic.wires, logic.networks, logic.longest_chain, logic.loops,
+        );
+        if !logic.flagged.is_empty() {
+            println!("[WARNING] {} logic networks are more complex than [logic] allows:", logic.flagged.len());
+            for network in &logic.flagged {
+                let (grid, chunk) = network.chunks.first().unwrap();
+                let more = match network.chunks.len() {
+                    1 => String::new(),
+                    n => format!(" and {} more chunks", n - 1),
+                };
+                println!(
+                    "  {} bricks, {} wires, chain of {}, {} loops, in grid {grid} chunk {chunk}{more}",
+                    network.bricks, network.wires, network.longest_chain, network.loops,
+                );
+            }
+        }
+        if !stats.joint_loops.is_empty() {
+            println!("[WARNING] {} loops of grids jointed together, which make physics unstable:", stats.joint_loops.len());
+            for joint_loop in &stats.joint_loops {
+                let entities: Vec<String> = joint_loop.entities.iter().map(|e| e.to_string()).collect();
+                let (a, b) = joint_loop.weakest;
+                println!("  {} -> {} (weakest link: {a} - {b})", entities.join(" -> "), joint_loop.entities[0]);
+            }
+        }
+
+        println!();
+        println!("worst chunks by lag score:");
+        for chunk in stats.chunks.iter().take(config.lag_score.top) {
+            let (x, y, z) = chunk.center();
+            println!(
+                "  {:>10.1}  chunk {} at ({x}, {y}, {z}): {} shadow lights, {} lights, {} unfrozen entities, {} wires, {} components, {} bricks",
+                chunk.score, chunk.chunk, chunk.shadow_lights, chunk.lights, chunk.unfrozen_entities,
+                chunk.wires, chunk.components, chunk.bricks,
+            );
+        }
+    }
+
+    println!("---SEP---");
+    println!("{} revisions", analysis.revisions);
+    println!("file size: {}", mb(analysis.file_size));
+    println!("  latest version of the world: {}", mb(analysis.sizes.current));
+    let mut folders: Vec<_> = analysis.folders.iter().collect();
+    folders.sort_by_key(|(_, size)| std::cmp::Reverse(**size));
+    for (folder, size) in folders {
+        println!("    {:>10}  {folder}", mb(*size));
+    }
+    println!("  older revisions: {}", mb(analysis.sizes.history));
+    println!("  everything else (file tables, free space): {}", mb(analysis.file_size.saturating_sub(analysis.sizes.current + analysis.sizes.history)));
+}
+
+// one line per grid, so it's easy to spot the vehicles worth deleting
+fn print_grids(grids: &[GridStats]) {
+    println!(
+        "  {:>8}  {:>8}  {:>10}  {:>10}  {:>6}  {:>22}  {:>6}  {:>7}",
+        "grid", "type", "bricks", "components", "chunks", "heaviest chunk", "engine", "frozen",
+    );
+    for grid in grids {
+        let heaviest = grid.heaviest_chunk.as_ref().map_or("-".to_string(), |(chunk, bricks)| format!("{chunk} ({bricks})"));
+        let frozen = match grid.frozen {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "-",
+        };
+        println!(
+            "  {:>8}  {:>8}  {:>10}  {:>10}  {:>6}  {:>22}  {:>6}  {:>7}",
+            grid.grid,
+            if grid.root { "static" } else { "physics" },
+            grid.bricks,
+            grid.components,
+            grid.chunks,
+            heaviest,
+            if grid.engine { "yes" } else { "no" },
+            frozen,
+        );
+    }
+    println!(
+        "  {:>8}  {:>8}  {:>10}  {:>10}  {:>6}",
+        "total",
+        "",
+        grids.iter().map(|g| g.bricks).sum::<usize>(),
+        grids.iter().map(|g| g.components).sum::<usize>(),
+        grids.iter().map(|g| g.chunks).sum::<usize>(),
+    );
+}
+
+// every type of component, in each world and in each of its grids
+fn print_component_census(analysis: &Analysis) {
+    for stats in &analysis.worlds {
+        println!("---SEP---");
+        println!("world {}, {} components:", stats.world, stats.components.values().sum::<usize>());
+        print_counts(&stats.components);
+
+        for grid in stats.grids.iter().filter(|g| !g.component_types.is_empty()) {
+            println!();
+            println!("grid {}, {} components:", grid.grid, grid.components);
+            print_counts(&grid.component_types);
+        }
+        if stats.unreadable_chunks > 0 {
+            println!();
+            println!("[WARNING] {} chunks couldn't be read, their components aren't counted", stats.unreadable_chunks);
+        }
+    }
+}
+
+// every type of entity, with how many of them are frozen
+fn print_entity_census(analysis: &Analysis) {
+    for stats in &analysis.worlds {
+        println!("---SEP---");
+        println!("world {}, {} entities:", stats.world, stats.frozen + stats.unfrozen);
+        println!("  {:>8}  {:>8}  {:>10}  type", "total", "frozen", "not frozen");
+
+        let mut entities: Vec<_> = stats.entities.iter().collect();
+        entities.sort_by_key(|(_, count)| std::cmp::Reverse(count.frozen + count.unfrozen));
+        for (name, count) in entities {
+            println!("  {:>8}  {:>8}  {:>10}  {name}", count.frozen + count.unfrozen, count.frozen, count.unfrozen);
+        }
+    }
+}
+
+// what everyone owns that costs performance, so admins know who to talk to
+fn print_owner_census(analysis: &Analysis) {
+    for stats in &analysis.worlds {
+        println!("---SEP---");
+        println!("world {}:", stats.world);
+        println!(
+            "  {:>8}  {:>14}  {:>8}  {:>8}  {:>17}  owner",
+            "lights", "shadow casting", "engines", "weights", "unfrozen entities",
+        );
+
+        let mut owners: Vec<&OwnerStats> = stats.owners.values().collect();
+        owners.sort_by_key(|o| std::cmp::Reverse((o.shadow_lights, o.lights, o.unfrozen_entities)));
+        for o in owners {
+            println!(
+                "  {:>8}  {:>14}  {:>8}  {:>8}  {:>17}  {}",
+                o.lights, o.shadow_lights, o.engines, o.weights, o.unfrozen_entities, o.name,
+            );
+        }
+        if stats.unreadable_chunks > 0 {
+            println!();
+            println!("[WARNING] {} chunks couldn't be read, their components aren't counted", stats.unreadable_chunks);
+        }
+    }
+}
+
+// counts by name, most common first
+fn print_counts(counts: &BTreeMap<String, usize>) {
+    let mut counts: Vec<_> = counts.iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(a.1));
+    for (name, count) in counts {
+        println!("  {count:>8}  {name}");
+    }
+}
+
+fn mb(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1024.0 / 1024.0)
+}
@@ -0,0 +1,40 @@
+/*
+ * compressed copies of the written world, for backups
+ *
+ * `--archive zstd` (or `archive = "zstd"` under [files]) writes <world>.optimized.brdb.zst
+ * next to the optimized world once everything else is done. `zstd -d` turns it back into the world.
+ * with --archive-only the uncompressed copy is deleted afterwards, so only the archive is left.
+ * the daemon, serve, pipe and remote worlds need that copy, so they refuse --archive-only
+ */
+
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::config::ArchiveFormat;
+
+// compresses the world at `path` into an archive next to it, returns the archive and its size (in bytes)
+pub fn write(path: &Path, format: ArchiveFormat, level: i32) -> Result<(PathBuf, u64), Box<dyn std::error::Error>> {
+    let name = path.file_name().unwrap().to_string_lossy();
+    let archive = match format {
+        ArchiveFormat::Zstd => {
+            let archive = path.with_file_name(format!("{name}.zst"));
+            let mut encoder = zstd::Encoder::new(File::create(&archive)?, level)?;
+            io::copy(&mut File::open(path)?, &mut encoder)?;
+            encoder.finish()?;
+            archive
+        }
+    };
+
+    let size = fs::metadata(&archive)?.len();
+    Ok((archive, size))
+}
+
+// the archive of an optimized world, if only the archive was kept (--archive-only)
+pub fn only(optimized: &Path) -> Option<PathBuf> {
+    let name = optimized.file_name()?.to_string_lossy();
+    let archive = optimized.with_file_name(format!("{name}.zst"));
+    (!optimized.exists() && archive.exists()).then_some(archive)
+}
@@ -0,0 +1,198 @@
+/*
+ * deduplicating file contents in the brdb's sqlite database
+ *
+ * every file in a brdb points at a blob (the table `blobs`) holding its contents.
+ * worlds that have been around for a while end up with lots of blobs that hold
+ * the exact same bytes, like chunks that were saved again without changing.
+ * this points every file at a single copy of each blob, and deletes the rest.
+ *
+ * blobs can also be compressed again harder than brdb does when writing (see recompress)
+ */
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, hash_map::DefaultHasher},
+    hash::{Hash, Hasher},
+    path::Path,
+};
+use rusqlite::{Connection, OpenFlags, params};
+use serde::Serialize;
+
+/*
+ * deduplicates the blobs of the database at `path`.
+ * returns how many blobs were removed, and how many bytes they took up
+ */
+pub fn dedupe(path: &Path) -> Result<(usize, u64), Box<dyn std::error::Error>> {
+    let mut conn = Connection::open(path)?;
+    let tx = conn.transaction()?;
+
+    // blobs can only be merged if they're stored the same way, so that's part of what has to match
+    let mut seen: HashMap<(i64, i64, Option<i64>, u64), Vec<i64>> = HashMap::new();
+    let mut duplicates = vec![];
+    {
+        let mut stmt = tx.prepare(
+            "SELECT blob_id, compression, size_uncompressed, delete_revision, content FROM blobs ORDER BY blob_id",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let content: Vec<u8> = row.get(4)?;
+            let mut hasher = DefaultHasher::new();
+            content.hash(&mut hasher);
+            let key = (row.get(1)?, row.get(2)?, row.get(3)?, hasher.finish());
+
+            // a hash match isn't proof, so the contents get compared before merging
+            let candidates = seen.entry(key).or_default();
+            let mut original = None;
+            for candidate in candidates.iter() {
+                let other: Vec<u8> = tx.query_row("SELECT content FROM blobs WHERE blob_id = ?1", [candidate], |r| r.get(0))?;
+                if other == content {
+                    original = Some(*candidate);
+                    break;
+                }
+            }
+            match original {
+                Some(original) => duplicates.push((id, original, content.len() as u64)),
+                None => candidates.push(id),
+            }
+        }
+    }
+
+    let mut bytes = 0;
+    for (duplicate, original, size) in &duplicates {
+        tx.execute("UPDATE files SET content_id = ?1 WHERE content_id = ?2", params![original, duplicate])?;
+        tx.execute("DELETE FROM blobs WHERE blob_id = ?1", [duplicate])?;
+        bytes += size;
+    }
+    tx.commit()?;
+
+    Ok((duplicates.len(), bytes))
+}
+
+// how a blob's contents are stored (the `compression` column)
+const STORED: i64 = 0;
+const ZSTD: i64 = 1;
+// the zstd level `compression = "max"` uses, past this zstd gets very slow and hungry for memory
+pub const MAX_LEVEL: i32 = 19;
+
+/*
+ * compresses every blob of the database at `path` again with zstd at `level`,
+ * keeping the new version only where it's smaller. blobs stored some other way are left alone.
+ * returns how many blobs got smaller, and by how many bytes in total
+ */
+pub fn recompress(path: &Path, level: i32) -> Result<(usize, u64), Box<dyn std::error::Error>> {
+    let mut conn = Connection::open(path)?;
+    let tx = conn.transaction()?;
+
+    let mut smaller = vec![];
+    {
+        let mut stmt = tx.prepare(
+            "SELECT blob_id, compression, size_uncompressed, content FROM blobs WHERE compression IN (?1, ?2)",
+        )?;
+        let mut rows = stmt.query(params![STORED, ZSTD])?;
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let compression: i64 = row.get(1)?;
+            let size: i64 = row.get(2)?;
+            let content: Vec<u8> = row.get(3)?;
+
+            let data = if compression == ZSTD { zstd::decode_all(content.as_slice())? } else { content.clone() };
+            // a blob that doesn't unpack to what it says it holds is better left as it is
+            if data.len() as i64 != size {
+                println!("[WARNING] blob {id} doesn't unpack to its size, not compressing it again");
+                continue;
+            }
+            let compressed = zstd::encode_all(data.as_slice(), level)?;
+            if compressed.len() < content.len() {
+                smaller.push((id, (content.len() - compressed.len()) as u64, compressed));
+            }
+        }
+    }
+
+    let mut bytes = 0;
+    for (id, saved, compressed) in &smaller {
+        tx.execute(
+            "UPDATE blobs SET compression = ?1, size_compressed = ?2, content = ?3 WHERE blob_id = ?4",
+            params![ZSTD, compressed.len() as i64, compressed, id],
+        )?;
+        bytes += saved;
+    }
+    tx.commit()?;
+
+    Ok((smaller.len(), bytes))
+}
+
+// how the stored file contents are split up, in bytes as stored on disk
+#[derive(Serialize)]
+pub struct Sizes {
+    // what the latest version of the world uses
+    pub current: u64,
+    // what's only kept around for older revisions
+    pub history: u64,
+}
+
+pub fn sizes(path: &Path) -> Result<Sizes, Box<dyn std::error::Error>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let total: i64 = conn.query_row("SELECT COALESCE(SUM(size_compressed), 0) FROM blobs", [], |r| r.get(0))?;
+    let current: i64 = conn.query_row(
+        "SELECT COALESCE(SUM(size_compressed), 0) FROM blobs
+         WHERE blob_id IN (SELECT content_id FROM files WHERE deleted_at IS NULL)",
+        [],
+        |r| r.get(0),
+    )?;
+
+    Ok(Sizes {
+        current: current as u64,
+        history: (total - current) as u64,
+    })
+}
+
+/*
+ * what the latest version of the world takes up on disk, by folder:
+ * every grid's bricks separately (World/0/Bricks/Grids/1, ..), the rest of a world
+ * by its folders (World/0/Entities, ..) and everything outside of the worlds by its top folder.
+ * contents shared between files are only counted once, for the first file using them
+ */
+pub fn folder_sizes(path: &Path) -> Result<BTreeMap<String, u64>, Box<dyn std::error::Error>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let folders: HashMap<i64, (Option<i64>, String)> = conn
+        .prepare("SELECT folder_id, parent_id, name FROM folders WHERE deleted_at IS NULL")?
+        .query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?
+        .collect::<Result<_, _>>()?;
+    // the path of a folder, from the top
+    let folder_path = |mut id: Option<i64>| {
+        let mut parts = vec![];
+        while let Some((parent, name)) = id.and_then(|id| folders.get(&id)) {
+            parts.push(name.as_str());
+            id = *parent;
+        }
+        parts.reverse();
+        parts
+    };
+
+    let mut sizes = BTreeMap::new();
+    let mut counted = HashSet::new();
+    let mut stmt = conn.prepare(
+        "SELECT f.parent_id, f.content_id, b.size_compressed FROM files f
+         JOIN blobs b ON b.blob_id = f.content_id
+         WHERE f.deleted_at IS NULL",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let content: i64 = row.get(1)?;
+        if !counted.insert(content) {
+            continue;
+        }
+        let parts = folder_path(row.get(0)?);
+        let depth = match parts.as_slice() {
+            ["World", _, "Bricks", "Grids", ..] => 5,
+            ["World", ..] => 3,
+            _ => 1,
+        };
+        let folder = if parts.is_empty() { "/".to_string() } else { parts[..depth.min(parts.len())].join("/") };
+        let size: i64 = row.get(2)?;
+        *sizes.entry(folder).or_default() += size as u64;
+    }
+
+    Ok(sizes)
+}
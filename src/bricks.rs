@@ -0,0 +1,440 @@
+/*
+ * helpers for whole brick chunks
+ *
+ * every chunk of a grid is spread over up to three files, one per folder:
+ *  - Chunks/0_0_0.mps: the bricks themselves
+ *  - Components/0_0_0.mps: the components of those bricks
+ *  - Wires/0_0_0.mps: the wires between them
+ * components and wires point at bricks by their index in the chunk,
+ * so removing bricks means everything after them has to be pointed at their new index.
+ *
+ * every grid also has a chunk index (ChunkIndex.mps)
+ * that stores how many bricks, components and wires each chunk has
+ */
+
+use std::collections::{HashMap, HashSet};
+use brdb::{
+    BitFlags, BrReader, Brdb, BrdbWorldGlobalData, BrickChunkSoA, BrickSize, BrickSizeCounter, ChunkIndex,
+    RelativePosition, pending::BrPendingFs,
+};
+
+use crate::{coalesce, hidden, owners::BudgetCuts, patch::{self, Entry}};
+
+pub const CHUNK_FOLDERS: &[&str] = &["Chunks", "Components", "Wires"];
+
+// a brick chunk with some bricks taken out, and the new index of every old brick
+pub type Retained = (BrickChunkSoA, Vec<u32>);
+
+// changes to a grid's chunk index
+#[derive(Default)]
+pub struct IndexChanges {
+    // new brick, component and wire counts of chunks that changed
+    pub bricks: Vec<(ChunkIndex, u32)>,
+    pub components: Vec<(ChunkIndex, u32)>,
+    pub wires: Vec<(ChunkIndex, u32)>,
+    // chunks that got deleted entirely
+    pub removed: Vec<ChunkIndex>,
+    // new chunks, with how many bricks they have. their components and wires go in `components` and `wires`
+    pub added: Vec<(ChunkIndex, u32)>,
+}
+
+impl IndexChanges {
+    pub fn is_empty(&self) -> bool {
+        self.bricks.is_empty()
+            && self.components.is_empty()
+            && self.wires.is_empty()
+            && self.removed.is_empty()
+            && self.added.is_empty()
+    }
+}
+
+/*
+ * creates an updated ChunkIndex.mps for a grid.
+ * returns (updated file, original file), the original is for the undo file
+ */
+pub fn chunk_index_files(
+    db: &BrReader<Brdb>,
+    grid: usize,
+    changes: &IndexChanges,
+) -> Result<(Entry, Entry), Box<dyn std::error::Error>> {
+    let schema = db.chunk_index_schema()?;
+    let mut index = db.brick_chunk_index_soa(grid)?;
+    let original = index.to_bytes(&schema)?;
+
+    // new chunks go in first, so their components and wires can be counted like any other chunk's
+    for (chunk, count) in &changes.added {
+        index.chunk_3d_indices.push(*chunk);
+        index.num_bricks.push(*count);
+        index.num_components.push(0);
+        index.num_wires.push(0);
+    }
+
+    let position = |index: &brdb::BrickChunkIndexSoA, chunk: &ChunkIndex| index.chunk_3d_indices.iter().position(|c| c == chunk);
+    for (chunk, count) in &changes.bricks {
+        if let Some(i) = position(&index, chunk) {
+            index.num_bricks[i] = *count;
+        }
+    }
+    for (chunk, count) in &changes.components {
+        if let Some(i) = position(&index, chunk) {
+            index.num_components[i] = *count;
+        }
+    }
+    for (chunk, count) in &changes.wires {
+        if let Some(i) = position(&index, chunk) {
+            index.num_wires[i] = *count;
+        }
+    }
+    for chunk in &changes.removed {
+        if let Some(i) = position(&index, chunk) {
+            index.chunk_3d_indices.remove(i);
+            index.num_bricks.remove(i);
+            index.num_components.remove(i);
+            index.num_wires.remove(i);
+        }
+    }
+
+    Ok((
+        ("ChunkIndex.mps".to_string(), BrPendingFs::File(Some(index.to_bytes(&schema)?))),
+        ("ChunkIndex.mps".to_string(), BrPendingFs::File(Some(original))),
+    ))
+}
+
+/*
+ * deletes every file of a chunk by writing tombstones over them.
+ * returns (folder, tombstone, original file) for each file the chunk has, the original is for the undo file
+ */
+pub fn delete_chunk(db: &BrReader<Brdb>, grid: usize, chunk: ChunkIndex) -> Vec<(&'static str, Entry, Entry)> {
+    let mut files = vec![];
+    for folder in CHUNK_FOLDERS {
+        // `db` sees the world as World/0 (see worlds.rs). files that aren't there don't need deleting
+        let Ok(bytes) = db.read_file(format!("World/0/Bricks/Grids/{grid}/{folder}/{chunk}.mps")) else {
+            continue;
+        };
+        files.push((*folder, patch::tombstone(chunk), patch::chunk_file(chunk, bytes)));
+    }
+    files
+}
+
+// the raw file of a chunk in one of the chunk folders, for the undo file
+pub fn original_file(db: &BrReader<Brdb>, grid: usize, folder: &str, chunk: ChunkIndex) -> Result<Entry, Box<dyn std::error::Error>> {
+    let bytes = db.read_file(format!("World/0/Bricks/Grids/{grid}/{folder}/{chunk}.mps"))?;
+    Ok(patch::chunk_file(chunk, bytes))
+}
+
+/*
+ * (grid, chunk) of every chunk that wires are attached to:
+ * the ones that store wires, and the ones that wires stored elsewhere start from.
+ * bricks in these chunks can't be removed without rewriting the wires too
+ */
+pub fn wired_chunks(db: &BrReader<Brdb>, grids: &[usize]) -> Result<HashSet<(usize, ChunkIndex)>, Box<dyn std::error::Error>> {
+    let mut wired = HashSet::new();
+    for grid in grids {
+        for chunk in db.brick_chunk_index(*grid)? {
+            if chunk.num_wires == 0 {
+                continue;
+            }
+            wired.insert((*grid, *chunk));
+
+            // unreadable wire chunks are left alone, so their sources don't matter
+            let Ok(wires) = db.wire_chunk_soa(*grid, *chunk) else {
+                continue;
+            };
+            for source in &wires.remote_wire_sources {
+                wired.insert((source.grid_persistent_index as usize, source.chunk_index));
+            }
+        }
+    }
+    Ok(wired)
+}
+
+// which bricks remove() should remove
+pub struct Passes<'a> {
+    // bricks that are exact copies of another brick in the same spot
+    pub dedupe: bool,
+    // bricks that are completely covered up by other bricks (see hidden.rs)
+    pub strip_hidden: bool,
+    // rows of identical bricks that can be one bigger brick (see coalesce.rs)
+    pub merge: bool,
+    // lumps of microbricks that get turned into one brick, as (fewest bricks in a lump, how much of its box it fills)
+    pub consolidate: Option<(usize, f32)>,
+    // owner indices whose bricks all go, components and all (see owners::purge)
+    pub purged: &'a HashSet<u32>,
+    // the newest bricks of people over their brick budget (see owners::over_brick_budget)
+    pub over_budget: &'a BudgetCuts,
+}
+
+impl Passes<'_> {
+    pub fn any(&self) -> bool {
+        self.dedupe || self.strip_hidden || self.merge || self.consolidate.is_some() || !self.purged.is_empty() || !self.over_budget.is_empty()
+    }
+}
+
+// how many bricks each pass removed from a chunk
+#[derive(Default)]
+pub struct Removed {
+    pub duplicates: usize,
+    pub hidden: usize,
+    pub purged: usize,
+    pub over_budget: usize,
+    // the same, per owner index
+    pub over_budget_by_owner: HashMap<u32, usize>,
+    // bricks that became part of a bigger brick
+    pub merged: usize,
+    pub consolidated: usize,
+}
+
+/*
+ * removes bricks from a chunk, `bricks` being the bricks in it.
+ * bricks with components are kept, unless their owner is purged (then only bricks with joints are kept).
+ * bricks over someone's budget go from the end of the chunk, skipping the ones with components.
+ * merging bricks removes all but one brick of every row, and makes that one cover the whole row.
+ * consolidating microbricks does the same for lumps of them.
+ * chunks with wires (see wired_chunks) should be left alone.
+ * returns the chunk without those bricks and the new index of every old brick,
+ * or None if nothing was removed
+ */
+pub fn remove(
+    db: &BrReader<Brdb>,
+    global: &BrdbWorldGlobalData,
+    grid: usize,
+    chunk: ChunkIndex,
+    num_components: u32,
+    bricks: &BrickChunkSoA,
+    passes: &Passes,
+) -> Result<Option<(Retained, Removed)>, Box<dyn std::error::Error>> {
+    // which bricks have components (and joints). if those can't be read, we can't know what's safe to remove
+    let (component_bricks, joint_bricks) = if num_components == 0 {
+        (vec![], vec![])
+    } else {
+        match db.component_chunk(grid, chunk) {
+            Ok((soa, _)) => (soa.component_brick_indices, soa.joint_brick_indices),
+            Err(_) => return Ok(None),
+        }
+    };
+
+    let mut keep = vec![true; bricks.brick_type_indices.len()];
+    let mut removed = Removed::default();
+
+    // everything of banned players goes, their components are removed along with them (see owners::purge)
+    if !passes.purged.is_empty() {
+        for (i, keep) in keep.iter_mut().enumerate() {
+            if passes.purged.contains(&bricks.owner_indices[i]) && !joint_bricks.contains(&(i as u32)) {
+                *keep = false;
+                removed.purged += 1;
+            }
+        }
+        if removed.purged > 0 {
+            println!("[grid:{grid}][{chunk}] removing {} bricks of purged owners..", removed.purged);
+        }
+    }
+
+    // someone's newest bricks in this chunk, the ones at the end
+    if let Some(cuts) = passes.over_budget.get(&(grid, chunk)) {
+        for (owner, count) in cuts {
+            let newest: Vec<usize> = (0..keep.len())
+                .rev()
+                .filter(|i| keep[*i] && bricks.owner_indices[*i] == *owner && !component_bricks.contains(&(*i as u32)))
+                .take(*count)
+                .collect();
+            for i in newest {
+                keep[i] = false;
+                removed.over_budget += 1;
+                *removed.over_budget_by_owner.entry(*owner).or_default() += 1;
+            }
+        }
+        if removed.over_budget > 0 {
+            println!("[grid:{grid}][{chunk}] removing {} bricks of people over their brick budget..", removed.over_budget);
+        }
+    }
+
+    // same asset and size (both part of the brick type), position and rotation
+    if passes.dedupe {
+        let mut seen = HashSet::new();
+        for (i, keep) in keep.iter_mut().enumerate() {
+            let key = (
+                bricks.brick_type_indices[i],
+                bricks.relative_positions[i],
+                bricks.orientations[i],
+            );
+            if *keep && !seen.insert(key) && !component_bricks.contains(&(i as u32)) {
+                *keep = false;
+                removed.duplicates += 1;
+            }
+        }
+        if removed.duplicates > 0 {
+            println!("[grid:{grid}][{chunk}] removing {} duplicate bricks..", removed.duplicates);
+        }
+    }
+
+    if passes.strip_hidden {
+        for i in hidden::find(bricks, global, &component_bricks, &keep) {
+            keep[i] = false;
+            removed.hidden += 1;
+        }
+        if removed.hidden > 0 {
+            println!("[grid:{grid}][{chunk}] removing {} hidden bricks..", removed.hidden);
+        }
+    }
+
+    // last, so nothing gets merged into a brick that's about to be removed
+    let mut merged = vec![];
+    if let Some((min_bricks, fill)) = passes.consolidate {
+        let (lumps, swallowed) = coalesce::consolidate(bricks, global, &component_bricks, &keep, min_bricks, fill);
+        for i in swallowed {
+            keep[i] = false;
+            removed.consolidated += 1;
+        }
+        if removed.consolidated > 0 {
+            println!("[grid:{grid}][{chunk}] turning {} lumps of microbricks into single bricks..", lumps.len());
+        }
+        // the grown bricks are left out of merging, which would look at their old size
+        for lump in &lumps {
+            keep[lump.brick] = false;
+        }
+        merged = lumps;
+    }
+    if passes.merge {
+        let (rows, swallowed) = coalesce::find(bricks, global, &component_bricks, &keep);
+        for i in swallowed {
+            keep[i] = false;
+            removed.merged += 1;
+        }
+        if removed.merged > 0 {
+            println!("[grid:{grid}][{chunk}] merging {} bricks into {} bigger ones..", removed.merged + rows.len(), rows.len());
+        }
+        merged.extend(rows);
+    }
+    for brick in &merged {
+        keep[brick.brick] = true;
+    }
+
+    if removed.duplicates + removed.hidden + removed.purged + removed.over_budget + removed.merged + removed.consolidated == 0 {
+        return Ok(None);
+    }
+    let (mut retained, map) = retain(bricks, &keep);
+    for brick in merged {
+        let i = map[brick.brick] as usize;
+        if let Some((asset, _)) = procedural_type(&retained, retained.brick_type_indices[i]) {
+            retained.brick_type_indices[i] = retained.procedural_brick_starting_index + size_index(&mut retained, asset, brick.size);
+            retained.relative_positions[i] = brick.position;
+        }
+    }
+    Ok(Some(((retained, map), removed)))
+}
+
+// the asset index (in the procedural brick assets) and size of a procedural brick type, None for basic bricks
+pub fn procedural_type(bricks: &BrickChunkSoA, type_index: u32) -> Option<(u32, BrickSize)> {
+    let size_index = type_index.checked_sub(bricks.procedural_brick_starting_index)?;
+    let size = bricks.brick_sizes.get(size_index as usize)?;
+
+    // brick_sizes is grouped by asset, brick_size_counters says how many sizes each asset has
+    let mut start = 0;
+    for counter in &bricks.brick_size_counters {
+        if size_index < start + counter.num_sizes {
+            return Some((counter.asset_index, *size));
+        }
+        start += counter.num_sizes;
+    }
+    None
+}
+
+/*
+ * copies brick `i` of `from` to the end of `to`, placing it at `position`.
+ * both chunks have to agree on where procedural bricks start (they do within the same world)
+ */
+pub fn push(to: &mut BrickChunkSoA, from: &BrickChunkSoA, i: usize, position: RelativePosition) {
+    let type_index = from.brick_type_indices[i];
+    let type_index = match procedural_type(from, type_index) {
+        // basic bricks are the same in every chunk
+        None => type_index,
+        // procedural bricks point at a size in the chunk's own list of sizes
+        Some((asset, size)) => to.procedural_brick_starting_index + size_index(to, asset, size),
+    };
+
+    to.brick_type_indices.push(type_index);
+    to.owner_indices.push(from.owner_indices[i]);
+    to.relative_positions.push(position);
+    to.orientations.push(from.orientations[i]);
+    to.collision_flags_player.push(from.collision_flags_player.get(i));
+    to.collision_flags_weapon.push(from.collision_flags_weapon.get(i));
+    to.collision_flags_interaction.push(from.collision_flags_interaction.get(i));
+    to.collision_flags_tool.push(from.collision_flags_tool.get(i));
+    to.visibility_flags.push(from.visibility_flags.get(i));
+    to.material_indices.push(from.material_indices[i]);
+    to.colors_and_alphas.push(from.colors_and_alphas[i]);
+}
+
+// where a procedural brick size is in the chunk's list of sizes, adding it if it isn't there yet
+fn size_index(bricks: &mut BrickChunkSoA, asset: u32, size: BrickSize) -> u32 {
+    let mut start = 0;
+    for counter in bricks.brick_size_counters.iter_mut() {
+        if counter.asset_index != asset {
+            start += counter.num_sizes;
+            continue;
+        }
+
+        let sizes = start as usize..(start + counter.num_sizes) as usize;
+        if let Some(j) = bricks.brick_sizes[sizes].iter().position(|s| *s == size) {
+            return start + j as u32;
+        }
+
+        // add it to the end of this asset's sizes, which moves every size after it up by one
+        let new = start + counter.num_sizes;
+        bricks.brick_sizes.insert(new as usize, size);
+        counter.num_sizes += 1;
+        for type_index in bricks.brick_type_indices.iter_mut() {
+            if *type_index >= bricks.procedural_brick_starting_index + new {
+                *type_index += 1;
+            }
+        }
+        return new;
+    }
+
+    // first brick of this asset in the chunk
+    bricks.brick_size_counters.push(BrickSizeCounter { asset_index: asset, num_sizes: 1 });
+    bricks.brick_sizes.push(size);
+    bricks.brick_sizes.len() as u32 - 1
+}
+
+// copies the chunk with only the bricks marked in `keep`
+pub fn retain(bricks: &BrickChunkSoA, keep: &[bool]) -> Retained {
+    fn pick<T: Clone>(values: &[T], keep: &[bool]) -> Vec<T> {
+        values.iter().zip(keep).filter(|(_, k)| **k).map(|(v, _)| v.clone()).collect()
+    }
+    fn pick_flags(flags: &BitFlags, keep: &[bool]) -> BitFlags {
+        let mut picked = BitFlags::default();
+        for (i, _) in keep.iter().enumerate().filter(|(_, k)| **k) {
+            picked.push(flags.get(i));
+        }
+        picked
+    }
+
+    let mut map = vec![];
+    let mut next = 0;
+    for k in keep {
+        // removed bricks keep pointing at the next brick, nothing should be pointing at them anyway
+        map.push(next);
+        if *k {
+            next += 1;
+        }
+    }
+
+    let retained = BrickChunkSoA {
+        procedural_brick_starting_index: bricks.procedural_brick_starting_index,
+        brick_size_counters: bricks.brick_size_counters.clone(),
+        brick_sizes: bricks.brick_sizes.clone(),
+        brick_type_indices: pick(&bricks.brick_type_indices, keep),
+        owner_indices: pick(&bricks.owner_indices, keep),
+        relative_positions: pick(&bricks.relative_positions, keep),
+        orientations: pick(&bricks.orientations, keep),
+        collision_flags_player: pick_flags(&bricks.collision_flags_player, keep),
+        collision_flags_weapon: pick_flags(&bricks.collision_flags_weapon, keep),
+        collision_flags_interaction: pick_flags(&bricks.collision_flags_interaction, keep),
+        collision_flags_tool: pick_flags(&bricks.collision_flags_tool, keep),
+        visibility_flags: pick_flags(&bricks.visibility_flags, keep),
+        material_indices: pick(&bricks.material_indices, keep),
+        colors_and_alphas: pick(&bricks.colors_and_alphas, keep),
+    };
+    (retained, map)
+}
@@ -0,0 +1,81 @@
+/*
+ * stripping data the game can rebuild by itself
+ *
+ * brdb files can carry a cache of minimap tiles and preview images next to the world.
+ * on worlds that have been explored a lot, those can grow to hundreds of MB,
+ * while the game just renders them again when they're missing
+ */
+
+use brdb::pending::BrPendingFs;
+
+use crate::{config::FileConfig, patch};
+
+// folders that only hold minimap tiles
+const MINIMAP_FOLDERS: &[&str] = &["Minimap", "MinimapTiles"];
+// beginnings of the names of preview image files
+const PREVIEW_PREFIXES: &[&str] = &["screenshot", "preview", "thumbnail"];
+
+/*
+ * deletes the minimap cache and oversized previews, as configured.
+ * returns (patch, undo patch, number of bytes removed)
+ */
+pub fn strip(source: &BrPendingFs, config: &FileConfig) -> Result<(BrPendingFs, BrPendingFs, usize), Box<dyn std::error::Error>> {
+    let mut patch = BrPendingFs::Root(vec![]);
+    let mut undo_patch = BrPendingFs::Root(vec![]);
+    let mut removed = 0;
+
+    let BrPendingFs::Root(root) = source else {
+        return Ok((patch, undo_patch, removed));
+    };
+
+    let mut found = vec![];
+    find(root, &mut vec![], config, &mut found);
+    for (path, node) in found {
+        let size = size(node);
+        println!("[cache] removing {} ({} KB)..", path.join("/"), size / 1024);
+        removed += size;
+
+        let (name, parent) = path.split_last().unwrap();
+        let parent: Vec<&str> = parent.iter().map(String::as_str).collect();
+        let tombstone = match node {
+            BrPendingFs::Folder(_) => BrPendingFs::Folder(None),
+            _ => BrPendingFs::File(None),
+        };
+        patch = patch.with_patch(patch::nested(&parent, vec![(name.clone(), tombstone)]))?;
+        undo_patch = undo_patch.with_patch(patch::nested(&parent, vec![(name.clone(), node.clone())]))?;
+    }
+
+    Ok((patch, undo_patch, removed))
+}
+
+// collects the path of everything that should be removed
+fn find<'a>(contents: &'a [patch::Entry], path: &mut Vec<String>, config: &FileConfig, found: &mut Vec<(Vec<String>, &'a BrPendingFs)>) {
+    for (name, node) in contents {
+        path.push(name.clone());
+        match node {
+            BrPendingFs::Folder(Some(_)) if config.strip_minimap && MINIMAP_FOLDERS.contains(&name.as_str()) => {
+                found.push((path.clone(), node));
+            }
+            BrPendingFs::Folder(Some(children)) => find(children, path, config, found),
+            BrPendingFs::File(Some(bytes)) => {
+                let lowercase = name.to_lowercase();
+                if PREVIEW_PREFIXES.iter().any(|prefix| lowercase.starts_with(prefix))
+                    && config.max_preview_size.is_some_and(|max| bytes.len() > max * 1024)
+                {
+                    found.push((path.clone(), node));
+                }
+            }
+            _ => {}
+        }
+        path.pop();
+    }
+}
+
+// how many bytes of files are in a file or folder
+fn size(node: &BrPendingFs) -> usize {
+    match node {
+        BrPendingFs::File(Some(bytes)) => bytes.len(),
+        BrPendingFs::Folder(Some(children)) | BrPendingFs::Root(children) => children.iter().map(|(_, n)| size(n)).sum(),
+        _ => 0,
+    }
+}
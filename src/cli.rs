@@ -0,0 +1,517 @@
+/*
+ * command line parsing
+ *
+ * usage:
+ *  brdb_optimize [options] <world.brdb>
+ *  brdb_optimize [options] - < world.brdb > optimized.brdb
+ *  brdb_optimize [options] [--upload <destination>] <https:// or s3:// url>
+ *  brdb_optimize --daemon [--schedule <cron>] [options] <saves folder>
+ *  brdb_optimize undo <world.optimized.brdb> [undo file]
+ *  brdb_optimize analyze <world.brdb>
+ *  brdb_optimize revisions list <world.brdb>
+ *  brdb_optimize export components --csv <world.brdb>
+ *  brdb_optimize export entities --json <world.brdb>
+ *  brdb_optimize export geometry --obj [--merge-chunks] <world.brdb>
+ *  brdb_optimize verify <world.brdb>
+ *  brdb_optimize trim --box <x1,y1,z1,x2,y2,z2> [--invert] <world.brdb>
+ *  brdb_optimize split (--tiles <x>x<y> | --regions <file>) <world.brdb>
+ *  brdb_optimize merge <a.brdb> <b.brdb> [..] -o <merged.brdb> [--offset <x,y,z> ..]
+ *  brdb_optimize extract --grid <id> <world.brdb>
+ *  brdb_optimize insert <prefab.brdb> --at <x,y,z> <world.brdb>
+ *  brdb_optimize serve [--listen <address>]
+ */
+
+use std::{
+    env,
+    path::PathBuf,
+    process,
+};
+
+use crate::{config::{ArchiveFormat, Compression, HEAVY_PASSES}, cron::Cron, merge, remote, revisions, split::{self, Split}, trim::Region};
+
+pub const USAGE: &str = "\
+usage:
+  brdb_optimize [options] <world.brdb>
+  brdb_optimize [options] - < world.brdb > optimized.brdb
+                                        optimize the world on stdin, and write it to stdout
+  brdb_optimize [options] [--upload <destination>] <https:// or s3:// url>
+                                        download the world, optimize it, and upload it (or write it here)
+  brdb_optimize --daemon [--schedule <cron>] [options] <saves folder>
+                                        keep running, and optimize every world in the folder when it gets saved
+  brdb_optimize undo <world.optimized.brdb> [undo file]
+  brdb_optimize analyze <world.brdb>    print what's in the world, without changing anything
+  brdb_optimize revisions list <world.brdb>
+                                        list the world's revisions, with when they were saved and their size
+  brdb_optimize export components --csv <world.brdb>
+                                        print every component with its properties as csv
+  brdb_optimize export entities --json <world.brdb>
+                                        print every entity with where it is and whether it's frozen as json
+  brdb_optimize export geometry --obj [--merge-chunks] <world.brdb>
+                                        print a mesh of the world with a box for every brick, to preview it in blender
+  brdb_optimize verify <world.brdb>     check that everything in the world can be read, without optimizing
+  brdb_optimize trim --box <x1,y1,z1,x2,y2,z2> [--invert] <world.brdb>
+                                        remove everything outside of the box (or inside, with --invert)
+  brdb_optimize split (--tiles <x>x<y> | --regions <file>) <world.brdb>
+                                        cut the world into pieces, and write every piece as a world of its own
+  brdb_optimize merge <a.brdb> <b.brdb> [..] -o <merged.brdb> [--offset <x,y,z> ..]
+                                        add everything in the other worlds to the first one
+  brdb_optimize extract --grid <id> <world.brdb>
+                                        write a vehicle, with its wheels and everything jointed to it, as a world of its own
+  brdb_optimize insert <prefab.brdb> --at <x,y,z> <world.brdb>
+                                        add the vehicles of an extracted prefab to the world, at x,y,z
+  brdb_optimize serve [--listen <address>]
+                                        take optimization jobs over http, for web control panels
+
+options:
+  --config <file>  read settings from this config file (default: brdb_optimize.toml, if it exists)
+  --force          optimize even if the world was already optimized with the same settings,
+                   or was saved by a game version the tool doesn't know about
+  --repair         drop the components of corrupt chunks instead of refusing to write the world
+  --max-lights-per-chunk <n>
+                   turn off all but the n brightest lights in every chunk
+  --max-lights-per-owner <n>
+                   turn off everyone's lights after their first n (see over_owner_quota in the config)
+  --strip-hidden-bricks
+                   remove bricks that are completely covered up by other bricks (slow on big worlds)
+  --merge-bricks   merge rows of identical bricks into bigger bricks, for imported voxel builds
+  --consolidate-microbricks
+                   turn big lumps of microbricks into single bricks, filling in small gaps
+                   (this changes the shape of builds a little)
+  --freeze-all     freeze every entity, except vehicles with an engine and their wheels
+  --unfreeze       unfreeze wheels, balls and stale bots/projectiles instead of freezing them,
+                   to undo an earlier run
+  --max-active-grids <n>
+                   freeze all but the n cheapest moving vehicles, going by their bricks, wheels and engines
+  --vacuum, --no-vacuum
+                   whether to compact the written file so it takes less space on disk (default: yes)
+  --archive zstd   also write a compressed copy of the optimized world (.brdb.zst), for backups
+  --archive-only   only keep the compressed copy, and delete the uncompressed optimized world
+  --compression <fast|default|max>
+                   how much time to spend shrinking the written world: fast skips merging
+                   identical files and compacting, max also compresses everything again as hard as it can
+  --manifest       write the sha-256 of the original and optimized world to a manifest file next to them
+  --keep-revisions-newer-than <age>
+                   keep the world's history, but only from the last <age> (like 7d, 12h or 30m)
+  --report html=<file>
+                   write a report of what was done to an html file, to share with others
+  --skip <a,b,..>  leave out these heavy passes: revisions (keep the history as it is), vacuum, dedupe_blobs
+  --ruleset <file> also run the component rules in this json or yaml file, can be given more than once
+  --purge-owner <id>
+                   remove all bricks, components and entities of this player (user id or name),
+                   can be given more than once
+  --enforce-brick-budget <n>
+                   remove the newest bricks of everyone with more than n bricks on the main grid
+  --upload <destination>
+                   where to put a world optimized from a url: an https:// or s3:// url, or a local path
+                   (ending in / for a folder)
+  --stats <file>   write how many entities and components got changed, and the new file size, as json
+
+daemon options:
+  --schedule <cron>
+                   only run the heavy passes at these times, like \"0 4 * * *\" for every night at 4,
+                   instead of on every save (see [[daemon.schedule]] in the config for more control)
+
+analyze options:
+  --census components
+                   only list every type of component with how many there are, in the world and per grid
+  --census entities
+                   only list every type of entity with how many there are, and how many of those are frozen
+  --census owners  only list how many lights, engines, weights and unfrozen entities everyone owns
+  --json           print everything as json instead, for scripts and hosting panels
+  --top <n>        how many of the largest physics grids and most attached entities to list (default: 10)
+  --heatmap <file.png>
+                   also draw where the main grid's lights (and shadow casting lights) are, seen from above
+  --density <file.png>
+                   also draw how many bricks the main grid has where, seen from above
+
+serve options:
+  --listen <address>
+                   the address to listen on (default: 127.0.0.1:9185). there's no authentication,
+                   so only listen on addresses you trust
+
+export options:
+  --csv            write csv (components)
+  --json           write json (entities)
+  --obj            write a wavefront .obj mesh (geometry)
+  --merge-chunks   make every chunk a single object, instead of every brick (geometry)
+  --box <x1,y1,z1,x2,y2,z2>
+                   the corners of the box to keep, in the game's units (trim)
+  --invert         remove everything inside of the box instead (trim)
+  --tiles <x>x<y>  cut the main grid into this many tiles along X and Y, like 3x2 (split)
+  --regions <file> cut out the named boxes in this file, one `<name> x1,y1,z1,x2,y2,z2` per line (split)
+  -o, --output <file>
+                   where to write the merged world (merge)
+  --offset <x,y,z> move the next world by this much, rounded to whole chunks of 2048 units,
+                   once for every world after the first (merge)
+  --grid <id>      the dynamic grid to extract, see `analyze` for their ids (extract)
+  --at <x,y,z>     where to put the prefab's vehicle, in the game's units (insert)
+  --props <a,b,..> the properties to export (default for components: shadows and every number
+                   the optimizer looks at, none for entities)";
+
+pub enum Command {
+    Optimize(PathBuf),
+    Undo(PathBuf, Option<PathBuf>),
+    Analyze(PathBuf),
+    RevisionList(PathBuf),
+    Export(Export, PathBuf),
+    Verify(PathBuf),
+    Trim(PathBuf, Region),
+    Split(PathBuf, Split),
+    Merge(Vec<PathBuf>, PathBuf),
+    Extract(PathBuf, usize),
+    Insert(PathBuf, PathBuf, [f32; 3]),
+    Daemon(PathBuf),
+    Serve(String),
+    Pipe,
+    Remote(String),
+}
+
+// what `export` dumps
+#[derive(Clone, Copy, PartialEq)]
+pub enum Export {
+    Components,
+    Entities,
+    Geometry,
+}
+
+// what `analyze --census` lists
+#[derive(Clone, Copy, PartialEq)]
+pub enum Census {
+    Components,
+    Entities,
+    Owners,
+}
+
+// a report of the optimization run, and where to write it
+pub enum Report {
+    Html(PathBuf),
+}
+
+pub struct Args {
+    pub command: Command,
+    pub force: bool,
+    pub repair: bool,
+    pub config: Option<PathBuf>,
+    pub max_lights_per_chunk: Option<usize>,
+    pub max_lights_per_owner: Option<usize>,
+    pub strip_hidden_bricks: bool,
+    pub merge_bricks: bool,
+    pub consolidate_microbricks: bool,
+    pub freeze_all: bool,
+    pub unfreeze: bool,
+    pub max_active_grids: Option<usize>,
+    pub vacuum: Option<bool>,
+    pub archive: Option<ArchiveFormat>,
+    pub archive_only: bool,
+    pub compression: Option<Compression>,
+    pub manifest: bool,
+    pub keep_revisions_newer_than: Option<String>,
+    pub report: Option<Report>,
+    pub census: Option<Census>,
+    pub heatmap: Option<PathBuf>,
+    pub density: Option<PathBuf>,
+    pub json: bool,
+    pub top: Option<usize>,
+    pub props: Option<Vec<String>>,
+    pub merge_chunks: bool,
+    pub region: Option<Region>,
+    pub invert: bool,
+    pub offsets: Vec<[f32; 3]>,
+    pub schedule: Option<String>,
+    pub skip: Vec<String>,
+    pub rulesets: Vec<PathBuf>,
+    pub purge_owners: Vec<String>,
+    pub enforce_brick_budget: Option<usize>,
+    pub stats: Option<PathBuf>,
+    pub upload: Option<String>,
+}
+
+// prints the usage text and exits
+pub fn usage_exit(error: &str) -> ! {
+    println!("{error}");
+    println!();
+    println!("{USAGE}");
+    process::exit(1);
+}
+
+// the value following an option like --config <file>
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> String {
+    args.next().unwrap_or_else(|| usage_exit(&format!("{flag} needs a value")))
+}
+
+// same, for options that take a number
+fn number<T: std::str::FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> T {
+    let value = value(args, flag);
+    value.parse().unwrap_or_else(|_| usage_exit(&format!("{flag} needs a number, got {value:?}")))
+}
+
+pub fn parse() -> Args {
+    let mut positional = vec![];
+    let mut force = false;
+    let mut repair = false;
+    let mut config = None;
+    let mut max_lights_per_chunk = None;
+    let mut max_lights_per_owner = None;
+    let mut strip_hidden_bricks = false;
+    let mut merge_bricks = false;
+    let mut consolidate_microbricks = false;
+    let mut freeze_all = false;
+    let mut unfreeze = false;
+    let mut max_active_grids = None;
+    let mut vacuum = None;
+    let mut archive = None;
+    let mut archive_only = false;
+    let mut compression = None;
+    let mut manifest = false;
+    let mut keep_revisions_newer_than = None;
+    let mut report = None;
+    let mut census = None;
+    let mut heatmap = None;
+    let mut density = None;
+    let mut json = false;
+    let mut top = None;
+    let mut csv = false;
+    let mut props = None;
+    let mut obj = false;
+    let mut merge_chunks = false;
+    let mut region = None;
+    let mut invert = false;
+    let mut split = None;
+    let mut output = None;
+    let mut offsets = vec![];
+    let mut grid = None;
+    let mut at = None;
+    let mut daemon = false;
+    let mut schedule = None;
+    let mut skip = vec![];
+    let mut rulesets = vec![];
+    let mut purge_owners = vec![];
+    let mut enforce_brick_budget = None;
+    let mut stats = None;
+    let mut upload = None;
+    let mut listen = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--force" => force = true,
+            "--repair" => repair = true,
+            "--config" => config = Some(PathBuf::from(value(&mut args, "--config"))),
+            "--max-lights-per-chunk" => max_lights_per_chunk = Some(number(&mut args, "--max-lights-per-chunk")),
+            "--max-lights-per-owner" => max_lights_per_owner = Some(number(&mut args, "--max-lights-per-owner")),
+            "--strip-hidden-bricks" => strip_hidden_bricks = true,
+            "--merge-bricks" => merge_bricks = true,
+            "--consolidate-microbricks" => consolidate_microbricks = true,
+            "--freeze-all" => freeze_all = true,
+            "--unfreeze" => unfreeze = true,
+            "--max-active-grids" => max_active_grids = Some(number(&mut args, "--max-active-grids")),
+            "--vacuum" => vacuum = Some(true),
+            "--no-vacuum" => vacuum = Some(false),
+            "--archive" => {
+                archive = match value(&mut args, "--archive").as_str() {
+                    "zstd" => Some(ArchiveFormat::Zstd),
+                    other => usage_exit(&format!("--archive only knows zstd, got {other:?}")),
+                }
+            }
+            "--archive-only" => archive_only = true,
+            "--compression" => {
+                compression = match value(&mut args, "--compression").as_str() {
+                    "fast" => Some(Compression::Fast),
+                    "default" => Some(Compression::Default),
+                    "max" => Some(Compression::Max),
+                    other => usage_exit(&format!("--compression is fast, default or max, got {other:?}")),
+                }
+            }
+            "--manifest" => manifest = true,
+            "--keep-revisions-newer-than" => {
+                let age = value(&mut args, "--keep-revisions-newer-than");
+                if revisions::parse_age(&age).is_none() {
+                    usage_exit(&format!("--keep-revisions-newer-than needs an age like 7d, 12h or 30m, got {age:?}"));
+                }
+                keep_revisions_newer_than = Some(age);
+            }
+            "--report" => {
+                let value = value(&mut args, "--report");
+                report = match value.split_once('=') {
+                    Some(("html", path)) if !path.is_empty() => Some(Report::Html(PathBuf::from(path))),
+                    _ => usage_exit(&format!("--report needs a format and a file, like html=report.html, got {value:?}")),
+                }
+            }
+            "--census" => {
+                census = match value(&mut args, "--census").as_str() {
+                    "components" => Some(Census::Components),
+                    "entities" => Some(Census::Entities),
+                    "owners" => Some(Census::Owners),
+                    other => usage_exit(&format!("--census can list components, entities or owners, got {other:?}")),
+                }
+            }
+            "--json" => json = true,
+            "--top" => top = Some(number(&mut args, "--top")),
+            "--csv" => csv = true,
+            "--props" => props = Some(value(&mut args, "--props").split(',').map(|p| p.trim().to_string()).collect()),
+            "--obj" => obj = true,
+            "--merge-chunks" => merge_chunks = true,
+            "--box" => {
+                let value = value(&mut args, "--box");
+                region = Some(Region::parse(&value).unwrap_or_else(|| {
+                    usage_exit(&format!("--box needs two corners as six numbers, like -100,-100,0,100,100,50, got {value:?}"))
+                }));
+            }
+            "--invert" => invert = true,
+            "--tiles" => {
+                let value = value(&mut args, "--tiles");
+                let (x, y) = split::parse_tiles(&value).unwrap_or_else(|| {
+                    usage_exit(&format!("--tiles needs two counts above 0, like 3x2, got {value:?}"))
+                });
+                split = Some(Split::Tiles(x, y));
+            }
+            "--regions" => split = Some(Split::Regions(PathBuf::from(value(&mut args, "--regions")))),
+            "-o" | "--output" => output = Some(PathBuf::from(value(&mut args, "--output"))),
+            "--grid" => grid = Some(number(&mut args, "--grid")),
+            "--at" => {
+                let value = value(&mut args, "--at");
+                at = Some(merge::parse_offset(&value).unwrap_or_else(|| {
+                    usage_exit(&format!("--at needs three numbers, like 1000,-500,200, got {value:?}"))
+                }));
+            }
+            "--offset" => {
+                let value = value(&mut args, "--offset");
+                offsets.push(merge::parse_offset(&value).unwrap_or_else(|| {
+                    usage_exit(&format!("--offset needs three numbers, like 20480,0,0, got {value:?}"))
+                }));
+            }
+            "--daemon" => daemon = true,
+            "--schedule" => {
+                let cron = value(&mut args, "--schedule");
+                if let Err(e) = Cron::parse(&cron) {
+                    usage_exit(&format!("--schedule needs a cron expression: {e}"));
+                }
+                schedule = Some(cron);
+            }
+            "--skip" => {
+                for pass in value(&mut args, "--skip").split(',').map(|p| p.trim().to_string()) {
+                    if !HEAVY_PASSES.contains(&pass.as_str()) {
+                        usage_exit(&format!("--skip can leave out {}, got {pass:?}", HEAVY_PASSES.join(", ")));
+                    }
+                    skip.push(pass);
+                }
+            }
+            "--ruleset" => rulesets.push(PathBuf::from(value(&mut args, "--ruleset"))),
+            "--purge-owner" => purge_owners.push(value(&mut args, "--purge-owner")),
+            "--enforce-brick-budget" => enforce_brick_budget = Some(number(&mut args, "--enforce-brick-budget")),
+            "--listen" => listen = Some(value(&mut args, "--listen")),
+            "--upload" => upload = Some(value(&mut args, "--upload")),
+            "--stats" => stats = Some(PathBuf::from(value(&mut args, "--stats"))),
+            "--heatmap" => heatmap = Some(PathBuf::from(value(&mut args, "--heatmap"))),
+            "--density" => density = Some(PathBuf::from(value(&mut args, "--density"))),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                process::exit(0);
+            }
+            flag if flag.starts_with("--") => usage_exit(&format!("unknown option {flag}")),
+            _ => positional.push(arg),
+        }
+    }
+
+    let command = match positional.first().map(String::as_str) {
+        Some(folder) if daemon => Command::Daemon(PathBuf::from(folder)),
+        None if daemon => usage_exit("--daemon needs the saves folder to watch."),
+        Some("undo") => match positional.get(1) {
+            Some(world) => Command::Undo(PathBuf::from(world), positional.get(2).map(PathBuf::from)),
+            None => usage_exit("undo needs the optimized world file to restore."),
+        },
+        Some("analyze") => match positional.get(1) {
+            Some(world) => Command::Analyze(PathBuf::from(world)),
+            None => usage_exit("analyze needs the world file to look at."),
+        },
+        Some("revisions") => match (positional.get(1).map(String::as_str), positional.get(2)) {
+            (Some("list"), Some(world)) => Command::RevisionList(PathBuf::from(world)),
+            (Some("list"), None) => usage_exit("revisions list needs the world file to look at."),
+            _ => usage_exit("revisions only knows `list`."),
+        },
+        Some("-") => Command::Pipe,
+        Some("serve") => Command::Serve(listen.unwrap_or_else(|| "127.0.0.1:9185".to_string())),
+        Some("verify") => match positional.get(1) {
+            Some(world) => Command::Verify(PathBuf::from(world)),
+            None => usage_exit("verify needs the world file to check."),
+        },
+        Some("export") => match (positional.get(1).map(String::as_str), positional.get(2)) {
+            (Some("components"), Some(_)) if !csv => usage_exit("export needs a format, like --csv."),
+            (Some("components"), Some(world)) => Command::Export(Export::Components, PathBuf::from(world)),
+            (Some("entities"), Some(_)) if !json => usage_exit("export entities needs a format, like --json."),
+            (Some("entities"), Some(world)) => Command::Export(Export::Entities, PathBuf::from(world)),
+            (Some("geometry"), Some(_)) if !obj => usage_exit("export geometry needs a format, like --obj."),
+            (Some("geometry"), Some(world)) => Command::Export(Export::Geometry, PathBuf::from(world)),
+            (Some("components" | "entities" | "geometry"), None) => usage_exit("export needs the world file to export from."),
+            _ => usage_exit("export only knows `components`, `entities` and `geometry`."),
+        },
+        Some("trim") => match (positional.get(1), region) {
+            (Some(world), Some(region)) => Command::Trim(PathBuf::from(world), region),
+            (None, _) => usage_exit("trim needs the world file to trim."),
+            (_, None) => usage_exit("trim needs a --box to keep (or to remove, with --invert)."),
+        },
+        Some("split") => match (positional.get(1), split) {
+            (Some(world), Some(split)) => Command::Split(PathBuf::from(world), split),
+            (None, _) => usage_exit("split needs the world file to split."),
+            (_, None) => usage_exit("split needs --tiles or --regions, to know where to cut."),
+        },
+        Some("merge") => match (positional.len(), output) {
+            (3.., Some(output)) => Command::Merge(positional[1..].iter().map(PathBuf::from).collect(), output),
+            (..3, _) => usage_exit("merge needs at least two world files to merge."),
+            (_, None) => usage_exit("merge needs -o with the file to write the merged world to."),
+        },
+        Some("extract") => match (positional.get(1), grid) {
+            (Some(world), Some(grid)) => Command::Extract(PathBuf::from(world), grid),
+            (None, _) => usage_exit("extract needs the world file to extract from."),
+            (_, None) => usage_exit("extract needs the --grid to extract."),
+        },
+        Some("insert") => match (positional.get(1), positional.get(2), at) {
+            (Some(prefab), Some(world), Some(at)) => Command::Insert(PathBuf::from(prefab), PathBuf::from(world), at),
+            (None, _, _) => usage_exit("insert needs the prefab to insert."),
+            (_, None, _) => usage_exit("insert needs the world file to insert the prefab into."),
+            (_, _, None) => usage_exit("insert needs --at, to know where to put the prefab."),
+        },
+        Some(url) if remote::is_remote(url) => Command::Remote(url.to_string()),
+        Some(world) => Command::Optimize(PathBuf::from(world)),
+        None => usage_exit("You must run the program with an argument that points to a world file."),
+    };
+
+    Args {
+        command,
+        force,
+        repair,
+        config,
+        max_lights_per_chunk,
+        max_lights_per_owner,
+        strip_hidden_bricks,
+        merge_bricks,
+        consolidate_microbricks,
+        freeze_all,
+        unfreeze,
+        max_active_grids,
+        vacuum,
+        archive,
+        archive_only,
+        compression,
+        manifest,
+        keep_revisions_newer_than,
+        report,
+        census,
+        heatmap,
+        density,
+        json,
+        top,
+        props,
+        merge_chunks,
+        region,
+        invert,
+        offsets,
+        schedule,
+        skip,
+        rulesets,
+        purge_owners,
+        enforce_brick_budget,
+        stats,
+        upload,
+    }
+}
@@ -0,0 +1,521 @@
+/*
+ * merges rows of identical bricks into fewer, bigger bricks,
+ * and turns big lumps of microbricks into single bricks
+ *
+ * imported voxel builds are made of thousands of little cubes sitting side by side.
+ * where a row of them has the same shape, material, color, owner and settings,
+ * one long brick looks exactly the same, so the row becomes a single brick.
+ * rows are merged along X first, then rows that line up are merged along Y, then along Z,
+ * so a solid block of cubes can end up as one brick.
+ *
+ * only plain, opaque boxes are merged (see hidden.rs), you'd see the seams inside glass.
+ * like hidden.rs this only looks within a chunk, bricks at either side of a chunk edge stay apart
+ *
+ * lumps of microbricks (see consolidate) are rougher: a lump that fills most of the box around it
+ * becomes one microbrick the size of that box, filling in the gaps. that changes the shape a little,
+ * so it's only done when asked for
+ */
+
+use std::collections::HashMap;
+use brdb::{BrdbWorldGlobalData, BrickChunkSoA, BrickSize, RelativePosition};
+
+use crate::{bricks, hidden};
+
+const MICROBRICK: &str = "PB_DefaultMicroBrick";
+
+// biggest a merged brick gets along a side (half its size, like BrickSize), the game won't place bigger ones
+const MAX_SIZE: i32 = 1000;
+
+// a brick that grew to take the place of others
+pub struct Merged {
+    pub brick: usize,
+    pub size: BrickSize,
+    pub position: RelativePosition,
+}
+
+// everything that has to match for two bricks to become one
+#[derive(PartialEq, Eq, Hash)]
+struct Key {
+    asset: u32,
+    material: u8,
+    color: [u8; 4],
+    owner: u32,
+    orientation: u8,
+    flags: [bool; 5],
+}
+
+// a brick that can be merged, and the space it takes up in the chunk's brick units
+struct Space {
+    key: Key,
+    min: [i32; 3],
+    max: [i32; 3],
+    // whether it took the place of others
+    grown: bool,
+}
+
+/*
+ * finds the bricks to merge. returns the bricks that grew, and the ones they swallowed (which should be removed).
+ * bricks that are already being removed (`keep` is false) and bricks with components (`component_bricks`) are left alone
+ */
+pub fn find(
+    bricks: &BrickChunkSoA,
+    global: &BrdbWorldGlobalData,
+    component_bricks: &[u32],
+    keep: &[bool],
+) -> (Vec<Merged>, Vec<usize>) {
+    let mut boxes = spaces(bricks, global, component_bricks, keep, hidden::BOX_ASSETS);
+    let swallowed = rows(&mut boxes);
+
+    let merged = boxes.iter()
+        .enumerate()
+        .filter_map(|(i, space)| {
+            let Space { min, max, grown, .. } = space.as_ref()?;
+            grown.then(|| resized(bricks, i, *min, *max))
+        })
+        .collect();
+    (merged, swallowed)
+}
+
+/*
+ * merges the rows in `boxes`, along X, then Y, then Z. the first brick of a row grows over the others,
+ * which become None and are returned
+ */
+fn rows(boxes: &mut [Option<Space>]) -> Vec<usize> {
+    let mut swallowed = vec![];
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        // bricks that could line up along this axis: same key, and the same size and place on the other two axes
+        let mut rows: HashMap<(&Key, [i32; 4]), Vec<usize>> = HashMap::new();
+        for (i, space) in boxes.iter().enumerate() {
+            if let Some(Space { key, min, max, .. }) = space {
+                rows.entry((key, [min[u], max[u], min[v], max[v]])).or_default().push(i);
+            }
+        }
+
+        let mut merges = vec![];
+        for row in rows.values_mut() {
+            // along this axis, every brick in the row is Some
+            let along = |i: usize| boxes[i].as_ref().map_or((0, 0), |b| (b.min[axis], b.max[axis]));
+            row.sort_by_key(|i| along(*i).0);
+            let mut start = row[0];
+            let (mut min, mut max) = along(start);
+            for i in row[1..].iter().copied() {
+                let (next_min, next_max) = along(i);
+                /*
+                 * the next brick has to start right where this one ends, and the merged brick
+                 * needs a whole number for its center (bricks can't sit at half a unit)
+                 */
+                if next_min == max && (min + next_max) % 2 == 0 && next_max - min <= MAX_SIZE * 2 {
+                    max = next_max;
+                    merges.push((start, i, max));
+                } else {
+                    (start, min, max) = (i, next_min, next_max);
+                }
+            }
+        }
+        for (start, i, max) in merges {
+            if let Some(space) = boxes[start].as_mut() {
+                space.max[axis] = max;
+                space.grown = true;
+            }
+            boxes[i] = None;
+            swallowed.push(i);
+        }
+    }
+    swallowed
+}
+
+/*
+ * finds lumps of at least `min_bricks` identical microbricks touching each other, that fill at least `fill`
+ * (0 to 1) of the box around them. every lump becomes its first brick, grown to the size of that box.
+ * returns the bricks that grew and the ones they swallowed, like find. lumps with overlapping bricks are skipped,
+ * those can't be told apart from gaps, and so are lumps whose box would swallow other bricks
+ * (a window of another color in a wall, or a door)
+ */
+pub fn consolidate(
+    bricks: &BrickChunkSoA,
+    global: &BrdbWorldGlobalData,
+    component_bricks: &[u32],
+    keep: &[bool],
+    min_bricks: usize,
+    fill: f32,
+) -> (Vec<Merged>, Vec<usize>) {
+    let boxes = spaces(bricks, global, component_bricks, keep, &[MICROBRICK]);
+
+    /*
+     * the space of every other brick, which lumps mustn't grow into.
+     * basic bricks don't store their size, so for those only where they sit is known (min and max are the same)
+     */
+    let others: Vec<(usize, [i32; 3], [i32; 3])> = (0..boxes.len())
+        .filter(|i| keep[*i])
+        .map(|i| {
+            let position = bricks.relative_positions[i];
+            let center = [position.x as i32, position.y as i32, position.z as i32];
+            let extents = match bricks::procedural_type(bricks, bricks.brick_type_indices[i]) {
+                Some((_, size)) => hidden::extents([size.x as i32, size.y as i32, size.z as i32], bricks.orientations[i]),
+                None => [0; 3],
+            };
+            (i, [0, 1, 2].map(|k| center[k] - extents[k]), [0, 1, 2].map(|k| center[k] + extents[k]))
+        })
+        .collect();
+
+    let mut merged = vec![];
+    let mut swallowed = vec![];
+    for (members, min, max) in lumps(&boxes, &others, min_bricks, fill) {
+        merged.push(resized(bricks, members[0], min, max));
+        swallowed.extend(&members[1..]);
+    }
+    (merged, swallowed)
+}
+
+/*
+ * the lumps in `boxes` that can become one brick, as their bricks (sorted) and the box around them.
+ * `others` is the space every brick that's kept takes up, see consolidate
+ */
+fn lumps(
+    boxes: &[Option<Space>],
+    others: &[(usize, [i32; 3], [i32; 3])],
+    min_bricks: usize,
+    fill: f32,
+) -> Vec<(Vec<usize>, [i32; 3], [i32; 3])> {
+    // sorted by where they start along X, so neighbours can be found without checking every brick
+    let mut sorted: Vec<(usize, &Space)> = boxes.iter().enumerate().filter_map(|(i, b)| Some((i, b.as_ref()?))).collect();
+    sorted.sort_by_key(|(_, b)| b.min[0]);
+
+    // lumps are found by joining every brick with the ones it touches (union-find)
+    let mut lump: Vec<usize> = (0..boxes.len()).collect();
+    fn root(lump: &mut [usize], mut i: usize) -> usize {
+        while lump[i] != i {
+            lump[i] = lump[lump[i]];
+            i = lump[i];
+        }
+        i
+    }
+    let mut overlapping = vec![false; boxes.len()];
+    for (n, (i, a)) in sorted.iter().enumerate() {
+        for (j, b) in sorted[n + 1..].iter().take_while(|(_, b)| b.min[0] <= a.max[0]) {
+            if a.key != b.key {
+                continue;
+            }
+            // how far they overlap along each axis, 0 is touching and below 0 is apart
+            let overlap = [0, 1, 2].map(|k| a.max[k].min(b.max[k]) - a.min[k].max(b.min[k]));
+            if overlap.iter().any(|o| *o < 0) {
+                continue;
+            }
+            match overlap.iter().filter(|o| **o == 0).count() {
+                // sharing a side
+                1 => {
+                    let (ri, rj) = (root(&mut lump, *i), root(&mut lump, *j));
+                    lump[rj] = ri;
+                }
+                // inside each other
+                0 => {
+                    overlapping[*i] = true;
+                    overlapping[*j] = true;
+                }
+                // only touching at an edge or corner
+                _ => {}
+            }
+        }
+    }
+
+    let mut by_root: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, _) in &sorted {
+        let r = root(&mut lump, *i);
+        by_root.entry(r).or_default().push(*i);
+    }
+
+    let mut found = vec![];
+    for mut members in by_root.into_values() {
+        if members.len() < min_bricks.max(2) || members.iter().any(|i| overlapping[*i]) {
+            continue;
+        }
+        members.sort();
+
+        let mut min = [i32::MAX; 3];
+        let mut max = [i32::MIN; 3];
+        let mut volume = 0.0;
+        for i in members.iter() {
+            let Some(b) = &boxes[*i] else {
+                continue;
+            };
+            min = [0, 1, 2].map(|k| min[k].min(b.min[k]));
+            max = [0, 1, 2].map(|k| max[k].max(b.max[k]));
+            volume += (0..3).map(|k| (b.max[k] - b.min[k]) as f64).product::<f64>();
+        }
+        let size_ok = (0..3).all(|k| (min[k] + max[k]) % 2 == 0 && max[k] - min[k] <= MAX_SIZE * 2);
+        let filled = volume / (0..3).map(|k| (max[k] - min[k]) as f64).product::<f64>();
+        if !size_ok || filled < fill as f64 {
+            continue;
+        }
+        // a basic brick is in the way as soon as it sits inside the box or on its edge, whatever its size
+        let in_the_way = others.iter().any(|(i, other_min, other_max)| {
+            let inside = if other_min == other_max {
+                (0..3).all(|k| min[k] <= other_min[k] && other_min[k] <= max[k])
+            } else {
+                (0..3).all(|k| other_min[k] < max[k] && min[k] < other_max[k])
+            };
+            inside && members.binary_search(i).is_err()
+        });
+        if in_the_way {
+            continue;
+        }
+
+        found.push((members, min, max));
+    }
+    found
+}
+
+// every brick that can be merged, None for the rest: only opaque bricks of these assets, see the top of this file
+fn spaces(
+    bricks: &BrickChunkSoA,
+    global: &BrdbWorldGlobalData,
+    component_bricks: &[u32],
+    keep: &[bool],
+    assets: &[&str],
+) -> Vec<Option<Space>> {
+    let mut boxes: Vec<Option<Space>> = (0..bricks.brick_type_indices.len()).map(|_| None).collect();
+    for (i, space) in boxes.iter_mut().enumerate() {
+        if !keep[i] || component_bricks.contains(&(i as u32)) {
+            continue;
+        }
+        let Some((asset, size)) = bricks::procedural_type(bricks, bricks.brick_type_indices[i]) else {
+            continue;
+        };
+        let material = global.material_asset_names.get(bricks.material_indices[i] as usize);
+        let solid = global.procedural_brick_asset_names.get(asset as usize).is_some_and(|a| assets.contains(&a.as_str()))
+            && material.is_some_and(|m| hidden::OPAQUE_MATERIALS.contains(&m.as_str()))
+            && bricks.colors_and_alphas[i].a == 255;
+        if !solid {
+            continue;
+        }
+
+        let color = bricks.colors_and_alphas[i];
+        let key = Key {
+            asset,
+            material: bricks.material_indices[i],
+            color: [color.r, color.g, color.b, color.a],
+            owner: bricks.owner_indices[i],
+            orientation: bricks.orientations[i],
+            flags: [
+                bricks.collision_flags_player.get(i),
+                bricks.collision_flags_weapon.get(i),
+                bricks.collision_flags_interaction.get(i),
+                bricks.collision_flags_tool.get(i),
+                bricks.visibility_flags.get(i),
+            ],
+        };
+        let extents = hidden::extents([size.x as i32, size.y as i32, size.z as i32], bricks.orientations[i]);
+        let position = bricks.relative_positions[i];
+        let center = [position.x as i32, position.y as i32, position.z as i32];
+        *space = Some(Space {
+            key,
+            min: [0, 1, 2].map(|k| center[k] - extents[k]),
+            max: [0, 1, 2].map(|k| center[k] + extents[k]),
+            grown: false,
+        });
+    }
+    boxes
+}
+
+// brick `i` grown (or shrunk) to fill the space from `min` to `max`
+fn resized(bricks: &BrickChunkSoA, i: usize, min: [i32; 3], max: [i32; 3]) -> Merged {
+    let extents = [0, 1, 2].map(|k| (max[k] - min[k]) / 2);
+    let center = [0, 1, 2].map(|k| (min[k] + max[k]) / 2);
+    Merged {
+        brick: i,
+        size: size(extents, bricks.orientations[i]),
+        position: RelativePosition { x: center[0] as i16, y: center[1] as i16, z: center[2] as i16 },
+    }
+}
+
+// the brick size that has these extents along X, Y and Z at this orientation, the other way around from hidden::extents
+fn size(extents: [i32; 3], orientation: u8) -> BrickSize {
+    // which side of the brick ends up along each axis of the world
+    let sides = hidden::extents([0, 1, 2], orientation);
+    let mut size = [0; 3];
+    for axis in 0..3 {
+        size[sides[axis] as usize] = extents[axis];
+    }
+    BrickSize { x: size[0] as u16, y: size[1] as u16, z: size[2] as u16 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a mergeable brick from `min` to `max`, bricks with the same `color` match
+    fn brick(color: u8, min: [i32; 3], max: [i32; 3]) -> Option<Space> {
+        let key = Key { asset: 0, material: 0, color: [color, 0, 0, 255], owner: 0, orientation: 16, flags: [true; 5] };
+        Some(Space { key, min, max, grown: false })
+    }
+
+    // `count` bricks of size 10 in a row along `axis`, starting at 0
+    fn row(axis: usize, count: i32) -> Vec<Option<Space>> {
+        (0..count)
+            .map(|n| {
+                let (mut min, mut max) = ([0; 3], [10; 3]);
+                min[axis] = n * 10;
+                max[axis] = n * 10 + 10;
+                brick(0, min, max)
+            })
+            .collect()
+    }
+
+    fn merged(boxes: &mut [Option<Space>]) -> Vec<usize> {
+        let mut swallowed = rows(boxes);
+        swallowed.sort();
+        swallowed
+    }
+
+    #[test]
+    fn merges_rows_along_every_axis() {
+        for axis in 0..3 {
+            let mut boxes = row(axis, 3);
+            assert_eq!(merged(&mut boxes), [1, 2]);
+
+            let first = boxes[0].as_ref().unwrap();
+            let mut max = [10; 3];
+            max[axis] = 30;
+            assert_eq!((first.min, first.max, first.grown), ([0; 3], max, true));
+            assert!(boxes[1].is_none() && boxes[2].is_none());
+        }
+    }
+
+    #[test]
+    fn merges_a_block_into_one_brick() {
+        let mut boxes = vec![];
+        for z in 0..2 {
+            for y in 0..2 {
+                for x in 0..2 {
+                    let min = [x * 10, y * 10, z * 10];
+                    boxes.push(brick(0, min, min.map(|m| m + 10)));
+                }
+            }
+        }
+        assert_eq!(merged(&mut boxes), (1..8).collect::<Vec<_>>());
+        let first = boxes[0].as_ref().unwrap();
+        assert_eq!((first.min, first.max), ([0; 3], [20; 3]));
+    }
+
+    #[test]
+    fn leaves_different_bricks_apart() {
+        let mut boxes = vec![brick(0, [0; 3], [10; 3]), brick(1, [10, 0, 0], [20, 10, 10])];
+        assert!(merged(&mut boxes).is_empty());
+        assert!(!boxes[0].as_ref().unwrap().grown);
+    }
+
+    #[test]
+    fn leaves_gaps_and_other_shapes_alone() {
+        // a gap along X
+        let mut boxes = vec![brick(0, [0; 3], [10; 3]), brick(0, [12, 0, 0], [22, 10, 10])];
+        assert!(merged(&mut boxes).is_empty());
+        // right next to it, but taller
+        let mut boxes = vec![brick(0, [0; 3], [10; 3]), brick(0, [10, 0, 0], [20, 10, 20])];
+        assert!(merged(&mut boxes).is_empty());
+    }
+
+    #[test]
+    fn keeps_centers_on_whole_units() {
+        // three bricks 1 unit wide: the first two make a brick centered on 1, all three would be centered on 1.5
+        let mut boxes: Vec<Option<Space>> = (0..3).map(|x| brick(0, [x, 0, 0], [x + 1, 10, 10])).collect();
+        assert_eq!(merged(&mut boxes), [1]);
+        assert_eq!(boxes[0].as_ref().unwrap().max, [2, 10, 10]);
+        assert!(boxes[2].as_ref().is_some_and(|b| !b.grown));
+    }
+
+    #[test]
+    fn doesnt_grow_past_the_biggest_brick() {
+        let mut boxes: Vec<Option<Space>> = (0..3).map(|x| brick(0, [x * 800, 0, 0], [x * 800 + 800, 10, 10])).collect();
+        assert_eq!(merged(&mut boxes), [1]);
+        assert_eq!(boxes[0].as_ref().unwrap().max[0], 1600);
+    }
+
+    // a microbrick (2 units wide) with its corner at `at`
+    fn micro(color: u8, at: [i32; 3]) -> Option<Space> {
+        brick(color, at, at.map(|a| a + 2))
+    }
+
+    // a 2x2x2 block of microbricks with its corner at 0
+    fn block() -> Vec<Option<Space>> {
+        let mut boxes = vec![];
+        for z in [0, 2] {
+            for y in [0, 2] {
+                for x in [0, 2] {
+                    boxes.push(micro(0, [x, y, z]));
+                }
+            }
+        }
+        boxes
+    }
+
+    // the space every brick in `boxes` takes up, and the basic bricks at `basic` (which aren't in `boxes`)
+    fn others(boxes: &[Option<Space>], basic: &[(usize, [i32; 3])]) -> Vec<(usize, [i32; 3], [i32; 3])> {
+        let mut others: Vec<_> = boxes.iter()
+            .enumerate()
+            .filter_map(|(i, b)| b.as_ref().map(|b| (i, b.min, b.max)))
+            .collect();
+        others.extend(basic.iter().map(|(i, at)| (*i, *at, *at)));
+        others
+    }
+
+    #[test]
+    fn consolidates_a_solid_lump() {
+        let boxes = block();
+        let found = lumps(&boxes, &others(&boxes, &[]), 8, 0.95);
+        assert_eq!(found, [((0..8).collect(), [0; 3], [4; 3])]);
+        // too few bricks
+        assert!(lumps(&boxes, &others(&boxes, &[]), 9, 0.95).is_empty());
+    }
+
+    #[test]
+    fn needs_the_lump_to_fill_its_box() {
+        // an L of three microbricks fills 3/4 of the box around it
+        let boxes = vec![micro(0, [0, 0, 0]), micro(0, [2, 0, 0]), micro(0, [0, 2, 0])];
+        assert!(lumps(&boxes, &others(&boxes, &[]), 2, 0.95).is_empty());
+        assert_eq!(lumps(&boxes, &others(&boxes, &[]), 2, 0.7).len(), 1);
+    }
+
+    #[test]
+    fn only_joins_matching_bricks_that_share_a_side() {
+        // another color
+        let boxes = vec![micro(0, [0, 0, 0]), micro(1, [2, 0, 0])];
+        assert!(lumps(&boxes, &others(&boxes, &[]), 2, 0.0).is_empty());
+        // only touching at an edge
+        let boxes = vec![micro(0, [0, 0, 0]), micro(0, [2, 2, 0])];
+        assert!(lumps(&boxes, &others(&boxes, &[]), 2, 0.0).is_empty());
+        // inside each other
+        let boxes = vec![micro(0, [0, 0, 0]), micro(0, [1, 0, 0]), micro(0, [3, 0, 0])];
+        assert!(lumps(&boxes, &others(&boxes, &[]), 2, 0.0).is_empty());
+    }
+
+    #[test]
+    fn never_takes_basic_bricks() {
+        // basic bricks don't store their size, so anything from inside the box to its edge is in the way
+        let mut boxes = block();
+        boxes.push(None);
+        for at in [[2, 2, 2], [0, 0, 0], [4, 4, 4], [4, 1, 1]] {
+            assert!(lumps(&boxes, &others(&boxes, &[(8, at)]), 8, 0.95).is_empty(), "basic brick at {at:?}");
+        }
+        // next to it is fine, and it's never one of the bricks the lump swallows
+        let found = lumps(&boxes, &others(&boxes, &[(8, [5, 1, 1])]), 8, 0.95);
+        assert_eq!(found.len(), 1);
+        assert!(!found[0].0.contains(&8));
+    }
+
+    #[test]
+    fn doesnt_grow_over_other_bricks() {
+        // a gap in the lump, with a brick of another color in it
+        let mut boxes = block();
+        boxes[7] = None;
+        boxes.push(micro(1, [2, 2, 2]));
+        assert!(lumps(&boxes, &others(&boxes, &[]), 7, 0.8).is_empty());
+        // without it the lump fills the gap
+        boxes[8] = None;
+        assert_eq!(lumps(&boxes, &others(&boxes, &[]), 7, 0.8).len(), 1);
+        // a brick right against the box isn't in it
+        boxes[8] = micro(1, [4, 0, 0]);
+        assert_eq!(lumps(&boxes, &others(&boxes, &[]), 7, 0.8).len(), 1);
+    }
+}
@@ -0,0 +1,79 @@
+/*
+ * comparing the original world with the optimized one
+ *
+ * after a run, a table of the numbers that matter side by side (file size, revisions,
+ * entities, components, frozen entities) shows what optimizing actually did to the world
+ */
+
+use std::{fs, path::Path};
+use brdb::{Brdb, IntoReader};
+
+use crate::{grids, revisions, worlds};
+
+#[derive(Default)]
+pub struct Totals {
+    pub file_size: u64,
+    pub revisions: usize,
+    pub entities: usize,
+    pub components: usize,
+    pub frozen: usize,
+}
+
+// counts everything in the world file at `path`, across all worlds in it
+pub fn totals(path: &Path) -> Result<Totals, Box<dyn std::error::Error>> {
+    let mut totals = Totals {
+        file_size: fs::metadata(path)?.len(),
+        revisions: revisions::list(path)?.len(),
+        ..Default::default()
+    };
+
+    let db = Brdb::open(path)?.into_reader();
+    let source = db.to_pending()?;
+    for world in worlds::indices(&source) {
+        worlds::read_world(&db, &source, &world, path, |world_db| {
+            for chunk in world_db.entity_chunk_index()? {
+                for entity in world_db.entity_chunk(chunk)? {
+                    totals.entities += 1;
+                    if entity.frozen {
+                        totals.frozen += 1;
+                    }
+                }
+            }
+            // the chunk index knows how many components each chunk has, no need to read them
+            for grid in grids::find(world_db, &source, &world)?.all() {
+                for chunk in world_db.brick_chunk_index(grid)? {
+                    totals.components += chunk.num_components as usize;
+                }
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(totals)
+}
+
+// the comparison table, one line per row
+pub fn table(before: &Totals, after: &Totals) -> Vec<String> {
+    let mb = |bytes: u64| format!("{:.1} MB", bytes as f64 / 1024.0 / 1024.0);
+    let mut lines = vec![
+        format!("{:<18}{:>14}{:>14}{:>14}", "", "before", "after", "change"),
+        format!(
+            "{:<18}{:>14}{:>14}{:>14}",
+            "file size",
+            mb(before.file_size),
+            mb(after.file_size),
+            format!("{:+.1} MB", (after.file_size as f64 - before.file_size as f64) / 1024.0 / 1024.0),
+        ),
+    ];
+
+    let rows = [
+        ("revisions", before.revisions, after.revisions),
+        ("entities", before.entities, after.entities),
+        ("components", before.components, after.components),
+        ("frozen entities", before.frozen, after.frozen),
+    ];
+    for (name, before, after) in rows {
+        lines.push(format!("{name:<18}{before:>14}{after:>14}{:>14}", format!("{:+}", after as i64 - before as i64)));
+    }
+    lines
+}
@@ -0,0 +1,46 @@
+/*
+ * checks whether a world's component schema matches what the optimizer expects
+ *
+ * brdb files carry their own schema, describing every struct and property
+ * the game used when saving. if a game update renames or removes a property
+ * we rely on, we'd rather find out before optimizing than halfway through
+ */
+
+use brdb::schema::BrdbSchema;
+
+use crate::rules::{RULE_PROPS, THRUST_PROPS, THRUSTER_COMPONENTS};
+
+/*
+ * returns a list of properties the tool needs but the world doesn't have.
+ * structs missing entirely are fine, that just means the world doesn't use that component.
+ * what's needed comes from the lists in rules.rs, the emissive properties aren't in there
+ * because they're optional on every component
+ */
+pub fn check(component_schema: &BrdbSchema) -> Vec<String> {
+    let mut problems = vec![];
+
+    for (components, props) in RULE_PROPS {
+        for struct_name in *components {
+            let Some(schema_props) = component_schema.structs.get(*struct_name) else {
+                continue;
+            };
+
+            for prop in *props {
+                if !schema_props.contains_key(*prop) {
+                    problems.push(format!("{struct_name} has no {prop} property"));
+                }
+            }
+        }
+    }
+
+    // every type of thruster keeps its force in one of these, depending on the type
+    for struct_name in THRUSTER_COMPONENTS {
+        if let Some(schema_props) = component_schema.structs.get(*struct_name)
+            && !THRUST_PROPS.iter().any(|prop| schema_props.contains_key(*prop))
+        {
+            problems.push(format!("{struct_name} has none of the {} properties", THRUST_PROPS.join("/")));
+        }
+    }
+
+    problems
+}
@@ -0,0 +1,268 @@
+/*
+ * helpers for changing which components a chunk contains
+ *
+ * component chunks store their components grouped by type:
+ *  - component_type_counters: how many components of each type follow, in order
+ *  - component_brick_indices: which brick (in the brick chunk) each component belongs to
+ * and the component data itself, in that same order.
+ * so whenever components get removed, all three have to be kept in sync.
+ *
+ * on top of that, every grid has a chunk index (ChunkIndex.mps)
+ * that stores how many components each chunk has, which needs updating too (see bricks.rs)
+ */
+
+use brdb::{BrdbComponent, ChunkIndex, ComponentChunkSoA, ComponentTypeCounter, Quat4f, Vector3f, schema::BrdbStruct};
+
+/*
+ * components that connect their brick to another entity (a grid or a wheel) with a joint.
+ * the joint arrays of the SoA have one entry for each of these, in the order they appear in
+ */
+pub const JOINT_COMPONENTS: &[&str] = &[
+    "BrickComponentData_Bearing",
+    "BrickComponentData_Slider",
+    "BrickComponentData_Servo",
+];
+
+/*
+ * removes the components at the given indices from the chunk.
+ * NOTE: joint components (bearings/sliders) have extra joint data in the SoA
+ * that isn't touched here, see remove_duplicate_joints
+ */
+pub fn remove(soa: &mut ComponentChunkSoA, components: &mut Vec<BrdbStruct>, indices: &[usize]) {
+    if indices.is_empty() {
+        return;
+    }
+
+    // rebuild the type counters, skipping the removed components
+    let mut i = 0;
+    for counter in soa.component_type_counters.iter_mut() {
+        let start = i;
+        i += counter.num_instances as usize;
+        let removed = indices.iter().filter(|idx| (start..i).contains(idx)).count();
+        counter.num_instances -= removed as u32;
+    }
+    soa.component_type_counters.retain(|counter| counter.num_instances > 0);
+
+    let mut i = 0;
+    soa.component_brick_indices.retain(|_| {
+        i += 1;
+        !indices.contains(&(i - 1))
+    });
+    let mut i = 0;
+    components.retain(|_| {
+        i += 1;
+        !indices.contains(&(i - 1))
+    });
+}
+
+/*
+ * removes components that belong to a brick that doesn't exist (anymore),
+ * which the game chokes on when loading the chunk. returns how many were removed.
+ * chunks with joints are left alone, see remove()
+ */
+pub fn remove_orphans(
+    grid: usize,
+    chunk: ChunkIndex,
+    num_bricks: u32,
+    soa: &mut ComponentChunkSoA,
+    components: &mut Vec<BrdbStruct>,
+) -> usize {
+    let orphans: Vec<usize> = soa.component_brick_indices.iter()
+        .enumerate()
+        .filter(|(_, brick)| **brick >= num_bricks)
+        .map(|(i, _)| i)
+        .collect();
+    if orphans.is_empty() {
+        return 0;
+    }
+
+    if !soa.joint_brick_indices.is_empty() {
+        println!("[grid:{grid}][{chunk}] has {} orphaned components, but also joints, leaving it alone", orphans.len());
+        return 0;
+    }
+
+    println!("[grid:{grid}][{chunk}] removing {} components whose brick no longer exists..", orphans.len());
+    remove(soa, components, &orphans);
+    orphans.len()
+}
+
+/*
+ * points components (and joints) at their brick's new index after bricks were removed from the chunk.
+ * `map` holds the new index of every old brick
+ */
+pub fn remap_bricks(soa: &mut ComponentChunkSoA, map: &[u32]) {
+    for brick in soa.component_brick_indices.iter_mut().chain(soa.joint_brick_indices.iter_mut()) {
+        if let Some(new) = map.get(*brick as usize) {
+            *brick = *new;
+        }
+    }
+}
+
+/*
+ * removes joint components that are exact copies of another joint in the chunk:
+ * same type, same settings, connected to the same entity at the same spot.
+ * the physics solver would otherwise solve the same constraint twice. returns how many were removed
+ */
+pub fn remove_duplicate_joints(
+    grid: usize,
+    chunk: ChunkIndex,
+    soa: &mut ComponentChunkSoA,
+    components: &mut Vec<BrdbStruct>,
+) -> usize {
+    if soa.joint_entity_references.len() < 2 {
+        return 0;
+    }
+    let Some(joints) = joint_components(grid, chunk, soa, components) else {
+        return 0;
+    };
+
+    let mut seen = vec![];
+    let mut duplicates = vec![];
+    for (joint, i) in joints.iter().enumerate() {
+        let offset = soa.joint_initial_relative_offsets.get(joint).map(|o| [o.x, o.y, o.z].map(f32::to_bits));
+        let rotation = soa.joint_initial_relative_rotations.get(joint).map(|r| [r.x, r.y, r.z, r.w].map(f32::to_bits));
+        let key = (soa.joint_entity_references[joint], offset, rotation, format!("{:?}", components[*i]));
+        if seen.contains(&key) {
+            duplicates.push(joint);
+        } else {
+            seen.push(key);
+        }
+    }
+    if duplicates.is_empty() {
+        return 0;
+    }
+
+    println!("[grid:{grid}][{chunk}] removing {} duplicate joints..", duplicates.len());
+    remove_joints(soa, components, &joints, &duplicates)
+}
+
+/*
+ * removes the joints at the given indices (of the joint arrays) to break loops of joints,
+ * see joints.rs. returns how many were removed
+ */
+pub fn break_joints(
+    grid: usize,
+    chunk: ChunkIndex,
+    soa: &mut ComponentChunkSoA,
+    components: &mut Vec<BrdbStruct>,
+    broken: &[usize],
+) -> usize {
+    let Some(joints) = joint_components(grid, chunk, soa, components) else {
+        return 0;
+    };
+
+    println!("[grid:{grid}][{chunk}] removing {} joints to break a loop of joints..", broken.len());
+    remove_joints(soa, components, &joints, broken)
+}
+
+// which components are the joints, in the order of the joint arrays. None if those don't line up
+fn joint_components(grid: usize, chunk: ChunkIndex, soa: &ComponentChunkSoA, components: &[BrdbStruct]) -> Option<Vec<usize>> {
+    let joints: Vec<usize> = components.iter()
+        .enumerate()
+        .filter(|(_, component)| JOINT_COMPONENTS.contains(&component.get_name()))
+        .map(|(i, _)| i)
+        .collect();
+    // if the joint data doesn't line up with the joint components, we don't know which is which
+    if joints.len() != soa.joint_entity_references.len() {
+        println!(
+            "[grid:{grid}][{chunk}] has {} joint components but {} joints, leaving its joints alone",
+            joints.len(),
+            soa.joint_entity_references.len()
+        );
+        return None;
+    }
+    Some(joints)
+}
+
+// removes the `removed` joints, both their joint data and their component in `joints`
+fn remove_joints(soa: &mut ComponentChunkSoA, components: &mut Vec<BrdbStruct>, joints: &[usize], removed: &[usize]) -> usize {
+    let kept: Vec<bool> = (0..joints.len()).map(|joint| !removed.contains(&joint)).collect();
+    retain_joints(&mut soa.joint_brick_indices, &kept);
+    retain_joints(&mut soa.joint_entity_references, &kept);
+    retain_joints(&mut soa.joint_initial_relative_offsets, &kept);
+    retain_joints(&mut soa.joint_initial_relative_rotations, &kept);
+
+    let removed: Vec<usize> = removed.iter().filter_map(|joint| joints.get(*joint).copied()).collect();
+    remove(soa, components, &removed);
+    removed.len()
+}
+
+fn retain_joints<T>(values: &mut Vec<T>, kept: &[bool]) {
+    let mut i = 0;
+    values.retain(|_| {
+        i += 1;
+        kept.get(i - 1).copied().unwrap_or(true)
+    });
+}
+
+// one component of a chunk, with everything the SoA stores about it (see split and join)
+pub struct Part {
+    pub type_index: u32,
+    pub brick: u32,
+    pub data: BrdbStruct,
+    pub joint: Option<Joint>,
+}
+
+// the joint data of a joint component
+pub struct Joint {
+    pub brick: u32,
+    pub entity: u32,
+    pub offset: Vector3f,
+    pub rotation: Quat4f,
+}
+
+/*
+ * takes a chunk's components apart, so they can be changed or mixed with the components
+ * of another chunk without keeping all the arrays in sync by hand. join puts them back together.
+ * None if the joint data doesn't line up with the joint components
+ */
+pub fn split(grid: usize, chunk: ChunkIndex, soa: ComponentChunkSoA, components: Vec<BrdbStruct>) -> Option<Vec<Part>> {
+    let joints = joint_components(grid, chunk, &soa, &components)?;
+    let types: Vec<u32> = soa.component_type_counters.iter()
+        .flat_map(|counter| std::iter::repeat_n(counter.type_index, counter.num_instances as usize))
+        .collect();
+
+    Some(components.into_iter()
+        .enumerate()
+        .map(|(i, data)| Part {
+            type_index: types.get(i).copied().unwrap_or_default(),
+            brick: soa.component_brick_indices.get(i).copied().unwrap_or_default(),
+            joint: joints.iter().position(|j| *j == i).map(|joint| Joint {
+                brick: soa.joint_brick_indices[joint],
+                entity: soa.joint_entity_references[joint],
+                offset: soa.joint_initial_relative_offsets.get(joint).copied().unwrap_or_default(),
+                rotation: soa.joint_initial_relative_rotations.get(joint).copied().unwrap_or_default(),
+            }),
+            data,
+        })
+        .collect())
+}
+
+// puts components back into a chunk, grouped by type in the order the types first show up
+pub fn join(mut parts: Vec<Part>) -> (ComponentChunkSoA, Vec<BrdbStruct>) {
+    let mut order = vec![];
+    for part in &parts {
+        if !order.contains(&part.type_index) {
+            order.push(part.type_index);
+        }
+    }
+    parts.sort_by_key(|part| order.iter().position(|t| *t == part.type_index));
+
+    let mut soa = ComponentChunkSoA::default();
+    let mut components = vec![];
+    for part in parts {
+        match soa.component_type_counters.last_mut() {
+            Some(counter) if counter.type_index == part.type_index => counter.num_instances += 1,
+            _ => soa.component_type_counters.push(ComponentTypeCounter { type_index: part.type_index, num_instances: 1 }),
+        }
+        soa.component_brick_indices.push(part.brick);
+        if let Some(joint) = part.joint {
+            soa.joint_brick_indices.push(joint.brick);
+            soa.joint_entity_references.push(joint.entity);
+            soa.joint_initial_relative_offsets.push(joint.offset);
+            soa.joint_initial_relative_rotations.push(joint.rotation);
+        }
+        components.push(part.data);
+    }
+    (soa, components)
+}
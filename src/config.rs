@@ -0,0 +1,910 @@
+/*
+ * the limits and settings the optimizer works with
+ *
+ * everything has a sensible default, and can be changed with a config file:
+ * either the one passed with --config, or brdb_optimize.toml in the current directory.
+ * see brdb_optimize.example.toml for all options
+ */
+
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}};
+use serde::Deserialize;
+
+use crate::{cli::Args, rulesets::{self, Ruleset}};
+
+pub const DEFAULT_PATH: &str = "brdb_optimize.toml";
+
+/*
+ * passes that take long on big worlds, which daemon mode can leave out of the runs
+ * on every save and do on a schedule instead (see --skip and [[daemon.schedule]])
+ */
+pub const HEAVY_PASSES: &[&str] = &["revisions", "vacuum", "dedupe_blobs"];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub lights: LightConfig,
+    pub weights: WeightConfig,
+    pub spawners: SpawnerConfig,
+    pub particles: ParticleConfig,
+    pub bricks: BrickConfig,
+    pub motors: MotorConfig,
+    pub wires: WireConfig,
+    pub text: TextConfig,
+    pub sensors: SensorConfig,
+    pub modifiers: ModifierConfig,
+    pub weld: WeldConfig,
+    pub entities: EntityConfig,
+    pub items: ItemConfig,
+    pub actors: ActorConfig,
+    pub files: FileConfig,
+    pub revisions: RevisionConfig,
+    pub owners: OwnerConfig,
+    pub thrusters: ThrusterConfig,
+    pub wheels: WheelConfig,
+    pub joints: JointConfig,
+    pub environment: EnvironmentConfig,
+    pub lag_score: LagScoreConfig,
+    pub physics_cost: PhysicsCostConfig,
+    pub logic: LogicConfig,
+    pub daemon: DaemonConfig,
+    pub notify: NotifyConfig,
+    pub rulesets: RulesetConfig,
+    pub remote: RemoteConfig,
+    pub component_patterns: PatternConfig,
+    // [clamp."<component>".<property>], limits for any property of any component, replacing the built-in ones (see rulesets.rs)
+    pub clamp: BTreeMap<String, BTreeMap<String, Clamp>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LightConfig {
+    // for some reason the game stores radiuses as thousands..
+    pub max_radius: f32,
+    pub max_brightness: f32,
+    pub cast_shadows: bool,
+    // widest inner and outer cone angle (in degrees) spot lights may have
+    pub spot_max_cone_angle: f32,
+    // spot light attenuation cap, off unless set
+    pub spot_max_attenuation: Option<f32>,
+    // merge point lights closer together than this, off unless set
+    pub dedupe_distance: Option<f32>,
+    // most lights a single chunk may have turned on, off unless set
+    pub max_per_chunk: Option<usize>,
+    // cap on how strongly any component glows, off unless set
+    pub max_emissive: Option<f32>,
+    // most lights a single owner may have turned on in the world, off unless set
+    pub max_per_owner: Option<usize>,
+    // what happens to the lights of an owner over that quota
+    pub over_owner_quota: OwnerQuota,
+}
+
+// what to do with lights over an owner's quota
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OwnerQuota {
+    // turn them off
+    Disable,
+    // keep them on, but without shadows
+    Shadows,
+}
+
+impl Default for LightConfig {
+    fn default() -> Self {
+        Self {
+            max_radius: 5000.0,
+            max_brightness: 400.0,
+            cast_shadows: false,
+            spot_max_cone_angle: 60.0,
+            spot_max_attenuation: None,
+            dedupe_distance: None,
+            max_per_chunk: None,
+            max_emissive: None,
+            max_per_owner: None,
+            over_owner_quota: OwnerQuota::Disable,
+        }
+    }
+}
+
+// what to do with weight bricks on dynamic (physics) grids
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DynamicWeights {
+    // leave them alone
+    Off,
+    // cap their mass at dynamic_max_mass
+    Clamp,
+    // zero them out, like on the main grid
+    Neutralize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WeightConfig {
+    pub dynamic_grids: DynamicWeights,
+    pub dynamic_max_mass: f32,
+    // leave weights alone on dynamic grids that have an engine (vehicles usually need them)
+    pub dynamic_skip_engines: bool,
+}
+
+impl Default for WeightConfig {
+    fn default() -> Self {
+        Self {
+            dynamic_grids: DynamicWeights::Off,
+            dynamic_max_mass: 100.0,
+            dynamic_skip_engines: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpawnerConfig {
+    // shortest time (in seconds) item spawners may take to respawn their item
+    pub min_respawn_time: f32,
+    // most items a single spawner may have out at once, off unless set
+    pub max_items: Option<i32>,
+}
+
+impl Default for SpawnerConfig {
+    fn default() -> Self {
+        Self {
+            min_respawn_time: 5.0,
+            max_items: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ParticleConfig {
+    // particles spawned per second
+    pub max_rate: f32,
+    // how long (in seconds) a single particle may live
+    pub max_lifetime: f32,
+    pub max_scale: f32,
+}
+
+impl Default for ParticleConfig {
+    fn default() -> Self {
+        Self {
+            max_rate: 50.0,
+            max_lifetime: 5.0,
+            max_scale: 4.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BrickConfig {
+    // remove bricks that are exact copies of another brick in the same spot
+    pub remove_duplicates: bool,
+    // remove bricks on the main grid that are completely covered up by other bricks
+    pub strip_hidden: bool,
+    // merge rows of identical bricks on the main grid into bigger bricks
+    pub merge: bool,
+    // turn lumps of microbricks on the main grid into single bricks, filling in small gaps
+    pub consolidate_microbricks: bool,
+    // fewest microbricks a lump needs to have
+    pub min_microbricks: usize,
+    // how much of the box around a lump it has to fill (0 to 1)
+    pub microbrick_fill: f32,
+}
+
+impl Default for BrickConfig {
+    fn default() -> Self {
+        Self {
+            remove_duplicates: false,
+            strip_hidden: false,
+            merge: false,
+            consolidate_microbricks: false,
+            min_microbricks: 64,
+            microbrick_fill: 0.95,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MotorConfig {
+    // fastest bearings, servos and motors may try to turn (in either direction)
+    pub max_speed: f32,
+    pub max_torque: f32,
+    pub max_force: f32,
+}
+
+impl Default for MotorConfig {
+    fn default() -> Self {
+        Self {
+            max_speed: 1000.0,
+            max_torque: 100000.0,
+            max_force: 100000.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WireConfig {
+    // remove wires that connect the exact same ports as another wire
+    pub remove_duplicates: bool,
+}
+
+impl Default for WireConfig {
+    fn default() -> Self {
+        Self {
+            remove_duplicates: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TextConfig {
+    // longest text (in characters) a text brick may show
+    pub max_length: usize,
+    pub max_font_size: f32,
+    // remove invisible control characters (everything except newlines)
+    pub strip_control_chars: bool,
+}
+
+impl Default for TextConfig {
+    fn default() -> Self {
+        Self {
+            max_length: 2000,
+            max_font_size: 200.0,
+            strip_control_chars: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SensorConfig {
+    // shortest time (in seconds) between two checks of a sensor
+    pub min_interval: f32,
+}
+
+impl Default for SensorConfig {
+    fn default() -> Self {
+        Self {
+            min_interval: 0.2,
+        }
+    }
+}
+
+// what to do with gravity wells and other physics modifiers
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModifierMode {
+    // leave them alone
+    Off,
+    // cap their strength and radius at max_strength and max_radius
+    Clamp,
+    // set their strength to 0, so they don't do anything anymore
+    Neutralize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModifierConfig {
+    pub mode: ModifierMode,
+    pub max_strength: f32,
+    pub max_radius: f32,
+    // owners (user name, display name or user id) whose modifiers are left alone
+    pub exempt_owners: Vec<String>,
+}
+
+impl Default for ModifierConfig {
+    fn default() -> Self {
+        Self {
+            mode: ModifierMode::Clamp,
+            max_strength: 1000.0,
+            max_radius: 5000.0,
+            exempt_owners: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WeldConfig {
+    // weld small frozen physics grids into the main grid
+    pub enabled: bool,
+    // biggest grid (in bricks) that still gets welded
+    pub max_bricks: usize,
+}
+
+impl Default for WeldConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_bricks: 50,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EntityConfig {
+    // remove entities that are outside of the bounds below, instead of freezing them
+    pub remove_out_of_bounds: bool,
+    // furthest an entity may be from the center of the world, along any axis
+    pub max_distance: f32,
+    // lowest an entity may be, anything below fell out of the world
+    pub min_z: f32,
+    // which entities get frozen
+    pub freeze: FreezeMode,
+    // entity types that get frozen in the "wheels" mode, as glob patterns
+    pub freeze_types: Vec<String>,
+    // unfreeze the entities the freeze pass would freeze, instead of freezing them
+    pub unfreeze: bool,
+    // most dynamic grids that may be simulating at once, the most expensive ones beyond that get frozen. off unless set
+    pub max_active_grids: Option<usize>,
+    // freeze entities that haven't moved in this many revisions before the latest one, off unless set
+    pub freeze_unmoved: Option<usize>,
+    // entities going faster than this (in units per second) get reset to where they were in an earlier revision, off unless set
+    pub runaway_speed: Option<f32>,
+    // same for entities that moved further than this since the last revision, off unless set
+    pub runaway_distance: Option<f32>,
+}
+
+impl Default for EntityConfig {
+    fn default() -> Self {
+        Self {
+            remove_out_of_bounds: true,
+            max_distance: 2000000.0,
+            min_z: -50000.0,
+            freeze: FreezeMode::Wheels,
+            freeze_types: vec!["Entity_Wheel*".to_string(), "Entity_Ball*".to_string()],
+            unfreeze: false,
+            max_active_grids: None,
+            freeze_unmoved: None,
+            runaway_speed: None,
+            runaway_distance: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ItemConfig {
+    // remove dropped items that have been lying around longer than this (in seconds), off unless set
+    pub max_age: Option<f32>,
+    // most dropped items a single chunk may have, the oldest go first. off unless set
+    pub max_per_chunk: Option<usize>,
+}
+
+// what to do with spawned actors (bots, projectiles, ..) that nobody owns anymore
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StaleActors {
+    // leave them alone
+    Off,
+    // freeze them, like wheels and balls
+    Freeze,
+    // remove them from the world
+    Remove,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActorConfig {
+    pub stale: StaleActors,
+}
+
+impl Default for ActorConfig {
+    fn default() -> Self {
+        Self {
+            stale: StaleActors::Freeze,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    // remove the cached minimap tiles, the game renders them again when needed
+    pub strip_minimap: bool,
+    // remove preview images bigger than this (in KB), off unless set
+    pub max_preview_size: Option<usize>,
+    // VACUUM the written file, so removed data actually makes it smaller
+    pub vacuum: bool,
+    // store file contents that are exactly the same only once
+    pub dedupe_blobs: bool,
+    // drop brick asset and material names nothing uses anymore from the world's name tables
+    pub compact_names: bool,
+    // merge brick asset and material names that are in the world's name tables more than once
+    pub dedupe_names: bool,
+    // also write a compressed copy of the optimized world for backups, off unless set
+    pub archive: Option<ArchiveFormat>,
+    // how hard to compress the archive, from 1 (fast) to 22 (small)
+    pub archive_level: i32,
+    // only keep the archive, and delete the uncompressed optimized world
+    pub archive_only: bool,
+    // how much time to spend shrinking the written world
+    pub compression: Compression,
+    // write the sha-256 of the original and optimized world (and archive) to a manifest file
+    pub manifest: bool,
+    // also put the sha-256 of every blob in the optimized world in the manifest
+    pub manifest_blobs: bool,
+}
+
+// how to compress archives of the optimized world, see archive.rs
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    Zstd,
+}
+
+/*
+ * how hard to shrink the written world. brdb compresses every file as it writes it,
+ * this is about what happens after: fast skips merging blobs and compacting the file,
+ * max also compresses every blob again as hard as zstd goes
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Fast,
+    Default,
+    Max,
+}
+
+impl Default for FileConfig {
+    fn default() -> Self {
+        Self {
+            strip_minimap: false,
+            max_preview_size: None,
+            vacuum: true,
+            dedupe_blobs: true,
+            compact_names: false,
+            dedupe_names: false,
+            archive: None,
+            archive_level: 19,
+            archive_only: false,
+            compression: Compression::Default,
+            manifest: false,
+            manifest_blobs: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RevisionConfig {
+    // keep the world's revision history, instead of writing just the latest state
+    pub keep_history: bool,
+    // remove revisions that didn't change anything from the history that's kept
+    pub remove_unchanged: bool,
+    // keep history, but only the revisions newer than this (like "7d"), off unless set
+    pub keep_newer_than: Option<String>,
+}
+
+impl Default for RevisionConfig {
+    fn default() -> Self {
+        Self {
+            keep_history: false,
+            remove_unchanged: true,
+            keep_newer_than: None,
+        }
+    }
+}
+
+impl RevisionConfig {
+    // whether the written world gets (some of) the original's history
+    pub fn keeps_history(&self) -> bool {
+        self.keep_history || self.keep_newer_than.is_some()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OwnerConfig {
+    // remove everyone from the owner table who doesn't own any bricks or entities anymore (going by the latest state, not the history)
+    pub prune_unowned: bool,
+    // owners (user name, display name or user id) whose bricks, components and entities are all removed
+    pub purge: Vec<String>,
+    // most bricks anyone may have on the main grid, off unless set
+    pub brick_budget: Option<usize>,
+    pub over_brick_budget: BrickBudget,
+}
+
+impl Default for OwnerConfig {
+    fn default() -> Self {
+        Self {
+            prune_unowned: false,
+            purge: vec![],
+            brick_budget: None,
+            over_brick_budget: BrickBudget::Warn,
+        }
+    }
+}
+
+// what to do with people who have more bricks than allowed
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BrickBudget {
+    // just list them
+    Warn,
+    // remove their newest bricks until they're within budget
+    Remove,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThrusterConfig {
+    // strongest force a thruster or rocket may push with (in either direction)
+    pub max_thrust: f32,
+}
+
+impl Default for ThrusterConfig {
+    fn default() -> Self {
+        Self {
+            max_thrust: 50000.0,
+        }
+    }
+}
+
+// which entities the freeze pass goes after
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FreezeMode {
+    // wheels, balls (or whatever freeze_types lists) and stale actors
+    Wheels,
+    // everything, except vehicles with an engine and their wheels
+    All,
+}
+
+// what to do with vehicles that have more wheels than allowed
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WheelBudget {
+    // just list them
+    Warn,
+    // freeze the whole vehicle
+    Freeze,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WheelConfig {
+    // most wheels a single vehicle may have, off unless set
+    pub max_per_grid: Option<usize>,
+    pub over_budget: WheelBudget,
+    // entity types that count as wheels, for the budget above and the cost of vehicles (max_active_grids), as glob patterns
+    pub types: Vec<String>,
+}
+
+impl Default for WheelConfig {
+    fn default() -> Self {
+        Self {
+            max_per_grid: None,
+            over_budget: WheelBudget::Warn,
+            types: vec!["Entity_Wheel*".to_string()],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JointConfig {
+    // remove bearings and sliders that are exact copies of another one on the same joint
+    pub remove_duplicates: bool,
+    // remove the weakest link of every loop of joints between grids (see joints.rs)
+    pub break_loops: bool,
+}
+
+impl Default for JointConfig {
+    fn default() -> Self {
+        Self {
+            remove_duplicates: true,
+            break_loops: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvironmentConfig {
+    // clamp the world's environment settings (sky, weather, fog, ..)
+    pub enabled: bool,
+    // highest value each setting may have, by "<group>.<setting>" (like "Sky.fogDensity")
+    pub max: BTreeMap<String, f64>,
+    // on/off settings that get turned off, named the same way
+    pub disable: Vec<String>,
+    // highest value each setting of every water (or other fluid) volume may have, by setting name
+    pub water: BTreeMap<String, f64>,
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max: BTreeMap::from([
+                ("Sky.fogDensity".to_string(), 0.5),
+                ("Sky.weatherIntensity".to_string(), 0.5),
+                ("Sky.precipitationParticleAmount".to_string(), 0.5),
+            ]),
+            disable: vec![],
+            water: BTreeMap::new(),
+        }
+    }
+}
+
+// how much every kind of thing in a chunk adds to its lag score (analyze only)
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LagScoreConfig {
+    pub shadow_lights: f64,
+    pub lights: f64,
+    pub unfrozen_entities: f64,
+    pub wires: f64,
+    pub components: f64,
+    pub bricks: f64,
+    // how many of the worst chunks to list
+    pub top: usize,
+}
+
+impl Default for LagScoreConfig {
+    fn default() -> Self {
+        Self {
+            shadow_lights: 10.0,
+            lights: 1.0,
+            unfrozen_entities: 5.0,
+            wires: 0.5,
+            components: 0.2,
+            bricks: 0.001,
+            top: 10,
+        }
+    }
+}
+
+// how much everything that can move adds to the estimated physics cost
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhysicsCostConfig {
+    pub unfrozen_entities: f64,
+    pub joints: f64,
+    // per brick of an unfrozen physics grid
+    pub dynamic_bricks: f64,
+}
+
+impl Default for PhysicsCostConfig {
+    fn default() -> Self {
+        Self {
+            unfrozen_entities: 10.0,
+            joints: 5.0,
+            dynamic_bricks: 0.01,
+        }
+    }
+}
+
+// how complex a network of wired together bricks may get before analyze warns about it
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogicConfig {
+    pub max_bricks: usize,
+    pub max_wires: usize,
+    // the longest path a signal can take through the network, in bricks
+    pub max_chain: usize,
+    // loops of bricks feeding back into each other
+    pub max_loops: usize,
+}
+
+impl Default for LogicConfig {
+    fn default() -> Self {
+        Self {
+            max_bricks: 1000,
+            max_wires: 2000,
+            max_chain: 100,
+            max_loops: 20,
+        }
+    }
+}
+
+// how --daemon watches the saves folder
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    // seconds between looking at the world files
+    pub poll_interval: u64,
+    // seconds a world file has to stay the same after changing before it counts as saved
+    pub settle_time: u64,
+    // keep the world as it was before optimizing, as <world>.unoptimized.brdb
+    pub keep_original: bool,
+    // heavy passes that only run at certain times, instead of on every save
+    pub schedule: Vec<Schedule>,
+    // where to serve prometheus metrics, like "127.0.0.1:9184", off unless set
+    pub metrics_address: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    // when to run, as a cron expression (see cron.rs)
+    pub cron: String,
+    // which of the heavy passes run at those times
+    #[serde(default = "all_heavy_passes")]
+    pub passes: Vec<String>,
+}
+
+pub fn all_heavy_passes() -> Vec<String> {
+    HEAVY_PASSES.iter().map(|p| p.to_string()).collect()
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    // discord webhook url to post a summary of every run to, off unless set
+    pub webhook: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RulesetConfig {
+    // json or yaml files with component rules to run on every component, see rulesets.rs
+    pub files: Vec<PathBuf>,
+    // the rules in those files, read by with_rulesets
+    #[serde(skip)]
+    pub loaded: Vec<Ruleset>,
+    // the [clamp] entries as a ruleset, these replace the built-in limits for their properties (see rules.rs)
+    #[serde(skip)]
+    pub clamps: Option<Ruleset>,
+}
+
+// the range a [clamp] entry keeps its property in, at least one of them has to be set
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Clamp {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+/*
+ * regular expressions for component names that count as lights, motors and so on,
+ * on top of the ones the tool knows about (see rules::Kinds)
+ */
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PatternConfig {
+    pub lights: Vec<String>,
+    pub motors: Vec<String>,
+    pub text: Vec<String>,
+    pub sensors: Vec<String>,
+    pub modifiers: Vec<String>,
+    pub thrusters: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RemoteConfig {
+    // where worlds optimized from a url go: an https:// or s3:// url, or a local path (see remote.rs).
+    // the current folder unless set
+    pub upload: Option<String>,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: 10,
+            settle_time: 30,
+            keep_original: true,
+            schedule: vec![],
+            metrics_address: None,
+        }
+    }
+}
+
+impl Config {
+    // loads the config file at `path`, or the default one if it exists, or the defaults
+    pub fn load(path: Option<&Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_PATH).exists() => Path::new(DEFAULT_PATH),
+            None => return Ok(Self::default()),
+        };
+
+        println!("Reading config {:?}", path);
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("can't read config file {:?}: {e}", path))?;
+        let config = toml::from_str(&text)
+            .map_err(|e| format!("invalid config file {:?}: {e}", path))?;
+
+        Ok(config)
+    }
+
+    // options given on the command line win over the config file
+    pub fn with_args(mut self, args: &Args) -> Self {
+        if let Some(max) = args.max_lights_per_chunk {
+            self.lights.max_per_chunk = Some(max);
+        }
+        if let Some(max) = args.max_lights_per_owner {
+            self.lights.max_per_owner = Some(max);
+        }
+        if args.strip_hidden_bricks {
+            self.bricks.strip_hidden = true;
+        }
+        if args.merge_bricks {
+            self.bricks.merge = true;
+        }
+        if args.consolidate_microbricks {
+            self.bricks.consolidate_microbricks = true;
+        }
+        if args.freeze_all {
+            self.entities.freeze = FreezeMode::All;
+        }
+        if args.unfreeze {
+            self.entities.unfreeze = true;
+        }
+        if let Some(max) = args.max_active_grids {
+            self.entities.max_active_grids = Some(max);
+        }
+        if let Some(vacuum) = args.vacuum {
+            self.files.vacuum = vacuum;
+        }
+        if let Some(format) = args.archive {
+            self.files.archive = Some(format);
+        }
+        if args.archive_only {
+            self.files.archive_only = true;
+            self.files.archive.get_or_insert(ArchiveFormat::Zstd);
+        }
+        if let Some(compression) = args.compression {
+            self.files.compression = compression;
+        }
+        if args.manifest {
+            self.files.manifest = true;
+        }
+        if let Some(age) = &args.keep_revisions_newer_than {
+            self.revisions.keep_newer_than = Some(age.clone());
+        }
+        if let Some(cron) = &args.schedule {
+            self.daemon.schedule.push(Schedule { cron: cron.clone(), passes: all_heavy_passes() });
+        }
+        for pass in &args.skip {
+            match pass.as_str() {
+                // the history stays as it is
+                "revisions" => {
+                    self.revisions.keep_history = true;
+                    self.revisions.remove_unchanged = false;
+                    self.revisions.keep_newer_than = None;
+                }
+                "vacuum" => self.files.vacuum = false,
+                "dedupe_blobs" => self.files.dedupe_blobs = false,
+                _ => {}
+            }
+        }
+        self.rulesets.files.extend(args.rulesets.iter().cloned());
+        self.owners.purge.extend(args.purge_owners.iter().cloned());
+        if let Some(max) = args.enforce_brick_budget {
+            self.owners.brick_budget = Some(max);
+            self.owners.over_brick_budget = BrickBudget::Remove;
+        }
+        self
+    }
+
+    // reads the ruleset files, after with_args added the ones from the command line, and turns [clamp] into one
+    pub fn with_rulesets(mut self) -> Result<Self, Box<dyn std::error::Error>> {
+        if !self.clamp.is_empty() {
+            self.rulesets.clamps = Some(rulesets::from_clamps(&self.clamp)?);
+        }
+        for path in &self.rulesets.files {
+            self.rulesets.loaded.push(rulesets::load(path)?);
+        }
+        Ok(self)
+    }
+
+    /*
+     * a short fingerprint of these settings,
+     * used to tell whether a world was optimized with the same settings before.
+     * this is FNV-1a over the debug output, so it stays stable between builds
+     */
+    pub fn hash(&self) -> String {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in format!("{self:?}").bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        format!("{hash:016x}")
+    }
+}
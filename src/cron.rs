@@ -0,0 +1,155 @@
+/*
+ * cron expressions, for running heavy passes on a schedule in daemon mode
+ *
+ * the usual five fields: minute, hour, day of the month, month, day of the week (0 or 7 is sunday).
+ * every field can be `*`, a number, a range (1-5), a list of those (1,15,30),
+ * and any of them can have a step: 0-30/10 is 0, 10, 20 and 30, a star with /15 every 15 minutes.
+ * like cron itself, if both the day of the month and the day of the week are given,
+ * either one matching is enough
+ */
+
+use chrono::{DateTime, Datelike, Local, Timelike};
+
+pub struct Cron {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    // whether the day fields were `*`
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("{text:?} needs 5 fields (minute hour day month weekday), it has {}", fields.len()));
+        };
+
+        let mut weekdays = field(weekday, 0, 7).map_err(|e| format!("weekday {e}"))?;
+        // sunday is both 0 and 7
+        weekdays[0] |= weekdays[7];
+        Ok(Self {
+            minutes: field(minute, 0, 59).map_err(|e| format!("minute {e}"))?,
+            hours: field(hour, 0, 23).map_err(|e| format!("hour {e}"))?,
+            days: field(day, 1, 31).map_err(|e| format!("day {e}"))?,
+            months: field(month, 1, 12).map_err(|e| format!("month {e}"))?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    // whether the minute `time` is in is one this should run at
+    pub fn matches(&self, time: &DateTime<Local>) -> bool {
+        let day = self.days[time.day() as usize];
+        let weekday = self.weekdays[time.weekday().num_days_from_sunday() as usize];
+        let day = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+
+        self.minutes[time.minute() as usize] && self.hours[time.hour() as usize] && self.months[time.month() as usize] && day
+    }
+}
+
+// which values from 0 up to `max` one field allows, values below `min` are never allowed
+fn field(text: &str, min: usize, max: usize) -> Result<Vec<bool>, String> {
+    let mut allowed = vec![false; max + 1];
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<usize>().map_err(|_| format!("has a bad step in {part:?}"))?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(format!("has a step of 0 in {part:?}"));
+        }
+
+        let number = |n: &str| match n.parse::<usize>() {
+            Ok(n) if (min..=max).contains(&n) => Ok(n),
+            _ => Err(format!("needs numbers from {min} to {max}, got {n:?}")),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (number(start)?, number(end)?),
+            // a single number with a step runs from there to the end, like cron does
+            None if step > 1 => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+
+        for value in (start..=end).step_by(step) {
+            allowed[value] = true;
+        }
+    }
+    Ok(allowed)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        // june 2026, the 1st is a monday
+        Local.with_ymd_and_hms(2026, 6, day, hour, minute, 0).unwrap()
+    }
+
+    fn allowed(values: &[bool]) -> Vec<usize> {
+        values.iter().enumerate().filter(|(_, v)| **v).map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn parses_every_kind_of_field() {
+        let cron = Cron::parse("*/15 1-3 1,15 1-12/3 *").unwrap();
+        assert_eq!(allowed(&cron.minutes), [0, 15, 30, 45]);
+        assert_eq!(allowed(&cron.hours), [1, 2, 3]);
+        assert_eq!(allowed(&cron.days), [1, 15]);
+        assert_eq!(allowed(&cron.months), [1, 4, 7, 10]);
+        assert!(cron.any_weekday && !cron.any_day);
+    }
+
+    #[test]
+    fn single_number_with_a_step_runs_to_the_end() {
+        let cron = Cron::parse("50/5 * * * *").unwrap();
+        assert_eq!(allowed(&cron.minutes), [50, 55]);
+    }
+
+    #[test]
+    fn sunday_is_0_and_7() {
+        let cron = Cron::parse("0 4 * * 7").unwrap();
+        assert!(cron.weekdays[0]);
+        assert!(cron.matches(&at(7, 4, 0)));
+        assert!(!cron.matches(&at(8, 4, 0)));
+    }
+
+    #[test]
+    fn refuses_bad_expressions() {
+        for text in ["* * * *", "* * * * * *", "60 * * * *", "* 24 * * *", "* * 0 * *", "* * * 0 *", "*/0 * * * *", "a * * * *", "1-x * * * *"] {
+            assert!(Cron::parse(text).is_err(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn matches_the_time() {
+        let cron = Cron::parse("30 4 * * *").unwrap();
+        assert!(cron.matches(&at(3, 4, 30)));
+        assert!(!cron.matches(&at(3, 4, 31)));
+        assert!(!cron.matches(&at(3, 5, 30)));
+    }
+
+    #[test]
+    fn either_day_field_is_enough_when_both_are_given() {
+        // the 1st of the month, or any friday
+        let cron = Cron::parse("0 0 1 * 5").unwrap();
+        assert!(cron.matches(&at(1, 0, 0)));
+        assert!(cron.matches(&at(5, 0, 0)));
+        assert!(!cron.matches(&at(4, 0, 0)));
+        // with only one of them, that one has to match
+        let cron = Cron::parse("0 0 * * 5").unwrap();
+        assert!(!cron.matches(&at(1, 0, 0)));
+        assert!(cron.matches(&at(5, 0, 0)));
+    }
+}
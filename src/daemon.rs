@@ -0,0 +1,287 @@
+/*
+ * keeping a server's worlds optimized, without having to run the tool by hand
+ *
+ * `brdb_optimize --daemon <saves folder>` keeps running, and looks at the world files
+ * in the folder every few seconds. once a world has been saved (it changed, and then
+ * stayed the same for a while, so the autosave is done writing) it gets optimized,
+ * and the optimized file is moved over the original, so the next time the server
+ * loads the world it's the optimized one. the original is kept next to it as
+ * <world>.unoptimized.brdb, and the undo file is written as usual.
+ *
+ * every world is optimized by running this tool again in a separate process, with the
+ * same options the daemon got. that way a world that makes it bail out (corrupt chunks,
+ * unknown game version, ..) doesn't take the daemon down with it
+ *
+ * heavy passes (see HEAVY_PASSES) can run on a schedule instead: with --schedule or
+ * [[daemon.schedule]] in the config, the passes a schedule names are left out of the runs
+ * on every save, and every world gets a run with them at the times the schedule says
+ *
+ * with `metrics_address` under [daemon], how the runs go is served to prometheus (see metrics.rs)
+ */
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    env,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use chrono::{DateTime, Duration as Minutes, Local, Timelike};
+
+use crate::{
+    archive,
+    config::{DaemonConfig, HEAVY_PASSES, Schedule},
+    cron::Cron,
+    metrics::{self, Metrics, RunStats},
+};
+
+// files this tool writes next to a world, which shouldn't get optimized themselves
+const OWN_FILES: &[&str] = &[".optimized", ".undo", ".unoptimized", ".tmp"];
+
+// what a world file looked like the last time we checked
+#[derive(Clone, Copy, PartialEq)]
+struct Seen {
+    modified: SystemTime,
+    size: u64,
+}
+
+pub fn run(folder: PathBuf, config: &DaemonConfig) -> Result<(), Box<dyn std::error::Error>> {
+    if !folder.is_dir() {
+        println!("saves folder {:?} does not exist", folder);
+        process::exit(1);
+    }
+
+    // everything we were started with, except what made us a daemon
+    let folder_arg = folder.to_string_lossy().to_string();
+    let mut options = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--schedule" => {
+                args.next();
+            }
+            "--daemon" => {}
+            _ if arg == folder_arg => {}
+            _ => options.push(arg),
+        }
+    }
+
+    let mut schedules = vec![];
+    for schedule in &config.schedule {
+        let cron = Cron::parse(&schedule.cron).unwrap_or_else(|e| {
+            println!("[daemon.schedule] has a bad cron expression: {e}");
+            process::exit(1);
+        });
+        if let Some(pass) = schedule.passes.iter().find(|p| !HEAVY_PASSES.contains(&p.as_str())) {
+            println!("[daemon.schedule] can only schedule {}, got {pass:?}", HEAVY_PASSES.join(", "));
+            process::exit(1);
+        }
+        schedules.push((cron, schedule));
+    }
+    // passes that wait for their schedule
+    let scheduled: BTreeSet<&str> = config.schedule.iter().flat_map(|s| s.passes.iter().map(String::as_str)).collect();
+
+    println!("watching {:?} for saved worlds, every {} seconds..", folder, config.poll_interval);
+    for schedule in &config.schedule {
+        println!("running {} at {:?}", schedule.passes.join(", "), schedule.cron);
+    }
+    let mut checked = minute(Local::now());
+
+    let metrics = Arc::new(Mutex::new(Metrics::default()));
+    if let Some(address) = &config.metrics_address {
+        metrics::serve(address, metrics.clone())?;
+    }
+
+    // worlds as they were when we started (or last optimized them), those are left alone
+    let mut done: HashMap<PathBuf, Seen> = worlds(&folder)?.into_iter().collect();
+    // worlds that changed, and how they looked when we noticed, so we know when they're done changing
+    let mut changing: HashMap<PathBuf, (Seen, SystemTime)> = HashMap::new();
+
+    loop {
+        thread::sleep(Duration::from_secs(config.poll_interval));
+
+        for (world, seen) in worlds(&folder)? {
+            if done.get(&world) == Some(&seen) {
+                continue;
+            }
+            // the game is still writing to it
+            if sqlite_busy(&world) {
+                continue;
+            }
+            match changing.get(&world) {
+                Some((last, since)) if *last == seen => {
+                    if since.elapsed().unwrap_or_default() < Duration::from_secs(config.settle_time) {
+                        continue;
+                    }
+                }
+                _ => {
+                    changing.insert(world, (seen, SystemTime::now()));
+                    continue;
+                }
+            }
+            changing.remove(&world);
+
+            println!("---SEP---");
+            println!("{:?} was saved, optimizing it..", world);
+            if optimize(&world, &with_skipped(&options, &scheduled, None), config, &metrics)? {
+                println!("{:?} is optimized.", world);
+            }
+            // either way, don't try again until the world gets saved again
+            if let Some(seen) = look(&world) {
+                done.insert(world, seen);
+            }
+        }
+
+        // every minute since we last looked, so none get missed
+        let now = minute(Local::now());
+        while checked < now {
+            checked += Minutes::minutes(1);
+            for (cron, schedule) in &schedules {
+                if !cron.matches(&checked) {
+                    continue;
+                }
+                for (world, _) in worlds(&folder)? {
+                    if sqlite_busy(&world) {
+                        println!("{:?} is being saved, skipping its scheduled run.", world);
+                        continue;
+                    }
+                    println!("---SEP---");
+                    println!("scheduled run of {} on {:?}..", schedule.passes.join(", "), world);
+                    if optimize(&world, &with_skipped(&options, &scheduled, Some(schedule)), config, &metrics)? {
+                        println!("{:?} is optimized.", world);
+                    }
+                    if let Some(seen) = look(&world) {
+                        done.insert(world, seen);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// the start of the minute `time` is in
+fn minute(time: DateTime<Local>) -> DateTime<Local> {
+    time.with_second(0).and_then(|t| t.with_nanosecond(0)).unwrap_or(time)
+}
+
+// the options for a run, leaving out the scheduled passes, except the ones of `schedule`
+fn with_skipped(options: &[String], scheduled: &BTreeSet<&str>, schedule: Option<&Schedule>) -> Vec<String> {
+    let skipped: Vec<&str> = scheduled.iter()
+        .filter(|pass| schedule.is_none_or(|s| !s.passes.iter().any(|p| p == *pass)))
+        .copied()
+        .collect();
+
+    let mut options = options.to_vec();
+    if !skipped.is_empty() {
+        options.push("--skip".to_string());
+        options.push(skipped.join(","));
+    }
+    options
+}
+
+/*
+ * optimizes the world in its own process, and swaps the optimized file in.
+ * returns false if it didn't get optimized
+ */
+fn optimize(
+    world: &Path,
+    options: &[String],
+    config: &DaemonConfig,
+    metrics: &Mutex<Metrics>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let name = world.file_name().unwrap().to_string_lossy();
+    let stem = world.file_stem().unwrap().to_string_lossy();
+    let optimized = world.with_file_name(format!("{stem}.optimized.brdb"));
+    if optimized.exists() {
+        fs::remove_file(&optimized)?;
+    }
+    let stats_path = env::temp_dir().join(format!("brdb_optimize_{}_stats.json", process::id()));
+    if stats_path.exists() {
+        fs::remove_file(&stats_path)?;
+    }
+
+    let started = Instant::now();
+    let status = process::Command::new(env::current_exe()?)
+        .args(options)
+        .arg("--stats")
+        .arg(&stats_path)
+        .arg(world)
+        .status()?;
+    let seconds = started.elapsed().as_secs_f64();
+    let record = |result, stats: Option<&RunStats>| {
+        if let Ok(mut metrics) = metrics.lock() {
+            metrics.record(&name, result, seconds, stats);
+        }
+    };
+
+    if !status.success() {
+        println!("[ERROR] optimizing {:?} failed, leaving it alone until it's saved again.", world);
+        record("failed", None);
+        return Ok(false);
+    }
+    if let Some(archive) = archive::only(&optimized) {
+        println!("[ERROR] only the archive of {:?} was kept (archive_only), there's nothing to swap in.", world);
+        fs::remove_file(&archive)?;
+        record("failed", None);
+        return Ok(false);
+    }
+    // already optimized with the same settings, nothing got written
+    if !optimized.exists() {
+        record("unchanged", None);
+        return Ok(false);
+    }
+
+    // the game saved again while we were busy, that save wins
+    if sqlite_busy(world) {
+        println!("[WARNING] {:?} got saved again while optimizing, not swapping it.", world);
+        fs::remove_file(&optimized)?;
+        record("failed", None);
+        return Ok(false);
+    }
+
+    if config.keep_original {
+        fs::copy(world, world.with_file_name(format!("{stem}.unoptimized.brdb")))?;
+    }
+    fs::rename(&optimized, world)?;
+
+    let stats: Option<RunStats> = fs::read_to_string(&stats_path).ok().and_then(|text| serde_json::from_str(&text).ok());
+    record("optimized", stats.as_ref());
+    Ok(true)
+}
+
+// every world file in the folder
+fn worlds(folder: &Path) -> Result<Vec<(PathBuf, Seen)>, Box<dyn std::error::Error>> {
+    let mut worlds = vec![];
+    for entry in fs::read_dir(folder)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|e| e != "brdb") {
+            continue;
+        }
+        let stem = path.file_stem().unwrap().to_string_lossy();
+        if OWN_FILES.iter().any(|suffix| stem.ends_with(suffix)) {
+            continue;
+        }
+        if let Some(seen) = look(&path) {
+            worlds.push((path, seen));
+        }
+    }
+    Ok(worlds)
+}
+
+fn look(path: &Path) -> Option<Seen> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(Seen {
+        modified: metadata.modified().ok()?,
+        size: metadata.len(),
+    })
+}
+
+// sqlite keeps a journal next to the database while writing to it
+fn sqlite_busy(world: &Path) -> bool {
+    let name = world.file_name().unwrap().to_string_lossy();
+    ["-journal", "-wal"].iter().any(|suffix| world.with_file_name(format!("{name}{suffix}")).exists())
+}
@@ -0,0 +1,393 @@
+/*
+ * deciding which entities get removed from the world entirely
+ * (instead of just being frozen, see freeze_entities in optimize.rs)
+ *
+ * dynamic grids are entities too, but removing one of those
+ * means deleting its grid as well, so those are collected up front
+ */
+
+use std::{collections::{HashMap, HashSet}, path::PathBuf};
+use brdb::{AsBrdbValue, BrReader, Brdb, BrdbComponent, Entity, IntoReader, Quat4f, Vector3f};
+use glob::Pattern;
+
+use crate::{config::{EntityConfig, ItemConfig, WheelConfig}, grids::{self, ENGINE_COMPONENTS, Grids}, props::Props, worlds};
+
+// entities of items lying around on the ground, waiting to be picked up
+pub const ITEM_ENTITIES: &[&str] = &["Entity_Pickup", "Entity_DroppedItem"];
+// no movement (or the center of the world)
+const ZERO: Vector3f = Vector3f { x: 0.0, y: 0.0, z: 0.0 };
+
+// beginnings of the names of entities that get spawned by something (bots, projectiles, spawned props)
+pub const ACTOR_PREFIXES: &[&str] = &["Entity_Bot", "Entity_Projectile", "Entity_Spawned"];
+
+// how much a wheel and an engine add to the cost of a vehicle, on top of one per brick
+const WHEEL_COST: usize = 50;
+const ENGINE_COST: usize = 200;
+// how far an entity may have drifted between revisions and still count as not having moved
+const STILL_DISTANCE: f32 = 1.0;
+
+// whether the entity ended up somewhere nobody will ever see it again (fell out of the world, got launched)
+pub fn out_of_bounds(entity: &Entity, config: &EntityConfig) -> bool {
+    let l = entity.location;
+    config.remove_out_of_bounds
+        && (l.z < config.min_z || [l.x, l.y, l.z].iter().any(|v| v.abs() > config.max_distance || !v.is_finite()))
+}
+
+// the configured entity types to freeze, like "Entity_Wheel*"
+pub fn freeze_patterns(config: &EntityConfig) -> Result<Vec<Pattern>, Box<dyn std::error::Error>> {
+    let mut patterns = vec![];
+    for pattern in &config.freeze_types {
+        patterns.push(Pattern::new(pattern).map_err(|e| format!("invalid freeze_types pattern {pattern:?}: {e}"))?);
+    }
+    Ok(patterns)
+}
+
+// the configured entity types that count as wheels, like "Entity_Wheel*"
+pub fn wheel_patterns(config: &WheelConfig) -> Result<Vec<Pattern>, Box<dyn std::error::Error>> {
+    let mut patterns = vec![];
+    for pattern in &config.types {
+        patterns.push(Pattern::new(pattern).map_err(|e| format!("invalid wheel types pattern {pattern:?}: {e}"))?);
+    }
+    Ok(patterns)
+}
+
+// the ids of every wheel entity in the world, going by `types` (see wheel_patterns)
+fn wheels(db: &BrReader<Brdb>, types: &[Pattern]) -> Result<HashSet<u32>, Box<dyn std::error::Error>> {
+    let mut wheels = HashSet::new();
+    for chunk in db.entity_chunk_index()? {
+        for entity in db.entity_chunk(chunk)? {
+            let is_wheel = entity.data.get_schema_struct().is_some_and(|(name, _)| types.iter().any(|t| t.matches(&name)));
+            if is_wheel && let Some(id) = entity.id {
+                wheels.insert(id as u32);
+            }
+        }
+    }
+    Ok(wheels)
+}
+
+// dynamic grids whose entity should be removed, along with the grid
+pub fn removed_grids(db: &BrReader<Brdb>, grids: &Grids, config: &EntityConfig) -> Result<Vec<usize>, Box<dyn std::error::Error>> {
+    let mut removed = vec![];
+    for chunk in db.entity_chunk_index()? {
+        for entity in db.entity_chunk(chunk)? {
+            let Some(id) = entity.id.filter(|id| grids.dynamic.contains(id)) else {
+                continue;
+            };
+            if out_of_bounds(&entity, config) {
+                println!("[grid:{id}] dynamic grid is out of bounds, removing it..");
+                removed.push(id);
+            }
+        }
+    }
+    Ok(removed)
+}
+
+// dynamic grids (vehicles) of purged owners, which get removed along with their entity
+pub fn purged_grids(db: &BrReader<Brdb>, grids: &Grids, purged: &HashSet<u32>) -> Result<Vec<usize>, Box<dyn std::error::Error>> {
+    let mut removed = vec![];
+    for chunk in db.entity_chunk_index()? {
+        for entity in db.entity_chunk(chunk)? {
+            let Some(id) = entity.id.filter(|id| grids.dynamic.contains(id)) else {
+                continue;
+            };
+            if entity.owner_index.is_some_and(|owner| purged.contains(&owner)) {
+                println!("[grid:{id}] dynamic grid belongs to a purged owner, removing it..");
+                removed.push(id);
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/*
+ * which of the dropped items in a chunk get cleaned up (as indices into `entities`):
+ * everything older than max_age, and the oldest ones past max_per_chunk
+ */
+pub fn dropped_items(entities: &[Entity], config: &ItemConfig, props: &mut Props) -> HashSet<usize> {
+    // (index, age in seconds) of every dropped item
+    let mut items = vec![];
+    for (i, entity) in entities.iter().enumerate() {
+        let Some((name, _)) = entity.data.get_schema_struct() else {
+            continue;
+        };
+        if !ITEM_ENTITIES.contains(&name.as_ref()) {
+            continue;
+        }
+
+        // only needed when there's an age limit or a chunk limit to pick the oldest with
+        let age = if config.max_age.is_some() || config.max_per_chunk.is_some() {
+            props.get(name.as_ref(), "Age", entity.data.prop("Age").and_then(|v| v.as_brdb_f32()))
+        } else {
+            None
+        };
+        items.push((i, age));
+    }
+
+    let mut removed = HashSet::new();
+    if let Some(max_age) = config.max_age {
+        removed.extend(items.iter().filter(|(_, age)| age.is_some_and(|age| age > max_age)).map(|(i, _)| *i));
+        items.retain(|(i, _)| !removed.contains(i));
+    }
+
+    // youngest first, so the oldest are the ones past the limit
+    if let Some(max) = config.max_per_chunk
+        && items.len() > max
+    {
+        items.sort_by(|a, b| a.1.unwrap_or(0.0).total_cmp(&b.1.unwrap_or(0.0)));
+        removed.extend(items[max..].iter().map(|(i, _)| *i));
+    }
+
+    removed
+}
+
+// whether the entity is a spawned actor that nobody owns anymore, so nothing will ever come back for it
+pub fn stale_actor(entity: &Entity, name: &str) -> bool {
+    ACTOR_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+        && entity.owner_index.is_none_or(|owner| owner == 0)
+}
+
+/*
+ * dynamic grids (vehicles) with more than `max` wheels attached, with how many they have.
+ * wheels are entities of their own, attached to the vehicle's bricks with a joint
+ */
+pub fn over_wheel_budget(
+    db: &BrReader<Brdb>,
+    grids: &Grids,
+    types: &[Pattern],
+    max: usize,
+) -> Result<Vec<(usize, usize)>, Box<dyn std::error::Error>> {
+    let wheels = wheels(db, types)?;
+
+    let mut counts: HashMap<usize, usize> = HashMap::new();
+    for grid in &grids.dynamic {
+        for chunk in db.brick_chunk_index(*grid)? {
+            if chunk.num_components == 0 {
+                continue;
+            }
+            // unreadable chunks get reported by the component pass
+            if let Ok((soa, _)) = db.component_chunk(*grid, *chunk) {
+                *counts.entry(*grid).or_default() += soa.joint_entity_references.iter().filter(|e| wheels.contains(e)).count();
+            }
+        }
+    }
+
+    let mut over: Vec<(usize, usize)> = counts.into_iter().filter(|(_, wheels)| *wheels > max).collect();
+    over.sort();
+    Ok(over)
+}
+
+/*
+ * the dynamic grids beyond the first `max` that are simulating (not frozen), with their cost.
+ * the cost is a rough guess of how hard a vehicle is on physics: its bricks, plus its wheels and engines.
+ * the cheapest grids get to keep moving, so as many vehicles as possible stay usable.
+ * grids in `frozen` are getting frozen already, so they don't count towards the budget.
+ * wheels are the entity types in `wheel_types` (see wheel_patterns)
+ */
+pub fn over_grid_budget(
+    db: &BrReader<Brdb>,
+    grids: &Grids,
+    frozen: &[usize],
+    wheel_types: &[Pattern],
+    max: usize,
+) -> Result<Vec<(usize, usize)>, Box<dyn std::error::Error>> {
+    let wheels = wheels(db, wheel_types)?;
+    let mut simulating = HashSet::new();
+    for chunk in db.entity_chunk_index()? {
+        for entity in db.entity_chunk(chunk)? {
+            let (Some(name), Some(id)) = (entity.data.get_schema_struct().map(|(name, _)| name), entity.id) else {
+                continue;
+            };
+            if name.as_ref() == "Entity_DynamicBrickGrid" && !entity.frozen {
+                simulating.insert(id);
+            }
+        }
+    }
+
+    let mut costs = vec![];
+    for grid in &grids.dynamic {
+        if !simulating.contains(grid) || frozen.contains(grid) {
+            continue;
+        }
+
+        let mut cost = 0;
+        for chunk in db.brick_chunk_index(*grid)? {
+            cost += chunk.num_bricks as usize;
+            if chunk.num_components == 0 {
+                continue;
+            }
+            // unreadable chunks get reported by the component pass
+            if let Ok((soa, components)) = db.component_chunk(*grid, *chunk) {
+                cost += WHEEL_COST * soa.joint_entity_references.iter().filter(|e| wheels.contains(e)).count();
+                cost += ENGINE_COST * components.iter().filter(|c| ENGINE_COMPONENTS.contains(&c.get_name())).count();
+            }
+        }
+        costs.push((*grid, cost));
+    }
+
+    // cheapest first, the ones after the budget get frozen
+    costs.sort_by_key(|(grid, cost)| (*cost, *grid));
+    Ok(costs.into_iter().skip(max).collect())
+}
+
+// where an entity was in an older revision, and how fast it was going
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    pub location: Vector3f,
+    pub rotation: Quat4f,
+    pub velocity: Vector3f,
+}
+
+/*
+ * the transforms of every entity of `world` in each of the older revisions in `revisions`
+ * (see revisions::checkout_recent), in the same order. stops at the first revision the world wasn't in yet
+ */
+pub fn history(world: &str, revisions: &[PathBuf]) -> Result<Vec<HashMap<usize, Transform>>, Box<dyn std::error::Error>> {
+    let mut history = vec![];
+    for revision in revisions {
+        let old = Brdb::open(revision)?.into_reader();
+        let pending = old.to_pending()?;
+        if !worlds::indices(&pending).iter().any(|w| w == world) {
+            break;
+        }
+
+        history.push(worlds::read_world(&old, &pending, world, revision, |old| {
+            let mut transforms = HashMap::new();
+            for chunk in old.entity_chunk_index()? {
+                for entity in old.entity_chunk(chunk)? {
+                    if let Some(id) = entity.id {
+                        transforms.insert(id, Transform { location: entity.location, rotation: entity.rotation, velocity: entity.velocity });
+                    }
+                }
+            }
+            Ok(transforms)
+        })?);
+    }
+    Ok(history)
+}
+
+/*
+ * the entities that are in the exact same spot (give or take STILL_DISTANCE) in the world now
+ * and in every one of the older revisions in `history`.
+ * nobody has touched those in a while, so they're probably abandoned.
+ * entities that are frozen already, or weren't there yet back then, aren't included
+ */
+pub fn unmoved(db: &BrReader<Brdb>, history: &[HashMap<usize, Transform>]) -> Result<HashSet<usize>, Box<dyn std::error::Error>> {
+    let mut still: HashMap<usize, Vector3f> = HashMap::new();
+    for chunk in db.entity_chunk_index()? {
+        for entity in db.entity_chunk(chunk)? {
+            if let Some(id) = entity.id
+                && !entity.frozen
+            {
+                still.insert(id, entity.location);
+            }
+        }
+    }
+
+    for transforms in history {
+        still.retain(|id, now| transforms.get(id).is_some_and(|then| distance(now, &then.location) <= STILL_DISTANCE));
+    }
+
+    Ok(still.into_keys().collect())
+}
+
+/*
+ * why the entity looks like it's been flung around by a physics explosion, if it does:
+ * going faster than runaway_speed, or having moved further than runaway_distance since the last revision
+ */
+pub fn runaway(entity: &Entity, config: &EntityConfig, history: &[HashMap<usize, Transform>]) -> Option<String> {
+    let speed = distance(&entity.velocity, &ZERO);
+    if let Some(max) = config.runaway_speed
+        && speed > max
+    {
+        return Some(format!("is going {speed:.0} units/s"));
+    }
+
+    let previous = history.first()?.get(&entity.id?)?;
+    let moved = distance(&entity.location, &previous.location);
+    if let Some(max) = config.runaway_distance
+        && moved > max
+    {
+        return Some(format!("moved {moved:.0} units since the last revision"));
+    }
+    None
+}
+
+// the newest transform of the entity in `history` that wasn't broken or going too fast itself
+pub fn last_sane(id: usize, config: &EntityConfig, history: &[HashMap<usize, Transform>]) -> Option<Transform> {
+    history.iter().filter_map(|transforms| transforms.get(&id)).copied().find(|t| {
+        let finite = [t.location.x, t.location.y, t.location.z, t.rotation.x, t.rotation.y, t.rotation.z, t.rotation.w]
+            .iter()
+            .all(|v| v.is_finite());
+        finite && config.runaway_speed.is_none_or(|max| distance(&t.velocity, &ZERO) <= max)
+    })
+}
+
+fn distance(a: &Vector3f, b: &Vector3f) -> f32 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+/*
+ * the entities that make up working vehicles: dynamic grids with an engine,
+ * and everything attached to them with a joint (their wheels, turrets, ..)
+ */
+pub fn vehicles(db: &BrReader<Brdb>, grids: &Grids) -> Result<HashSet<usize>, Box<dyn std::error::Error>> {
+    let mut vehicles = HashSet::new();
+    for grid in &grids.dynamic {
+        if !grids::has_engine(db, *grid)? {
+            continue;
+        }
+        vehicles.insert(*grid);
+
+        for chunk in db.brick_chunk_index(*grid)? {
+            if chunk.num_components == 0 {
+                continue;
+            }
+            if let Ok((soa, _)) = db.component_chunk(*grid, *chunk) {
+                vehicles.extend(soa.joint_entity_references.iter().map(|e| *e as usize));
+            }
+        }
+    }
+    Ok(vehicles)
+}
+
+/*
+ * resets parts of an entity's transform that are NaN or infinite, which crash the game on load.
+ * returns the names of what got reset, empty if everything was fine.
+ * a broken location puts the entity at the center of the world, frozen, since there's no telling where it was
+ */
+pub fn sanitize(entity: &mut Entity) -> Vec<&'static str> {
+    let finite = |v: &Vector3f| v.x.is_finite() && v.y.is_finite() && v.z.is_finite();
+    let mut fixed = vec![];
+
+    if !finite(&entity.location) {
+        entity.location = ZERO;
+        entity.frozen = true;
+        fixed.push("location");
+    }
+    let r = entity.rotation;
+    if ![r.x, r.y, r.z, r.w].iter().all(|v| v.is_finite()) {
+        entity.rotation = Quat4f { x: 0.0, y: 0.0, z: 0.0, w: 1.0 };
+        fixed.push("rotation");
+    }
+    if !finite(&entity.velocity) {
+        entity.velocity = ZERO;
+        fixed.push("velocity");
+    }
+    if !finite(&entity.angular_velocity) {
+        entity.angular_velocity = ZERO;
+        fixed.push("angular velocity");
+    }
+
+    fixed
+}
+
+// zeroes an entity's linear and angular velocity, returns whether it was moving at all
+pub fn stop(entity: &mut Entity) -> bool {
+    let (v, a) = (entity.velocity, entity.angular_velocity);
+    if [v.x, v.y, v.z, a.x, a.y, a.z].iter().all(|c| *c == 0.0) {
+        return false;
+    }
+    entity.velocity = ZERO;
+    entity.angular_velocity = ZERO;
+    true
+}
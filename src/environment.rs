@@ -0,0 +1,115 @@
+/*
+ * taming the world's environment settings
+ *
+ * the environment (sky, weather, fog, water, ..) is stored as a preset in World/<n>/Environment.bp,
+ * a json file that groups its settings like this:
+ *  { "data": { "groups": { "Sky": { "fogDensity": 0.2, "weatherIntensity": 1.0, .. }, "Water": { .. } } } }
+ * settings are named "<group>.<setting>" in the config, like "Sky.fogDensity".
+ * water (and other fluid) volumes each get their own group starting with "Water",
+ * the [environment.water] limits apply to all of them at once
+ */
+
+use brdb::{BrReader, Brdb, pending::BrPendingFs};
+use serde_json::Value;
+
+use crate::{config::EnvironmentConfig, patch};
+
+pub const ENVIRONMENT_FILE: &str = "Environment.bp";
+
+/*
+ * clamps the environment settings of the world to what's configured.
+ * returns (patch, undo patch, number of settings changed)
+ */
+pub fn optimize(
+    db: &BrReader<Brdb>,
+    world: &str,
+    config: &EnvironmentConfig,
+) -> Result<(BrPendingFs, BrPendingFs, usize), Box<dyn std::error::Error>> {
+    let none = || (BrPendingFs::Root(vec![]), BrPendingFs::Root(vec![]), 0);
+    if !config.enabled {
+        return Ok(none());
+    }
+
+    // not every world has its own environment
+    let Ok(original) = db.read_file(format!("World/0/{ENVIRONMENT_FILE}")) else {
+        return Ok(none());
+    };
+    let mut preset: Value = match serde_json::from_slice(&original) {
+        Ok(preset) => preset,
+        Err(e) => {
+            println!("[environment] can't read the environment settings ({e}), leaving them alone");
+            return Ok(none());
+        }
+    };
+
+    let mut changed = 0;
+    for (name, max) in limits(&preset, config) {
+        if let Some(value) = setting(&mut preset, &name)
+            && let Some(number) = value.as_f64()
+            && number > max
+        {
+            println!("[environment] {name} is {number}, forcing down to {max}..");
+            *value = Value::from(max);
+            changed += 1;
+        }
+    }
+    for name in &config.disable {
+        if let Some(value) = setting(&mut preset, name)
+            && value.as_bool() == Some(true)
+        {
+            println!("[environment] turning off {name}..");
+            *value = Value::Bool(false);
+            changed += 1;
+        }
+    }
+
+    if changed == 0 {
+        return Ok(none());
+    }
+
+    let bytes = serde_json::to_vec_pretty(&preset)?;
+    let path = ["World", world];
+    Ok((
+        patch::nested(&path, vec![(ENVIRONMENT_FILE.to_string(), BrPendingFs::File(Some(bytes)))]),
+        patch::nested(&path, vec![(ENVIRONMENT_FILE.to_string(), BrPendingFs::File(Some(original)))]),
+        changed,
+    ))
+}
+
+// the settings of a written environment that are still over their limits
+pub fn check(db: &BrReader<Brdb>, config: &EnvironmentConfig) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let bytes = db.read_file(format!("World/0/{ENVIRONMENT_FILE}"))?;
+    let mut preset: Value = serde_json::from_slice(&bytes)?;
+
+    let mut problems = vec![];
+    for (name, max) in limits(&preset, config) {
+        if setting(&mut preset, &name).and_then(|v| v.as_f64()).is_some_and(|v| v > max) {
+            problems.push(name);
+        }
+    }
+    for name in &config.disable {
+        if setting(&mut preset, name).and_then(|v| v.as_bool()) == Some(true) {
+            problems.push(name.clone());
+        }
+    }
+    Ok(problems)
+}
+
+// every setting with a limit as ("<group>.<setting>", max), including the ones of each water group in the preset
+fn limits(preset: &Value, config: &EnvironmentConfig) -> Vec<(String, f64)> {
+    let mut limits: Vec<(String, f64)> = config.max.iter().map(|(name, max)| (name.clone(), *max)).collect();
+
+    let groups = preset.get("data").and_then(|d| d.get("groups")).and_then(|g| g.as_object());
+    for group in groups.into_iter().flat_map(|groups| groups.keys()) {
+        if group.to_lowercase().starts_with("water") {
+            limits.extend(config.water.iter().map(|(key, max)| (format!("{group}.{key}"), *max)));
+        }
+    }
+    limits
+}
+
+// looks up a setting like "Sky.fogDensity" in the preset
+fn setting<'a>(preset: &'a mut Value, name: &str) -> Option<&'a mut Value> {
+    let (group, key) = name.split_once('.')?;
+    preset.get_mut("data")?.get_mut("groups")?.get_mut(group)?.get_mut(key)
+}
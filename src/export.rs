@@ -0,0 +1,285 @@
+/*
+ * dumping what's in a world, for looking at it with other tools
+ *
+ * `brdb_optimize export components --csv <world.brdb>` writes every component
+ * in the world as a line of csv to stdout: where it is, what it is, who owns it,
+ * and the properties the optimizer cares about (or the ones given with --props).
+ * properties a component doesn't have are left empty
+ *
+ * `brdb_optimize export entities --json <world.brdb>` writes every entity as json to stdout:
+ * its type, id, owner, where it is and how it's moving, whether it's frozen,
+ * and the properties given with --props, for map viewers and audit scripts
+ *
+ * `brdb_optimize export geometry --obj <world.brdb>` writes a coarse mesh of the world to stdout,
+ * one box per brick, to look around an unfamiliar world in blender before deciding what to strip.
+ * basic bricks (fixed meshes) don't store a size, so they show up as a 1x1 brick.
+ * every brick is an object of its own, or with --merge-chunks every chunk is
+ */
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, BufWriter, Write},
+    path::Path,
+    process,
+};
+use brdb::{AsBrdbValue, BrReader, Brdb, BrdbComponent, IntoReader, Quat4f, Vector3f, pending::BrPendingFs, schema::BrdbValue};
+use serde_json::{Value, json};
+
+use crate::{
+    bricks,
+    cli::Args,
+    grids,
+    hidden,
+    obj::{CORNERS, Obj},
+    owners,
+    rules::FLOAT_PROPS,
+    welds::CHUNK_SIZE,
+    worlds,
+};
+
+// half the size of a 1x1 brick, which basic bricks are drawn as
+const BASIC_BRICK: [i32; 3] = [5, 5, 6];
+
+pub fn components(src: &Path, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    if !src.exists() {
+        println!("world file {:?} does not exist", src);
+        process::exit(1);
+    }
+
+    let props: Vec<String> = match &args.props {
+        Some(props) => props.clone(),
+        None => ["bCastShadows"].iter().chain(FLOAT_PROPS).map(|p| p.to_string()).collect(),
+    };
+
+    let mut csv = csv::Writer::from_writer(io::stdout().lock());
+    let mut header = vec!["world", "grid", "chunk", "index", "component", "owner"];
+    header.extend(props.iter().map(String::as_str));
+    csv.write_record(&header)?;
+
+    let db = Brdb::open(src)?.into_reader();
+    let source = db.to_pending()?;
+    for world in worlds::indices(&source) {
+        worlds::read_world(&db, &source, &world, src, |world_db| {
+            write_world(world_db, &source, &world, &props, &mut csv)
+        })?;
+    }
+    csv.flush()?;
+
+    Ok(())
+}
+
+fn write_world(
+    db: &BrReader<Brdb>,
+    source: &BrPendingFs,
+    world: &str,
+    props: &[String],
+    csv: &mut csv::Writer<impl io::Write>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for grid in grids::find(db, source, world)?.all() {
+        for chunk in db.brick_chunk_index(grid)? {
+            if chunk.num_components == 0 {
+                continue;
+            }
+            // stdout is the csv, so problems go to stderr
+            let Ok((soa, components)) = db.component_chunk(grid, *chunk) else {
+                eprintln!("[grid:{grid}][{}] can't read components, skipping the chunk", *chunk);
+                continue;
+            };
+            let component_owners = owners::of_components(db, grid, *chunk, &soa).unwrap_or_default();
+
+            for (i, component) in components.iter().enumerate() {
+                let mut record = vec![
+                    world.to_string(),
+                    grid.to_string(),
+                    chunk.to_string(),
+                    i.to_string(),
+                    component.get_name().to_string(),
+                    component_owners.get(i).map(|o| o.to_string()).unwrap_or_default(),
+                ];
+                record.extend(props.iter().map(|prop| component.prop(prop).map(cell).unwrap_or_default()));
+                csv.write_record(&record)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn entities(src: &Path, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    if !src.exists() {
+        println!("world file {:?} does not exist", src);
+        process::exit(1);
+    }
+    let props = args.props.clone().unwrap_or_default();
+
+    let db = Brdb::open(src)?.into_reader();
+    let source = db.to_pending()?;
+    let mut entities = vec![];
+    for world in worlds::indices(&source) {
+        worlds::read_world(&db, &source, &world, src, |world_db| {
+            for chunk in world_db.entity_chunk_index()? {
+                // stdout is the json, so problems go to stderr
+                let Ok(chunk_entities) = world_db.entity_chunk(chunk) else {
+                    eprintln!("[world:{world}][entities][{chunk}] can't read entities, skipping the chunk");
+                    continue;
+                };
+                for entity in chunk_entities {
+                    let entity_type = entity.data.get_schema_struct().map_or("unknown".to_string(), |s| s.0.to_string());
+                    let entity_props: BTreeMap<&str, Value> = props.iter()
+                        .filter_map(|prop| entity.data.prop(prop).ok().map(|value| (prop.as_str(), json_value(value))))
+                        .collect();
+                    entities.push(json!({
+                        "world": world,
+                        "id": entity.id,
+                        "type": entity_type,
+                        "owner": entity.owner_index,
+                        "location": vector(&entity.location),
+                        "rotation": { "x": entity.rotation.x, "y": entity.rotation.y, "z": entity.rotation.z, "w": entity.rotation.w },
+                        "velocity": vector(&entity.velocity),
+                        "angular_velocity": vector(&entity.angular_velocity),
+                        "frozen": entity.frozen,
+                        "sleeping": entity.sleeping,
+                        "props": entity_props,
+                    }));
+                }
+            }
+            Ok(())
+        })?;
+    }
+
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, &entities)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+pub fn geometry(src: &Path, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    if !src.exists() {
+        println!("world file {:?} does not exist", src);
+        process::exit(1);
+    }
+
+    let mut obj = Obj::new(BufWriter::new(io::stdout().lock()));
+    obj.comment(&format!("{} exported by brdb_optimize, one box per brick", src.display()))?;
+
+    let db = Brdb::open(src)?.into_reader();
+    let source = db.to_pending()?;
+    for world in worlds::indices(&source) {
+        worlds::read_world(&db, &source, &world, src, |world_db| {
+            write_geometry(world_db, &source, &world, args.merge_chunks, &mut obj)
+        })?;
+    }
+    obj.flush()?;
+
+    Ok(())
+}
+
+fn write_geometry(
+    db: &BrReader<Brdb>,
+    source: &BrPendingFs,
+    world: &str,
+    merge_chunks: bool,
+    obj: &mut Obj<impl Write>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // where the dynamic grids are and which way they face, root grids are where the world is
+    let mut placements: HashMap<usize, (Vector3f, Quat4f)> = HashMap::new();
+    for chunk in db.entity_chunk_index()? {
+        // stdout is the mesh, so problems go to stderr
+        let Ok(entities) = db.entity_chunk(chunk) else {
+            eprintln!("[world:{world}][entities][{chunk}] can't read entities, its grids end up in the wrong spot");
+            continue;
+        };
+        for entity in entities {
+            if let Some(id) = entity.id {
+                placements.insert(id, (entity.location, entity.rotation));
+            }
+        }
+    }
+
+    for grid in grids::find(db, source, world)?.all() {
+        let placement = placements.get(&grid);
+        for chunk in db.brick_chunk_index(grid)? {
+            if chunk.num_bricks == 0 {
+                continue;
+            }
+            let Ok(bricks) = db.brick_chunk_soa(grid, *chunk) else {
+                eprintln!("[grid:{grid}][{}] can't read bricks, skipping the chunk", *chunk);
+                continue;
+            };
+            let name = format!("world{world}_grid{grid}_chunk{}", *chunk);
+            if merge_chunks {
+                obj.object(&name)?;
+            }
+
+            for (i, position) in bricks.relative_positions.iter().enumerate() {
+                if !merge_chunks {
+                    obj.object(&format!("{name}_brick{i}"))?;
+                }
+                let extents = match bricks::procedural_type(&bricks, bricks.brick_type_indices[i]) {
+                    Some((_, size)) => hidden::extents([size.x as i32, size.y as i32, size.z as i32], bricks.orientations[i]),
+                    None => BASIC_BRICK,
+                };
+                let center = [
+                    chunk.x as i32 * CHUNK_SIZE + position.x as i32,
+                    chunk.y as i32 * CHUNK_SIZE + position.y as i32,
+                    chunk.z as i32 * CHUNK_SIZE + position.z as i32,
+                ];
+
+                let corners = CORNERS.map(|corner| {
+                    let point = [0, 1, 2].map(|k| center[k] as f32 + corner[k] * extents[k] as f32);
+                    match placement {
+                        Some((location, rotation)) => {
+                            let [x, y, z] = rotate(rotation, point);
+                            [location.x + x, location.y + y, location.z + z]
+                        }
+                        None => point,
+                    }
+                });
+                obj.cuboid(&corners)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// `v` turned by the rotation `q`
+fn rotate(q: &Quat4f, v: [f32; 3]) -> [f32; 3] {
+    let cross = |a: [f32; 3], b: [f32; 3]| [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]];
+    let axis = [q.x, q.y, q.z];
+    let t = cross(axis, v).map(|c| c * 2.0);
+    let u = cross(axis, t);
+    [0, 1, 2].map(|k| v[k] + q.w * t[k] + u[k])
+}
+
+fn vector(v: &Vector3f) -> Value {
+    json!({ "x": v.x, "y": v.y, "z": v.z })
+}
+
+// a property's value as json, for any kind of value
+fn json_value(value: &BrdbValue) -> Value {
+    if let Ok(v) = value.as_brdb_bool() {
+        json!(v)
+    } else if let Ok(v) = value.as_brdb_f32() {
+        json!(v)
+    } else if let Ok(v) = value.as_brdb_i64() {
+        json!(v)
+    } else if let Ok(v) = value.as_brdb_str() {
+        json!(v)
+    } else {
+        json!(format!("{value:?}"))
+    }
+}
+
+// a property's value as text, for any kind of value
+fn cell(value: &BrdbValue) -> String {
+    if let Ok(v) = value.as_brdb_bool() {
+        v.to_string()
+    } else if let Ok(v) = value.as_brdb_f32() {
+        v.to_string()
+    } else if let Ok(v) = value.as_brdb_i64() {
+        v.to_string()
+    } else if let Ok(v) = value.as_brdb_str() {
+        v.to_string()
+    } else {
+        format!("{value:?}")
+    }
+}
@@ -0,0 +1,177 @@
+/*
+ * pulling a vehicle out of a world, to rescue it from a world that's falling apart
+ *
+ * `brdb_optimize extract --grid <id> <world.brdb>` writes <world>.grid<id>.brdb, a copy of the world
+ * with nothing in it but that dynamic grid: its bricks, components and wires, and everything it has
+ * a joint to (wheels, and grids on bearings or sliders, with everything those have a joint to).
+ * the main grid and every other entity are removed, and so are the other worlds in the file.
+ * the vehicle stays where it was, so it can be loaded as is or merged into another world (see merge.rs).
+ *
+ * wires to grids that didn't come along are cut, they'd point at nothing.
+ * the owner table is left as it was, optimizing the extracted world with `prune_unowned` under [owners] tidies it up
+ */
+
+use std::{collections::HashSet, path::PathBuf, process};
+use brdb::{BrReader, Brdb, EntityChunkSoA, IntoReader, WireChunkSoA, pending::BrPendingFs};
+
+use crate::{
+    bricks::{self, IndexChanges},
+    grids,
+    lock::Lock,
+    patch::{self, Entry},
+    trim,
+    worlds,
+};
+
+// what's in the extracted world
+#[derive(Default)]
+struct Extracted {
+    grids: usize,
+    entities: usize,
+    bricks: usize,
+    cut_wires: usize,
+}
+
+pub fn run(src: PathBuf, grid: usize) -> Result<(), Box<dyn std::error::Error>> {
+    if !src.exists() {
+        println!("world file {:?} does not exist", src);
+        process::exit(1);
+    }
+
+    let Some(_lock) = Lock::acquire(&src)? else {
+        process::exit(1);
+    };
+
+    let stem = src.file_stem().unwrap().to_string_lossy();
+    let dst = src.with_file_name(format!("{stem}.grid{grid}.brdb"));
+
+    println!("Reading file {:?}", src);
+    let db = Brdb::open(&src)?.into_reader();
+    let source = db.to_pending()?;
+
+    let (mut combined_patch, extracted) = extract(&db, &source, grid)?;
+    // only World/0 is read (see worlds.rs), the vehicle is in there
+    let other_worlds: Vec<Entry> = worlds::indices(&source).into_iter()
+        .filter(|world| world != "0")
+        .map(|world| (world, BrPendingFs::Folder(None)))
+        .collect();
+    if !other_worlds.is_empty() {
+        combined_patch = combined_patch.with_patch(patch::nested(&["World"], other_worlds))?;
+    }
+
+    println!();
+    println!(
+        "extracted {} grids ({} bricks) and {} entities, cutting {} wires to the rest of the world.",
+        extracted.grids, extracted.bricks, extracted.entities, extracted.cut_wires
+    );
+    println!("writing to world file..");
+    trim::write(&src, &dst, &format!("Extract Grid {grid}"), combined_patch)?;
+    println!("grid {grid} written to {:?}", dst);
+
+    Ok(())
+}
+
+// the patch that removes everything but `grid` and what it has a joint to
+fn extract(db: &BrReader<Brdb>, source: &BrPendingFs, grid: usize) -> Result<(BrPendingFs, Extracted), Box<dyn std::error::Error>> {
+    let grids = grids::find(db, source, "0")?;
+    if !grids.dynamic.contains(&grid) {
+        return Err(format!("grid {grid} isn't a vehicle (dynamic grid) in this world, see `analyze` for which ones there are").into());
+    }
+
+    // the grid, everything it has a joint to, everything those have a joint to, and so on
+    let mut kept_grids = vec![grid];
+    let mut kept = HashSet::from([grid]);
+    let mut i = 0;
+    while i < kept_grids.len() {
+        for entity in trim::joint_references(db, &[kept_grids[i]])? {
+            if kept.insert(entity) && grids.dynamic.contains(&entity) {
+                kept_grids.push(entity);
+            }
+        }
+        i += 1;
+    }
+    let mut extracted = Extracted { grids: kept_grids.len(), entities: kept.len(), ..Default::default() };
+    println!("[grid:{grid}] has {} grids and {} other entities attached to it", kept_grids.len() - 1, kept.len() - kept_grids.len());
+
+    let removed_grids: Vec<usize> = grids.dynamic.iter().filter(|g| !kept_grids.contains(g)).copied().collect();
+    let (grids_patch, _) = grids::delete(source, "0", &removed_grids);
+
+    // every other entity goes
+    let global_data = db.global_data()?;
+    let entity_schema = db.entities_schema()?;
+    let mut entity_chunk_files = vec![];
+    for chunk in db.entity_chunk_index()? {
+        let entities = db.entity_chunk(chunk)?;
+        let mut soa = EntityChunkSoA::default();
+        let mut chunk_modified = false;
+        for entity in &entities {
+            let id = entity.id.unwrap();
+            if kept.contains(&id) {
+                soa.add_entity(&global_data, entity, id as u32);
+            } else {
+                chunk_modified = true;
+            }
+        }
+        if chunk_modified {
+            entity_chunk_files.push(patch::chunk_file(chunk, soa.to_bytes(&entity_schema)?));
+        }
+    }
+    let entities_patch = patch::nested(&["World", "0", "Entities", "Chunks"], entity_chunk_files);
+
+    let mut grid_folders = vec![];
+    // the main grid is emptied, but stays around so the world still has one
+    for root in &grids.root {
+        let mut index_changes = IndexChanges::default();
+        let mut grid_folder = vec![];
+        for chunk in db.brick_chunk_index(*root)? {
+            for (folder, tombstone, _) in bricks::delete_chunk(db, *root, *chunk) {
+                patch::push_into(&mut grid_folder, folder, tombstone);
+            }
+            index_changes.removed.push(*chunk);
+        }
+        if index_changes.is_empty() {
+            continue;
+        }
+        let (index_file, _) = bricks::chunk_index_files(db, *root, &index_changes)?;
+        grid_folder.push(index_file);
+        grid_folders.push((root.to_string(), BrPendingFs::Folder(Some(grid_folder))));
+    }
+
+    // wires coming from grids that are gone get cut
+    let wire_schema = db.wires_schema()?;
+    for kept_grid in &kept_grids {
+        let mut index_changes = IndexChanges::default();
+        let mut grid_folder = vec![];
+        for chunk in db.brick_chunk_index(*kept_grid)? {
+            extracted.bricks += chunk.num_bricks as usize;
+            if chunk.num_wires == 0 {
+                continue;
+            }
+            let wires = db.wire_chunk_soa(*kept_grid, *chunk)?;
+            let (sources, targets): (Vec<_>, Vec<_>) = wires.remote_wire_sources.iter()
+                .zip(&wires.remote_wire_targets)
+                .filter(|(source, _)| kept_grids.contains(&(source.grid_persistent_index as usize)))
+                .map(|(source, target)| (*source, *target))
+                .unzip();
+            let cut = wires.remote_wire_sources.len() - sources.len();
+            if cut == 0 {
+                continue;
+            }
+
+            println!("[grid:{kept_grid}][{}] cutting {cut} wires to the rest of the world..", *chunk);
+            extracted.cut_wires += cut;
+            let wires = WireChunkSoA { remote_wire_sources: sources, remote_wire_targets: targets, ..wires };
+            patch::push_into(&mut grid_folder, "Wires", patch::chunk_file(*chunk, wires.to_bytes(&wire_schema)?));
+            index_changes.wires.push((*chunk, (wires.local_wire_sources.len() + wires.remote_wire_sources.len()) as u32));
+        }
+        if index_changes.is_empty() {
+            continue;
+        }
+        let (index_file, _) = bricks::chunk_index_files(db, *kept_grid, &index_changes)?;
+        grid_folder.push(index_file);
+        grid_folders.push((kept_grid.to_string(), BrPendingFs::Folder(Some(grid_folder))));
+    }
+    let bricks_patch = patch::nested(&["World", "0", "Bricks", "Grids"], grid_folders);
+
+    Ok((entities_patch.with_patch(grids_patch)?.with_patch(bricks_patch)?, extracted))
+}
@@ -0,0 +1,121 @@
+/*
+ * finding out which brick grids a world has
+ *
+ * there are two kinds:
+ * - root grids: the static world itself, anything that isn't a physics object.
+ *   usually there's exactly one, with id 1
+ * - dynamic grids: physics objects (vehicles, contraptions),
+ *   every one of them belongs to an Entity_DynamicBrickGrid entity with the same id
+ */
+
+use brdb::{BrReader, Brdb, BrdbComponent, pending::BrPendingFs};
+
+use crate::patch;
+
+// components that make a grid drive around on its own (so basically, a vehicle)
+pub const ENGINE_COMPONENTS: &[&str] = &["BrickComponentData_WheelEngine"];
+
+pub struct Grids {
+    pub root: Vec<usize>,
+    pub dynamic: Vec<usize>,
+}
+
+impl Grids {
+    // root grids first, then dynamic grids
+    pub fn all(&self) -> Vec<usize> {
+        self.root.iter().chain(&self.dynamic).copied().collect()
+    }
+
+    pub fn is_root(&self, grid: usize) -> bool {
+        self.root.contains(&grid)
+    }
+}
+
+/*
+ * `db` reads the world as World/0 (see worlds.rs),
+ * `source` is the whole file and `world` the real index of the world in it
+ */
+pub fn find(db: &BrReader<Brdb>, source: &BrPendingFs, world: &str) -> Result<Grids, Box<dyn std::error::Error>> {
+    // every dynamic grid has an entity
+    let mut dynamic = vec![];
+    for chunk in db.entity_chunk_index()? {
+        for entity in db.entity_chunk(chunk)? {
+            if entity.data
+                .get_schema_struct()
+                .is_some_and(|s| s.0.as_ref() == "Entity_DynamicBrickGrid")
+                && let Some(id) = entity.id
+            {
+                dynamic.push(id);
+            }
+        }
+    }
+
+    // every grid has a folder in /World/<world>/Bricks/Grids/, the ones without an entity are root grids
+    let mut root: Vec<usize> = patch::folder(source, &["World", world, "Bricks", "Grids"])
+        .map(|grids| {
+            grids.iter()
+                .filter_map(|(name, _)| name.parse().ok())
+                .filter(|id| !dynamic.contains(id))
+                .collect()
+        })
+        .unwrap_or_default();
+    root.sort();
+
+    // couldn't find any, so fall back to the usual main grid
+    if root.is_empty() {
+        root.push(1);
+    }
+
+    Ok(Grids { root, dynamic })
+}
+
+// dynamic grids without a single brick left, usually leftovers of deleted vehicles
+pub fn empty_dynamic(db: &BrReader<Brdb>, grids: &Grids) -> Vec<usize> {
+    grids.dynamic.iter()
+        .filter(|grid| {
+            // a grid without a chunk index has no bricks either
+            db.brick_chunk_index(**grid).map_or(true, |chunks| chunks.iter().all(|c| c.num_bricks == 0))
+        })
+        .copied()
+        .collect()
+}
+
+/*
+ * deletes the folders of the given grids.
+ * returns (patch, undo patch), the undo patch puts the original folders back
+ */
+pub fn delete(source: &BrPendingFs, world: &str, grids: &[usize]) -> (BrPendingFs, BrPendingFs) {
+    let path = ["World", world, "Bricks", "Grids"];
+    let folders = patch::folder(source, &path);
+
+    let mut deleted = vec![];
+    let mut originals = vec![];
+    for grid in grids {
+        // grids without a folder don't need deleting
+        let Some((name, original)) = folders.and_then(|f| f.iter().find(|(name, _)| *name == grid.to_string())) else {
+            continue;
+        };
+        deleted.push((name.clone(), BrPendingFs::Folder(None)));
+        originals.push((name.clone(), original.clone()));
+    }
+
+    (patch::nested(&path, deleted), patch::nested(&path, originals))
+}
+
+// whether any chunk of the grid has an engine component. unreadable chunks are skipped
+pub fn has_engine(db: &BrReader<Brdb>, grid: usize) -> Result<bool, Box<dyn std::error::Error>> {
+    for chunk in db.brick_chunk_index(grid)? {
+        if chunk.num_components == 0 {
+            continue;
+        }
+        let Ok((_, components)) = db.component_chunk(grid, *chunk) else {
+            continue;
+        };
+
+        if components.iter().any(|c| ENGINE_COMPONENTS.contains(&c.get_name())) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
@@ -0,0 +1,94 @@
+/*
+ * drawing the main grid seen from above
+ *
+ * every chunk column of the main grid becomes a square in the image, with x going to the right
+ * and y going down. there are two maps:
+ * - lights: green shows how many lights a chunk has, red how many of them cast shadows,
+ *   so over-lit builds stand out as bright (yellow to red) spots
+ * - density: the more bricks a chunk column has, the whiter it is,
+ *   so the big builds in a world stand out
+ * everything is scaled to the busiest chunk in the world
+ */
+
+use std::{collections::BTreeMap, fs::File, io::BufWriter, path::Path};
+
+// how many pixels wide a single chunk is
+const CHUNK_PIXELS: u32 = 8;
+// the biggest the image may get along either side, so huge worlds don't make huge images
+const MAX_PIXELS: u32 = 4096;
+
+/*
+ * writes the heatmap of `lights`: (chunk x, chunk y) -> (lights, shadow casting lights).
+ * returns false if there aren't any lights to draw
+ */
+pub fn lights(path: &Path, lights: &BTreeMap<(i16, i16), (usize, usize)>) -> Result<bool, Box<dyn std::error::Error>> {
+    let most_lights = lights.values().map(|(lights, _)| *lights).max().unwrap_or(0).max(1);
+    let most_shadows = lights.values().map(|(_, shadows)| *shadows).max().unwrap_or(0).max(1);
+
+    let cells = lights.iter()
+        .map(|(spot, (count, shadows))| (*spot, [shade(*shadows, most_shadows), shade(*count, most_lights), 0]))
+        .collect();
+    draw(path, &cells)
+}
+
+/*
+ * writes the brick density map of `bricks`: (chunk x, chunk y) -> bricks in that column.
+ * returns false if there aren't any bricks to draw
+ */
+pub fn density(path: &Path, bricks: &BTreeMap<(i16, i16), usize>) -> Result<bool, Box<dyn std::error::Error>> {
+    let most = bricks.values().copied().max().unwrap_or(0).max(1);
+
+    let cells = bricks.iter()
+        .map(|(spot, count)| {
+            let shade = shade(*count, most);
+            (*spot, [shade, shade, shade])
+        })
+        .collect();
+    draw(path, &cells)
+}
+
+// square roots, so a few very busy chunks don't make everything else invisible
+fn shade(count: usize, most: usize) -> u8 {
+    ((count as f64 / most as f64).sqrt() * 255.0) as u8
+}
+
+// draws every chunk column in its color, anything without a color stays black
+fn draw(path: &Path, cells: &BTreeMap<(i16, i16), [u8; 3]>) -> Result<bool, Box<dyn std::error::Error>> {
+    if cells.is_empty() {
+        return Ok(false);
+    }
+
+    let min_x = cells.keys().map(|(x, _)| *x as i32).min().unwrap();
+    let max_x = cells.keys().map(|(x, _)| *x as i32).max().unwrap();
+    let min_y = cells.keys().map(|(_, y)| *y as i32).min().unwrap();
+    let max_y = cells.keys().map(|(_, y)| *y as i32).max().unwrap();
+    let chunks_wide = (max_x - min_x + 1) as u32;
+    let chunks_high = (max_y - min_y + 1) as u32;
+    let width = (chunks_wide * CHUNK_PIXELS).min(MAX_PIXELS);
+    let height = (chunks_high * CHUNK_PIXELS).min(MAX_PIXELS);
+    // the pixels a chunk covers along one side. on huge worlds, chunks share pixels
+    let span = |chunk: u32, chunks: u32, pixels: u32| {
+        let start = (chunk as u64 * pixels as u64 / chunks as u64) as u32;
+        let end = ((chunk as u64 + 1) * pixels as u64 / chunks as u64) as u32;
+        start..end.max(start + 1).min(pixels)
+    };
+
+    let mut pixels = vec![0u8; (width * height * 3) as usize];
+    for ((x, y), color) in cells {
+        for py in span((*y as i32 - min_y) as u32, chunks_high, height) {
+            for px in span((*x as i32 - min_x) as u32, chunks_wide, width) {
+                let i = ((py * width + px) * 3) as usize;
+                for c in 0..3 {
+                    pixels[i + c] = pixels[i + c].max(color[c]);
+                }
+            }
+        }
+    }
+
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+
+    Ok(true)
+}
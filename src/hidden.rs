@@ -0,0 +1,273 @@
+/*
+ * finds bricks that can never be seen, because every side of them
+ * is completely covered by solid, opaque bricks (think of the inside of a huge solid wall)
+ *
+ * only bricks inside the same chunk are looked at, so bricks at the edge
+ * of a chunk never count as hidden. that's fine, it just means we miss a few
+ */
+
+use brdb::{BrdbWorldGlobalData, BrickChunkSoA};
+
+use crate::bricks;
+
+// brick shapes that are plain boxes, so they cover everything behind their sides
+pub const BOX_ASSETS: &[&str] = &["PB_DefaultBrick", "PB_DefaultMicroBrick"];
+// materials you can't see through
+pub const OPAQUE_MATERIALS: &[&str] = &["BMC_Plastic", "BMC_Metallic"];
+
+// the space a brick takes up, in the chunk's brick units
+#[derive(Clone, Copy)]
+struct Bounds {
+    min: [i32; 3],
+    max: [i32; 3],
+}
+
+/*
+ * returns the indices of all hidden bricks.
+ * bricks that are already being removed (`keep` is false) don't cover anything,
+ * and bricks with components (`component_bricks`) are never returned
+ */
+pub fn find(bricks: &BrickChunkSoA, global: &BrdbWorldGlobalData, component_bricks: &[u32], keep: &[bool]) -> Vec<usize> {
+    let num_bricks = bricks.brick_type_indices.len();
+
+    // bounds of every brick we know the size of, and whether it covers what's behind it
+    let mut bounds = vec![None; num_bricks];
+    let mut solid = vec![false; num_bricks];
+    for i in 0..num_bricks {
+        let Some((asset, size)) = procedural(bricks, global, i) else {
+            continue;
+        };
+        let extents = extents(size, bricks.orientations[i]);
+        let position = bricks.relative_positions[i];
+        let center = [position.x as i32, position.y as i32, position.z as i32];
+        bounds[i] = Some(Bounds {
+            min: [0, 1, 2].map(|k| center[k] - extents[k]),
+            max: [0, 1, 2].map(|k| center[k] + extents[k]),
+        });
+
+        let material = global.material_asset_names.get(bricks.material_indices[i] as usize);
+        solid[i] = keep[i]
+            && BOX_ASSETS.contains(&asset)
+            && material.is_some_and(|m| OPAQUE_MATERIALS.contains(&m.as_str()))
+            && bricks.colors_and_alphas[i].a == 255
+            && bricks.visibility_flags.get(i);
+    }
+
+    // the solid bricks sorted by where they start along X, so neighbours can be found without checking every brick
+    let mut occluders: Vec<(usize, Bounds)> = (0..num_bricks)
+        .filter(|i| solid[*i])
+        .filter_map(|i| bounds[i].map(|b| (i, b)))
+        .collect();
+    occluders.sort_by_key(|(_, b)| b.min[0]);
+
+    let mut hidden = vec![];
+    for i in 0..num_bricks {
+        let Some(brick) = bounds[i] else {
+            continue;
+        };
+        if !keep[i] || component_bricks.contains(&(i as u32)) {
+            continue;
+        }
+
+        // every brick touching or overlapping this one
+        let end = occluders.partition_point(|(_, b)| b.min[0] <= brick.max[0]);
+        let neighbours: Vec<Bounds> = occluders[..end].iter()
+            .filter(|(j, b)| *j != i && b.max[0] >= brick.min[0])
+            .map(|(_, b)| *b)
+            .collect();
+
+        let covered = (0..3).all(|axis| {
+            covered(&brick, &neighbours, axis, true) && covered(&brick, &neighbours, axis, false)
+        });
+        if covered {
+            hidden.push(i);
+        }
+    }
+
+    hidden
+}
+
+// the asset name and size of a procedural brick. basic bricks (fixed meshes) don't store a size, so they're skipped
+fn procedural<'a>(bricks: &BrickChunkSoA, global: &'a BrdbWorldGlobalData, i: usize) -> Option<(&'a str, [i32; 3])> {
+    let (asset, size) = bricks::procedural_type(bricks, bricks.brick_type_indices[i])?;
+    let asset = global.procedural_brick_asset_names.get(asset as usize)?;
+    Some((asset.as_str(), [size.x as i32, size.y as i32, size.z as i32]))
+}
+
+/*
+ * half the size of a brick along the X, Y and Z axis of the world.
+ * the top 6 bits of the orientation are the direction the brick's top faces (+X, -X, +Y, -Y, +Z, -Z),
+ * the bottom 2 the quarter turns around that direction
+ */
+pub fn extents(size: [i32; 3], orientation: u8) -> [i32; 3] {
+    let [x, y, z] = size;
+    // a quarter turn swaps the brick's width and length
+    let (a, b) = if orientation & 1 == 1 { (y, x) } else { (x, y) };
+    match orientation >> 2 {
+        0 | 1 => [z, a, b],
+        2 | 3 => [a, z, b],
+        _ => [a, b, z],
+    }
+}
+
+// whether one side of `brick` is completely covered by `neighbours`
+fn covered(brick: &Bounds, neighbours: &[Bounds], axis: usize, positive: bool) -> bool {
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+    // the parts of the neighbours that lie against this side, as rectangles on it
+    let plane = if positive { brick.max[axis] } else { brick.min[axis] };
+    let rects: Vec<[i32; 4]> = neighbours.iter()
+        .filter(|n| if positive {
+            n.min[axis] <= plane && n.max[axis] > plane
+        } else {
+            n.min[axis] < plane && n.max[axis] >= plane
+        })
+        .map(|n| [
+            n.min[u].max(brick.min[u]),
+            n.max[u].min(brick.max[u]),
+            n.min[v].max(brick.min[v]),
+            n.max[v].min(brick.max[v]),
+        ])
+        .filter(|r| r[0] < r[1] && r[2] < r[3])
+        .collect();
+    if rects.is_empty() {
+        return false;
+    }
+
+    /*
+     * cut the side up along every rectangle edge,
+     * then every piece needs to be inside at least one rectangle
+     */
+    let mut us = vec![brick.min[u], brick.max[u]];
+    let mut vs = vec![brick.min[v], brick.max[v]];
+    for r in &rects {
+        us.extend([r[0], r[1]]);
+        vs.extend([r[2], r[3]]);
+    }
+    us.sort();
+    us.dedup();
+    vs.sort();
+    vs.dedup();
+
+    us.windows(2).all(|pu| {
+        vs.windows(2).all(|pv| {
+            rects.iter().any(|r| r[0] <= pu[0] && pu[1] <= r[1] && r[2] <= pv[0] && pv[1] <= r[3])
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds(min: [i32; 3], max: [i32; 3]) -> Bounds {
+        Bounds { min, max }
+    }
+
+    // a brick with a neighbour of the same size against every side, except the ones in `open`
+    fn surrounded(brick: Bounds, open: &[(usize, bool)]) -> Vec<Bounds> {
+        let mut neighbours = vec![];
+        for axis in 0..3 {
+            for positive in [true, false] {
+                if open.contains(&(axis, positive)) {
+                    continue;
+                }
+                let size = brick.max[axis] - brick.min[axis];
+                let (mut min, mut max) = (brick.min, brick.max);
+                let shift = if positive { size } else { -size };
+                min[axis] += shift;
+                max[axis] += shift;
+                neighbours.push(bounds(min, max));
+            }
+        }
+        neighbours
+    }
+
+    fn hidden(brick: &Bounds, neighbours: &[Bounds]) -> bool {
+        (0..3).all(|axis| covered(brick, neighbours, axis, true) && covered(brick, neighbours, axis, false))
+    }
+
+    #[test]
+    fn extents_follow_the_orientation() {
+        // top facing +Z, no turns
+        assert_eq!(extents([1, 2, 3], 4 << 2), [1, 2, 3]);
+        // a quarter turn swaps width and length
+        assert_eq!(extents([1, 2, 3], (4 << 2) | 1), [2, 1, 3]);
+        // two quarter turns don't
+        assert_eq!(extents([1, 2, 3], (4 << 2) | 2), [1, 2, 3]);
+        // top facing -X, +Y and -Z
+        assert_eq!(extents([1, 2, 3], 1 << 2), [3, 1, 2]);
+        assert_eq!(extents([1, 2, 3], 2 << 2), [1, 3, 2]);
+        assert_eq!(extents([1, 2, 3], 5 << 2), [1, 2, 3]);
+        // top facing +X with a quarter turn
+        assert_eq!(extents([1, 2, 3], 1), [3, 2, 1]);
+    }
+
+    #[test]
+    fn side_covered_by_one_brick() {
+        let brick = bounds([0, 0, 0], [10, 10, 10]);
+        let neighbours = [bounds([10, 0, 0], [20, 10, 10])];
+        assert!(covered(&brick, &neighbours, 0, true));
+        assert!(!covered(&brick, &neighbours, 0, false));
+        assert!(!covered(&brick, &neighbours, 1, true));
+    }
+
+    #[test]
+    fn side_covered_by_several_bricks() {
+        let brick = bounds([0, 0, 0], [10, 10, 10]);
+        let halves = [bounds([10, 0, 0], [12, 5, 10]), bounds([10, 5, 0], [12, 10, 10])];
+        assert!(covered(&brick, &halves, 0, true));
+        // with a gap left between them
+        let gap = [bounds([10, 0, 0], [12, 4, 10]), bounds([10, 5, 0], [12, 10, 10])];
+        assert!(!covered(&brick, &gap, 0, true));
+        // one half isn't enough
+        assert!(!covered(&brick, &halves[..1], 0, true));
+    }
+
+    #[test]
+    fn bigger_and_overlapping_neighbours_cover() {
+        let brick = bounds([0, 0, 0], [10, 10, 10]);
+        // sticks out on every side, and reaches into the brick
+        let neighbours = [bounds([-5, -5, 8], [15, 15, 30])];
+        assert!(covered(&brick, &neighbours, 2, true));
+    }
+
+    #[test]
+    fn touching_at_an_edge_doesnt_cover() {
+        let brick = bounds([0, 0, 0], [10, 10, 10]);
+        // next to the +X side, but not against it
+        let neighbours = [bounds([10, 10, 0], [20, 20, 10])];
+        assert!(!covered(&brick, &neighbours, 0, true));
+    }
+
+    #[test]
+    fn only_hidden_with_every_side_covered() {
+        let brick = bounds([0, 0, 0], [10, 10, 10]);
+        assert!(hidden(&brick, &surrounded(brick, &[])));
+        for axis in 0..3 {
+            for positive in [true, false] {
+                assert!(!hidden(&brick, &surrounded(brick, &[(axis, positive)])));
+            }
+        }
+    }
+
+    #[test]
+    fn chunk_edge_isnt_covered() {
+        // a brick against the edge of its chunk: the neighbours on the other side are in another chunk, so they aren't passed in
+        let brick = bounds([2038, 0, 0], [2048, 10, 10]);
+        assert!(!hidden(&brick, &surrounded(brick, &[(0, true)])));
+    }
+
+    #[test]
+    fn rotated_brick_uses_its_rotated_extents() {
+        // 10 long along the brick's X, turned so its top faces +X: it's 10 long along the world's Y
+        let extents = extents([10, 5, 2], 0);
+        assert_eq!(extents, [2, 10, 5]);
+        let brick = bounds(extents.map(|e| -e), extents);
+
+        // against the rotated +Y side
+        assert!(covered(&brick, &[bounds([-2, 10, -5], [2, 20, 5])], 1, true));
+        // where the +Y side would be without the rotation, which is inside the brick
+        assert!(!covered(&brick, &[bounds([-2, 5, -5], [2, 10, 5])], 1, true));
+    }
+}
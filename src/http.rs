@@ -0,0 +1,69 @@
+/*
+ * just enough http to answer a local control panel or prometheus
+ *
+ * one request per connection, no keep-alive, no chunked bodies.
+ * nothing here is meant to face the internet, so only listen on addresses you trust
+ */
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+// bodies bigger than this get refused, nothing we take needs more
+const MAX_BODY: usize = 1024 * 1024;
+
+// how long a client gets to send its request, so one that never finishes doesn't tie us up forever
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+// reads one request from `stream`, None if it isn't a request we can make sense of
+pub fn read(stream: &TcpStream) -> Option<Request> {
+    stream.set_read_timeout(Some(READ_TIMEOUT)).ok()?;
+    let mut reader = BufReader::new(stream);
+
+    // like "GET /metrics HTTP/1.1"
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse().ok()?;
+        }
+    }
+    if length > MAX_BODY {
+        return None;
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    Some(Request { method, path, body })
+}
+
+// writes the whole response and closes the connection
+pub fn respond(mut stream: &TcpStream, status: &str, content_type: &str, body: &str) {
+    // the other side hanging up early isn't our problem
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len(),
+    );
+}
@@ -0,0 +1,72 @@
+/*
+ * putting a vehicle back into a world, the other half of extract.rs
+ *
+ * `brdb_optimize insert <prefab.brdb> --at x,y,z <world.brdb>` adds the vehicles of the prefab
+ * (a world written by `extract`, or any other world) to a copy of the world, written to <world>.inserted.brdb.
+ * the vehicle that nothing else in the prefab has a joint to is put at x,y,z, and everything else moves
+ * along with it. grids and entities get new ids, and everything pointing at them is pointed at the new ones.
+ *
+ * under the hood it's a merge (see merge.rs) without the prefab's main grid,
+ * so the prefab's name tables and owners come along the same way
+ */
+
+use std::{path::PathBuf, process};
+use brdb::{Brdb, IntoReader};
+
+use crate::{grids, lock::Lock, merge::Merge, trim};
+
+pub fn run(prefab: PathBuf, src: PathBuf, at: [f32; 3]) -> Result<(), Box<dyn std::error::Error>> {
+    for world in [&prefab, &src] {
+        if !world.exists() {
+            println!("world file {:?} does not exist", world);
+            process::exit(1);
+        }
+    }
+
+    let Some(_lock) = Lock::acquire(&src)? else {
+        process::exit(1);
+    };
+    let Some(_prefab_lock) = Lock::acquire(&prefab)? else {
+        // exiting skips destructors, so the world's lock has to go first
+        drop(_lock);
+        process::exit(1);
+    };
+
+    let stem = src.file_stem().unwrap().to_string_lossy();
+    let dst = src.with_file_name(format!("{stem}.inserted.brdb"));
+
+    // where the prefab's vehicle is now, so everything can be moved by how far that is from `at`
+    let location = {
+        let db = Brdb::open(&prefab)?.into_reader();
+        let source = db.to_pending()?;
+        let grids = grids::find(&db, &source, "0")?;
+        let jointed = trim::joint_references(&db, &grids.dynamic)?;
+        let Some(grid) = grids.dynamic.iter().find(|g| !jointed.contains(g)).or(grids.dynamic.first()).copied() else {
+            return Err(format!("{:?} has no vehicle (dynamic grid) in it to insert", prefab).into());
+        };
+
+        let mut location = None;
+        for chunk in db.entity_chunk_index()? {
+            if let Some(entity) = db.entity_chunk(chunk)?.into_iter().find(|e| e.id == Some(grid)) {
+                location = Some([entity.location.x, entity.location.y, entity.location.z]);
+            }
+        }
+        println!("[grid:{grid}] is the prefab's vehicle, moving it to {at:?}..");
+        location.ok_or_else(|| format!("[grid:{grid}] has no entity, can't tell where it is"))?
+    };
+
+    println!("Reading file {:?}", src);
+    let db = Brdb::open(&src)?.into_reader();
+    let source = db.to_pending()?;
+    let mut merge = Merge::new(&db, &source)?;
+    merge.world(&prefab, [0, 1, 2].map(|axis| at[axis] - location[axis]), None)?;
+
+    println!();
+    merge.print_summary();
+    println!("writing to world file..");
+    let name = prefab.file_stem().unwrap().to_string_lossy();
+    trim::write(&src, &dst, &format!("Insert Prefab ({name})"), merge.patch()?)?;
+    println!("world with the prefab written to {:?}", dst);
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+/*
+ * finding closed loops of joints between grids
+ *
+ * a bearing or slider connects the grid it's on to another entity. when those connections
+ * go around in a circle (grid A is jointed to B, B to C and C back to A), the physics
+ * solver has to satisfy all of them at once, which it often can't: the whole contraption
+ * starts jittering, or flies apart.
+ * two joints between the same pair of grids (like the two hinges of a door) are normal,
+ * so only loops through three or more grids count.
+ *
+ * with [joints] break_loops, every loop gets broken at its weakest link: the connection
+ * between the two lightest grids in it, by bricks. all joints on that link get removed
+ */
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque, hash_map::Entry};
+use brdb::{BrReader, Brdb, ChunkIndex};
+use serde::Serialize;
+
+use crate::grids::Grids;
+
+#[derive(Serialize)]
+pub struct JointLoop {
+    // every grid (or other entity) in the loop, in order
+    pub entities: Vec<usize>,
+    // the pair of entities whose joints break the loop
+    pub weakest: (usize, usize),
+}
+
+// the joints connecting one pair of entities, as (grid, chunk, index in the chunk's joint arrays)
+type Link = Vec<(usize, ChunkIndex, usize)>;
+
+pub struct Loops {
+    pub loops: Vec<JointLoop>,
+    // the joints that have to go to break every loop, by (grid, chunk)
+    pub breaks: HashMap<(usize, ChunkIndex), BTreeSet<usize>>,
+}
+
+pub fn loops(db: &BrReader<Brdb>, grids: &Grids) -> Result<Loops, Box<dyn std::error::Error>> {
+    let mut bricks: HashMap<usize, usize> = HashMap::new();
+    // by pair of entities, smallest first. sorted, so loops always come out the same
+    let mut links: BTreeMap<(usize, usize), Link> = BTreeMap::new();
+    for grid in grids.all() {
+        for chunk in db.brick_chunk_index(grid)? {
+            *bricks.entry(grid).or_default() += chunk.num_bricks as usize;
+            if chunk.num_components == 0 {
+                continue;
+            }
+            // unreadable chunks get reported by the component pass
+            let Ok((soa, _)) = db.component_chunk(grid, *chunk) else {
+                continue;
+            };
+            for (joint, entity) in soa.joint_entity_references.iter().enumerate() {
+                let entity = *entity as usize;
+                if entity == grid {
+                    continue;
+                }
+                links.entry((grid.min(entity), grid.max(entity))).or_default().push((grid, *chunk, joint));
+            }
+        }
+    }
+
+    /*
+     * every link that connects two entities that are already connected some other way
+     * closes a loop, made up of that link and the way they were already connected.
+     * the weakest link of the loop gets dropped from what counts as connected,
+     * so what's left never has a loop in it and every loop gets broken exactly once
+     */
+    let mut neighbours: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut found = Loops { loops: vec![], breaks: HashMap::new() };
+    let weight = |(a, b): (usize, usize)| bricks.get(&a).copied().unwrap_or(0) + bricks.get(&b).copied().unwrap_or(0);
+    for (pair, link) in &links {
+        let (a, b) = *pair;
+        if let Some(path) = path(&neighbours, a, b) {
+            let mut weakest = *pair;
+            for step in path.windows(2) {
+                let step = (step[0].min(step[1]), step[0].max(step[1]));
+                if weight(step) < weight(weakest) {
+                    weakest = step;
+                }
+            }
+            for (grid, chunk, joint) in if weakest == *pair { link } else { &links[&weakest] } {
+                found.breaks.entry((*grid, *chunk)).or_default().insert(*joint);
+            }
+            found.loops.push(JointLoop { entities: path, weakest });
+            if weakest == *pair {
+                continue;
+            }
+            let (x, y) = weakest;
+            neighbours.get_mut(&x).unwrap().retain(|n| *n != y);
+            neighbours.get_mut(&y).unwrap().retain(|n| *n != x);
+        }
+        neighbours.entry(a).or_default().push(b);
+        neighbours.entry(b).or_default().push(a);
+    }
+
+    Ok(found)
+}
+
+// the entities on the way from `from` to `to` over the links seen so far, if there is one
+fn path(neighbours: &HashMap<usize, Vec<usize>>, from: usize, to: usize) -> Option<Vec<usize>> {
+    let mut came_from = HashMap::from([(from, from)]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(entity) = queue.pop_front() {
+        if entity == to {
+            let mut path = vec![to];
+            let mut at = to;
+            while at != from {
+                at = came_from[&at];
+                path.push(at);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for next in neighbours.get(&entity).into_iter().flatten() {
+            if let Entry::Vacant(e) = came_from.entry(*next) {
+                e.insert(entity);
+                queue.push_back(*next);
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,59 @@
+/*
+ * brdb_optimize as a library, for server plugins that use the brdb crate themselves.
+ * the way in is optimize::optimize_pending, which returns the patch that optimizes a world
+ * without writing anything. everything else is what the command line tool is built from
+ */
+
+pub mod analyze;
+pub mod archive;
+pub mod blobs;
+pub mod bricks;
+pub mod cache;
+pub mod cli;
+pub mod coalesce;
+pub mod compat;
+pub mod compare;
+pub mod components;
+pub mod config;
+pub mod cron;
+pub mod daemon;
+pub mod entities;
+pub mod environment;
+pub mod export;
+pub mod extract;
+pub mod grids;
+pub mod heatmap;
+pub mod hidden;
+pub mod http;
+pub mod insert;
+pub mod joints;
+pub mod lights;
+pub mod lock;
+pub mod logic;
+pub mod manifest;
+pub mod merge;
+pub mod metrics;
+pub mod names;
+pub mod notify;
+pub mod obj;
+pub mod optimize;
+pub mod owners;
+pub mod patch;
+pub mod physics;
+pub mod pipe;
+pub mod props;
+pub mod quarantine;
+pub mod remote;
+pub mod report;
+pub mod revisions;
+pub mod rules;
+pub mod rulesets;
+pub mod serve;
+pub mod split;
+pub mod trim;
+pub mod undo;
+pub mod vacuum;
+pub mod verify;
+pub mod welds;
+pub mod wires;
+pub mod worlds;
@@ -0,0 +1,221 @@
+/*
+ * light passes that look at a whole chunk at once,
+ * instead of at one component at a time like the rules in rules.rs
+ */
+
+use std::collections::HashMap;
+use brdb::{
+    AsBrdbValue, BrReader, Brdb, BrdbComponent, BrickChunkSoA, ChunkIndex, ComponentChunkSoA,
+    schema::{BrdbStruct, BrdbValue},
+};
+
+use crate::{components, config::{LightConfig, OwnerQuota}, owners, props::Props};
+
+/*
+ * people love stacking point lights on top of each other to make them brighter.
+ * this finds point lights within `dedupe_distance` of each other and merges every cluster
+ * into its first light, which gets their combined brightness (clamped to the max).
+ * returns how many lights were collapsed
+ *
+ * NOTE: only lights within the same chunk are compared
+ */
+pub fn dedupe(
+    db: &BrReader<Brdb>,
+    grid: usize,
+    chunk: ChunkIndex,
+    soa: &mut ComponentChunkSoA,
+    components: &mut Vec<BrdbStruct>,
+    limits: &LightConfig,
+    props: &mut Props,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let Some(distance) = limits.dedupe_distance else {
+        return Ok(0);
+    };
+
+    let lights: Vec<usize> = (0..components.len())
+        .filter(|i| components[*i].get_name() == "BrickComponentData_PointLight")
+        .collect();
+    if lights.len() < 2 {
+        return Ok(0);
+    }
+
+    let bricks = db.brick_chunk_soa(grid, chunk)?;
+    let position = |i: usize| position(&bricks, soa, i);
+
+    // (light index, position, combined brightness) of the lights we keep
+    let mut keepers: Vec<(usize, (f32, f32, f32), f32)> = vec![];
+    let mut removed = vec![];
+    for i in lights {
+        let Some(pos) = position(i) else {
+            continue;
+        };
+        let Some(brightness) = props.get(
+            "BrickComponentData_PointLight",
+            "Brightness",
+            components[i].prop("Brightness").and_then(|v| v.as_brdb_f32()),
+        ) else {
+            continue;
+        };
+
+        let near = keepers.iter_mut().find(|(_, other, _)| dist(pos, *other) <= distance);
+        match near {
+            Some(keeper) => {
+                keeper.2 += brightness;
+                removed.push(i);
+            }
+            None => keepers.push((i, pos, brightness)),
+        }
+    }
+
+    if removed.is_empty() {
+        return Ok(0);
+    }
+
+    // give every kept light the brightness of its whole cluster
+    for (i, _, brightness) in keepers {
+        components[i].set_prop("Brightness", BrdbValue::F32(brightness.min(limits.max_brightness)));
+    }
+    components::remove(soa, components, &removed);
+
+    println!("[grid:{grid}][{chunk}] light: collapsed {} stacked point lights", removed.len());
+    Ok(removed.len())
+}
+
+/*
+ * chunks with hundreds of lights are a lag source even when every light is within limits.
+ * if a chunk has more than `max_per_chunk` lights turned on, this keeps the brightest ones
+ * (the ones closest to the middle of the chunk's lights, when equally bright)
+ * and turns the rest off by setting their brightness to 0.
+ * returns how many lights were turned off
+ */
+pub fn budget(
+    db: &BrReader<Brdb>,
+    grid: usize,
+    chunk: ChunkIndex,
+    soa: &ComponentChunkSoA,
+    components: &mut [BrdbStruct],
+    limits: &LightConfig,
+    props: &mut Props,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let Some(max) = limits.max_per_chunk else {
+        return Ok(0);
+    };
+
+    // (light index, brightness) of every light that's on
+    let mut lights = vec![];
+    for (i, component) in components.iter().enumerate() {
+        let name = component.get_name();
+        if name != "BrickComponentData_PointLight" && name != "BrickComponentData_SpotLight" {
+            continue;
+        }
+        let brightness = props.get(name, "Brightness", component.prop("Brightness").and_then(|v| v.as_brdb_f32()));
+        if let Some(brightness) = brightness
+            && brightness > 0.0
+        {
+            lights.push((i, brightness));
+        }
+    }
+    if lights.len() <= max {
+        return Ok(0);
+    }
+
+    // the middle of all lights in the chunk
+    let bricks = db.brick_chunk_soa(grid, chunk)?;
+    let positions: Vec<_> = lights.iter().filter_map(|(i, _)| position(&bricks, soa, *i)).collect();
+    let n = positions.len().max(1) as f32;
+    let center = positions.iter().fold((0.0, 0.0, 0.0), |c, p| (c.0 + p.0 / n, c.1 + p.1 / n, c.2 + p.2 / n));
+    let centrality = |i: usize| position(&bricks, soa, i).map_or(f32::MAX, |p| dist(p, center));
+
+    // brightest first, then most central
+    lights.sort_by(|a, b| b.1.total_cmp(&a.1).then(centrality(a.0).total_cmp(&centrality(b.0))));
+
+    let disabled = lights.len() - max;
+    for (i, _) in &lights[max..] {
+        components[*i].set_prop("Brightness", BrdbValue::F32(0.0));
+    }
+
+    println!("[grid:{grid}][{chunk}] light: {} lights exceed the budget of {max}, turned off {disabled}", lights.len());
+    Ok(disabled)
+}
+
+/*
+ * a fair share of lights for everyone: everyone's first `max_per_owner` lights that are on
+ * (in the order the world stores them) stay as they are, the rest get turned off,
+ * or only lose their shadows (see OwnerQuota).
+ * `counts` is how many lights everyone had in the chunks before this one.
+ * lights on public bricks (owner 0) don't belong to anyone, so they don't count.
+ * returns how many lights were turned off or lost their shadows
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn owner_quota(
+    db: &BrReader<Brdb>,
+    grid: usize,
+    chunk: ChunkIndex,
+    soa: &ComponentChunkSoA,
+    components: &mut [BrdbStruct],
+    limits: &LightConfig,
+    counts: &mut HashMap<u32, usize>,
+    props: &mut Props,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let Some(max) = limits.max_per_owner else {
+        return Ok(0);
+    };
+    if !components.iter().any(|c| matches!(c.get_name(), "BrickComponentData_PointLight" | "BrickComponentData_SpotLight")) {
+        return Ok(0);
+    }
+
+    let component_owners = owners::of_components(db, grid, chunk, soa)?;
+    let mut over = 0;
+    for (i, component) in components.iter_mut().enumerate() {
+        let name = component.get_name().to_string();
+        if name != "BrickComponentData_PointLight" && name != "BrickComponentData_SpotLight" {
+            continue;
+        }
+        let owner = component_owners.get(i).copied().unwrap_or(0);
+        if owner == 0 {
+            continue;
+        }
+        let brightness = props.get(&name, "Brightness", component.prop("Brightness").and_then(|v| v.as_brdb_f32()));
+        if brightness.is_none_or(|brightness| brightness <= 0.0) {
+            continue;
+        }
+
+        let count = counts.entry(owner).or_default();
+        *count += 1;
+        if *count <= max {
+            continue;
+        }
+        match limits.over_owner_quota {
+            OwnerQuota::Disable => {
+                component.set_prop("Brightness", BrdbValue::F32(0.0));
+                over += 1;
+            }
+            OwnerQuota::Shadows => {
+                if props.get(&name, "bCastShadows", component.prop("bCastShadows").and_then(|v| v.as_brdb_bool())) == Some(true) {
+                    component.set_prop("bCastShadows", BrdbValue::Bool(false));
+                    over += 1;
+                }
+            }
+        }
+    }
+
+    if over > 0 {
+        let action = match limits.over_owner_quota {
+            OwnerQuota::Disable => "turned off",
+            OwnerQuota::Shadows => "disabled shadows of",
+        };
+        println!("[grid:{grid}][{chunk}] light: {action} {over} lights of owners over the quota of {max}");
+    }
+    Ok(over)
+}
+
+// lights don't have a position of their own, they're where their brick is
+fn position(bricks: &BrickChunkSoA, soa: &ComponentChunkSoA, component: usize) -> Option<(f32, f32, f32)> {
+    let brick = *soa.component_brick_indices.get(component)? as usize;
+    bricks.relative_positions.get(brick).map(|p| (p.x as f32, p.y as f32, p.z as f32))
+}
+
+fn dist(a: (f32, f32, f32), b: (f32, f32, f32)) -> f32 {
+    let (dx, dy, dz) = (a.0 - b.0, a.1 - b.1, a.2 - b.2);
+    (dx * dx + dy * dy + dz * dz).sqrt()
+}
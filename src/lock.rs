@@ -0,0 +1,91 @@
+/*
+ * advisory lock so two instances never process the same world at once
+ *
+ * while a world is being processed, a world.brdb.lock file sits next to it
+ * containing the process id of the instance holding it.
+ * it's removed again when done; if an instance crashed (or exited early)
+ * and left it behind, the next run notices the process is gone and takes over.
+ * where we can't check whether a process is still around (anything but linux),
+ * a lock older than STALE_AFTER is taken over instead
+ */
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
+
+// how old a lock has to be before it counts as stale, when we can't tell if its process is still running
+const STALE_AFTER: Duration = Duration::from_secs(12 * 60 * 60);
+
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    // returns None (after saying why) if another instance is already working on this world
+    pub fn acquire(world: &Path) -> Result<Option<Lock>, Box<dyn std::error::Error>> {
+        let name = world.file_name().unwrap().to_string_lossy();
+        let path = world.with_file_name(format!("{name}.lock"));
+
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    write!(file, "{}", process::id())?;
+                    return Ok(Some(Lock { path }));
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let owner = fs::read_to_string(&path).ok().and_then(|pid| pid.trim().parse::<u32>().ok());
+
+                    if let Some(pid) = owner
+                        && is_running(pid) == Some(false)
+                    {
+                        println!("removing stale lock {:?} (process {pid} is gone)", path);
+                        fs::remove_file(&path)?;
+                        continue;
+                    }
+
+                    let age = fs::metadata(&path).and_then(|m| m.modified()).ok().and_then(|t| t.elapsed().ok());
+                    let unknown = owner.is_none_or(|pid| is_running(pid).is_none());
+                    if unknown && age.is_some_and(|age| age > STALE_AFTER) {
+                        println!("removing stale lock {:?} (it's more than {} hours old)", path, STALE_AFTER.as_secs() / 3600);
+                        fs::remove_file(&path)?;
+                        continue;
+                    }
+
+                    println!(
+                        "{:?} is already being processed by another instance (process {}).",
+                        world,
+                        owner.map_or("unknown".to_string(), |pid| pid.to_string())
+                    );
+                    println!("if you're sure nothing else is running, delete {:?} and try again.", path);
+                    if unknown {
+                        println!("otherwise it gets taken over once it's {} hours old.", STALE_AFTER.as_secs() / 3600);
+                    }
+                    return Ok(None);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// whether the process is still running, None if there's no way to tell
+#[cfg(target_os = "linux")]
+fn is_running(pid: u32) -> Option<bool> {
+    Some(Path::new("/proc").join(pid.to_string()).exists())
+}
+
+// no cheap way to check elsewhere, the age of the lock decides instead
+#[cfg(not(target_os = "linux"))]
+fn is_running(_pid: u32) -> Option<bool> {
+    None
+}
@@ -0,0 +1,230 @@
+/*
+ * measuring how complex the logic (wired together bricks) in a world is
+ *
+ * every brick with a wire going into or out of it is a node, every wire an edge from
+ * the brick it starts at to the brick it ends at. bricks connected by wires, no matter
+ * the direction, form a network. for every network we count:
+ * - its bricks and wires
+ * - the longest chain: the most bricks a signal can pass through one after another.
+ *   a loop counts as a single brick, otherwise the chain would be endless
+ * - its loops: groups of bricks that feed back into each other (clocks, latches, ..)
+ * networks going over the limits in [logic] get flagged, with the chunks they're in
+ */
+
+use std::collections::{BTreeSet, HashMap};
+use brdb::{ChunkIndex, WireChunkSoA};
+use serde::Serialize;
+
+use crate::config::LogicConfig;
+
+#[derive(Default, Serialize)]
+pub struct LogicStats {
+    pub bricks: usize,
+    pub wires: usize,
+    pub networks: usize,
+    pub longest_chain: usize,
+    pub loops: usize,
+    // the networks going over the limits in [logic], most bricks first
+    pub flagged: Vec<Network>,
+}
+
+#[derive(Serialize)]
+pub struct Network {
+    pub bricks: usize,
+    pub wires: usize,
+    pub longest_chain: usize,
+    pub loops: usize,
+    // (grid, chunk) of every chunk the network has bricks in
+    pub chunks: BTreeSet<(usize, String)>,
+}
+
+// a brick: (grid, chunk, index of the brick in the chunk)
+type Node = (usize, ChunkIndex, u32);
+
+#[derive(Default)]
+pub struct Graph {
+    ids: HashMap<Node, usize>,
+    nodes: Vec<Node>,
+    // outgoing wires of every node
+    edges: Vec<Vec<usize>>,
+    num_wires: usize,
+}
+
+impl Graph {
+    // adds every wire ending in `chunk` of `grid`
+    pub fn add_chunk(&mut self, grid: usize, chunk: ChunkIndex, soa: &WireChunkSoA) {
+        for (source, target) in soa.local_wire_sources.iter().zip(&soa.local_wire_targets) {
+            self.add_wire((grid, chunk, source.brick_index_in_chunk), (grid, chunk, target.brick_index_in_chunk));
+        }
+        for (source, target) in soa.remote_wire_sources.iter().zip(&soa.remote_wire_targets) {
+            self.add_wire(
+                (source.grid_persistent_index as usize, source.chunk_index, source.brick_index_in_chunk),
+                (grid, chunk, target.brick_index_in_chunk),
+            );
+        }
+    }
+
+    fn add_wire(&mut self, source: Node, target: Node) {
+        let source = self.id(source);
+        let target = self.id(target);
+        self.edges[source].push(target);
+        self.num_wires += 1;
+    }
+
+    fn id(&mut self, node: Node) -> usize {
+        *self.ids.entry(node).or_insert_with(|| {
+            self.nodes.push(node);
+            self.edges.push(vec![]);
+            self.nodes.len() - 1
+        })
+    }
+
+    pub fn stats(&self, limits: &LogicConfig) -> LogicStats {
+        let num_nodes = self.nodes.len();
+
+        // networks, by following wires both ways
+        let mut network_of: Vec<usize> = (0..num_nodes).collect();
+        for (from, targets) in self.edges.iter().enumerate() {
+            for to in targets {
+                let (a, b) = (root(&mut network_of, from), root(&mut network_of, *to));
+                network_of[a] = b;
+            }
+        }
+
+        let mut networks: HashMap<usize, Network> = HashMap::new();
+        for node in 0..num_nodes {
+            let (grid, chunk, _) = self.nodes[node];
+            let network = networks.entry(root(&mut network_of, node)).or_insert_with(|| Network {
+                bricks: 0,
+                wires: 0,
+                longest_chain: 0,
+                loops: 0,
+                chunks: BTreeSet::new(),
+            });
+            network.bricks += 1;
+            network.wires += self.edges[node].len();
+            network.chunks.insert((grid, chunk.to_string()));
+        }
+
+        /*
+         * loops and chains. the groups come out with everything they lead to before them,
+         * so the chain starting at a group is one longer than the longest chain it leads to
+         */
+        let groups = strongly_connected(&self.edges);
+        let mut group_of = vec![0; num_nodes];
+        for (i, group) in groups.iter().enumerate() {
+            for node in group {
+                group_of[*node] = i;
+            }
+        }
+        let mut chain = vec![0; groups.len()];
+        for (i, group) in groups.iter().enumerate() {
+            let mut longest = 0;
+            let mut looped = group.len() > 1;
+            for node in group {
+                for to in &self.edges[*node] {
+                    if group_of[*to] == i {
+                        looped = true;
+                    } else {
+                        longest = longest.max(chain[group_of[*to]]);
+                    }
+                }
+            }
+            chain[i] = longest + 1;
+
+            let network = networks.get_mut(&root(&mut network_of, group[0])).unwrap();
+            network.longest_chain = network.longest_chain.max(chain[i]);
+            network.loops += looped as usize;
+        }
+
+        let mut stats = LogicStats {
+            bricks: num_nodes,
+            wires: self.num_wires,
+            networks: networks.len(),
+            longest_chain: chain.iter().copied().max().unwrap_or(0),
+            loops: networks.values().map(|n| n.loops).sum(),
+            flagged: vec![],
+        };
+        stats.flagged = networks.into_values()
+            .filter(|n| {
+                n.bricks > limits.max_bricks
+                    || n.wires > limits.max_wires
+                    || n.longest_chain > limits.max_chain
+                    || n.loops > limits.max_loops
+            })
+            .collect();
+        stats.flagged.sort_by_key(|n| std::cmp::Reverse(n.bricks));
+        stats
+    }
+}
+
+// the node that stands for the whole network `node` is in
+fn root(network_of: &mut [usize], mut node: usize) -> usize {
+    while network_of[node] != node {
+        network_of[node] = network_of[network_of[node]];
+        node = network_of[node];
+    }
+    node
+}
+
+/*
+ * groups of nodes that can all reach each other (tarjan's algorithm, without recursion
+ * since logic can get deep). a group comes after every group it has wires into
+ */
+fn strongly_connected(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let unvisited = usize::MAX;
+    let mut index = vec![unvisited; edges.len()];
+    let mut low = vec![0; edges.len()];
+    let mut on_stack = vec![false; edges.len()];
+    let mut stack = vec![];
+    let mut groups = vec![];
+    let mut next = 0;
+
+    for start in 0..edges.len() {
+        if index[start] != unvisited {
+            continue;
+        }
+        index[start] = next;
+        low[start] = next;
+        next += 1;
+        stack.push(start);
+        on_stack[start] = true;
+        // (node, the next of its wires to follow)
+        let mut work = vec![(start, 0)];
+
+        while let Some(&(node, edge)) = work.last() {
+            if let Some(&to) = edges[node].get(edge) {
+                work.last_mut().unwrap().1 += 1;
+                if index[to] == unvisited {
+                    index[to] = next;
+                    low[to] = next;
+                    next += 1;
+                    stack.push(to);
+                    on_stack[to] = true;
+                    work.push((to, 0));
+                } else if on_stack[to] {
+                    low[node] = low[node].min(index[to]);
+                }
+                continue;
+            }
+
+            work.pop();
+            if let Some(&(parent, _)) = work.last() {
+                low[parent] = low[parent].min(low[node]);
+            }
+            if low[node] == index[node] {
+                let mut group = vec![];
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    group.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                groups.push(group);
+            }
+        }
+    }
+
+    groups
+}
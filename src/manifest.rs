@@ -0,0 +1,82 @@
+/*
+ * checksums of what a run wrote, for backup tooling
+ *
+ * with --manifest (or `manifest` under [files]) a <world>.optimized.manifest.json is written
+ * next to the optimized world, with the sha-256 of the original world, the optimized world
+ * and the archive (if there is one), so copies of them can be checked after moving them around.
+ * with `manifest_blobs` it also gets the sha-256 of every blob in the optimized world,
+ * the stored contents of every file in every revision, to pin down what got damaged
+ */
+
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+use chrono::Local;
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+#[derive(Serialize)]
+struct Manifest {
+    created: String,
+    files: Vec<FileSum>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blobs: Option<Vec<BlobSum>>,
+}
+
+#[derive(Serialize)]
+struct FileSum {
+    name: String,
+    size: u64,
+    sha256: String,
+}
+
+#[derive(Serialize)]
+struct BlobSum {
+    blob_id: i64,
+    size: usize,
+    sha256: String,
+}
+
+/*
+ * writes the manifest of `files` to `path`. `blobs_of` is the world whose blobs get listed too, if any.
+ * returns where it was written
+ */
+pub fn write(path: PathBuf, files: &[&Path], blobs_of: Option<&Path>) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let mut manifest = Manifest {
+        created: Local::now().to_rfc3339(),
+        files: vec![],
+        blobs: None,
+    };
+
+    for file in files {
+        let mut hasher = Sha256::new();
+        io::copy(&mut File::open(file)?, &mut hasher)?;
+        manifest.files.push(FileSum {
+            name: file.file_name().unwrap().to_string_lossy().to_string(),
+            size: fs::metadata(file)?.len(),
+            sha256: format!("{:x}", hasher.finalize()),
+        });
+    }
+
+    if let Some(world) = blobs_of {
+        let conn = Connection::open_with_flags(world, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let mut stmt = conn.prepare("SELECT blob_id, content FROM blobs ORDER BY blob_id")?;
+        let mut rows = stmt.query([])?;
+        let mut blobs = vec![];
+        while let Some(row) = rows.next()? {
+            let content: Vec<u8> = row.get(1)?;
+            blobs.push(BlobSum {
+                blob_id: row.get(0)?,
+                size: content.len(),
+                sha256: format!("{:x}", Sha256::digest(&content)),
+            });
+        }
+        manifest.blobs = Some(blobs);
+    }
+
+    fs::write(&path, serde_json::to_string_pretty(&manifest)?)?;
+    Ok(path)
+}
@@ -0,0 +1,627 @@
+/*
+ * merging several worlds into one
+ *
+ * `brdb_optimize merge a.brdb b.brdb [c.brdb ..] -o merged.brdb [--offset x,y,z ..]`
+ * adds everything in b (and c, ..) to a copy of a: the bricks, components and wires of the main grid
+ * go into a's main grid, vehicles and every other entity come along with new ids, and so does everyone
+ * in the owner table who isn't in a's already. the first --offset moves b, the second c, and so on.
+ *
+ * offsets get rounded to whole chunks (2048 units), so every chunk lands in one piece
+ * and its components and wires still point at the right bricks. where a chunk lands on a chunk
+ * that's already there, its bricks get added after the ones in it.
+ *
+ * every world has its own name tables (GlobalData.mps, see names.rs), the names of the other worlds
+ * get added to a's and everything is pointed at them. the schema is a's though, so components and entities
+ * of types a doesn't know (like from a newer version of the game) are left out, with a warning.
+ * only World/0 of every file gets merged
+ */
+
+use std::{collections::{BTreeMap, HashMap, HashSet}, path::{Path, PathBuf}, process, sync::Arc};
+use brdb::{
+    BrReader, Brdb, BrdbComponent, BrdbWorldGlobalData, BrickChunkIndexSoA, BrickChunkSoA, ChunkIndex, Entity,
+    EntityChunkSoA, IntoReader, LocalWirePortSource, OwnerTableSoA, RemoteWirePortSource, WireChunkSoA, WirePortTarget,
+    pending::BrPendingFs, schema::BrdbSchema,
+};
+
+use crate::{
+    analyze,
+    bricks::{self, IndexChanges},
+    components::{self, Part},
+    grids,
+    lock::Lock,
+    names::GLOBAL_DATA_FILE,
+    owners::OWNERS_FILE,
+    patch::{self, Entry},
+    trim,
+    welds::CHUNK_SIZE,
+};
+
+// reads an offset (or a location, see insert.rs) like x,y,z
+pub fn parse_offset(text: &str) -> Option<[f32; 3]> {
+    let numbers: Vec<f32> = text.split(',').map(|n| n.trim().parse().ok()).collect::<Option<_>>()?;
+    numbers.try_into().ok()
+}
+
+// what got added to the first world
+#[derive(Default)]
+struct Merged {
+    bricks: usize,
+    components: usize,
+    wires: usize,
+    entities: usize,
+    grids: usize,
+    owners: usize,
+    // left out, because the first world doesn't know their type
+    dropped_components: usize,
+    dropped_entities: usize,
+}
+
+// a chunk of the merged world, with everything that's in it
+struct Chunk {
+    bricks: BrickChunkSoA,
+    components: Vec<Part>,
+    wires: WireChunkSoA,
+    // whether the first world already had this chunk
+    existing: bool,
+}
+
+// where every index of one of the other worlds points in the merged world
+struct Maps {
+    basic: Vec<u32>,
+    procedural: Vec<u32>,
+    materials: Vec<u32>,
+    component_types: Vec<u32>,
+    ports: Vec<u32>,
+    // by owner index as bricks store them, 0 (public) stays 0
+    owners: Vec<u32>,
+}
+
+impl Maps {
+    fn owner(&self, owner: u32) -> u32 {
+        self.owners.get(owner as usize).copied().unwrap_or(0)
+    }
+
+    // a component type and port, for wires
+    fn port(&self, component_type: u16, port: u16) -> (u16, u16) {
+        (
+            get(&self.component_types, component_type as u32) as u16,
+            get(&self.ports, port as u32) as u16,
+        )
+    }
+
+    // points a chunk's bricks at the merged name and owner tables, with procedural bricks starting at `start`
+    fn remap(&self, bricks: &mut BrickChunkSoA, start: u32) -> Result<(), Box<dyn std::error::Error>> {
+        let old_start = bricks.procedural_brick_starting_index;
+        for type_index in bricks.brick_type_indices.iter_mut() {
+            *type_index = if *type_index < old_start {
+                get(&self.basic, *type_index)
+            } else {
+                *type_index - old_start + start
+            };
+        }
+        bricks.procedural_brick_starting_index = start;
+
+        for counter in bricks.brick_size_counters.iter_mut() {
+            counter.asset_index = get(&self.procedural, counter.asset_index);
+        }
+        for material in bricks.material_indices.iter_mut() {
+            *material = u8::try_from(get(&self.materials, *material as u32))
+                .map_err(|_| "the merged world would have more than 256 materials")?;
+        }
+        for owner in bricks.owner_indices.iter_mut() {
+            *owner = self.owner(*owner);
+        }
+        Ok(())
+    }
+}
+
+fn get(map: &[u32], old: u32) -> u32 {
+    map.get(old as usize).copied().unwrap_or(old)
+}
+
+// where the names of `other` are in `names`, adding the ones that aren't there yet
+fn extend(names: &mut Vec<String>, other: &[String]) -> Vec<u32> {
+    other.iter()
+        .map(|name| match names.iter().position(|n| n == name) {
+            Some(i) => i as u32,
+            None => {
+                names.push(name.clone());
+                names.len() as u32 - 1
+            }
+        })
+        .collect()
+}
+
+// moves where a chunk's procedural bricks start, moving the procedural bricks along with it
+fn set_start(bricks: &mut BrickChunkSoA, start: u32) {
+    let old_start = bricks.procedural_brick_starting_index;
+    for type_index in bricks.brick_type_indices.iter_mut() {
+        if *type_index >= old_start {
+            *type_index = *type_index - old_start + start;
+        }
+    }
+    bricks.procedural_brick_starting_index = start;
+}
+
+// the merged world while it's being put together, see insert.rs for the other user
+pub struct Merge<'a> {
+    db: &'a BrReader<Brdb>,
+    // a's main grid, where the main grids of the other worlds go
+    root: usize,
+    // chunks of a's main grid, with how many components and wires they have
+    root_chunks: HashMap<ChunkIndex, (u32, u32)>,
+    entity_chunks: HashSet<ChunkIndex>,
+    component_schema: Arc<BrdbSchema>,
+    entity_schema: Arc<BrdbSchema>,
+    global: BrdbWorldGlobalData,
+    owners: OwnerTableSoA,
+    next_id: usize,
+    chunks: BTreeMap<(usize, ChunkIndex), Chunk>,
+    entities: BTreeMap<ChunkIndex, Vec<(Entity, u32)>>,
+    // grids that are new in the merged world
+    new_grids: Vec<usize>,
+    merged: Merged,
+}
+
+pub fn run(worlds: &[PathBuf], dst: &Path, offsets: &[[f32; 3]]) -> Result<(), Box<dyn std::error::Error>> {
+    for world in worlds {
+        if !world.exists() {
+            println!("world file {:?} does not exist", world);
+            process::exit(1);
+        }
+        if world == dst {
+            println!("the merged world can't be written over {:?}, that's one of the worlds to merge", world);
+            process::exit(1);
+        }
+    }
+    if offsets.len() >= worlds.len() {
+        println!("got {} offsets for {} worlds, every world after the first gets one", offsets.len(), worlds.len());
+        process::exit(1);
+    }
+
+    let mut locks = vec![];
+    for world in worlds {
+        let Some(lock) = Lock::acquire(world)? else {
+            // exiting skips destructors, so the locks we already have go first
+            drop(locks);
+            process::exit(1);
+        };
+        locks.push(lock);
+    }
+
+    let src = &worlds[0];
+    println!("Reading file {:?}", src);
+    let db = Brdb::open(src)?.into_reader();
+    let source = db.to_pending()?;
+    let mut merge = Merge::new(&db, &source)?;
+
+    for (i, world) in worlds.iter().enumerate().skip(1) {
+        let offset = offsets.get(i - 1).copied().unwrap_or_default();
+        let shift = offset.map(|o| (o / CHUNK_SIZE as f32).round() as i32);
+        if shift.iter().zip(offset).any(|(s, o)| (s * CHUNK_SIZE) as f32 != o) {
+            println!("rounding the offset of {:?} to whole chunks: {:?}", world, shift.map(|s| s * CHUNK_SIZE));
+        }
+        println!("---SEP---");
+        merge.world(world, shift.map(|s| (s * CHUNK_SIZE) as f32), Some(shift))?;
+    }
+
+    println!("---SEP---");
+    merge.print_summary();
+
+    println!("writing to world file..");
+    let description = format!("Merge Worlds ({})", worlds.len());
+    trim::write(src, dst, &description, merge.patch()?)?;
+    println!("merged world written to {:?}", dst);
+
+    Ok(())
+}
+
+impl<'a> Merge<'a> {
+    // starts merging into the world of `db`, `source` is its whole file
+    pub fn new(db: &'a BrReader<Brdb>, source: &BrPendingFs) -> Result<Self, Box<dyn std::error::Error>> {
+        let grids = grids::find(db, source, "0")?;
+
+        // new entities (and grids) get ids after everything that's already there
+        let mut last_id = grids.all().into_iter().max().unwrap_or(0);
+        let mut entity_chunks = HashSet::new();
+        for chunk in db.entity_chunk_index()? {
+            entity_chunks.insert(chunk);
+            for entity in db.entity_chunk(chunk)? {
+                last_id = last_id.max(entity.id.unwrap_or(0));
+            }
+        }
+        if let Some(grid_folders) = patch::folder(source, &["World", "0", "Bricks", "Grids"]) {
+            last_id = grid_folders.iter().filter_map(|(name, _)| name.parse().ok()).fold(last_id, usize::max);
+        }
+
+        let root = grids.root[0];
+        Ok(Self {
+            db,
+            root,
+            root_chunks: db.brick_chunk_index(root)?.iter().map(|c| (**c, (c.num_components, c.num_wires))).collect(),
+            entity_chunks,
+            component_schema: db.components_schema()?,
+            entity_schema: db.entities_schema()?,
+            global: (*db.global_data()?).clone(),
+            owners: db.owners_soa()?,
+            next_id: last_id + 1,
+            chunks: BTreeMap::new(),
+            entities: BTreeMap::new(),
+            new_grids: vec![],
+            merged: Merged::default(),
+        })
+    }
+
+    pub fn print_summary(&self) {
+        let merged = &self.merged;
+        println!(
+            "adding {} bricks, {} components, {} wires, {} entities ({} of them vehicles) and {} owners.",
+            merged.bricks, merged.components, merged.wires, merged.entities, merged.grids, merged.owners
+        );
+        if merged.dropped_components + merged.dropped_entities > 0 {
+            println!(
+                "[WARNING] left out {} components and {} entities of types the world merged into doesn't know.",
+                merged.dropped_components, merged.dropped_entities
+            );
+        }
+    }
+
+    /*
+     * adds the world in `path` to the merged world, with its entities moved by `offset`.
+     * its main grid goes into the main grid moved by `shift` chunks, which should add up to `offset`,
+     * or is left out with None
+     */
+    pub fn world(&mut self, path: &Path, offset: [f32; 3], shift: Option<[i32; 3]>) -> Result<(), Box<dyn std::error::Error>> {
+        println!("Reading file {:?}", path);
+        let other = Brdb::open(path)?.into_reader();
+        let source = other.to_pending()?;
+        let grids = grids::find(&other, &source, "0")?;
+        let maps = self.maps(&other)?;
+        let start = self.global.basic_brick_asset_names.len() as u32;
+
+        // entities go first, the grids of vehicles get their entity's new id
+        let mut ids = HashMap::new();
+        let mut kept = vec![];
+        for chunk in other.entity_chunk_index()? {
+            for mut entity in other.entity_chunk(chunk)? {
+                let Some(id) = entity.id else {
+                    continue;
+                };
+                if !known(&self.entity_schema, entity.data.as_ref()) {
+                    println!("[WARNING] entity {id} is a {}, which the world merged into doesn't know, leaving it out", entity.data.get_name());
+                    self.merged.dropped_entities += 1;
+                    continue;
+                }
+                ids.insert(id, self.next_id);
+                entity.id = Some(self.next_id);
+                entity.location.x += offset[0];
+                entity.location.y += offset[1];
+                entity.location.z += offset[2];
+                entity.owner_index = entity.owner_index.map(|owner| maps.owner(owner));
+                self.next_id += 1;
+                // entity chunks go by where the entities are, so a moved entity can end up in another one
+                kept.push((analyze::world_chunk(&entity.location), entity));
+            }
+        }
+
+        // the grid (and how many chunks to move it by) every grid goes into
+        let mut targets = vec![];
+        match shift {
+            Some(shift) => targets.extend(grids.root.iter().map(|grid| (*grid, self.root, shift))),
+            None if grids.root.iter().any(|g| other.brick_chunk_index(*g).is_ok_and(|c| c.iter().any(|c| c.num_bricks > 0))) => {
+                println!("leaving out the main grid of {:?}", path);
+            }
+            None => {}
+        }
+        for grid in &grids.dynamic {
+            match ids.get(grid) {
+                Some(id) => {
+                    targets.push((*grid, *id, [0; 3]));
+                    self.new_grids.push(*id);
+                    self.merged.grids += 1;
+                }
+                None => println!("[grid:{grid}] its entity was left out, so it goes too"),
+            }
+        }
+
+        // the bricks and components, remembering where every chunk went for the wires
+        let mut placed = HashMap::new();
+        let mut unknown_types = HashSet::new();
+        for (grid, target, shift) in &targets {
+            for chunk in other.brick_chunk_index(*grid)? {
+                if chunk.num_bricks == 0 {
+                    continue;
+                }
+                let mut bricks = other.brick_chunk_soa(*grid, *chunk)?;
+                maps.remap(&mut bricks, start)?;
+
+                let to_index = move_chunk(*chunk, *shift)?;
+                let to = self.chunk(*target, to_index)?;
+                set_start(&mut to.bricks, start);
+                let first = to.bricks.brick_type_indices.len() as u32;
+                for (i, position) in bricks.relative_positions.iter().enumerate() {
+                    bricks::push(&mut to.bricks, &bricks, i, *position);
+                }
+                placed.insert((*grid, *chunk), (*target, to_index, first));
+                self.merged.bricks += bricks.brick_type_indices.len();
+
+                if chunk.num_components == 0 {
+                    continue;
+                }
+                let (soa, components) = other.component_chunk(*grid, *chunk)?;
+                let parts = components::split(*grid, *chunk, soa, components)
+                    .ok_or_else(|| format!("[grid:{grid}][{}] of {:?} has joints that don't line up, can't merge it", *chunk, path))?;
+                let mut added = vec![];
+                for mut part in parts {
+                    // types the first world's schema doesn't have (like from a newer game version) can't be written into it
+                    if !known(&self.component_schema, &part.data) {
+                        unknown_types.insert(part.type_index);
+                        self.merged.dropped_components += 1;
+                        continue;
+                    }
+                    // joints to an entity that didn't come along would point at some entity of the first world
+                    if part.joint.as_ref().is_some_and(|joint| !ids.contains_key(&(joint.entity as usize))) {
+                        self.merged.dropped_components += 1;
+                        continue;
+                    }
+                    part.type_index = get(&maps.component_types, part.type_index);
+                    part.brick += first;
+                    if let Some(joint) = part.joint.as_mut() {
+                        joint.brick += first;
+                        joint.entity = ids[&(joint.entity as usize)] as u32;
+                    }
+                    added.push(part);
+                }
+                self.merged.components += added.len();
+                let to = self.chunk(*target, to_index)?;
+                to.components.extend(added);
+            }
+        }
+
+        // wires, now that every brick has its place. wires to left out components go too
+        for (grid, _, _) in &targets {
+            for chunk in other.brick_chunk_index(*grid)? {
+                if chunk.num_wires == 0 {
+                    continue;
+                }
+                let Some((target, to_index, first)) = placed.get(&(*grid, *chunk)).copied() else {
+                    continue;
+                };
+                let wires = other.wire_chunk_soa(*grid, *chunk)?;
+                let known_port = |component_type: u16| !unknown_types.contains(&(component_type as u32));
+                let wire_target = |t: &WirePortTarget| {
+                    let (component_type_index, port_index) = maps.port(t.component_type_index, t.port_index);
+                    WirePortTarget { brick_index_in_chunk: t.brick_index_in_chunk + first, component_type_index, port_index }
+                };
+
+                let mut added = WireChunkSoA::default();
+                for (source, target) in wires.local_wire_sources.iter().zip(&wires.local_wire_targets) {
+                    if !known_port(source.component_type_index) || !known_port(target.component_type_index) {
+                        continue;
+                    }
+                    let (component_type_index, port_index) = maps.port(source.component_type_index, source.port_index);
+                    added.local_wire_sources.push(LocalWirePortSource {
+                        brick_index_in_chunk: source.brick_index_in_chunk + first,
+                        component_type_index,
+                        port_index,
+                    });
+                    added.local_wire_targets.push(wire_target(target));
+                }
+                for (source, target) in wires.remote_wire_sources.iter().zip(&wires.remote_wire_targets) {
+                    let from = placed.get(&(source.grid_persistent_index as usize, source.chunk_index));
+                    let Some((source_grid, source_chunk, source_first)) = from.copied() else {
+                        continue;
+                    };
+                    if !known_port(source.component_type_index) || !known_port(target.component_type_index) {
+                        continue;
+                    }
+                    let (component_type_index, port_index) = maps.port(source.component_type_index, source.port_index);
+                    added.remote_wire_sources.push(RemoteWirePortSource {
+                        grid_persistent_index: source_grid as u32,
+                        chunk_index: source_chunk,
+                        brick_index_in_chunk: source.brick_index_in_chunk + source_first,
+                        component_type_index,
+                        port_index,
+                    });
+                    added.remote_wire_targets.push(wire_target(target));
+                }
+
+                self.merged.wires += added.local_wire_sources.len() + added.remote_wire_sources.len();
+                let to = self.chunk(target, to_index)?;
+                to.wires.local_wire_sources.extend(added.local_wire_sources);
+                to.wires.local_wire_targets.extend(added.local_wire_targets);
+                to.wires.remote_wire_sources.extend(added.remote_wire_sources);
+                to.wires.remote_wire_targets.extend(added.remote_wire_targets);
+            }
+        }
+
+        self.merged.entities += kept.len();
+        for (chunk, entity) in kept {
+            let id = entity.id.unwrap() as u32;
+            self.entity_chunk(chunk)?.push((entity, id));
+        }
+
+        Ok(())
+    }
+
+    // adds the names and owners of `other` to the merged tables, and works out where they went
+    fn maps(&mut self, other: &BrReader<Brdb>) -> Result<Maps, Box<dyn std::error::Error>> {
+        let global = other.global_data()?;
+        let names = &mut self.global;
+        extend(&mut names.entity_type_names, &global.entity_type_names);
+        extend(&mut names.component_data_struct_names, &global.component_data_struct_names);
+
+        let table = other.owners_soa()?;
+        let mut owners = vec![0];
+        for i in 0..table.user_ids.len() {
+            if let Some(j) = self.owners.user_ids.iter().position(|id| *id == table.user_ids[i]) {
+                // already in the table, so only their counts go up
+                for (to, from) in [
+                    (&mut self.owners.entity_counts, &table.entity_counts),
+                    (&mut self.owners.brick_counts, &table.brick_counts),
+                    (&mut self.owners.component_counts, &table.component_counts),
+                    (&mut self.owners.wire_counts, &table.wire_counts),
+                ] {
+                    if let (Some(to), Some(from)) = (to.get_mut(j), from.get(i)) {
+                        *to += from;
+                    }
+                }
+                owners.push(j as u32 + 1);
+                continue;
+            }
+
+            let owner = &mut self.owners;
+            owner.user_ids.push(table.user_ids[i]);
+            owner.user_names.push(table.user_names.get(i).cloned().unwrap_or_default());
+            owner.display_names.push(table.display_names.get(i).cloned().unwrap_or_default());
+            owner.entity_counts.push(table.entity_counts.get(i).copied().unwrap_or_default());
+            owner.brick_counts.push(table.brick_counts.get(i).copied().unwrap_or_default());
+            owner.component_counts.push(table.component_counts.get(i).copied().unwrap_or_default());
+            owner.wire_counts.push(table.wire_counts.get(i).copied().unwrap_or_default());
+            owners.push(owner.user_ids.len() as u32);
+            self.merged.owners += 1;
+        }
+
+        Ok(Maps {
+            basic: extend(&mut names.basic_brick_asset_names, &global.basic_brick_asset_names),
+            procedural: extend(&mut names.procedural_brick_asset_names, &global.procedural_brick_asset_names),
+            materials: extend(&mut names.material_asset_names, &global.material_asset_names),
+            component_types: extend(&mut names.component_type_names, &global.component_type_names),
+            ports: extend(&mut names.component_wire_port_names, &global.component_wire_port_names),
+            owners,
+        })
+    }
+
+    // a chunk of the merged world, read from the first world if it's there
+    fn chunk(&mut self, grid: usize, chunk: ChunkIndex) -> Result<&mut Chunk, Box<dyn std::error::Error>> {
+        if !self.chunks.contains_key(&(grid, chunk)) {
+            let mut merged = Chunk {
+                bricks: BrickChunkSoA::default(),
+                components: vec![],
+                wires: WireChunkSoA::default(),
+                existing: false,
+            };
+            if grid == self.root
+                && let Some((num_components, num_wires)) = self.root_chunks.get(&chunk).copied()
+            {
+                merged.existing = true;
+                merged.bricks = self.db.brick_chunk_soa(grid, chunk)?;
+                if num_components > 0 {
+                    let (soa, components) = self.db.component_chunk(grid, chunk)?;
+                    merged.components = components::split(grid, chunk, soa, components)
+                        .ok_or_else(|| format!("[grid:{grid}][{chunk}] has joints that don't line up, can't merge into it"))?;
+                }
+                if num_wires > 0 {
+                    merged.wires = self.db.wire_chunk_soa(grid, chunk)?;
+                }
+            }
+            self.chunks.insert((grid, chunk), merged);
+        }
+        Ok(self.chunks.get_mut(&(grid, chunk)).unwrap())
+    }
+
+    // the entities of an entity chunk of the merged world, read from the first world if it's there
+    fn entity_chunk(&mut self, chunk: ChunkIndex) -> Result<&mut Vec<(Entity, u32)>, Box<dyn std::error::Error>> {
+        if !self.entities.contains_key(&chunk) {
+            let mut entities = vec![];
+            if self.entity_chunks.contains(&chunk) {
+                for entity in self.db.entity_chunk(chunk)? {
+                    let id = entity.id.unwrap() as u32;
+                    entities.push((entity, id));
+                }
+            }
+            self.entities.insert(chunk, entities);
+        }
+        Ok(self.entities.get_mut(&chunk).unwrap())
+    }
+
+    // everything that changed in the first world, as a patch
+    pub fn patch(self) -> Result<BrPendingFs, Box<dyn std::error::Error>> {
+        let db = self.db;
+        let brick_schema = db.bricks_schema()?;
+        let wire_schema = db.wires_schema()?;
+        let index_schema = db.chunk_index_schema()?;
+
+        let mut root_folder = vec![];
+        let mut root_changes = IndexChanges::default();
+        let mut new_folders: BTreeMap<usize, (Vec<Entry>, BrickChunkIndexSoA)> = BTreeMap::new();
+        for ((grid, chunk), merged) in self.chunks {
+            let num_bricks = merged.bricks.brick_type_indices.len() as u32;
+            let num_components = merged.components.len() as u32;
+            let num_wires = (merged.wires.local_wire_sources.len() + merged.wires.remote_wire_sources.len()) as u32;
+
+            let folder = if grid == self.root {
+                if merged.existing {
+                    root_changes.bricks.push((chunk, num_bricks));
+                } else {
+                    root_changes.added.push((chunk, num_bricks));
+                }
+                root_changes.components.push((chunk, num_components));
+                root_changes.wires.push((chunk, num_wires));
+                &mut root_folder
+            } else {
+                let (folder, index) = new_folders.entry(grid).or_default();
+                index.chunk_3d_indices.push(chunk);
+                index.num_bricks.push(num_bricks);
+                index.num_components.push(num_components);
+                index.num_wires.push(num_wires);
+                folder
+            };
+
+            patch::push_into(folder, "Chunks", patch::chunk_file(chunk, merged.bricks.to_bytes(&brick_schema)?));
+            if num_components > 0 {
+                let (mut soa, components) = components::join(merged.components);
+                for component in components {
+                    soa.unwritten_struct_data.push(Box::new(component));
+                }
+                patch::push_into(folder, "Components", patch::chunk_file(chunk, soa.to_bytes(&self.component_schema)?));
+            }
+            if num_wires > 0 {
+                patch::push_into(folder, "Wires", patch::chunk_file(chunk, merged.wires.to_bytes(&wire_schema)?));
+            }
+        }
+
+        let mut grid_folders = vec![];
+        if !root_changes.is_empty() {
+            let (index_file, _) = bricks::chunk_index_files(db, self.root, &root_changes)?;
+            root_folder.push(index_file);
+            grid_folders.push((self.root.to_string(), BrPendingFs::Folder(Some(root_folder))));
+        }
+        for grid in &self.new_grids {
+            // vehicles without any bricks still get a folder, like in the world they came from
+            let (mut folder, index) = new_folders.remove(grid).unwrap_or_default();
+            folder.push(("ChunkIndex.mps".to_string(), BrPendingFs::File(Some(index.to_bytes(&index_schema)?))));
+            grid_folders.push((grid.to_string(), BrPendingFs::Folder(Some(folder))));
+        }
+
+        let mut entity_files = vec![];
+        for (chunk, entities) in self.entities {
+            let mut soa = EntityChunkSoA::default();
+            for (entity, id) in &entities {
+                soa.add_entity(&self.global, entity, *id);
+            }
+            entity_files.push(patch::chunk_file(chunk, soa.to_bytes(&self.entity_schema)?));
+        }
+
+        let (global_schema, owners_schema) = (db.global_data_schema()?, db.owners_schema()?);
+        let tables = vec![
+            (GLOBAL_DATA_FILE.to_string(), BrPendingFs::File(Some(self.global.to_bytes(&global_schema)?))),
+            (OWNERS_FILE.to_string(), BrPendingFs::File(Some(self.owners.to_bytes(&owners_schema)?))),
+        ];
+
+        Ok(patch::nested(&["World", "0", "Bricks", "Grids"], grid_folders)
+            .with_patch(patch::nested(&["World", "0", "Entities", "Chunks"], entity_files))?
+            .with_patch(patch::nested(&["World", "0"], tables))?)
+    }
+}
+
+// whether the first world's schema has the struct of this component or entity
+fn known(schema: &BrdbSchema, data: &dyn BrdbComponent) -> bool {
+    data.get_schema_struct().is_none_or(|(name, _)| schema.structs.contains_key(name.as_ref()))
+}
+
+// a chunk index moved by `shift` chunks
+fn move_chunk(chunk: ChunkIndex, shift: [i32; 3]) -> Result<ChunkIndex, Box<dyn std::error::Error>> {
+    let axis = |c: i16, s: i32| i16::try_from(c as i32 + s).map_err(|_| "the offset moves chunks too far away");
+    Ok(ChunkIndex { x: axis(chunk.x, shift[0])?, y: axis(chunk.y, shift[1])?, z: axis(chunk.z, shift[2])? })
+}
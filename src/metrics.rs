@@ -0,0 +1,116 @@
+/*
+ * prometheus metrics for daemon mode
+ *
+ * with `metrics_address` under [daemon] set, the daemon serves /metrics on that address
+ * in the prometheus text format, so hosting providers can graph how their worlds are doing:
+ * - brdb_optimize_runs_total{result=..}: runs, by how they went
+ *   (optimized, unchanged when there was nothing to do, failed)
+ * - brdb_optimize_entities_modified_total and brdb_optimize_components_modified_total
+ * - brdb_optimize_last_run_duration_seconds{world=..} and brdb_optimize_world_size_bytes{world=..}
+ *
+ * every world is optimized in a process of its own (see daemon.rs), which writes
+ * what it did to a --stats file for the daemon to pick up
+ */
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::http;
+
+// what a run did, written by `--stats <file>`
+#[derive(Serialize, Deserialize)]
+pub struct RunStats {
+    pub entities_modified: u64,
+    pub components_modified: u64,
+    // size of the written world file, in bytes
+    pub size: u64,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    runs: BTreeMap<&'static str, u64>,
+    entities_modified: u64,
+    components_modified: u64,
+    // by world file name
+    durations: BTreeMap<String, f64>,
+    sizes: BTreeMap<String, u64>,
+}
+
+impl Metrics {
+    // `result` is how the run went: "optimized", "unchanged" or "failed"
+    pub fn record(&mut self, world: &str, result: &'static str, seconds: f64, stats: Option<&RunStats>) {
+        *self.runs.entry(result).or_default() += 1;
+        self.durations.insert(world.to_string(), seconds);
+        if let Some(stats) = stats {
+            self.entities_modified += stats.entities_modified;
+            self.components_modified += stats.components_modified;
+            self.sizes.insert(world.to_string(), stats.size);
+        }
+    }
+
+    // the prometheus text format
+    fn render(&self) -> Result<String, std::fmt::Error> {
+        let mut text = String::new();
+        writeln!(text, "# HELP brdb_optimize_runs_total optimization runs, by how they went")?;
+        writeln!(text, "# TYPE brdb_optimize_runs_total counter")?;
+        for result in ["optimized", "unchanged", "failed"] {
+            writeln!(text, "brdb_optimize_runs_total{{result=\"{result}\"}} {}", self.runs.get(result).unwrap_or(&0))?;
+        }
+        writeln!(text, "# HELP brdb_optimize_entities_modified_total entities changed by all runs")?;
+        writeln!(text, "# TYPE brdb_optimize_entities_modified_total counter")?;
+        writeln!(text, "brdb_optimize_entities_modified_total {}", self.entities_modified)?;
+        writeln!(text, "# HELP brdb_optimize_components_modified_total components changed by all runs")?;
+        writeln!(text, "# TYPE brdb_optimize_components_modified_total counter")?;
+        writeln!(text, "brdb_optimize_components_modified_total {}", self.components_modified)?;
+        writeln!(text, "# HELP brdb_optimize_last_run_duration_seconds how long the last run on a world took")?;
+        writeln!(text, "# TYPE brdb_optimize_last_run_duration_seconds gauge")?;
+        for (world, seconds) in &self.durations {
+            writeln!(text, "brdb_optimize_last_run_duration_seconds{{world=\"{}\"}} {seconds:.3}", label(world))?;
+        }
+        writeln!(text, "# HELP brdb_optimize_world_size_bytes size of a world file after its last optimization")?;
+        writeln!(text, "# TYPE brdb_optimize_world_size_bytes gauge")?;
+        for (world, size) in &self.sizes {
+            writeln!(text, "brdb_optimize_world_size_bytes{{world=\"{}\"}} {size}", label(world))?;
+        }
+        Ok(text)
+    }
+}
+
+// label values are quoted, so quotes and backslashes in them need escaping
+fn label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/*
+ * serves /metrics on `address` (like 127.0.0.1:9184) in the background.
+ * anything else gets a 404
+ */
+pub fn serve(address: &str, metrics: Arc<Mutex<Metrics>>) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(address).map_err(|e| format!("can't serve metrics on {address}: {e}"))?;
+    println!("serving metrics on http://{address}/metrics");
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let Some(request) = http::read(&stream) else {
+                continue;
+            };
+
+            if request.path == "/metrics" {
+                let body = metrics.lock().ok().and_then(|m| m.render().ok()).unwrap_or_default();
+                http::respond(&stream, "200 OK", "text/plain; version=0.0.4", &body);
+            } else {
+                http::respond(&stream, "404 Not Found", "text/plain", "not found, try /metrics\n");
+            }
+        }
+    });
+    Ok(())
+}
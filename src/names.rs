@@ -0,0 +1,224 @@
+/*
+ * compacting the world's global name tables
+ *
+ * bricks don't store the name of their asset or material, just an index
+ * into one of the lists in the world's GlobalData.mps:
+ *  - basic_brick_asset_names: bricks with a fixed mesh
+ *  - procedural_brick_asset_names: bricks with a size (PB_DefaultBrick, ..)
+ *  - material_asset_names
+ * those lists only ever grow, so old worlds carry around names of things
+ * that were deleted ages ago, and imports can add the same name more than once.
+ * dropping or merging names means every index after them shifts,
+ * so every brick chunk that points past one has to be rewritten.
+ *
+ * only the brick related lists are compacted. component, entity and wire port names
+ * are referenced all over the component and entity data, and are left alone.
+ * (colors don't have a table at all, every brick stores its own.)
+ * usage is counted on the world as it was before optimizing, so names only used
+ * by bricks removed in this same run get dropped the next time
+ */
+
+use std::collections::HashMap;
+use brdb::{BrReader, Brdb, BrdbWorldGlobalData, BrickChunkSoA, pending::BrPendingFs};
+
+use crate::patch::Entry;
+
+pub const GLOBAL_DATA_FILE: &str = "GlobalData.mps";
+
+// what happens to one of the tables
+struct Table {
+    // the new index of every old one. duplicates point at the copy that's kept, unused names at nothing
+    map: Vec<Option<u32>>,
+    // which of the old entries are gone from the table
+    dropped: Vec<bool>,
+}
+
+impl Table {
+    /*
+     * works out the new table, given which names are used.
+     * with `merge`, names that are in the table more than once are only kept once
+     */
+    fn new(names: &[String], used: &[bool], merge: bool) -> Self {
+        // the first copy of every name
+        let mut first = HashMap::new();
+        let canonical: Vec<usize> = (0..names.len())
+            .map(|i| if merge { *first.entry(&names[i]).or_insert(i) } else { i })
+            .collect();
+
+        // a name is kept if it, or any of its copies, is used
+        let mut live = vec![false; names.len()];
+        for i in 0..names.len() {
+            if used[i] {
+                live[canonical[i]] = true;
+            }
+        }
+
+        let mut new_index = vec![None; names.len()];
+        let mut next = 0;
+        for i in 0..names.len() {
+            if live[i] && canonical[i] == i {
+                new_index[i] = Some(next);
+                next += 1;
+            }
+        }
+
+        Self {
+            map: canonical.iter().map(|c| new_index[*c]).collect(),
+            dropped: new_index.iter().map(|i| i.is_none()).collect(),
+        }
+    }
+
+    fn get(&self, old: u32) -> Option<u32> {
+        self.map.get(old as usize).copied().flatten()
+    }
+
+    fn retain(&self, names: &mut Vec<String>) {
+        let mut i = 0;
+        names.retain(|_| {
+            i += 1;
+            !self.dropped[i - 1]
+        });
+    }
+}
+
+// where every name in the tables ends up
+pub struct Compaction {
+    basic: Table,
+    procedural: Table,
+    materials: Table,
+    // the compacted tables
+    pub global: BrdbWorldGlobalData,
+}
+
+impl Compaction {
+    // how many names get dropped or merged in total
+    pub fn removed(&self) -> usize {
+        [&self.basic, &self.procedural, &self.materials].iter()
+            .map(|table| table.dropped.iter().filter(|d| **d).count())
+            .sum()
+    }
+
+    /*
+     * points a chunk's bricks at the compacted tables.
+     * returns whether anything in the chunk changed
+     */
+    pub fn remap(&self, bricks: &mut BrickChunkSoA) -> bool {
+        let mut changed = false;
+        let start = bricks.procedural_brick_starting_index;
+        // procedural bricks come after all the basic ones, so they move down by however many basic names were dropped
+        let dropped_basic = self.basic.dropped.iter().take(start as usize).filter(|d| **d).count() as u32;
+
+        for type_index in bricks.brick_type_indices.iter_mut() {
+            let new = if *type_index < start {
+                self.basic.get(*type_index)
+            } else {
+                Some(*type_index - dropped_basic)
+            };
+            if let Some(new) = new && new != *type_index {
+                *type_index = new;
+                changed = true;
+            }
+        }
+        if dropped_basic > 0 {
+            bricks.procedural_brick_starting_index -= dropped_basic;
+            changed = true;
+        }
+
+        for counter in bricks.brick_size_counters.iter_mut() {
+            if let Some(new) = self.procedural.get(counter.asset_index)
+                && new != counter.asset_index
+            {
+                counter.asset_index = new;
+                changed = true;
+            }
+        }
+
+        for material in bricks.material_indices.iter_mut() {
+            if let Some(new) = self.materials.get(*material as u32)
+                && new != *material as u32
+            {
+                *material = new as u8;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    /*
+     * the compacted GlobalData.mps.
+     * returns (compacted file, original file), the original is for the undo file
+     */
+    pub fn files(&self, db: &BrReader<Brdb>) -> Result<(Entry, Entry), Box<dyn std::error::Error>> {
+        let schema = db.global_data_schema()?;
+        let bytes = self.global.to_bytes(&schema)?;
+        let original = db.read_file(format!("World/0/{GLOBAL_DATA_FILE}"))?;
+        Ok((
+            (GLOBAL_DATA_FILE.to_string(), BrPendingFs::File(Some(bytes))),
+            (GLOBAL_DATA_FILE.to_string(), BrPendingFs::File(Some(original))),
+        ))
+    }
+}
+
+/*
+ * works out how to compact the name tables:
+ * with `unused`, names no brick in any of `grids` uses anymore get dropped,
+ * with `duplicates`, names that are in a table more than once get merged.
+ * returns None if there's nothing to compact
+ */
+pub fn plan(
+    db: &BrReader<Brdb>,
+    grids: &[usize],
+    unused: bool,
+    duplicates: bool,
+) -> Result<Option<Compaction>, Box<dyn std::error::Error>> {
+    let global = db.global_data()?;
+    let mut basic = vec![!unused; global.basic_brick_asset_names.len()];
+    let mut procedural = vec![!unused; global.procedural_brick_asset_names.len()];
+    let mut materials = vec![!unused; global.material_asset_names.len()];
+
+    for grid in grids.iter().filter(|_| unused) {
+        for chunk in db.brick_chunk_index(*grid)? {
+            if chunk.num_bricks == 0 {
+                continue;
+            }
+            // a chunk we can't read might use anything, so nothing gets dropped
+            let Ok(bricks) = db.brick_chunk_soa(*grid, *chunk) else {
+                println!("[grid:{grid}][{}] can't read bricks, not compacting the name tables", *chunk);
+                return Ok(None);
+            };
+
+            for type_index in &bricks.brick_type_indices {
+                if *type_index < bricks.procedural_brick_starting_index {
+                    mark(&mut basic, *type_index as usize);
+                }
+            }
+            for counter in &bricks.brick_size_counters {
+                mark(&mut procedural, counter.asset_index as usize);
+            }
+            for material in &bricks.material_indices {
+                mark(&mut materials, *material as usize);
+            }
+        }
+    }
+
+    let mut compacted = (*global).clone();
+    let basic = Table::new(&global.basic_brick_asset_names, &basic, duplicates);
+    let procedural = Table::new(&global.procedural_brick_asset_names, &procedural, duplicates);
+    let materials = Table::new(&global.material_asset_names, &materials, duplicates);
+    basic.retain(&mut compacted.basic_brick_asset_names);
+    procedural.retain(&mut compacted.procedural_brick_asset_names);
+    materials.retain(&mut compacted.material_asset_names);
+
+    let compaction = Compaction { basic, procedural, materials, global: compacted };
+    if compaction.removed() == 0 {
+        return Ok(None);
+    }
+    Ok(Some(compaction))
+}
+
+fn mark(used: &mut [bool], i: usize) {
+    if let Some(used) = used.get_mut(i) {
+        *used = true;
+    }
+}
@@ -0,0 +1,29 @@
+/*
+ * telling the admins how a run went, where they already talk
+ *
+ * with `webhook` under [notify] set to a discord webhook url, a summary of every run
+ * (what got done, how the size and counts changed, and any warnings) gets posted there.
+ * a failing webhook never fails the run, it only gets a warning in the log
+ */
+
+use serde_json::json;
+
+// discord refuses messages longer than this
+const MAX_LENGTH: usize = 2000;
+
+// posts `lines` to the webhook at `url`, with `title` in bold above them
+pub fn post(url: &str, title: &str, lines: &[String]) {
+    let mut message = format!("**{title}**\n{}", lines.join("\n"));
+    if message.len() > MAX_LENGTH {
+        let mut end = MAX_LENGTH - 3;
+        while !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        message.truncate(end);
+        message.push_str("...");
+    }
+
+    if let Err(e) = ureq::post(url).send_json(json!({ "content": message })) {
+        println!("[WARNING] couldn't post to the webhook: {e}");
+    }
+}
@@ -0,0 +1,67 @@
+/*
+ * writing wavefront .obj meshes, just enough for one box per brick
+ *
+ * every box is 8 corners and 6 quads. corner i has bit 0 set for +X, bit 1 for +Y and bit 2 for +Z,
+ * so CORNERS[i] is the direction of that corner from the middle of the box
+ */
+
+use std::io::{self, Write};
+
+pub const CORNERS: [[f32; 3]; 8] = [
+    [-1.0, -1.0, -1.0],
+    [1.0, -1.0, -1.0],
+    [-1.0, 1.0, -1.0],
+    [1.0, 1.0, -1.0],
+    [-1.0, -1.0, 1.0],
+    [1.0, -1.0, 1.0],
+    [-1.0, 1.0, 1.0],
+    [1.0, 1.0, 1.0],
+];
+
+// the sides of a box, counter-clockwise when looking at them from outside so their normals point out
+const FACES: [[usize; 4]; 6] = [
+    [0, 4, 6, 2],
+    [1, 3, 7, 5],
+    [0, 1, 5, 4],
+    [2, 6, 7, 3],
+    [0, 2, 3, 1],
+    [4, 5, 7, 6],
+];
+
+pub struct Obj<W: Write> {
+    out: W,
+    // vertices written so far, faces point at vertices by their number (starting at 1)
+    vertices: usize,
+}
+
+impl<W: Write> Obj<W> {
+    pub fn new(out: W) -> Self {
+        Self { out, vertices: 0 }
+    }
+
+    pub fn comment(&mut self, text: &str) -> io::Result<()> {
+        writeln!(self.out, "# {text}")
+    }
+
+    // starts a new object, everything after this belongs to it
+    pub fn object(&mut self, name: &str) -> io::Result<()> {
+        writeln!(self.out, "o {name}")
+    }
+
+    // a box, from its corners in the order of CORNERS
+    pub fn cuboid(&mut self, corners: &[[f32; 3]; 8]) -> io::Result<()> {
+        for [x, y, z] in corners {
+            writeln!(self.out, "v {x} {y} {z}")?;
+        }
+        for [a, b, c, d] in FACES {
+            let v = self.vertices + 1;
+            writeln!(self.out, "f {} {} {} {}", v + a, v + b, v + c, v + d)?;
+        }
+        self.vertices += 8;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
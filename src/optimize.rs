@@ -0,0 +1,1628 @@
+/*
+ * the optimization pass itself
+ *
+ * essential to know in understanding this program,
+ * is that it takes a brdb world file and doesn't just modify the existing one,
+ * but creates a brand new copy,
+ * which involves copying every single thing over into the new file
+ * while modifying anything that we want to change
+ */
+
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    process,
+    path::{Path, PathBuf},
+};
+use brdb::{
+    BrReader, Brdb, BrdbComponent, ChunkIndex, ComponentChunkSoA, EntityChunkSoA, IntoReader, pending::BrPendingFs,
+};
+use glob::Pattern;
+
+use crate::{
+    analyze,
+    archive,
+    blobs,
+    bricks,
+    cache,
+    cli::{Args, Report},
+    compare,
+    compat,
+    components,
+    config::{BrickBudget, Compression, Config, DynamicWeights, FreezeMode, OwnerQuota, StaleActors, WheelBudget},
+    entities::{self, Transform},
+    environment,
+    grids::{self, Grids},
+    joints::{self, Loops},
+    lights,
+    lock::Lock,
+    manifest,
+    metrics::RunStats,
+    names::{self, Compaction},
+    notify,
+    owners::{self, BudgetCuts, Pruning},
+    patch,
+    physics,
+    props::Props,
+    quarantine::Quarantine,
+    report,
+    revisions::{self, Marker},
+    rules,
+    rulesets,
+    undo,
+    vacuum,
+    verify,
+    welds::{self, Welded},
+    wires,
+    worlds,
+};
+
+pub fn run(src: PathBuf, args: &Args, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    // set up paths
+    let name = src.file_name().unwrap().to_string_lossy().to_string();
+    let stem = src.file_stem().unwrap().to_string_lossy();
+    let dst = src.with_file_name(format!("{stem}.optimized.brdb"));
+    let undo_dst = undo::undo_path_for(&src);
+
+    assert!(src.exists());
+
+    // old .brs saves are a different format altogether, which the brdb crate can't read (yet)
+    if src.extension().is_some_and(|e| e.eq_ignore_ascii_case("brs")) {
+        return Err(format!("{:?} is an old .brs save, which can't be converted yet. convert it to a .brdb world first", src).into());
+    }
+
+    // make sure no other instance is working on this world right now
+    let Some(_lock) = Lock::acquire(&src)? else {
+        process::exit(1);
+    };
+
+    /*
+     * if the latest revision was written by this version of us with the same settings,
+     * nothing has changed since the last run and optimizing again
+     * would only add another revision
+     */
+    let marker = Marker::new(config.hash());
+    if !args.force
+        && let Some((path, revision)) = already_optimized(&src, &dst, &marker)?
+    {
+        println!(
+            "{:?} was already optimized by brdb_optimize v{} with the same settings (revision {} of {:?}), skipping.",
+            src, marker.version, revision, path
+        );
+        println!("use --force to optimize it anyway.");
+        return Ok(());
+    }
+
+    // how old kept revisions may be, checked before doing any work
+    let max_revision_age = match &config.revisions.keep_newer_than {
+        Some(age) => Some(
+            revisions::parse_age(age)
+                .ok_or_else(|| format!("invalid keep_newer_than {age:?}, expected an age like 7d, 12h or 30m"))?,
+        ),
+        None => None,
+    };
+
+    // read brdb database and initialize variables
+    println!("Reading file {:?}", src);
+    let size_before = std::fs::metadata(&src)?.len();
+    let db = Brdb::open(&src)?.into_reader();
+    let source = db.to_pending()?;
+
+    let mut run = Run::new(&src);
+    let options = Options { force: args.force, repair: args.repair };
+
+    /*
+     * older revisions to look for entities that haven't moved in (see entities::unmoved),
+     * and to reset runaway entities to (see entities::runaway)
+     */
+    let unmoved = config.entities.freeze_unmoved.filter(|_| !config.entities.unfreeze).unwrap_or(0);
+    let runaway = usize::from(config.entities.runaway_speed.is_some() || config.entities.runaway_distance.is_some());
+    let count = unmoved.max(runaway);
+    let history = if count > 0 { revisions::checkout_recent(&src, count, &dst)? } else { vec![] };
+    if count > 0 && history.is_empty() {
+        println!("the world has fewer than {} revisions, not comparing entities against earlier ones.", count + 1);
+    }
+    let result = optimize_all(&db, &source, &dst, &history, &options, config, &mut run);
+    for revision in &history {
+        std::fs::remove_file(revision)?;
+    }
+    let (combined_patch, combined_undo_patch) = result?;
+
+    println!("---SEP---");
+
+    if run.quarantine.count > 0 {
+        println!("raw data of {} corrupt chunks saved to {:?}", run.quarantine.count, run.quarantine.dir);
+    }
+
+    if run.corrupted {
+        println!("[ERROR] corruptions found! please read back through the log to see what went wrong.");
+        println!("for safety, the world file was not written.");
+        println!("run again with --repair to drop the corrupt chunks' components and write the world anyway.");
+        if let Some(url) = &config.notify.webhook {
+            notify::post(url, &format!("{name}: corruptions found, the world was not written"), &[
+                "run the optimizer again with --repair to drop the corrupt chunks' components.".to_string(),
+            ]);
+        }
+        // returning (instead of exiting) lets the lock clean up after itself
+        return Err("corruptions found, the world was not written".into());
+    }
+
+    if run.num_chunks_repaired > 0 {
+        println!("[WARNING] repaired {} corrupt chunks by dropping their components.", run.num_chunks_repaired);
+    }
+
+    /* 
+    println!("stripping revisions..");
+    db.conn.execute(
+    */
+
+    println!();
+    for line in run.summary(config) {
+        println!("{line}");
+    }
+    println!("writing to world file..");
+
+    // ------------------
+    // Write combined patch as a new revision
+    // ------------------
+    if dst.exists() {
+        std::fs::remove_file(&dst)?;
+    }
+    if config.revisions.keeps_history() {
+        // the changes go on top of a copy of the original, as a new revision
+        std::fs::copy(&src, &dst)?;
+        Brdb::open(&dst)?.write_pending(marker.describe("Optimize World"), combined_patch)?;
+
+        if let Some(age) = max_revision_age
+            && let Some(text) = &config.revisions.keep_newer_than
+        {
+            let removed = revisions::remove_older_than(&dst, marker.timestamp.saturating_sub(age))?;
+            if removed > 0 {
+                println!("removed {removed} revisions older than {text}.");
+            }
+        }
+
+        if config.revisions.remove_unchanged {
+            let removed = revisions::remove_unchanged(&dst)?;
+            if removed > 0 {
+                println!("removed {removed} revisions that didn't change anything.");
+            }
+        }
+    } else {
+        // a brand new file with just the latest state, which gets rid of all revisions
+        let pending = source.with_patch(combined_patch)?;
+        Brdb::new(&dst)?.write_pending(marker.describe("Optimize World"), pending)?;
+    }
+
+    println!("world written to {:?}", dst);
+
+    // ------------------
+    // Verify the written world
+    // ------------------
+    println!("verifying written world..");
+    let problems = verify::written(&dst, &run.expected, config)?;
+    if !problems.is_empty() {
+        for problem in &problems {
+            println!("[verify]{problem}");
+        }
+        println!("[ERROR] the written world doesn't match what was optimized!");
+        println!("it has been deleted again, your original world is untouched.");
+        if let Some(url) = &config.notify.webhook {
+            notify::post(url, &format!("{name}: the optimized world didn't verify, it was not written"), &problems);
+        }
+        std::fs::remove_file(&dst)?;
+        return Err("the optimized world didn't verify, it was not written".into());
+    }
+    println!(
+        "all {} patched chunks verified.",
+        run.expected.entity_chunks.len() + run.expected.component_chunks.len()
+    );
+
+    let physics_after = physics::estimate_file(&dst, &config.physics_cost)?;
+    if run.physics_before > 0.0 {
+        let change = (physics_after - run.physics_before) / run.physics_before * 100.0;
+        println!("estimated physics cost went from {:.0} to {physics_after:.0} ({change:+.1}%).", run.physics_before);
+    }
+
+    // ------------------
+    // Deduplicate identical file contents
+    // ------------------
+    // fast compression skips this and compacting, they take a while on big worlds
+    let fast = config.files.compression == Compression::Fast;
+    if config.files.dedupe_blobs && !fast {
+        println!("deduplicating file contents..");
+        let (blobs, bytes) = blobs::dedupe(&dst)?;
+        if blobs > 0 {
+            println!("merged {blobs} duplicate blobs, reclaiming {} MB.", bytes / 1024 / 1024);
+        }
+    }
+
+    // ------------------
+    // Compress file contents harder
+    // ------------------
+    if config.files.compression == Compression::Max {
+        println!("compressing file contents again..");
+        let (blobs, bytes) = blobs::recompress(&dst, blobs::MAX_LEVEL)?;
+        println!("{blobs} blobs got smaller, saving {} MB.", bytes / 1024 / 1024);
+    }
+
+    // ------------------
+    // Compact the written world
+    // ------------------
+    if config.files.vacuum && !fast {
+        println!("compacting world file..");
+        let (before, after) = vacuum::run(&dst)?;
+        println!("world file is {} MB (was {} MB before compacting)", after / 1024 / 1024, before / 1024 / 1024);
+    }
+
+    // ------------------
+    // Write the undo file
+    // ------------------
+    if run.changed() {
+        if undo_dst.exists() {
+            std::fs::remove_file(&undo_dst)?;
+        }
+        Brdb::new(&undo_dst)?.write_pending("Optimize World (undo data)", combined_undo_patch)?;
+
+        println!("undo data written to {:?}", undo_dst);
+    }
+
+    // ------------------
+    // Write the report
+    // ------------------
+    if let Some(Report::Html(path)) = &args.report {
+        println!("writing report..");
+        let analysis = analyze::analyze(&dst, config)?;
+        report::html(path, &name, &run.summary(config), size_before, &analysis, config)?;
+        println!("report written to {:?}", path);
+    }
+
+    println!();
+    let table = compare::table(&compare::totals(&src)?, &compare::totals(&dst)?);
+    for line in &table {
+        println!("{line}");
+    }
+
+    if let Some(path) = &args.stats {
+        let stats = RunStats {
+            entities_modified: run.num_entities_modified as u64,
+            components_modified: run.num_components_modified as u64,
+            size: std::fs::metadata(&dst)?.len(),
+        };
+        std::fs::write(path, serde_json::to_string(&stats)?)?;
+    }
+
+    // ------------------
+    // Write the compressed archive
+    // ------------------
+    let archive = match config.files.archive {
+        Some(format) => {
+            println!("writing compressed archive..");
+            let (archive, size) = archive::write(&dst, format, config.files.archive_level)?;
+            println!("archive written to {:?} ({} MB)", archive, size / 1024 / 1024);
+            Some(archive)
+        }
+        None => None,
+    };
+
+    // ------------------
+    // Write the checksum manifest
+    // ------------------
+    if config.files.manifest {
+        println!("writing checksum manifest..");
+        let mut files = vec![src.as_path(), dst.as_path()];
+        files.extend(archive.as_deref());
+        let blobs_of = config.files.manifest_blobs.then_some(dst.as_path());
+        let path = manifest::write(src.with_file_name(format!("{stem}.optimized.manifest.json")), &files, blobs_of)?;
+        println!("checksums written to {:?}", path);
+    }
+
+    // the manifest still lists the uncompressed world, to check it after unpacking the archive
+    if archive.is_some() && config.files.archive_only {
+        std::fs::remove_file(&dst)?;
+        println!("removed {:?}, only the archive is kept", dst);
+    }
+
+    if let Some(url) = &config.notify.webhook {
+        let mut lines = vec![];
+        if run.num_chunks_repaired > 0 {
+            lines.push(format!(":warning: repaired {} corrupt chunks by dropping their components.", run.num_chunks_repaired));
+        }
+        lines.extend(run.summary(config));
+        lines.push(format!("```\n{}\n```", table.join("\n")));
+        notify::post(url, &format!("{name} optimized"), &lines);
+    }
+
+    Ok(())
+}
+
+/*
+ * where `src` was already optimized with the settings and version in `marker`, as the world and revision that say so.
+ * that's the world itself once the optimized world took its place (or when history is kept),
+ * otherwise it's the optimized world next to it, as long as the world didn't get saved again since
+ */
+fn already_optimized(src: &Path, dst: &Path, marker: &Marker) -> Result<Option<(PathBuf, i64)>, Box<dyn std::error::Error>> {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let dst_is_current = dst.exists() && modified(src) <= modified(dst);
+
+    for path in [src].into_iter().chain(dst_is_current.then_some(dst)) {
+        if let Some(latest) = revisions::list(path)?.pop()
+            && let Some(previous) = Marker::parse(&latest.description)
+            && previous.config_hash == marker.config_hash
+            && previous.version == marker.version
+        {
+            return Ok(Some((path.to_path_buf(), latest.id)));
+        }
+    }
+    Ok(None)
+}
+
+/*
+ * for using this as a library: optimizes everything `db` reads, and returns the patch
+ * that does it instead of writing a file, so server plugins that use the brdb crate
+ * can optimize a world inline while saving it (`db.to_pending()?.with_patch(patch)`).
+ * other worlds than World/0 still get mounted into a temporary file to read them (see worlds.rs).
+ * fails instead of writing anything broken when there are corrupt chunks, or when the world
+ * was saved by a game version the tool doesn't know about
+ */
+pub fn optimize_pending(db: &BrReader<Brdb>, config: &Config) -> Result<BrPendingFs, Box<dyn std::error::Error>> {
+    let near = env::temp_dir().join(format!("brdb_optimize_{}.brdb", process::id()));
+    let source = db.to_pending()?;
+
+    let mut run = Run::new(&near);
+    let (patch, _) = optimize_all(db, &source, &near, &[], &Options::default(), config, &mut run)?;
+    if run.corrupted {
+        return Err("corruptions found, read back through the log to see what went wrong".into());
+    }
+    Ok(patch)
+}
+
+// how careful to be, see --force and --repair
+#[derive(Default)]
+pub struct Options {
+    // optimize worlds saved by game versions we don't know about
+    pub force: bool,
+    // drop the components of corrupt chunks instead of refusing to write the world
+    pub repair: bool,
+}
+
+/*
+ * optimizes every world in the file (and the caches outside of them),
+ * and combines their patches into one. `near` is where other worlds get mounted next to,
+ * `history` are older revisions of the file, checked out to look for entities that haven't moved
+ */
+fn optimize_all(
+    db: &BrReader<Brdb>,
+    source: &BrPendingFs,
+    near: &Path,
+    history: &[PathBuf],
+    options: &Options,
+    config: &Config,
+    run: &mut Run,
+) -> Result<(BrPendingFs, BrPendingFs), Box<dyn std::error::Error>> {
+    let mut combined_patch = BrPendingFs::Root(vec![]);
+    let mut combined_undo_patch = BrPendingFs::Root(vec![]);
+    for world in worlds::indices(source) {
+        println!("---SEP---");
+        println!("optimizing world {world}..");
+
+        let (world_patch, world_undo_patch) = worlds::read_world(db, source, &world, near, |world_db| {
+            optimize_world(world_db, source, &world, history, options, config, run)
+        })?;
+        combined_patch = combined_patch.with_patch(world_patch)?;
+        combined_undo_patch = combined_undo_patch.with_patch(world_undo_patch)?;
+    }
+
+    // caches outside of the worlds themselves
+    let (cache_patch, cache_undo_patch, cache_bytes) = cache::strip(source, &config.files)?;
+    combined_patch = combined_patch.with_patch(cache_patch)?;
+    combined_undo_patch = combined_undo_patch.with_patch(cache_undo_patch)?;
+    run.num_cache_bytes_removed += cache_bytes;
+
+    Ok((combined_patch, combined_undo_patch))
+}
+
+// everything gathered while optimizing, across all worlds in the file
+struct Run {
+    num_entities_modified: u32,
+    num_components_modified: u32,
+    corrupted: bool,
+    num_chunks_repaired: u32,
+    num_orphans_removed: usize,
+    num_empty_chunks_removed: usize,
+    num_duplicate_bricks_removed: usize,
+    num_hidden_bricks_removed: usize,
+    num_bricks_merged: usize,
+    num_microbricks_consolidated: usize,
+    num_duplicate_wires_removed: usize,
+    num_empty_grids_removed: usize,
+    num_grids_welded: usize,
+    num_entities_removed: usize,
+    num_items_removed: usize,
+    num_cache_bytes_removed: usize,
+    num_lights_collapsed: usize,
+    num_lights_disabled: usize,
+    num_lights_over_quota: usize,
+    num_names_removed: usize,
+    num_owners_removed: usize,
+    num_wheel_heavy_vehicles: usize,
+    num_grids_over_budget: usize,
+    num_unmoved_frozen: usize,
+    num_purged_bricks: usize,
+    num_purged_components: usize,
+    num_purged_entities: usize,
+    // everyone over the brick budget, with how many bricks they have
+    over_brick_budget: Vec<String>,
+    num_budget_bricks_removed: usize,
+    // how many bricks were removed of every owner (by index) over the brick budget
+    budget_bricks_removed: HashMap<u32, usize>,
+    // everyone still over the brick budget after removing, with how many bricks they have left
+    still_over_brick_budget: Vec<String>,
+    // what happened to every runaway entity, for the summary and the report
+    runaway_resets: Vec<String>,
+    num_values_sanitized: usize,
+    num_duplicate_joints_removed: usize,
+    num_joints_broken: usize,
+    num_ruleset_removed: usize,
+    num_environment_settings_changed: usize,
+    // estimated physics cost of the original world, see physics.rs
+    physics_before: f64,
+    quarantine: Quarantine,
+    props: Props,
+    // what every chunk we write should contain, checked after writing
+    expected: verify::Expected,
+}
+
+impl Run {
+    // `world` is where corrupt chunks get quarantined next to
+    fn new(world: &Path) -> Self {
+        Run {
+            num_entities_modified: 0,
+            num_components_modified: 0,
+            corrupted: false,
+            num_chunks_repaired: 0,
+            num_orphans_removed: 0,
+            num_empty_chunks_removed: 0,
+            num_duplicate_bricks_removed: 0,
+            num_hidden_bricks_removed: 0,
+            num_bricks_merged: 0,
+            num_microbricks_consolidated: 0,
+            num_duplicate_wires_removed: 0,
+            num_empty_grids_removed: 0,
+            num_grids_welded: 0,
+            num_entities_removed: 0,
+            num_items_removed: 0,
+            num_cache_bytes_removed: 0,
+            num_lights_collapsed: 0,
+            num_lights_disabled: 0,
+            num_lights_over_quota: 0,
+            num_names_removed: 0,
+            num_owners_removed: 0,
+            num_wheel_heavy_vehicles: 0,
+            num_grids_over_budget: 0,
+            num_unmoved_frozen: 0,
+            num_purged_bricks: 0,
+            num_purged_components: 0,
+            num_purged_entities: 0,
+            over_brick_budget: vec![],
+            num_budget_bricks_removed: 0,
+            budget_bricks_removed: HashMap::new(),
+            still_over_brick_budget: vec![],
+            runaway_resets: vec![],
+            num_values_sanitized: 0,
+            num_duplicate_joints_removed: 0,
+            num_joints_broken: 0,
+            num_ruleset_removed: 0,
+            num_environment_settings_changed: 0,
+            physics_before: 0.0,
+            quarantine: Quarantine::new(world),
+            props: Props::default(),
+            expected: verify::Expected::default(),
+        }
+    }
+
+    // what got done, one line per kind of change
+    fn summary(&self, config: &Config) -> Vec<String> {
+        let mut lines = vec![format!(
+            "optimized {} entities and {} components!",
+            self.num_entities_modified, self.num_components_modified
+        )];
+        if self.num_orphans_removed > 0 {
+            lines.push(format!("removed {} orphaned components.", self.num_orphans_removed));
+        }
+        if self.num_empty_chunks_removed > 0 {
+            lines.push(format!("removed {} empty chunks.", self.num_empty_chunks_removed));
+        }
+        if self.num_duplicate_bricks_removed > 0 {
+            lines.push(format!("removed {} duplicate bricks.", self.num_duplicate_bricks_removed));
+        }
+        if self.num_hidden_bricks_removed > 0 {
+            lines.push(format!("removed {} hidden bricks.", self.num_hidden_bricks_removed));
+        }
+        if self.num_bricks_merged > 0 {
+            lines.push(format!("merged {} bricks into bigger ones.", self.num_bricks_merged));
+        }
+        if self.num_microbricks_consolidated > 0 {
+            lines.push(format!("consolidated {} microbricks into bigger ones.", self.num_microbricks_consolidated));
+        }
+        if self.num_duplicate_wires_removed > 0 {
+            lines.push(format!("removed {} duplicate wires.", self.num_duplicate_wires_removed));
+        }
+        if self.num_duplicate_joints_removed > 0 {
+            lines.push(format!("removed {} duplicate joints.", self.num_duplicate_joints_removed));
+        }
+        if self.num_joints_broken > 0 {
+            lines.push(format!("removed {} joints to break loops of joints.", self.num_joints_broken));
+        }
+        if self.num_ruleset_removed > 0 {
+            lines.push(format!("removed {} components by rulesets.", self.num_ruleset_removed));
+        }
+        if self.num_purged_bricks + self.num_purged_components + self.num_purged_entities > 0 {
+            lines.push(format!(
+                "removed {} bricks, {} components and {} entities of purged owners.",
+                self.num_purged_bricks, self.num_purged_components, self.num_purged_entities
+            ));
+        }
+        if !self.over_brick_budget.is_empty() {
+            lines.push(format!("{} people are over the brick budget:", self.over_brick_budget.len()));
+            lines.extend(self.over_brick_budget.iter().map(|owner| format!("  {owner}")));
+        }
+        if self.num_budget_bricks_removed > 0 {
+            lines.push(format!("removed {} of the newest bricks of people over the brick budget.", self.num_budget_bricks_removed));
+        }
+        if !self.still_over_brick_budget.is_empty() {
+            lines.push(format!(
+                "{} people are still over the brick budget (bricks with components and chunks with wires aren't removed):",
+                self.still_over_brick_budget.len()
+            ));
+            lines.extend(self.still_over_brick_budget.iter().map(|owner| format!("  {owner}")));
+        }
+        if self.num_empty_grids_removed > 0 {
+            lines.push(format!("removed {} empty dynamic grids.", self.num_empty_grids_removed));
+        }
+        if self.num_entities_removed > 0 {
+            lines.push(format!("removed {} out of bounds or abandoned entities.", self.num_entities_removed));
+        }
+        if self.num_items_removed > 0 {
+            lines.push(format!("cleaned up {} dropped items.", self.num_items_removed));
+        }
+        if self.num_cache_bytes_removed > 0 {
+            lines.push(format!("stripped {} MB of minimap and preview cache.", self.num_cache_bytes_removed / 1024 / 1024));
+        }
+        if self.num_grids_welded > 0 {
+            lines.push(format!("welded {} small physics grids into the main grid.", self.num_grids_welded));
+        }
+        if self.num_lights_collapsed > 0 {
+            lines.push(format!("collapsed {} stacked point lights.", self.num_lights_collapsed));
+        }
+        if self.num_lights_disabled > 0 {
+            lines.push(format!("turned off {} lights in chunks over the light budget.", self.num_lights_disabled));
+        }
+        if self.num_lights_over_quota > 0 {
+            let action = match config.lights.over_owner_quota {
+                OwnerQuota::Disable => "turned off",
+                OwnerQuota::Shadows => "disabled shadows of",
+            };
+            lines.push(format!("{action} {} lights of people over their light quota.", self.num_lights_over_quota));
+        }
+        if self.num_names_removed > 0 {
+            lines.push(format!("dropped {} unused or duplicate names from the name tables.", self.num_names_removed));
+        }
+        if self.num_owners_removed > 0 {
+            lines.push(format!("removed {} people who don't own anything anymore from the owner table.", self.num_owners_removed));
+        }
+        if self.num_environment_settings_changed > 0 {
+            lines.push(format!("toned down {} environment settings.", self.num_environment_settings_changed));
+        }
+        if self.num_values_sanitized > 0 {
+            lines.push(format!("fixed NaN or infinite values in {} entities and components.", self.num_values_sanitized));
+        }
+        if self.num_wheel_heavy_vehicles > 0 {
+            let action = if config.wheels.over_budget == WheelBudget::Freeze { "froze" } else { "found" };
+            lines.push(format!("{action} {} vehicles with more wheels than the budget.", self.num_wheel_heavy_vehicles));
+        }
+        if self.num_grids_over_budget > 0 {
+            lines.push(format!("froze {} vehicles over the budget of moving vehicles.", self.num_grids_over_budget));
+        }
+        if self.num_unmoved_frozen > 0 {
+            lines.push(format!("froze {} entities that haven't moved in a while.", self.num_unmoved_frozen));
+        }
+        if !self.runaway_resets.is_empty() {
+            lines.push(format!("reset {} runaway entities:", self.runaway_resets.len()));
+            lines.extend(self.runaway_resets.iter().map(|reset| format!("  {reset}")));
+        }
+        lines
+    }
+
+    // whether anything at all was changed (and so there's something to undo)
+    fn changed(&self) -> bool {
+        self.num_entities_modified > 0
+            || self.num_components_modified > 0
+            || self.num_chunks_repaired > 0
+            || self.num_orphans_removed > 0
+            || self.num_empty_chunks_removed > 0
+            || self.num_duplicate_bricks_removed > 0
+            || self.num_hidden_bricks_removed > 0
+            || self.num_bricks_merged > 0
+            || self.num_microbricks_consolidated > 0
+            || self.num_duplicate_wires_removed > 0
+            || self.num_empty_grids_removed > 0
+            || self.num_grids_welded > 0
+            || self.num_entities_removed > 0
+            || self.num_items_removed > 0
+            || self.num_cache_bytes_removed > 0
+            || self.num_lights_collapsed > 0
+            || self.num_lights_disabled > 0
+            || self.num_lights_over_quota > 0
+            || self.num_names_removed > 0
+            || self.num_owners_removed > 0
+            || self.num_duplicate_joints_removed > 0
+            || self.num_joints_broken > 0
+            || self.num_ruleset_removed > 0
+            || self.num_purged_bricks > 0
+            || self.num_purged_components > 0
+            || self.num_purged_entities > 0
+            || self.num_budget_bricks_removed > 0
+            || self.num_environment_settings_changed > 0
+    }
+}
+
+/*
+ * optimizes a single world.
+ * `db` reads that world as World/0, `world` is its real index in the file,
+ * which is where the returned patch and undo patch put everything
+ */
+fn optimize_world(
+    db: &BrReader<Brdb>,
+    source: &BrPendingFs,
+    world: &str,
+    history: &[PathBuf],
+    options: &Options,
+    config: &Config,
+    run: &mut Run,
+) -> Result<(BrPendingFs, BrPendingFs), Box<dyn std::error::Error>> {
+    // make sure this world was saved by a game version we understand
+    let component_schema = db.components_schema()?;
+    let incompatibilities = compat::check(&component_schema);
+    if !incompatibilities.is_empty() {
+        for problem in &incompatibilities {
+            println!("[schema] {problem}");
+        }
+        println!("this world was probably saved by a newer (or older) game version than this tool knows about.");
+        if !options.force {
+            return Err("refusing to optimize it. use --force to try anyway.".into());
+        }
+        println!("--force was given, continuing anyway..");
+    }
+
+    let mut grids = grids::find(db, source, world)?;
+    run.physics_before += physics::estimate(db, &grids, &config.physics_cost)?.score;
+    println!("root grids: {:?}, dynamic grids: {}", grids.root, grids.dynamic.len());
+    for root in &grids.root {
+        run.expected.root_grids.push((world.to_string(), *root));
+    }
+
+    // brick asset and material names that no brick uses anymore, or that are in the tables twice
+    let names = if config.files.compact_names || config.files.dedupe_names {
+        names::plan(db, &grids.all(), config.files.compact_names, config.files.dedupe_names)?
+    } else {
+        None
+    };
+    let (names_patch, names_undo_patch) = match &names {
+        Some(names) => {
+            println!("dropping {} unused or duplicate names from the name tables..", names.removed());
+            run.num_names_removed += names.removed();
+            run.expected.global_names.push((
+                world.to_string(),
+                names.global.basic_brick_asset_names.len(),
+                names.global.procedural_brick_asset_names.len(),
+                names.global.material_asset_names.len(),
+            ));
+            let (file, original) = names.files(db)?;
+            (patch::nested(&["World", world], vec![file]), patch::nested(&["World", world], vec![original]))
+        }
+        None => (BrPendingFs::Root(vec![]), BrPendingFs::Root(vec![])),
+    };
+
+    // people in the owner table that don't own anything anymore
+    let owners = if config.owners.prune_unowned {
+        owners::unused(db, &grids.all())?
+    } else {
+        None
+    };
+    let (owners_patch, owners_undo_patch) = match &owners {
+        Some(owners) => {
+            println!("removing {} people who don't own anything anymore from the owner table..", owners.removed.len());
+            for name in &owners.removed {
+                println!("[owners] removing {name}");
+            }
+            run.num_owners_removed += owners.removed.len();
+            run.expected.owners.push((world.to_string(), owners.table.user_ids.len()));
+            let (file, original) = owners.files(db)?;
+            (patch::nested(&["World", world], vec![file]), patch::nested(&["World", world], vec![original]))
+        }
+        None => (BrPendingFs::Root(vec![]), BrPendingFs::Root(vec![])),
+    };
+
+    // dynamic grids without any bricks get deleted, together with their entity
+    let empty_grids = grids::empty_dynamic(db, &grids);
+    for grid in &empty_grids {
+        println!("[grid:{grid}] dynamic grid has no bricks, removing it..");
+    }
+    run.num_empty_grids_removed += empty_grids.len();
+    grids.dynamic.retain(|grid| !empty_grids.contains(grid));
+
+    // dynamic grids that ended up way out of bounds
+    let lost_grids = entities::removed_grids(db, &grids, &config.entities)?;
+    run.num_entities_removed += lost_grids.len();
+    grids.dynamic.retain(|grid| !lost_grids.contains(grid));
+
+    // everything of banned players goes, starting with their vehicles
+    let purged = if config.owners.purge.is_empty() {
+        HashSet::new()
+    } else {
+        let purged = owners::find(&db.owners_soa()?, &config.owners.purge);
+        if purged.is_empty() {
+            println!("[WARNING] none of the owners to purge ({}) are in this world", config.owners.purge.join(", "));
+        }
+        purged
+    };
+    let purged_grids = entities::purged_grids(db, &grids, &purged)?;
+    run.num_purged_entities += purged_grids.len();
+    grids.dynamic.retain(|grid| !purged_grids.contains(grid));
+
+    // people with more bricks on the main grid than the budget lose their newest ones (or just get listed)
+    let mut budget_cuts = BudgetCuts::new();
+    let mut over_brick_budget = vec![];
+    if let Some(max) = config.owners.brick_budget {
+        let wired = bricks::wired_chunks(db, &grids.root)?;
+        let (over, cuts) = owners::over_brick_budget(db, &grids.root, &wired, max)?;
+        for owner in &over {
+            println!("[owners] {} has {} bricks, {} more than the budget of {max}", owner.name, owner.bricks, owner.bricks - max);
+            run.over_brick_budget.push(format!("{}: {} bricks", owner.name, owner.bricks));
+        }
+        if config.owners.over_brick_budget == BrickBudget::Remove {
+            budget_cuts = cuts;
+            over_brick_budget = over;
+            // owner indices are per world
+            run.budget_bricks_removed.clear();
+        }
+    }
+
+    // small frozen physics grids become part of the main grid, their own grid and entity get deleted
+    let welded = if config.weld.enabled {
+        welds::weld(db, &grids, &config.weld)?
+    } else {
+        Welded::default()
+    };
+    run.num_grids_welded += welded.grids.len();
+    grids.dynamic.retain(|grid| !welded.grids.contains(grid));
+
+    // vehicles with way too many wheels
+    let mut freezing = Freezing {
+        types: entities::freeze_patterns(&config.entities)?,
+        grids: vec![],
+        spared: None,
+        unmoved: HashSet::new(),
+        history: vec![],
+    };
+    let wheel_types = entities::wheel_patterns(&config.wheels)?;
+    if let Some(max) = config.wheels.max_per_grid {
+        for (grid, wheels) in entities::over_wheel_budget(db, &grids, &wheel_types, max)? {
+            println!("[grid:{grid}] vehicle has {wheels} wheels, more than the budget of {max}");
+            run.num_wheel_heavy_vehicles += 1;
+            if config.wheels.over_budget == WheelBudget::Freeze {
+                freezing.grids.push(grid);
+            }
+        }
+    }
+    // freezing everything leaves working vehicles alone
+    if config.entities.freeze == FreezeMode::All {
+        let vehicles = entities::vehicles(db, &grids)?;
+        println!("{} entities are part of a vehicle with an engine, leaving those unfrozen", vehicles.len());
+        freezing.spared = Some(vehicles);
+    }
+    // entities that haven't moved in a while are probably abandoned
+    freezing.history = entities::history(world, history)?;
+    if let Some(count) = config.entities.freeze_unmoved
+        && count > 0
+        && freezing.history.len() >= count
+        && !config.entities.unfreeze
+    {
+        freezing.unmoved = entities::unmoved(db, &freezing.history[..count])?;
+        println!("{} entities haven't moved in the last {} revisions", freezing.unmoved.len(), count + 1);
+    }
+    // too many vehicles moving at once, the most expensive ones get frozen
+    if let Some(max) = config.entities.max_active_grids {
+        // grids that are getting frozen anyway don't count
+        let mut frozen = freezing.grids.clone();
+        if let Some(vehicles) = &freezing.spared
+            && !config.entities.unfreeze
+        {
+            frozen.extend(grids.dynamic.iter().filter(|grid| !vehicles.contains(grid)));
+        }
+        for (grid, cost) in entities::over_grid_budget(db, &grids, &frozen, &wheel_types, max)? {
+            println!("[grid:{grid}] more than {max} vehicles are moving, freezing this one (cost {cost})..");
+            run.num_grids_over_budget += 1;
+            freezing.grids.push(grid);
+        }
+    }
+
+    let removed_grids = [empty_grids, lost_grids, purged_grids, welded.grids.clone()].concat();
+    for grid in &removed_grids {
+        run.expected.removed_grids.push((world.to_string(), *grid));
+    }
+    let (grids_patch, grids_undo_patch) = grids::delete(source, world, &removed_grids);
+
+    // sky, weather and fog
+    let (environment_patch, environment_undo_patch, settings) = environment::optimize(db, world, &config.environment)?;
+    if settings > 0 {
+        run.num_environment_settings_changed += settings;
+        run.expected.environments.push(world.to_string());
+    }
+
+    // grids jointed together in a circle
+    let joint_loops = joints::loops(db, &grids)?;
+    for joint_loop in &joint_loops.loops {
+        let (a, b) = joint_loop.weakest;
+        let entities: Vec<String> = joint_loop.entities.iter().map(|e| e.to_string()).collect();
+        println!(
+            "[joints] grids {} -> {} are jointed together in a loop, the weakest link is between {a} and {b}",
+            entities.join(" -> "),
+            joint_loop.entities[0],
+        );
+    }
+    if !joint_loops.loops.is_empty() && !config.joints.break_loops {
+        println!("set break_loops under [joints] in the config to break these loops.");
+    }
+
+    let rewrites = Rewrites {
+        welded: &welded,
+        names: names.as_ref(),
+        owners: owners.as_ref(),
+        joint_loops: if config.joints.break_loops { Some(&joint_loops) } else { None },
+        purged: &purged,
+        over_budget: &budget_cuts,
+    };
+    let (entities_patch, entities_undo_patch) = freeze_entities(db, world, &removed_grids, &freezing, &rewrites, config, run)?;
+    let (components_patch, components_undo_patch) = optimize_components(db, world, &grids, &rewrites, options, config, run)?;
+
+    // not every brick over the budget can go, so check who's still over it
+    if let Some(max) = config.owners.brick_budget {
+        for owner in &over_brick_budget {
+            let left = owner.bricks - run.budget_bricks_removed.get(&owner.owner).copied().unwrap_or(0);
+            if left > max {
+                println!("[WARNING] {} still has {left} bricks, {} more than the budget of {max}", owner.name, left - max);
+                run.still_over_brick_budget.push(format!("{}: {left} bricks", owner.name));
+            }
+        }
+    }
+
+    Ok((
+        entities_patch
+            .with_patch(grids_patch)?
+            .with_patch(components_patch)?
+            .with_patch(names_patch)?
+            .with_patch(owners_patch)?
+            .with_patch(environment_patch)?,
+        entities_undo_patch
+            .with_patch(grids_undo_patch)?
+            .with_patch(components_undo_patch)?
+            .with_patch(names_undo_patch)?
+            .with_patch(owners_undo_patch)?
+            .with_patch(environment_undo_patch)?,
+    ))
+}
+
+// ------------------
+// Freeze all entities that are known to cause lag
+// (and remove the entities of grids that got deleted, and ones that are out of bounds)
+// ------------------
+fn freeze_entities(
+    db: &BrReader<Brdb>,
+    world: &str,
+    removed_grids: &[usize],
+    freezing: &Freezing,
+    rewrites: &Rewrites,
+    config: &Config,
+    run: &mut Run,
+) -> Result<(BrPendingFs, BrPendingFs), Box<dyn std::error::Error>> {
+    println!("freezing entities..");
+
+    let global_data = db.global_data()?;
+    let entity_schema = db.entities_schema()?;
+
+    // loop through all entity chunks
+    let mut entity_chunk_files = vec![];
+    let mut undo_entity_chunk_files = vec![];
+    for chunk in db.entity_chunk_index()? {
+        let entities = db.entity_chunk(chunk)?;
+        let mut chunk_modified = false;
+        let mut num_chunk_entities = 0;
+        let mut num_chunk_frozen = 0;
+
+        /*
+         * create a new entity chunk SoA (StructureOfArrays),
+         * that we store our new entities in.
+         *
+         * SoA is defined in zeblote's msgpack-schema format:
+         * https://gist.github.com/Zeblote/0fc682b9df1a3e82942b613ab70d8a04
+         *
+         * it's the way brdb files store this information
+         */
+        let mut soa = EntityChunkSoA::default();
+        let dropped_items = entities::dropped_items(&entities, &config.items, &mut run.props);
+        for (i, mut entity) in entities.into_iter().enumerate() {
+            // get the type of the entity as a string (basically its name)
+            let ent_type = entity.data.get_schema_struct().unwrap().0;
+
+            // the entity of a deleted grid goes with it, by not copying it over
+            if ent_type.as_ref() == "Entity_DynamicBrickGrid" {
+                if entity.id.is_some_and(|id| removed_grids.contains(&id)) {
+                    println!("[entity:{}] removing the entity of deleted grid..", entity.id.unwrap());
+                    chunk_modified = true;
+                    continue;
+                }
+            } else if entities::out_of_bounds(&entity, &config.entities) {
+                // same for anything that flew off into the void
+                println!("[entity:{}] {ent_type} is out of bounds, removing it..", entity.id.unwrap());
+                run.num_entities_removed += 1;
+                chunk_modified = true;
+                continue;
+            } else if entity.owner_index.is_some_and(|owner| rewrites.purged.contains(&owner)) {
+                println!("[entity:{}] removing {ent_type} of a purged owner..", entity.id.unwrap());
+                run.num_purged_entities += 1;
+                chunk_modified = true;
+                continue;
+            } else if dropped_items.contains(&i) {
+                println!("[entity:{}] cleaning up dropped item..", entity.id.unwrap());
+                run.num_items_removed += 1;
+                chunk_modified = true;
+                continue;
+            } else if config.actors.stale == StaleActors::Remove && entities::stale_actor(&entity, &ent_type) {
+                println!("[entity:{}] removing unowned {ent_type}..", entity.id.unwrap());
+                run.num_entities_removed += 1;
+                chunk_modified = true;
+                continue;
+            }
+
+            // stale spawned actors get frozen along with wheels and balls, if they weren't removed above
+            let stale_actor = config.actors.stale == StaleActors::Freeze && entities::stale_actor(&entity, &ent_type);
+
+            // NaN or infinite transforms crash the game on load
+            let fixed = entities::sanitize(&mut entity);
+            if !fixed.is_empty() {
+                println!("[entity:{}] {ent_type} has broken {}, reset them", entity.id.unwrap(), fixed.join(", "));
+                run.num_values_sanitized += 1;
+                run.num_entities_modified += 1;
+                chunk_modified = true;
+            }
+
+            // flung around by a physics explosion, put it back where it was and keep it there
+            let runaway = entities::runaway(&entity, &config.entities, &freezing.history);
+            if let Some(reason) = &runaway {
+                let id = entity.id.unwrap();
+                let reset = match entities::last_sane(id, &config.entities, &freezing.history) {
+                    Some(sane) => {
+                        entity.location = sane.location;
+                        entity.rotation = sane.rotation;
+                        let l = sane.location;
+                        format!("{ent_type} {id} {reason}, reset it to ({:.0}, {:.0}, {:.0}) and froze it", l.x, l.y, l.z)
+                    }
+                    None => format!("{ent_type} {id} {reason}, froze it where it is (no earlier spot to put it back to)"),
+                };
+                println!("[entity:{id}] {reset}");
+                entities::stop(&mut entity);
+                entity.frozen = true;
+                run.runaway_resets.push(format!("{world}: {reset}"));
+                run.num_entities_modified += 1;
+                chunk_modified = true;
+            }
+
+            // vehicles over the wheel or active grid budget get frozen as a whole
+            let heavy_vehicle = entity.id.is_some_and(|id| freezing.grids.contains(&id));
+            let unmoved = entity.id.is_some_and(|id| freezing.unmoved.contains(&id));
+
+            // wheels, balls/spheres (or whatever's configured) and stale actors are what the freeze pass goes after, or everything but vehicles
+            let target = match &freezing.spared {
+                Some(vehicles) => !entity.id.is_some_and(|id| vehicles.contains(&id)),
+                None => freezing.types.iter().any(|t| t.matches(&ent_type)) || stale_actor,
+            };
+
+            // unfreezing turns that around, to undo an earlier freeze sweep. vehicles over a budget still get frozen
+            if config.entities.unfreeze && target && !heavy_vehicle && runaway.is_none() {
+                if entity.frozen {
+                    println!("[entity:{}] unfreezing {ent_type}..", entity.id.unwrap());
+                    entity.frozen = false;
+                    run.num_entities_modified += 1;
+                    chunk_modified = true;
+                }
+            } else if target || heavy_vehicle || unmoved {
+                // if this entity isn't frozen yet
+                if !entity.frozen {
+                    // then freeze it
+                    if unmoved && !target && !heavy_vehicle {
+                        println!("[entity:{}] {ent_type} hasn't moved in a while, freezing it..", entity.id.unwrap());
+                        run.num_unmoved_frozen += 1;
+                    } else {
+                        println!("[entity:{}] freezing {ent_type}..", entity.id.unwrap());
+                    }
+                    entity.frozen = true;
+                    run.num_entities_modified += 1;
+                    chunk_modified = true;
+                }
+
+                /*
+                 * a frozen entity keeps whatever speed it had,
+                 * and gets jolted with it as soon as someone unfreezes it
+                 */
+                if entities::stop(&mut entity) {
+                    chunk_modified = true;
+                }
+            }
+
+            // the owner table got pruned, so point the entity at its owner's new index
+            if let Some(owners) = rewrites.owners
+                && let Some(owner) = entity.owner_index
+                && owners.owner(owner) != owner
+            {
+                entity.owner_index = Some(owners.owner(owner));
+                chunk_modified = true;
+            }
+
+            // add a new entity to our SoA
+            soa.add_entity(&global_data, &entity, entity.id.unwrap() as u32);
+            num_chunk_entities += 1;
+            if entity.frozen {
+                num_chunk_frozen += 1;
+            }
+        }
+
+        run.expected.entity_chunks.push((world.to_string(), chunk, num_chunk_entities, num_chunk_frozen));
+
+        // convert our entity SoA into a brdb .mps file that will be written to the brdb later
+        // this contains the values for the properties of all the entities
+        entity_chunk_files.push(patch::chunk_file(chunk, soa.to_bytes(&entity_schema)?));
+
+        if chunk_modified {
+            // keep an untouched copy of this chunk in the undo file
+            let mut original = EntityChunkSoA::default();
+            for entity in db.entity_chunk(chunk)? {
+                original.add_entity(&global_data, &entity, entity.id.unwrap() as u32);
+            }
+            undo_entity_chunk_files.push(patch::chunk_file(chunk, original.to_bytes(&entity_schema)?));
+        }
+    }
+
+    /*
+     * write all the entity chunk files we created
+     * into the brdb file, as a new revision (patch)
+     */
+    let entities_path = ["World", world, "Entities", "Chunks"];
+    Ok((
+        patch::nested(&entities_path, entity_chunk_files),
+        patch::nested(&entities_path, undo_entity_chunk_files),
+    ))
+}
+
+// ------------------
+// Optimize components
+// ------------------
+
+// which entities get frozen besides the usual ones
+struct Freezing {
+    // the entity types that get frozen, like wheels and balls
+    types: Vec<Pattern>,
+    // vehicles over the wheel budget or the active grid budget, frozen as a whole
+    grids: Vec<usize>,
+    // when freezing everything, the entities of vehicles with an engine, which are left alone
+    spared: Option<HashSet<usize>>,
+    // entities that are in the same spot as they were a few revisions ago, frozen no matter their type
+    unmoved: HashSet<usize>,
+    // where every entity was in the older revisions that were checked out, newest first
+    history: Vec<HashMap<usize, Transform>>,
+}
+
+// world wide changes that reach into the chunks of every grid
+struct Rewrites<'a> {
+    // bricks of small grids that got welded into the main grid
+    welded: &'a Welded,
+    // compacted name tables, that bricks have to be pointed at
+    names: Option<&'a Compaction>,
+    // pruned owner table, that bricks and entities have to be pointed at
+    owners: Option<&'a Pruning>,
+    // loops of joints to break, if they get broken
+    joint_loops: Option<&'a Loops>,
+    // owner indices of banned players, whose bricks, components and entities get removed
+    purged: &'a HashSet<u32>,
+    // the newest bricks of people over the brick budget, that get removed
+    over_budget: &'a BudgetCuts,
+}
+
+fn optimize_components(
+    db: &BrReader<Brdb>,
+    world: &str,
+    grids: &Grids,
+    rewrites: &Rewrites,
+    options: &Options,
+    config: &Config,
+    run: &mut Run,
+) -> Result<(BrPendingFs, BrPendingFs), Box<dyn std::error::Error>> {
+    println!("optimizing components..");
+    let Rewrites { welded, names, owners, joint_loops, purged, over_budget } = *rewrites;
+
+    let component_schema = db.components_schema()?;
+    let brick_schema = db.bricks_schema()?;
+    let wire_schema = db.wires_schema()?;
+
+    // owners whose physics modifiers are left alone
+    let modifier_exempt = if config.modifiers.exempt_owners.is_empty() {
+        Default::default()
+    } else {
+        owners::find(&db.owners_soa()?, &config.modifiers.exempt_owners)
+    };
+    let global_data = db.global_data()?;
+    let kinds = rules::Kinds::new(&config.component_patterns)?;
+    // how many lights everyone has so far, for the light quota
+    let mut lights_per_owner = HashMap::new();
+
+    // removing bricks or joints breaks wires pointing at them, so chunks with wires are left alone
+    let wired = if config.bricks.remove_duplicates
+        || config.bricks.strip_hidden
+        || config.bricks.merge
+        || config.bricks.consolidate_microbricks
+        || config.joints.remove_duplicates
+        || joint_loops.is_some()
+        || rulesets::removes(&config.rulesets.loaded)
+        || !purged.is_empty()
+        || !over_budget.is_empty()
+    {
+        bricks::wired_chunks(db, &grids.all())?
+    } else {
+        Default::default()
+    };
+
+    /*
+     * this will contain a modified copy
+     * of all brick grids
+     */
+    let mut brick_grids_folder = vec![];
+    // and this one an untouched copy of every chunk we modified, for the undo file
+    let mut undo_brick_grids_folder = vec![];
+
+    // loop through all grids
+    for grid in &grids.all() {
+        // get all chunks in the grid
+        let chunks = db.brick_chunk_index(*grid)?;
+        let mut chunk_files = vec![];
+        let mut undo_chunk_files = vec![];
+        let mut num_grid_modified = 0;
+        let mut grid_repaired = false;
+        // brick/component counts that changed and chunks that got removed, for the chunk index
+        let mut index_changes = bricks::IndexChanges::default();
+        // files outside of the Components folder (brick chunks, tombstones), as (folder, file)
+        let mut other_files = vec![];
+        let mut undo_other_files = vec![];
+
+        /*
+         * which bricks get removed from this grid.
+         * hidden bricks still add to the weight of physics grids, so those keep them.
+         * merging is kept to the main grid too, where imported builds end up
+         */
+        let passes = bricks::Passes {
+            dedupe: config.bricks.remove_duplicates,
+            strip_hidden: config.bricks.strip_hidden && grids.is_root(*grid),
+            merge: config.bricks.merge && grids.is_root(*grid),
+            consolidate: (config.bricks.consolidate_microbricks && grids.is_root(*grid))
+                .then_some((config.bricks.min_microbricks, config.bricks.microbrick_fill)),
+            purged,
+            over_budget,
+        };
+
+        // chunks that got bricks welded into them, with all of their bricks
+        let no_welds = Default::default();
+        let welded_chunks = if *grid == welded.target { &welded.chunks } else { &no_welds };
+        let existing_chunks: Vec<ChunkIndex> = chunks.iter().map(|c| **c).collect();
+
+        // how to treat weights on this grid, if it's a dynamic one
+        let mut dynamic_weights = config.weights.dynamic_grids;
+        if !grids.is_root(*grid)
+            && dynamic_weights != DynamicWeights::Off
+            && config.weights.dynamic_skip_engines
+            && grids::has_engine(db, *grid)?
+        {
+            println!("[grid:{grid}] has an engine, leaving its weights alone");
+            dynamic_weights = DynamicWeights::Off;
+        }
+
+        /*
+         * chunks that have nothing left in them (everything in them got deleted, but the files stayed)
+         * get deleted entirely, so the world doesn't keep carrying them around
+         */
+        for chunk in chunks.iter().filter(|c| {
+            c.num_bricks == 0 && c.num_components == 0 && c.num_wires == 0 && !welded_chunks.contains_key(&**c)
+        }) {
+            println!("[grid:{grid}][{}] chunk is empty, removing it..", **chunk);
+            for (folder, tombstone, original) in bricks::delete_chunk(db, *grid, **chunk) {
+                other_files.push((folder, tombstone));
+                undo_other_files.push((folder, original));
+            }
+            index_changes.removed.push(**chunk);
+            run.expected.removed_chunks.push((world.to_string(), *grid, **chunk));
+        }
+        run.num_empty_chunks_removed += index_changes.removed.len();
+
+        // loop through all chunks in this grid
+        for chunk in chunks {
+            // passes over the bricks themselves
+            let mut brick_map = None;
+            let mut new_bricks = welded_chunks.get(&*chunk).cloned();
+            if passes.any()
+                && chunk.num_bricks > 0
+                && !wired.contains(&(*grid, *chunk))
+            {
+                let bricks = match &new_bricks {
+                    Some(bricks) => bricks.clone(),
+                    None => db.brick_chunk_soa(*grid, *chunk)?,
+                };
+                if let Some(((retained, map), removed)) =
+                    bricks::remove(db, &global_data, *grid, *chunk, chunk.num_components, &bricks, &passes)?
+                {
+                    run.num_duplicate_bricks_removed += removed.duplicates;
+                    run.num_hidden_bricks_removed += removed.hidden;
+                    run.num_bricks_merged += removed.merged;
+                    run.num_microbricks_consolidated += removed.consolidated;
+                    run.num_purged_bricks += removed.purged;
+                    run.num_budget_bricks_removed += removed.over_budget;
+                    for (owner, count) in &removed.over_budget_by_owner {
+                        *run.budget_bricks_removed.entry(*owner).or_default() += count;
+                    }
+                    new_bricks = Some(retained);
+                    brick_map = Some(map);
+                }
+            }
+            // compacted name and owner tables move the indices bricks point at
+            if (names.is_some() || owners.is_some())
+                && (chunk.num_bricks > 0 || new_bricks.is_some())
+            {
+                let mut changed = new_bricks.is_some();
+                let mut bricks = match new_bricks.take() {
+                    Some(bricks) => bricks,
+                    None => db.brick_chunk_soa(*grid, *chunk)?,
+                };
+                if let Some(names) = names {
+                    changed |= names.remap(&mut bricks);
+                }
+                if let Some(owners) = owners {
+                    changed |= owners.remap(&mut bricks);
+                }
+                if changed {
+                    new_bricks = Some(bricks);
+                }
+            }
+            if let Some(new_bricks) = new_bricks {
+                let num_bricks = new_bricks.brick_type_indices.len();
+                other_files.push(("Chunks", patch::chunk_file(*chunk, new_bricks.to_bytes(&brick_schema)?)));
+                undo_other_files.push(("Chunks", bricks::original_file(db, *grid, "Chunks", *chunk)?));
+                index_changes.bricks.push((*chunk, num_bricks as u32));
+                run.expected.brick_chunks.push((world.to_string(), *grid, *chunk, num_bricks));
+            }
+
+            // passes over the chunk's wires
+            if config.wires.remove_duplicates
+                && chunk.num_wires > 1
+                && let Ok(mut wire_soa) = db.wire_chunk_soa(*grid, *chunk)
+            {
+                let removed = wires::dedupe(*grid, *chunk, &mut wire_soa);
+                if removed > 0 {
+                    let num_wires = wire_soa.local_wire_sources.len() + wire_soa.remote_wire_sources.len();
+                    run.num_duplicate_wires_removed += removed;
+                    other_files.push(("Wires", patch::chunk_file(*chunk, wire_soa.to_bytes(&wire_schema)?)));
+                    undo_other_files.push(("Wires", bricks::original_file(db, *grid, "Wires", *chunk)?));
+                    index_changes.wires.push((*chunk, num_wires as u32));
+                    run.expected.wire_chunks.push((world.to_string(), *grid, *chunk, num_wires));
+                }
+            }
+
+            // skip if there are no components
+            if chunk.num_components == 0 {
+                continue;
+            }
+
+            // get component data: the SoA (StructureOfArrays) and the actual components
+            let (mut soa, mut components) = match db.component_chunk(*grid, *chunk) {
+                Ok(value) => value,
+                Err(e) => {
+                    // skip corrupt chunks
+                    
+                    println!("[grid:{grid}][{}] found corrupt chunk! corruption: {e}", *chunk);
+                    run.quarantine.add_component_chunk(db, world, *grid, *chunk, &e)?;
+
+                    if options.repair {
+                        /*
+                         * drop all components in the chunk by writing an empty component chunk over it.
+                         * the bricks themselves are kept, they just lose their components
+                         * (and the original can't be put back with undo, since it can't be read)
+                         */
+                        println!("[grid:{grid}][{}] repairing: replacing it with an empty component chunk..", *chunk);
+                        chunk_files.push(patch::chunk_file(
+                            *chunk,
+                            ComponentChunkSoA::default().to_bytes(&component_schema)?,
+                        ));
+                        index_changes.components.push((*chunk, 0));
+                        grid_repaired = true;
+                        run.num_chunks_repaired += 1;
+                    } else {
+                        // if a corrupt chunk was found, dont risk saving the database
+                        run.corrupted = true;
+                    }
+                    continue
+                }
+            };
+
+            let mut num_chunk_modified = 0;
+
+            // passes that look at all components in the chunk at once
+            let num_components = components.len();
+            let orphans = components::remove_orphans(*grid, *chunk, chunk.num_bricks, &mut soa, &mut components);
+            run.num_orphans_removed += orphans;
+            num_chunk_modified += orphans;
+            // the components on bricks of purged owners, whose bricks got removed above
+            let purged_components = owners::purge(
+                db,
+                *grid,
+                *chunk,
+                wired.contains(&(*grid, *chunk)),
+                &mut soa,
+                &mut components,
+                purged,
+            )?;
+            run.num_purged_components += purged_components;
+            num_chunk_modified += purged_components;
+            // joint indices are from before any other joints got removed, so this goes first
+            if let Some(breaks) = joint_loops.and_then(|l| l.breaks.get(&(*grid, *chunk))) {
+                if wired.contains(&(*grid, *chunk)) {
+                    println!("[grid:{grid}][{}] has wires, can't break the loop of joints here", *chunk);
+                } else {
+                    let broken: Vec<usize> = breaks.iter().copied().collect();
+                    let joints = components::break_joints(*grid, *chunk, &mut soa, &mut components, &broken);
+                    run.num_joints_broken += joints;
+                    num_chunk_modified += joints;
+                }
+            }
+            if config.joints.remove_duplicates && !wired.contains(&(*grid, *chunk)) {
+                let joints = components::remove_duplicate_joints(*grid, *chunk, &mut soa, &mut components);
+                run.num_duplicate_joints_removed += joints;
+                num_chunk_modified += joints;
+            }
+            let collapsed = lights::dedupe(db, *grid, *chunk, &mut soa, &mut components, &config.lights, &mut run.props)?;
+            run.num_lights_collapsed += collapsed;
+            num_chunk_modified += collapsed;
+            let disabled = lights::budget(db, *grid, *chunk, &soa, &mut components, &config.lights, &mut run.props)?;
+            run.num_lights_disabled += disabled;
+            num_chunk_modified += disabled;
+            let over_quota = lights::owner_quota(
+                db,
+                *grid,
+                *chunk,
+                &soa,
+                &mut components,
+                &config.lights,
+                &mut lights_per_owner,
+                &mut run.props,
+            )?;
+            run.num_lights_over_quota += over_quota;
+            num_chunk_modified += over_quota;
+            let removed = rulesets::remove(
+                *grid,
+                *chunk,
+                wired.contains(&(*grid, *chunk)),
+                &mut soa,
+                &mut components,
+                &config.rulesets.loaded,
+                &mut run.props,
+            );
+            run.num_ruleset_removed += removed;
+            num_chunk_modified += removed;
+
+            if components.len() != num_components {
+                index_changes.components.push((*chunk, components.len() as u32));
+            }
+
+            // who owns each component, only looked up when something needs to know
+            let component_owners = if modifier_exempt.is_empty() {
+                vec![]
+            } else {
+                owners::of_components(db, *grid, *chunk, &soa)?
+            };
+
+            // bricks got removed from the chunk, so point the components at their brick's new index
+            if let Some(map) = &brick_map {
+                components::remap_bricks(&mut soa, map);
+                num_chunk_modified += 1;
+            }
+
+            // loop through components in this chunk
+            for (i, mut component) in components.into_iter().enumerate() {
+                let component_name = String::from(component.get_name());
+                let mut modified: bool = false;
+
+                let mut ctx = rules::Ctx {
+                    grid: *grid,
+                    chunk: *chunk,
+                    name: &component_name,
+                    owner: component_owners.get(i).copied(),
+                    kinds: &kinds,
+                    props: &mut run.props,
+                    clamps: config.rulesets.clamps.as_ref(),
+                };
+
+                // broken values first, so the rules below can work with what's left
+                let sanitized = rules::sanitize_floats(&mut component, &mut ctx);
+                if sanitized {
+                    run.num_values_sanitized += 1;
+                }
+                modified |= sanitized;
+
+                if grids.is_root(*grid) {
+                    /*
+                     * main grid (usually grid 1)
+                     * this is the root grid, anything that's not a physics grid or entity
+                     */
+                    modified |= rules::neutralize_weight(&mut component, &mut ctx);
+                    modified |= rules::neutralize_engine_weight(&mut component, &mut ctx);
+                } else {
+                    // dynamic grids only get their weights touched if the config says so
+                    modified |= match dynamic_weights {
+                        DynamicWeights::Off => false,
+                        DynamicWeights::Clamp => rules::clamp_weight(&mut component, config.weights.dynamic_max_mass, &mut ctx),
+                        DynamicWeights::Neutralize => rules::neutralize_weight(&mut component, &mut ctx),
+                    };
+                }
+
+                /*
+                if component.prop("bAnglesArePercentages").is_ok() {
+                    component.set_prop("bAnglesArePercentages", BrdbValue::Bool(false));
+                }
+                */
+
+                modified |= rules::limit_light(&mut component, &config.lights, &mut ctx);
+                modified |= rules::limit_spot_cone(&mut component, &config.lights, &mut ctx);
+                modified |= rules::limit_emissive(&mut component, &config.lights, &mut ctx);
+                modified |= rules::throttle_spawner(&mut component, &config.spawners, &mut ctx);
+                modified |= rules::limit_emitter(&mut component, &config.particles, &mut ctx);
+                modified |= rules::limit_motor(&mut component, &config.motors, &mut ctx);
+                modified |= rules::limit_thruster(&mut component, &config.thrusters, &mut ctx);
+                modified |= rules::limit_text(&mut component, &config.text, &mut ctx);
+                modified |= rules::throttle_sensor(&mut component, &config.sensors, &mut ctx);
+                modified |= rules::limit_modifier(&mut component, &config.modifiers, &modifier_exempt, &mut ctx);
+                // then the [clamp] entries, which the rules above left their properties to
+                modified |= rulesets::apply(&mut component, config.rulesets.clamps.as_slice(), &mut ctx);
+                // shared rulesets last, so they get the last word
+                modified |= rulesets::apply(&mut component, &config.rulesets.loaded, &mut ctx);
+
+                if modified {
+                    num_grid_modified += 1;
+                    num_chunk_modified += 1;
+                    run.num_components_modified += 1;
+                }
+
+                /*
+                 * add the component to the current chunk's component StructureOfArrays
+                 * IMPORTANT: regardless of if we modified it!
+                 * because we're copying ALL components into the new file
+                 */
+                soa.unwritten_struct_data.push(Box::new(component));
+            }
+
+            if num_chunk_modified > 0 {
+                /*
+                 * now take the new chunk's SoA
+                 * and convert it to an .mps file
+                 * and add it to the vector array of files
+                 * that we will write to the correct folder later
+                 *
+                 * example vector array:
+                 *  - -1_-1_-1.mps
+                 *  - 0_0_0.mps
+                 * eventually becomes, in the filesystem:
+                 *  - /World/<world>/Bricks/Grids/1/Components/-1_-1_-1.mps
+                 *  - /World/<world>/Bricks/Grids/1/Components/0_0_0.mps
+                 *
+                 * properties that are still at their default value get written too.
+                 * the schema lists every property of a component type and every component has to have
+                 * all of them, the format has no way to leave one out (and the schema has no defaults to fall back on)
+                 *
+                 * the same goes for components that are exact copies of each other (copy-pasted lights):
+                 * every one gets its own entry, the format can't point several components at the same data.
+                 * brdb compresses every chunk file though, so the copies cost a lot less than they seem to
+                 */
+                chunk_files.push(patch::chunk_file(*chunk, soa.to_bytes(&component_schema)?));
+                run.expected.component_chunks.push((world.to_string(), *grid, *chunk, soa.unwritten_struct_data.len()));
+
+                // re-read the chunk to get the original, unmodified components for the undo file
+                let (mut original, components) = db.component_chunk(*grid, *chunk)?;
+                for component in components {
+                    original.unwritten_struct_data.push(Box::new(component));
+                }
+                undo_chunk_files.push(patch::chunk_file(*chunk, original.to_bytes(&component_schema)?));
+            }
+        }
+
+        // chunks that didn't exist before, but got bricks welded into them
+        for (chunk, new_bricks) in welded_chunks {
+            if existing_chunks.contains(chunk) {
+                continue;
+            }
+            let mut new_bricks = new_bricks.clone();
+            if let Some(names) = names {
+                names.remap(&mut new_bricks);
+            }
+            if let Some(owners) = owners {
+                owners.remap(&mut new_bricks);
+            }
+            let num_bricks = new_bricks.brick_type_indices.len();
+            other_files.push(("Chunks", patch::chunk_file(*chunk, new_bricks.to_bytes(&brick_schema)?)));
+            // undoing deletes them again
+            undo_other_files.push(("Chunks", patch::tombstone(*chunk)));
+            index_changes.added.push((*chunk, num_bricks as u32));
+            run.expected.brick_chunks.push((world.to_string(), *grid, *chunk, num_bricks));
+        }
+
+        if num_grid_modified > 0 || grid_repaired || !other_files.is_empty() {
+            println!(
+                "[grid:{grid}] {num_grid_modified} components optimized"
+            );
+
+            /* 
+             * now create a folder for the loop's current brick grid,
+             * such as /World/0/Bricks/Grids/1/
+             * then create a folder called Components inside it,
+             * and insert all the chunk mps files we created earlier
+             * (plus an updated chunk index, if the number of components changed).
+             * example:
+             *  - /World/0/Bricks/Grids/
+             *      - 1/ (this is the level we're currently working with)
+             *          - Components/
+             *              - -1_-1_-1.mps
+             *              - 0_0_0.mps
+             */
+            let mut grid_folder = vec![(
+                "Components".to_string(),
+                BrPendingFs::Folder(Some(chunk_files)),
+            )];
+            let mut undo_grid_folder = vec![(
+                "Components".to_string(),
+                BrPendingFs::Folder(Some(undo_chunk_files)),
+            )];
+
+            // brick chunks, and tombstones for the files of removed chunks
+            for (folder, file) in other_files {
+                patch::push_into(&mut grid_folder, folder, file);
+            }
+            for (folder, file) in undo_other_files {
+                patch::push_into(&mut undo_grid_folder, folder, file);
+            }
+
+            // if chunks lost bricks or components or got removed, the grid's chunk index needs to know
+            if !index_changes.is_empty() {
+                let (index_file, undo_index_file) = bricks::chunk_index_files(db, *grid, &index_changes)?;
+                grid_folder.push(index_file);
+                undo_grid_folder.push(undo_index_file);
+            }
+
+            brick_grids_folder.push((grid.to_string(), BrPendingFs::Folder(Some(grid_folder))));
+            undo_brick_grids_folder.push((grid.to_string(), BrPendingFs::Folder(Some(undo_grid_folder))));
+        }
+    }
+
+    /*
+     * create a revision (patch) out of all the
+     * component data we gathered earlier
+     */
+    let grids_path = ["World", world, "Bricks", "Grids"];
+    Ok((
+        patch::nested(&grids_path, brick_grids_folder),
+        patch::nested(&grids_path, undo_brick_grids_folder),
+    ))
+}
@@ -0,0 +1,281 @@
+/*
+ * figuring out who built what
+ *
+ * every world has an owner table with everyone who ever built in it,
+ * and every brick stores the index of its owner in that table.
+ * bricks count owners from 1, 0 means the brick doesn't belong to anyone (public)
+ */
+
+use std::collections::{HashMap, HashSet};
+use brdb::{
+    BrReader, Brdb, BrdbComponent, BrickChunkSoA, ChunkIndex, ComponentChunkSoA, OwnerTableSoA, pending::BrPendingFs,
+    schema::BrdbStruct,
+};
+
+use crate::{components::{self, JOINT_COMPONENTS}, patch::Entry};
+
+pub const OWNERS_FILE: &str = "Owners.mps";
+
+/*
+ * the owner table without everyone who doesn't own anything anymore.
+ * removing someone moves everyone after them up, so bricks and entities
+ * have to be pointed at their owner's new index
+ */
+pub struct Pruning {
+    // the new index of every old owner index (0 stays 0)
+    map: Vec<u32>,
+    // the user names of everyone who got removed
+    pub removed: Vec<String>,
+    pub table: OwnerTableSoA,
+}
+
+impl Pruning {
+    // an owner's new index
+    pub fn owner(&self, old: u32) -> u32 {
+        self.map.get(old as usize).copied().unwrap_or(old)
+    }
+
+    // points a chunk's bricks at their owner's new index, returns whether any of them changed
+    pub fn remap(&self, bricks: &mut BrickChunkSoA) -> bool {
+        let mut changed = false;
+        for owner in bricks.owner_indices.iter_mut() {
+            let new = self.owner(*owner);
+            if new != *owner {
+                *owner = new;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /*
+     * the pruned Owners.mps.
+     * returns (pruned file, original file), the original is for the undo file
+     */
+    pub fn files(&self, db: &BrReader<Brdb>) -> Result<(Entry, Entry), Box<dyn std::error::Error>> {
+        let schema = db.owners_schema()?;
+        let bytes = self.table.to_bytes(&schema)?;
+        let original = db.read_file(format!("World/0/{OWNERS_FILE}"))?;
+        Ok((
+            (OWNERS_FILE.to_string(), BrPendingFs::File(Some(bytes))),
+            (OWNERS_FILE.to_string(), BrPendingFs::File(Some(original))),
+        ))
+    }
+}
+
+/*
+ * finds everyone in the owner table who doesn't own a single brick or entity in `grids` anymore,
+ * like people who joined once, built something and had it cleared.
+ * returns None if everyone still owns something
+ */
+pub fn unused(db: &BrReader<Brdb>, grids: &[usize]) -> Result<Option<Pruning>, Box<dyn std::error::Error>> {
+    let table = db.owners_soa()?;
+    let mut used = vec![false; table.user_ids.len() + 1];
+    // 0 isn't anyone, it always stays
+    used[0] = true;
+
+    for grid in grids {
+        for chunk in db.brick_chunk_index(*grid)? {
+            if chunk.num_bricks == 0 {
+                continue;
+            }
+            // a chunk we can't read might belong to anyone, so nobody gets removed
+            let Ok(bricks) = db.brick_chunk_soa(*grid, *chunk) else {
+                println!("[grid:{grid}][{}] can't read bricks, not pruning the owner table", *chunk);
+                return Ok(None);
+            };
+            for owner in &bricks.owner_indices {
+                if let Some(used) = used.get_mut(*owner as usize) {
+                    *used = true;
+                }
+            }
+        }
+    }
+    for chunk in db.entity_chunk_index()? {
+        for entity in db.entity_chunk(chunk)? {
+            if let Some(used) = entity.owner_index.and_then(|owner| used.get_mut(owner as usize)) {
+                *used = true;
+            }
+        }
+    }
+
+    if used.iter().all(|used| *used) {
+        return Ok(None);
+    }
+
+    let mut map = Vec::with_capacity(used.len());
+    let mut next = 0;
+    for used in &used {
+        map.push(if *used { next } else { 0 });
+        if *used {
+            next += 1;
+        }
+    }
+
+    let mut pruned = OwnerTableSoA::default();
+    let mut removed = vec![];
+    for i in 0..table.user_ids.len() {
+        if !used[i + 1] {
+            removed.push(table.user_names.get(i).cloned().unwrap_or_default());
+            continue;
+        }
+        keep(&mut pruned.user_ids, &table.user_ids, i);
+        keep(&mut pruned.user_names, &table.user_names, i);
+        keep(&mut pruned.display_names, &table.display_names, i);
+        keep(&mut pruned.entity_counts, &table.entity_counts, i);
+        keep(&mut pruned.brick_counts, &table.brick_counts, i);
+        keep(&mut pruned.component_counts, &table.component_counts, i);
+        keep(&mut pruned.wire_counts, &table.wire_counts, i);
+    }
+
+    Ok(Some(Pruning { map, removed, table: pruned }))
+}
+
+fn keep<T: Clone>(to: &mut Vec<T>, from: &[T], i: usize) {
+    if let Some(value) = from.get(i) {
+        to.push(value.clone());
+    }
+}
+
+/*
+ * the owner indices (as bricks store them) of everyone matching one of `names`.
+ * a name can be someone's user name, display name or user id
+ */
+pub fn find(table: &OwnerTableSoA, names: &[String]) -> HashSet<u32> {
+    let mut found = HashSet::new();
+    for i in 0..table.user_ids.len() {
+        let matches = names.iter().any(|name| {
+            table.user_names.get(i) == Some(name)
+                || table.display_names.get(i) == Some(name)
+                || table.user_ids[i].to_string().eq_ignore_ascii_case(name)
+        });
+        if matches {
+            found.insert(i as u32 + 1);
+        }
+    }
+    found
+}
+
+/*
+ * removes the components of purged owners (banned players, see `purge` under [owners]) from the chunk,
+ * returns how many were removed. their bricks go in bricks::remove, which keeps the bricks with joints,
+ * so joint components stay here too. chunks with wires are left alone altogether
+ */
+pub fn purge(
+    db: &BrReader<Brdb>,
+    grid: usize,
+    chunk: ChunkIndex,
+    wired: bool,
+    soa: &mut ComponentChunkSoA,
+    components: &mut Vec<BrdbStruct>,
+    purged: &HashSet<u32>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    if purged.is_empty() || wired {
+        return Ok(0);
+    }
+
+    let owners = of_components(db, grid, chunk, soa)?;
+    let removed: Vec<usize> = components.iter()
+        .enumerate()
+        .filter(|(i, component)| {
+            owners.get(*i).is_some_and(|owner| purged.contains(owner)) && !JOINT_COMPONENTS.contains(&component.get_name())
+        })
+        .map(|(i, _)| i)
+        .collect();
+    if removed.is_empty() {
+        return Ok(0);
+    }
+
+    println!("[grid:{grid}][{chunk}] removing {} components of purged owners..", removed.len());
+    components::remove(soa, components, &removed);
+    Ok(removed.len())
+}
+
+// the owner index of every component in a chunk (the owner of the brick it sits on)
+pub fn of_components(
+    db: &BrReader<Brdb>,
+    grid: usize,
+    chunk: ChunkIndex,
+    soa: &ComponentChunkSoA,
+) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+    let bricks = db.brick_chunk_soa(grid, chunk)?;
+    Ok(soa.component_brick_indices.iter()
+        .map(|brick| bricks.owner_indices.get(*brick as usize).copied().unwrap_or(0))
+        .collect())
+}
+
+// how many of whose newest bricks go from every chunk, as (grid, chunk) -> owner index -> bricks
+pub type BudgetCuts = HashMap<(usize, ChunkIndex), HashMap<u32, usize>>;
+
+// how many bricks someone has in every chunk, as ((grid, chunk), bricks)
+type OwnerChunks = Vec<((usize, ChunkIndex), usize)>;
+
+// someone with more bricks than the budget
+pub struct OverBudget {
+    // index in the owner table, like bricks store it
+    pub owner: u32,
+    pub name: String,
+    pub bricks: usize,
+}
+
+/*
+ * everyone with more than `max` bricks in `grids` (the main grid), and which of their bricks to remove
+ * to get them back within budget: the newest ones. bricks don't know when they were placed,
+ * so newest goes by the order they're stored in: new bricks go at the end of their chunk,
+ * and chunks that get built in for the first time go at the end of the chunk index.
+ * chunks with wires (see bricks::wired_chunks) don't lose any bricks, so more go elsewhere.
+ * public bricks (owner 0) don't count towards anyone's budget
+ */
+pub fn over_brick_budget(
+    db: &BrReader<Brdb>,
+    grids: &[usize],
+    wired: &HashSet<(usize, ChunkIndex)>,
+    max: usize,
+) -> Result<(Vec<OverBudget>, BudgetCuts), Box<dyn std::error::Error>> {
+    // every owner's bricks per chunk, in the order the chunks are stored in
+    let mut per_owner: HashMap<u32, OwnerChunks> = HashMap::new();
+    for grid in grids {
+        for chunk in db.brick_chunk_index(*grid)? {
+            if chunk.num_bricks == 0 {
+                continue;
+            }
+            let Ok(bricks) = db.brick_chunk_soa(*grid, *chunk) else {
+                println!("[grid:{grid}][{}] can't read bricks, not counting them towards anyone's brick budget", *chunk);
+                continue;
+            };
+            let mut counts: HashMap<u32, usize> = HashMap::new();
+            for owner in bricks.owner_indices.iter().filter(|owner| **owner != 0) {
+                *counts.entry(*owner).or_default() += 1;
+            }
+            for (owner, count) in counts {
+                per_owner.entry(owner).or_default().push(((*grid, *chunk), count));
+            }
+        }
+    }
+
+    let table = db.owners_soa()?;
+    let mut over = vec![];
+    let mut cuts = BudgetCuts::new();
+    let mut owners: Vec<_> = per_owner.into_iter().collect();
+    owners.sort_by_key(|(owner, _)| *owner);
+    for (owner, chunks) in owners {
+        let bricks: usize = chunks.iter().map(|(_, count)| count).sum();
+        if bricks <= max {
+            continue;
+        }
+
+        let mut excess = bricks - max;
+        for (chunk, count) in chunks.iter().rev().filter(|(chunk, _)| !wired.contains(chunk)) {
+            if excess == 0 {
+                break;
+            }
+            let cut = excess.min(*count);
+            cuts.entry(*chunk).or_default().insert(owner, cut);
+            excess -= cut;
+        }
+
+        let name = table.user_names.get(owner as usize - 1).cloned().unwrap_or_else(|| format!("owner {owner}"));
+        over.push(OverBudget { owner, name, bricks });
+    }
+    Ok((over, cuts))
+}
@@ -0,0 +1,63 @@
+/*
+ * helpers for building patches (BrPendingFs trees)
+ * that get written into a brdb file as a new revision
+ */
+
+use brdb::pending::BrPendingFs;
+
+// a named file or folder inside a folder
+pub type Entry = (String, BrPendingFs);
+
+/*
+ * wraps a list of files/folders in the nested folders named by `path`
+ *
+ * example:
+ *  nested(&["World", "0", "Entities", "Chunks"], chunk_files)
+ * eventually becomes, in the filesystem:
+ *  - /World/0/Entities/Chunks/0_0_0.mps
+ *  - /World/0/Entities/Chunks/...
+ */
+pub fn nested(path: &[&str], contents: Vec<Entry>) -> BrPendingFs {
+    let mut node = BrPendingFs::Folder(Some(contents));
+    for name in path[1..].iter().rev() {
+        node = BrPendingFs::Folder(Some(vec![(name.to_string(), node)]));
+    }
+
+    BrPendingFs::Root(vec![(path[0].to_string(), node)])
+}
+
+// creates an .mps file entry for a chunk, named after its chunk index (e.g. 0_0_0.mps)
+pub fn chunk_file(chunk: impl std::fmt::Display, bytes: Vec<u8>) -> Entry {
+    (format!("{chunk}.mps"), BrPendingFs::File(Some(bytes)))
+}
+
+// a deletion marker for a chunk's .mps file, which removes it from the world when written
+pub fn tombstone(chunk: impl std::fmt::Display) -> Entry {
+    (format!("{chunk}.mps"), BrPendingFs::File(None))
+}
+
+// adds an entry to the subfolder `name` of a folder, creating the subfolder if it isn't there yet
+pub fn push_into(folder: &mut Vec<Entry>, name: &str, entry: Entry) {
+    match folder.iter_mut().find(|(n, _)| n == name) {
+        Some((_, BrPendingFs::Folder(Some(contents)))) => contents.push(entry),
+        _ => folder.push((name.to_string(), BrPendingFs::Folder(Some(vec![entry])))),
+    }
+}
+
+// looks up the contents of the folder at `path` in a tree, like /World/0/Bricks/Grids
+pub fn folder<'a>(tree: &'a BrPendingFs, path: &[&str]) -> Option<&'a Vec<Entry>> {
+    let BrPendingFs::Root(root) = tree else {
+        return None;
+    };
+
+    let mut contents = root;
+    for name in path {
+        let (_, node) = contents.iter().find(|(n, _)| n == name)?;
+        let BrPendingFs::Folder(Some(children)) = node else {
+            return None;
+        };
+        contents = children;
+    }
+
+    Some(contents)
+}
@@ -0,0 +1,76 @@
+/*
+ * estimating how much work a world's physics is for the server
+ *
+ * frozen things cost next to nothing, so only what can move is counted:
+ * unfrozen entities, the joints (bearings, sliders, ..) of unfrozen physics grids,
+ * and the bricks of unfrozen physics grids (bigger grids have bigger collision shapes).
+ * the score is those counts weighted by [physics_cost] in the config. it doesn't mean
+ * anything on its own, but it can be compared between worlds, and before and after optimizing
+ */
+
+use std::{collections::HashMap, path::Path};
+use brdb::{BrReader, Brdb, IntoReader};
+use serde::Serialize;
+
+use crate::{config::PhysicsCostConfig, grids::{self, Grids}, worlds};
+
+#[derive(Default, Serialize)]
+pub struct PhysicsCost {
+    pub unfrozen_entities: usize,
+    pub joints: usize,
+    pub dynamic_bricks: usize,
+    pub score: f64,
+}
+
+// the physics cost of the world `db` reads as World/0
+pub fn estimate(db: &BrReader<Brdb>, grids: &Grids, weights: &PhysicsCostConfig) -> Result<PhysicsCost, Box<dyn std::error::Error>> {
+    let mut cost = PhysicsCost::default();
+
+    let mut frozen = HashMap::new();
+    for chunk in db.entity_chunk_index()? {
+        for entity in db.entity_chunk(chunk)? {
+            if let Some(id) = entity.id {
+                frozen.insert(id, entity.frozen);
+            }
+            if !entity.frozen {
+                cost.unfrozen_entities += 1;
+            }
+        }
+    }
+
+    for grid in &grids.dynamic {
+        if frozen.get(grid).copied().unwrap_or(false) {
+            continue;
+        }
+        for chunk in db.brick_chunk_index(*grid)? {
+            cost.dynamic_bricks += chunk.num_bricks as usize;
+            if chunk.num_components == 0 {
+                continue;
+            }
+            // unreadable chunks get reported by the passes that need them
+            if let Ok((soa, _)) = db.component_chunk(*grid, *chunk) {
+                cost.joints += soa.joint_brick_indices.len();
+            }
+        }
+    }
+
+    cost.score = cost.unfrozen_entities as f64 * weights.unfrozen_entities
+        + cost.joints as f64 * weights.joints
+        + cost.dynamic_bricks as f64 * weights.dynamic_bricks;
+    Ok(cost)
+}
+
+// the combined physics score of every world in the file at `path`
+pub fn estimate_file(path: &Path, weights: &PhysicsCostConfig) -> Result<f64, Box<dyn std::error::Error>> {
+    let db = Brdb::open(path)?.into_reader();
+    let source = db.to_pending()?;
+
+    let mut score = 0.0;
+    for world in worlds::indices(&source) {
+        score += worlds::read_world(&db, &source, &world, path, |world_db| {
+            let grids = grids::find(world_db, &source, &world)?;
+            Ok(estimate(world_db, &grids, weights)?.score)
+        })?;
+    }
+    Ok(score)
+}
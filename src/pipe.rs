@@ -0,0 +1,61 @@
+/*
+ * optimizing a world that comes in on stdin, for backup pipelines
+ *
+ * `brdb_optimize [options] - < world.brdb > world.optimized.brdb`
+ * sqlite needs a real file, so the world gets spooled into a temporary folder,
+ * optimized there by running this tool again in a process of its own (with the same options),
+ * and the optimized world is written to stdout. stdout is the world, so everything
+ * the optimizer says goes to stderr instead.
+ * a world that didn't need optimizing comes out as it went in
+ */
+
+use std::{
+    env,
+    fs::{self, File},
+    io,
+    path::Path,
+    process::{self, Stdio},
+};
+
+use crate::archive;
+
+pub fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = env::temp_dir().join(format!("brdb_optimize_pipe_{}", process::id()));
+    fs::create_dir_all(&dir)?;
+    let result = spool(&dir);
+    fs::remove_dir_all(&dir)?;
+
+    let code = result?;
+    if code != 0 {
+        process::exit(code);
+    }
+    Ok(())
+}
+
+// optimizes stdin inside of `dir`, returns the exit code of the optimizer
+fn spool(dir: &Path) -> Result<i32, Box<dyn std::error::Error>> {
+    let world = dir.join("world.brdb");
+    io::copy(&mut io::stdin().lock(), &mut File::create(&world)?)?;
+
+    // everything we were started with, except the `-`
+    let options: Vec<String> = env::args().skip(1).filter(|a| a != "-").collect();
+    let status = process::Command::new(env::current_exe()?)
+        .args(&options)
+        .arg(&world)
+        .stdout(Stdio::from(io::stderr()))
+        .status()?;
+    if !status.success() {
+        eprintln!("[ERROR] optimizing failed, nothing was written to stdout.");
+        return Ok(status.code().unwrap_or(1));
+    }
+
+    let optimized = dir.join("world.optimized.brdb");
+    // archive_only in the config, which we couldn't check before without printing to stdout
+    if archive::only(&optimized).is_some() {
+        eprintln!("[ERROR] only the archive of the optimized world was kept (archive_only under [files]), nothing was written to stdout.");
+        return Ok(1);
+    }
+    let output = if optimized.exists() { optimized } else { world };
+    io::copy(&mut File::open(output)?, &mut io::stdout().lock())?;
+    Ok(0)
+}
@@ -0,0 +1,34 @@
+/*
+ * tolerant property access
+ *
+ * component schemas change between game versions. when a property we want
+ * isn't there (or has a different type), we'd rather skip the one rule that
+ * needs it than abort the whole run, so property reads go through here.
+ * each missing property is only warned about once per component type
+ */
+
+use std::{collections::HashSet, fmt::Display};
+
+#[derive(Default)]
+pub struct Props {
+    warned: HashSet<(String, String)>,
+}
+
+impl Props {
+    /*
+     * turns the result of reading `prop` from a `component` into an Option,
+     * logging a warning the first time it fails. example:
+     *  props.get(&name, "Radius", component.prop("Radius").and_then(|v| v.as_brdb_f32()))
+     */
+    pub fn get<T, E: Display>(&mut self, component: &str, prop: &str, value: Result<T, E>) -> Option<T> {
+        match value {
+            Ok(value) => Some(value),
+            Err(e) => {
+                if self.warned.insert((component.to_string(), prop.to_string())) {
+                    println!("[WARNING] can't read {prop} of {component} ({e}), skipping the rules that use it");
+                }
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+/*
+ * saves the raw data of corrupt chunks into a sidecar folder (world.corrupt/)
+ * so they can be looked at (or sent to the brdb maintainers)
+ * without needing the entire world file
+ *
+ * every corrupt chunk gets two files:
+ *  - world_0_grid_1_chunk_0_0_0.mps (the raw bytes, exactly as stored in the world)
+ *  - world_0_grid_1_chunk_0_0_0.txt (where it came from and what went wrong decoding it)
+ */
+
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+use brdb::{BrReader, Brdb, ChunkIndex};
+
+pub struct Quarantine {
+    pub dir: PathBuf,
+    pub count: u32,
+}
+
+impl Quarantine {
+    pub fn new(world: &Path) -> Self {
+        let stem = world.file_stem().unwrap().to_string_lossy();
+        Self {
+            dir: world.with_file_name(format!("{stem}.corrupt")),
+            count: 0,
+        }
+    }
+
+    pub fn add_component_chunk(
+        &mut self,
+        db: &BrReader<Brdb>,
+        world: &str,
+        grid: usize,
+        chunk: ChunkIndex,
+        error: &dyn Display,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(&self.dir)?;
+
+        // `db` sees the world as World/0 (see worlds.rs), the report uses its real index
+        let path = format!("Bricks/Grids/{grid}/Components/{chunk}.mps");
+        let name = format!("world_{world}_grid_{grid}_chunk_{chunk}");
+
+        let mut report = format!("file: World/{world}/{path}\ndecode error: {error}\n");
+        match db.read_file(format!("World/0/{path}")) {
+            Ok(bytes) => {
+                report.push_str(&format!("size: {} bytes\n", bytes.len()));
+                fs::write(self.dir.join(format!("{name}.mps")), bytes)?;
+            }
+            // the raw file itself couldn't be read either, at least keep the error
+            Err(e) => report.push_str(&format!("could not read raw file: {e}\n")),
+        }
+        fs::write(self.dir.join(format!("{name}.txt")), report)?;
+
+        self.count += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+/*
+ * optimizing worlds that live somewhere else, for backups kept in object storage
+ *
+ * `brdb_optimize [options] https://example.com/saves/world.brdb` (or s3://bucket/saves/world.brdb)
+ * downloads the world into a temporary folder, optimizes it there by running this tool again
+ * in a process of its own (with the same options), and then puts the optimized world
+ * wherever `--upload` (or `upload` under [remote]) says:
+ * - an https:// (or http://) url, which it gets PUT to
+ * - an s3:// url, which it gets copied to with the aws command line tool,
+ *   so its usual credentials and settings apply
+ * - a local file or folder
+ * a destination ending in / is a folder, the world keeps its file name in there.
+ * without a destination, the optimized world is written to the current folder.
+ * a world that didn't need optimizing gets put there as it was downloaded
+ */
+
+use std::{
+    env,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    process,
+};
+
+use crate::{archive, config::RemoteConfig};
+
+// whether `path` is a world somewhere else instead of a local file
+pub fn is_remote(path: &str) -> bool {
+    ["https://", "http://", "s3://"].iter().any(|scheme| path.starts_with(scheme))
+}
+
+pub fn run(url: &str, upload: Option<&str>, config: &RemoteConfig) -> Result<(), Box<dyn std::error::Error>> {
+    // the last part of the url, without a query
+    let name = url.split(['?', '#']).next().unwrap_or(url).rsplit('/').next().filter(|n| !n.is_empty()).unwrap_or("world.brdb");
+    let destination = upload.or(config.upload.as_deref()).map(|d| d.to_string());
+
+    let dir = env::temp_dir().join(format!("brdb_optimize_remote_{}", process::id()));
+    fs::create_dir_all(&dir)?;
+    let result = optimize(url, name, destination.as_deref(), &dir);
+    fs::remove_dir_all(&dir)?;
+
+    let code = result?;
+    if code != 0 {
+        process::exit(code);
+    }
+    Ok(())
+}
+
+// optimizes the world at `url` inside of `dir`, returns the exit code of the optimizer
+fn optimize(url: &str, name: &str, destination: Option<&str>, dir: &Path) -> Result<i32, Box<dyn std::error::Error>> {
+    let world = dir.join(name);
+    println!("downloading {url}..");
+    download(url, &world)?;
+
+    // everything we were started with, except the url and where to upload to
+    let mut options = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--upload" => {
+                args.next();
+            }
+            _ if arg == url => {}
+            _ => options.push(arg),
+        }
+    }
+    let status = process::Command::new(env::current_exe()?)
+        .args(&options)
+        .arg(&world)
+        .status()?;
+    if !status.success() {
+        println!("[ERROR] optimizing failed, nothing was uploaded.");
+        return Ok(status.code().unwrap_or(1));
+    }
+
+    let stem = world.file_stem().unwrap().to_string_lossy();
+    let optimized = world.with_file_name(format!("{stem}.optimized.brdb"));
+    if archive::only(&optimized).is_some() {
+        println!("[ERROR] only the archive of the optimized world was kept (archive_only), nothing was uploaded.");
+        return Ok(1);
+    }
+    let (output, output_name) = if optimized.exists() {
+        (optimized, format!("{stem}.optimized.brdb"))
+    } else {
+        println!("nothing changed, putting the world back as it was.");
+        (world.clone(), name.to_string())
+    };
+
+    match destination {
+        // a folder, so the world keeps its name
+        Some(destination) if destination.ends_with('/') => upload(&output, &format!("{destination}{name}")),
+        Some(destination) => upload(&output, destination),
+        None => {
+            fs::copy(&output, &output_name)?;
+            println!("wrote {output_name}");
+            Ok(())
+        }
+    }?;
+    Ok(0)
+}
+
+fn download(url: &str, to: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if url.starts_with("s3://") {
+        return aws_copy(url, &to.to_string_lossy());
+    }
+
+    let response = ureq::get(url).call().map_err(|e| format!("can't download {url}: {e}"))?;
+    io::copy(&mut response.into_reader(), &mut File::create(to)?)?;
+    Ok(())
+}
+
+fn upload(world: &Path, destination: &str) -> Result<(), Box<dyn std::error::Error>> {
+    println!("uploading to {destination}..");
+    if destination.starts_with("s3://") {
+        aws_copy(&world.to_string_lossy(), destination)?;
+    } else if is_remote(destination) {
+        ureq::put(destination)
+            .set("Content-Type", "application/octet-stream")
+            .send(File::open(world)?)
+            .map_err(|e| format!("can't upload to {destination}: {e}"))?;
+    } else {
+        fs::copy(world, PathBuf::from(destination))?;
+    }
+    Ok(())
+}
+
+// copies from or to s3 with the aws command line tool
+fn aws_copy(from: &str, to: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let status = process::Command::new("aws")
+        .args(["s3", "cp", "--only-show-errors", from, to])
+        .status()
+        .map_err(|e| format!("can't run the aws command line tool, which s3:// urls need: {e}"))?;
+    if !status.success() {
+        return Err(format!("copying {from} to {to} failed").into());
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+/*
+ * reports about an optimization run, to share with others
+ *
+ * `--report html=<path>` writes a single html file (no images or scripts to go with it)
+ * with what the run did, the size of the world before and after,
+ * and the grid table and worst chunks of the optimized world, like `analyze` prints them
+ */
+
+use std::{fmt::Write, fs, path::Path};
+
+use crate::{analyze::Analysis, config::Config};
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em auto; max-width: 70em; padding: 0 1em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 1.5em; }
+th, td { border: 1px solid #ccc; padding: 0.25em 0.6em; text-align: right; }
+th { background: #eee; }
+td.text { text-align: left; }
+";
+
+/*
+ * writes the html report.
+ * `summary` is what the run did, `before` the size of the original world in bytes,
+ * `analysis` the optimized world
+ */
+pub fn html(
+    path: &Path,
+    world: &str,
+    summary: &[String],
+    before: u64,
+    analysis: &Analysis,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(html, "<title>brdb_optimize report for {}</title>", escape(world))?;
+    writeln!(html, "<style>{STYLE}</style>\n</head>\n<body>")?;
+    writeln!(html, "<h1>brdb_optimize report for {}</h1>", escape(world))?;
+
+    writeln!(html, "<h2>what was done</h2>\n<ul>")?;
+    for line in summary {
+        writeln!(html, "<li>{}</li>", escape(line))?;
+    }
+    writeln!(html, "</ul>")?;
+
+    let after = analysis.file_size;
+    writeln!(html, "<h2>size</h2>\n<table>")?;
+    writeln!(html, "<tr><th>before</th><th>after</th><th>saved</th></tr>")?;
+    writeln!(
+        html,
+        "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n</table>",
+        mb(before),
+        mb(after),
+        mb(before.saturating_sub(after)),
+    )?;
+
+    for stats in &analysis.worlds {
+        writeln!(html, "<h2>world {}</h2>", escape(&stats.world))?;
+        writeln!(
+            html,
+            "<p>{} entities ({} frozen), {} lights ({} cast shadows)</p>",
+            stats.frozen + stats.unfrozen, stats.frozen, stats.lights, stats.shadow_lights,
+        )?;
+
+        writeln!(html, "<h3>grids</h3>\n<table>")?;
+        writeln!(
+            html,
+            "<tr><th>grid</th><th>type</th><th>bricks</th><th>components</th><th>chunks</th>\
+             <th>heaviest chunk</th><th>engine</th><th>frozen</th></tr>",
+        )?;
+        for grid in &stats.grids {
+            let heaviest = grid.heaviest_chunk.as_ref().map_or("-".to_string(), |(chunk, bricks)| format!("{chunk} ({bricks})"));
+            let frozen = match grid.frozen {
+                Some(true) => "yes",
+                Some(false) => "no",
+                None => "-",
+            };
+            writeln!(
+                html,
+                "<tr><td>{}</td><td class=\"text\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                grid.grid,
+                if grid.root { "static" } else { "physics" },
+                grid.bricks,
+                grid.components,
+                grid.chunks,
+                escape(&heaviest),
+                if grid.engine { "yes" } else { "no" },
+                frozen,
+            )?;
+        }
+        writeln!(html, "</table>")?;
+
+        writeln!(html, "<h3>worst chunks by lag score</h3>\n<table>")?;
+        writeln!(
+            html,
+            "<tr><th>score</th><th>chunk</th><th>center</th><th>shadow lights</th><th>lights</th>\
+             <th>unfrozen entities</th><th>wires</th><th>components</th><th>bricks</th></tr>",
+        )?;
+        for chunk in stats.chunks.iter().take(config.lag_score.top) {
+            let (x, y, z) = chunk.center();
+            writeln!(
+                html,
+                "<tr><td>{:.1}</td><td>{}</td><td>{x}, {y}, {z}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                chunk.score, chunk.chunk, chunk.shadow_lights, chunk.lights, chunk.unfrozen_entities,
+                chunk.wires, chunk.components, chunk.bricks,
+            )?;
+        }
+        writeln!(html, "</table>")?;
+    }
+
+    writeln!(html, "</body>\n</html>")?;
+    fs::write(path, html)?;
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn mb(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1024.0 / 1024.0)
+}
@@ -0,0 +1,339 @@
+/*
+ * reading (and trimming) revision history straight from the brdb's sqlite database
+ *
+ * every save (and every run of this tool) adds a revision
+ * with a description and the time it was made.
+ * files and folders are stored once, with the revision they were created in
+ * and the revision they were deleted (or replaced) in, if any
+ */
+
+use std::{fs, path::{Path, PathBuf}, process};
+use chrono::{DateTime, Local};
+use rusqlite::{Connection, OpenFlags};
+
+pub struct Revision {
+    pub id: i64,
+    pub description: String,
+    // unix timestamp
+    pub created_at: i64,
+    // roughly how many bytes the files saved in this revision take up
+    pub size: u64,
+}
+
+// lists all revisions in the world file, oldest first
+pub fn list(path: &Path) -> Result<Vec<Revision>, Box<dyn std::error::Error>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt = conn.prepare(
+        "SELECT r.revision_id, r.description, r.created_at, (
+            SELECT COALESCE(SUM(b.size_compressed), 0) FROM files f
+            JOIN blobs b ON b.blob_id = f.content_id
+            WHERE f.created_at = r.revision_id
+        ) FROM revisions r ORDER BY r.revision_id",
+    )?;
+
+    let revisions = stmt
+        .query_map([], |row| {
+            Ok(Revision {
+                id: row.get(0)?,
+                description: row.get(1)?,
+                created_at: row.get(2)?,
+                size: row.get::<_, i64>(3)? as u64,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(revisions)
+}
+
+// `brdb_optimize revisions list <world.brdb>`, prints every revision with when it was made and how big it is
+pub fn print_list(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if !path.exists() {
+        println!("world file {:?} does not exist", path);
+        process::exit(1);
+    }
+
+    let revisions = list(path)?;
+    println!("{:>6}  {:>8}  {:<19}  {:>10}  description", "#", "id", "saved at", "size");
+    for (i, revision) in revisions.iter().enumerate() {
+        let time = DateTime::from_timestamp(revision.created_at, 0)
+            .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| "?".to_string());
+        println!(
+            "{:>6}  {:>8}  {:<19}  {:>7.1} MB  {}",
+            i + 1,
+            revision.id,
+            time,
+            revision.size as f64 / 1024.0 / 1024.0,
+            revision.description,
+        );
+    }
+    println!("{} revisions", revisions.len());
+
+    Ok(())
+}
+
+/*
+ * removes revisions that didn't change anything compared to the revision before them,
+ * like autosaves of a server nobody was building on. returns how many were removed.
+ *
+ * a revision counts as unchanged if it didn't add or remove any folders,
+ * and every file it saved replaced a file with the exact same contents.
+ * the first revision is always kept, and so is the latest (it carries our marker)
+ */
+pub fn remove_unchanged(path: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut conn = Connection::open(path)?;
+    let tx = conn.transaction()?;
+
+    let ids: Vec<i64> = tx
+        .prepare("SELECT revision_id FROM revisions ORDER BY revision_id")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+
+    let mut removed = 0;
+    for id in ids.iter().skip(1).take(ids.len().saturating_sub(2)) {
+        if !unchanged(&tx, *id)? {
+            continue;
+        }
+
+        // the files saved in this revision go, and the versions they replaced live on in their place
+        tx.execute(
+            "UPDATE files SET deleted_at = (
+                SELECT n.deleted_at FROM files n
+                WHERE n.created_at = ?1 AND n.parent_id IS files.parent_id AND n.name = files.name
+            ) WHERE deleted_at = ?1",
+            [id],
+        )?;
+        tx.execute("DELETE FROM files WHERE created_at = ?1", [id])?;
+        tx.execute("DELETE FROM revisions WHERE revision_id = ?1", [id])?;
+        removed += 1;
+    }
+
+    // contents that no file points at anymore
+    if removed > 0 {
+        tx.execute("DELETE FROM blobs WHERE blob_id NOT IN (SELECT content_id FROM files)", [])?;
+    }
+    tx.commit()?;
+
+    Ok(removed)
+}
+
+/*
+ * removes the history from before `cutoff` (a unix timestamp).
+ * the newest revision from before it stays, as the state the world was in at that time,
+ * but everything that was replaced or deleted by then is gone. returns how many revisions were removed
+ */
+pub fn remove_older_than(path: &Path, cutoff: u64) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut conn = Connection::open(path)?;
+    let tx = conn.transaction()?;
+
+    let base: Option<i64> = tx.query_row(
+        "SELECT MAX(revision_id) FROM revisions WHERE created_at < ?1",
+        [cutoff as i64],
+        |row| row.get(0),
+    )?;
+    let Some(base) = base else {
+        return Ok(0);
+    };
+
+    let removed = tx.execute("DELETE FROM revisions WHERE revision_id < ?1", [base])?;
+    if removed > 0 {
+        // whatever was gone by then isn't part of any revision that's left
+        tx.execute("DELETE FROM files WHERE deleted_at <= ?1", [base])?;
+        tx.execute("DELETE FROM folders WHERE deleted_at <= ?1", [base])?;
+        // and whatever existed by then now comes from the base revision
+        tx.execute("UPDATE files SET created_at = ?1 WHERE created_at < ?1", [base])?;
+        tx.execute("UPDATE folders SET created_at = ?1 WHERE created_at < ?1", [base])?;
+        tx.execute("DELETE FROM blobs WHERE blob_id NOT IN (SELECT content_id FROM files)", [])?;
+    }
+    tx.commit()?;
+
+    Ok(removed)
+}
+
+/*
+ * copies the world at `path` to `to`, rewound to how it was right after revision `id`:
+ * later revisions are dropped, files and folders they made are gone,
+ * and the ones they replaced or deleted are back
+ */
+pub fn checkout(path: &Path, id: i64, to: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    fs::copy(path, to)?;
+
+    let mut conn = Connection::open(to)?;
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM files WHERE created_at > ?1", [id])?;
+    tx.execute("DELETE FROM folders WHERE created_at > ?1", [id])?;
+    tx.execute("UPDATE files SET deleted_at = NULL WHERE deleted_at > ?1", [id])?;
+    tx.execute("UPDATE folders SET deleted_at = NULL WHERE deleted_at > ?1", [id])?;
+    tx.execute("DELETE FROM revisions WHERE revision_id > ?1", [id])?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+/*
+ * checks out the `count` revisions before the latest one into temporary files next to `near`, newest first.
+ * empty if the world doesn't have that many
+ */
+pub fn checkout_recent(path: &Path, count: usize, near: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let revisions = list(path)?;
+    if revisions.len() <= count {
+        return Ok(vec![]);
+    }
+
+    let stem = near.file_stem().unwrap().to_string_lossy();
+    let mut checkouts = vec![];
+    for revision in revisions.iter().rev().skip(1).take(count) {
+        let to = near.with_file_name(format!("{stem}.revision{}.tmp.brdb", revision.id));
+        checkout(path, revision.id, &to)?;
+        checkouts.push(to);
+    }
+    Ok(checkouts)
+}
+
+/*
+ * reads an age like 7d, 12h, 30m or 45s (just a number is seconds) into seconds
+ */
+pub fn parse_age(age: &str) -> Option<u64> {
+    let age = age.trim();
+    let (number, unit) = match age.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => age.split_at(i),
+        None => (age, "s"),
+    };
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(seconds)
+}
+
+fn unchanged(conn: &Connection, id: i64) -> Result<bool, rusqlite::Error> {
+    let count = |sql: &str| conn.query_row(sql, [id], |row| row.get::<_, i64>(0));
+
+    let folders = count("SELECT COUNT(*) FROM folders WHERE created_at = ?1 OR deleted_at = ?1")?;
+    let saved = count("SELECT COUNT(*) FROM files WHERE created_at = ?1")?;
+    let replaced = count("SELECT COUNT(*) FROM files WHERE deleted_at = ?1")?;
+    if folders > 0 || saved != replaced {
+        return Ok(false);
+    }
+
+    // files saved in this revision that aren't an exact copy of the file they replaced
+    let different = count(
+        "SELECT COUNT(*) FROM files n JOIN blobs nb ON nb.blob_id = n.content_id
+        WHERE n.created_at = ?1 AND NOT EXISTS (
+            SELECT 1 FROM files o JOIN blobs ob ON ob.blob_id = o.content_id
+            WHERE o.deleted_at = ?1 AND o.parent_id IS n.parent_id AND o.name = n.name
+                AND (o.content_id = n.content_id OR (ob.compression = nb.compression AND ob.content = nb.content))
+        )",
+    )?;
+    Ok(different == 0)
+}
+
+/*
+ * the marker we stamp into the description of our own revisions, like:
+ *  Optimize World [brdb_optimize v0.1.0 config=0123456789abcdef time=1700000000]
+ */
+pub struct Marker {
+    pub version: String,
+    pub config_hash: String,
+    pub timestamp: u64,
+}
+
+impl Marker {
+    pub fn new(config_hash: String) -> Self {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            config_hash,
+            timestamp,
+        }
+    }
+
+    pub fn describe(&self, description: &str) -> String {
+        format!(
+            "{description} [brdb_optimize v{} config={} time={}]",
+            self.version, self.config_hash, self.timestamp
+        )
+    }
+
+    // reads the marker back out of a revision description, if it has one
+    pub fn parse(description: &str) -> Option<Self> {
+        let start = description.rfind("[brdb_optimize v")?;
+        let inner = description[start + 1..].strip_suffix(']')?;
+
+        let mut version = None;
+        let mut config_hash = None;
+        let mut timestamp = None;
+        for field in inner.split_whitespace() {
+            if let Some(v) = field.strip_prefix("v") {
+                version = Some(v.to_string());
+            } else if let Some(v) = field.strip_prefix("config=") {
+                config_hash = Some(v.to_string());
+            } else if let Some(v) = field.strip_prefix("time=") {
+                timestamp = v.parse().ok();
+            }
+        }
+
+        Some(Self {
+            version: version?,
+            config_hash: config_hash?,
+            timestamp: timestamp?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ages() {
+        assert_eq!(parse_age("30"), Some(30));
+        assert_eq!(parse_age("30s"), Some(30));
+        assert_eq!(parse_age("30m"), Some(30 * 60));
+        assert_eq!(parse_age(" 12h "), Some(12 * 60 * 60));
+        assert_eq!(parse_age("7d"), Some(7 * 24 * 60 * 60));
+        assert_eq!(parse_age("2w"), Some(2 * 7 * 24 * 60 * 60));
+    }
+
+    #[test]
+    fn refuses_bad_ages() {
+        for age in ["", "d", "7y", "7 d", "-7d", "1.5h", "99999999999999999999w"] {
+            assert_eq!(parse_age(age), None, "{age:?}");
+        }
+    }
+
+    #[test]
+    fn reads_back_the_marker() {
+        let marker = Marker { version: "1.2.3".to_string(), config_hash: "abc123".to_string(), timestamp: 1700000000 };
+        let parsed = Marker::parse(&marker.describe("Optimize World")).unwrap();
+        assert_eq!((parsed.version, parsed.config_hash, parsed.timestamp), ("1.2.3".to_string(), "abc123".to_string(), 1700000000));
+    }
+
+    #[test]
+    fn reads_the_last_marker() {
+        let description = "Optimize World [brdb_optimize v1.0.0 config=old time=1] [brdb_optimize v2.0.0 config=new time=2]";
+        let parsed = Marker::parse(description).unwrap();
+        assert_eq!((parsed.version.as_str(), parsed.config_hash.as_str(), parsed.timestamp), ("2.0.0", "new", 2));
+    }
+
+    #[test]
+    fn needs_every_field() {
+        for description in [
+            "Autosave",
+            "Optimize World [brdb_optimize v1.0.0 config=abc]",
+            "Optimize World [brdb_optimize v1.0.0 time=1]",
+            "Optimize World [brdb_optimize v1.0.0 config=abc time=soon]",
+            "Optimize World [brdb_optimize v1.0.0 config=abc time=1",
+        ] {
+            assert!(Marker::parse(description).is_none(), "{description:?}");
+        }
+    }
+}
@@ -0,0 +1,548 @@
+/*
+ * optimization rules for single components
+ *
+ * every rule looks at one component, changes whatever it wants to change,
+ * and returns whether it modified anything.
+ * rules ignore components they don't apply to, so they can all be run on every component
+ */
+
+use std::collections::HashSet;
+use brdb::{AsBrdbValue, ChunkIndex, schema::{BrdbStruct, BrdbValue}};
+use regex::Regex;
+
+use crate::{
+    config::{
+        LightConfig, ModifierConfig, ModifierMode, MotorConfig, ParticleConfig, PatternConfig, SensorConfig, SpawnerConfig,
+        TextConfig, ThrusterConfig,
+    },
+    props::Props,
+    rulesets::{self, Ruleset},
+};
+
+// components that light up their surroundings
+pub const LIGHT_COMPONENTS: &[&str] = &["BrickComponentData_PointLight", "BrickComponentData_SpotLight"];
+
+// components that drive a joint
+pub const MOTOR_COMPONENTS: &[&str] = &[
+    "BrickComponentData_Bearing",
+    "BrickComponentData_Servo",
+    "BrickComponentData_Motor",
+];
+
+// components that show text
+pub const TEXT_COMPONENTS: &[&str] = &["BrickComponentData_Text", "BrickComponentData_Display"];
+
+// components that keep checking their surroundings
+pub const SENSOR_COMPONENTS: &[&str] = &[
+    "BrickComponentData_ProximitySensor",
+    "BrickComponentData_PlayerDetector",
+];
+
+// components that push physics objects around them
+pub const MODIFIER_COMPONENTS: &[&str] = &[
+    "BrickComponentData_GravityWell",
+    "BrickComponentData_PhysicsModifier",
+];
+
+// components that push their grid forward on their own
+pub const THRUSTER_COMPONENTS: &[&str] = &[
+    "BrickComponentData_Thruster",
+    "BrickComponentData_Rocket",
+];
+
+// the properties thrusters store their force in, depending on the type
+pub const THRUST_PROPS: &[&str] = &["Thrust", "ThrustForce", "Force"];
+
+// properties that make components glow, on whatever component has them
+pub const EMISSIVE_PROPS: &[&str] = &["EmissiveIntensity", "GlowIntensity"];
+
+/*
+ * the properties the rules below read, by the components they read them from.
+ * compat.rs checks every world's schema against this (and THRUST_PROPS), so add to it along with the rules
+ */
+pub const RULE_PROPS: &[(&[&str], &[&str])] = &[
+    (LIGHT_COMPONENTS, &["Radius", "Brightness", "bCastShadows"]),
+    (&["BrickComponentData_SpotLight"], &["InnerConeAngle", "OuterConeAngle", "Attenuation"]),
+    (&["BrickComponentData_WeightBrick"], &["Mass", "MassSize"]),
+    (&["BrickComponentData_WheelEngine"], &["CustomMass"]),
+    (&["BrickComponentData_ItemSpawn"], &["PickupMinSpawnTime", "PickupMaxSpawnTime", "MaxSpawnedItems"]),
+    (&["BrickComponentData_ParticleEmitter"], &["SpawnRate", "Lifetime", "Scale"]),
+    (MOTOR_COMPONENTS, &["TargetSpeed", "MaxTorque", "MaxForce"]),
+    (TEXT_COMPONENTS, &["Text", "FontSize"]),
+    (SENSOR_COMPONENTS, &["PollInterval"]),
+    (MODIFIER_COMPONENTS, &["Strength", "Radius"]),
+];
+
+/*
+ * float properties that get checked for NaN and infinity on every component.
+ * every comparison with NaN is false, so the limits in the other rules would never catch them
+ */
+pub const FLOAT_PROPS: &[&str] = &[
+    "Radius", "Brightness", "InnerConeAngle", "OuterConeAngle", "Attenuation", "Mass", "CustomMass",
+    "PickupMinSpawnTime", "PickupMaxSpawnTime", "SpawnRate", "Lifetime", "Scale", "TargetSpeed", "MaxTorque",
+    "MaxForce", "FontSize", "PollInterval", "Strength", "EmissiveIntensity", "GlowIntensity", "Thrust",
+    "ThrustForce", "Force",
+];
+
+/*
+ * which components count as lights, motors and so on.
+ * the lists above are the component types the tool knows about, [component_patterns]
+ * in the config adds regular expressions for more (like BrickComponentData_.*Light),
+ * so new types a game update brings are covered without a new version of the tool
+ */
+#[derive(Default)]
+pub struct Kinds {
+    lights: Option<Regex>,
+    motors: Option<Regex>,
+    text: Option<Regex>,
+    sensors: Option<Regex>,
+    modifiers: Option<Regex>,
+    thrusters: Option<Regex>,
+}
+
+impl Kinds {
+    pub fn new(patterns: &PatternConfig) -> Result<Self, String> {
+        Ok(Self {
+            lights: pattern(&patterns.lights).map_err(|e| format!("invalid light pattern: {e}"))?,
+            motors: pattern(&patterns.motors).map_err(|e| format!("invalid motor pattern: {e}"))?,
+            text: pattern(&patterns.text).map_err(|e| format!("invalid text pattern: {e}"))?,
+            sensors: pattern(&patterns.sensors).map_err(|e| format!("invalid sensor pattern: {e}"))?,
+            modifiers: pattern(&patterns.modifiers).map_err(|e| format!("invalid modifier pattern: {e}"))?,
+            thrusters: pattern(&patterns.thrusters).map_err(|e| format!("invalid thruster pattern: {e}"))?,
+        })
+    }
+
+    pub fn light(&self, name: &str) -> bool {
+        is(LIGHT_COMPONENTS, &self.lights, name)
+    }
+
+    pub fn motor(&self, name: &str) -> bool {
+        is(MOTOR_COMPONENTS, &self.motors, name)
+    }
+
+    pub fn text(&self, name: &str) -> bool {
+        is(TEXT_COMPONENTS, &self.text, name)
+    }
+
+    pub fn sensor(&self, name: &str) -> bool {
+        is(SENSOR_COMPONENTS, &self.sensors, name)
+    }
+
+    pub fn modifier(&self, name: &str) -> bool {
+        is(MODIFIER_COMPONENTS, &self.modifiers, name)
+    }
+
+    pub fn thruster(&self, name: &str) -> bool {
+        is(THRUSTER_COMPONENTS, &self.thrusters, name)
+    }
+}
+
+// one regex matching whole names that match any of `patterns`, None if there aren't any
+pub fn pattern(patterns: &[String]) -> Result<Option<Regex>, regex::Error> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let any: Vec<String> = patterns.iter().map(|p| format!("(?:{p})")).collect();
+    Regex::new(&format!("^(?:{})$", any.join("|"))).map(Some)
+}
+
+fn is(known: &[&str], pattern: &Option<Regex>, name: &str) -> bool {
+    known.contains(&name) || pattern.as_ref().is_some_and(|p| p.is_match(name))
+}
+
+// where the component lives (for logging), what it is and who placed it
+pub struct Ctx<'a> {
+    pub grid: usize,
+    pub chunk: ChunkIndex,
+    pub name: &'a str,
+    // the owner index of the component's brick (see owners.rs), if it was looked up
+    pub owner: Option<u32>,
+    pub kinds: &'a Kinds,
+    pub props: &'a mut Props,
+    // the [clamp] entries of the config, the properties they cover are left to them
+    pub clamps: Option<&'a Ruleset>,
+}
+
+impl Ctx<'_> {
+    /*
+     * whether a [clamp] entry covers this property of the component, instead of the rule looking at it.
+     * the helpers below act as if the property isn't there then, so the rules skip it
+     */
+    fn clamped(&self, prop: &str) -> bool {
+        self.clamps.is_some_and(|clamps| rulesets::touches(std::slice::from_ref(clamps), self.name, prop))
+    }
+
+    fn f32(&mut self, component: &BrdbStruct, prop: &str) -> Option<f32> {
+        if self.clamped(prop) {
+            return None;
+        }
+        self.props.get(self.name, prop, component.prop(prop).and_then(|v| v.as_brdb_f32()))
+    }
+
+    fn i32(&mut self, component: &BrdbStruct, prop: &str) -> Option<i32> {
+        if self.clamped(prop) {
+            return None;
+        }
+        self.props.get(self.name, prop, component.prop(prop).and_then(|v| v.as_brdb_i32()))
+    }
+
+    fn string(&mut self, component: &BrdbStruct, prop: &str) -> Option<String> {
+        if self.clamped(prop) {
+            return None;
+        }
+        self.props.get(self.name, prop, component.prop(prop).and_then(|v| v.as_brdb_str().map(String::from)))
+    }
+
+    fn bool(&mut self, component: &BrdbStruct, prop: &str) -> Option<bool> {
+        if self.clamped(prop) {
+            return None;
+        }
+        self.props.get(self.name, prop, component.prop(prop).and_then(|v| v.as_brdb_bool()))
+    }
+}
+
+// sets the mass and mass size of weight bricks to 0
+pub fn neutralize_weight(component: &mut BrdbStruct, ctx: &mut Ctx) -> bool {
+    // if it's a weight component/brick
+    if ctx.name != "BrickComponentData_WeightBrick" {
+        return false;
+    }
+    let mut modified = false;
+
+    // set the mass size to (X:0,Y:0,Z:0)
+    if let Some(weight_size) = ctx.props.get(ctx.name, "MassSize", component.prop_mut("MassSize")) {
+        for axis in ["X", "Y", "Z"] {
+            let size = weight_size.prop(axis).and_then(|v| v.as_brdb_i32());
+            if ctx.props.get(ctx.name, &format!("MassSize.{axis}"), size).is_some_and(|size| size > 0) {
+                weight_size.set_prop(axis, BrdbValue::I32(0));
+                modified = true;
+            }
+        }
+    }
+
+    // if mass is above 0,
+    if ctx.f32(component, "Mass").is_some_and(|weight| weight > 0.0) {
+        // set it to 0
+        component.set_prop("Mass", BrdbValue::F32(0.0));
+        modified = true;
+    }
+
+    if modified {
+        println!("[grid:{}][{}] weight neutralized", ctx.grid, ctx.chunk);
+    }
+    modified
+}
+
+// a softer version of the above: caps the mass of weight bricks instead of zeroing it
+pub fn clamp_weight(component: &mut BrdbStruct, max_mass: f32, ctx: &mut Ctx) -> bool {
+    if ctx.name != "BrickComponentData_WeightBrick" {
+        return false;
+    }
+
+    if ctx.f32(component, "Mass").is_some_and(|weight| weight > max_mass) {
+        println!("[grid:{}][{}] weight exceeds {max_mass}, forcing down..", ctx.grid, ctx.chunk);
+        component.set_prop("Mass", BrdbValue::F32(max_mass));
+        return true;
+    }
+    false
+}
+
+// zeroes the extra mass wheel engines add
+pub fn neutralize_engine_weight(component: &mut BrdbStruct, ctx: &mut Ctx) -> bool {
+    // if it's a wheel engine component/brick
+    if ctx.name != "BrickComponentData_WheelEngine" {
+        return false;
+    }
+
+    // if weight is above 0,
+    if ctx.f32(component, "CustomMass").is_some_and(|weight| weight > 0.0) {
+        // neutralize the weight (set it to 0)
+        println!("[grid:{}][{}] wheel engine weight neutralized", ctx.grid, ctx.chunk);
+        component.set_prop("CustomMass", BrdbValue::F32(0.0));
+        return true;
+    }
+    false
+}
+
+// forces light radius and brightness down, and turns off shadows
+pub fn limit_light(component: &mut BrdbStruct, limits: &LightConfig, ctx: &mut Ctx) -> bool {
+    // if it's any type of light,
+    if !ctx.kinds.light(ctx.name) {
+        return false;
+    }
+    let mut modified = false;
+
+    // limit light radius
+    if ctx.f32(component, "Radius").is_some_and(|radius| radius > limits.max_radius) {
+        println!("[grid:{}][{}] light: radius exceeds {}, forcing down..", ctx.grid, ctx.chunk, limits.max_radius / 10.0);
+
+        // for some reason the game stores radiuses as thousands..
+        component.set_prop("Radius", BrdbValue::F32(limits.max_radius));
+
+        modified = true;
+    }
+    // limit light brightness
+    if ctx.f32(component, "Brightness").is_some_and(|brightness| brightness > limits.max_brightness) {
+        println!("[grid:{}][{}] light: brightness exceeds {}, forcing down..", ctx.grid, ctx.chunk, limits.max_brightness);
+        component.set_prop("Brightness", BrdbValue::F32(limits.max_brightness));
+
+        modified = true;
+    }
+
+    // force cast shadows to off
+    if ctx.bool(component, "bCastShadows") == Some(true) && !limits.cast_shadows {
+        println!("[grid:{}][{}] light: disabling cast shadows..", ctx.grid, ctx.chunk);
+        component.set_prop("bCastShadows", BrdbValue::Bool(false));
+
+        modified = true;
+    }
+
+    modified
+}
+
+// narrows down very wide spot lights, which overlap everything around them
+pub fn limit_spot_cone(component: &mut BrdbStruct, limits: &LightConfig, ctx: &mut Ctx) -> bool {
+    if ctx.name != "BrickComponentData_SpotLight" {
+        return false;
+    }
+    let mut modified = false;
+
+    for prop in ["InnerConeAngle", "OuterConeAngle"] {
+        if ctx.f32(component, prop).is_some_and(|angle| angle > limits.spot_max_cone_angle) {
+            println!("[grid:{}][{}] spot light: {prop} exceeds {}, forcing down..", ctx.grid, ctx.chunk, limits.spot_max_cone_angle);
+            component.set_prop(prop, BrdbValue::F32(limits.spot_max_cone_angle));
+            modified = true;
+        }
+    }
+
+    if let Some(max_attenuation) = limits.spot_max_attenuation
+        && ctx.f32(component, "Attenuation").is_some_and(|attenuation| attenuation > max_attenuation)
+    {
+        println!("[grid:{}][{}] spot light: attenuation exceeds {max_attenuation}, forcing down..", ctx.grid, ctx.chunk);
+        component.set_prop("Attenuation", BrdbValue::F32(max_attenuation));
+        modified = true;
+    }
+
+    modified
+}
+
+// slows down item spawners, since spawners spitting out items every frame are a classic way to kill a server
+pub fn throttle_spawner(component: &mut BrdbStruct, limits: &SpawnerConfig, ctx: &mut Ctx) -> bool {
+    if ctx.name != "BrickComponentData_ItemSpawn" {
+        return false;
+    }
+    let mut modified = false;
+
+    // spawners pick a respawn time between the min and max, so both get raised
+    for prop in ["PickupMinSpawnTime", "PickupMaxSpawnTime"] {
+        if ctx.f32(component, prop).is_some_and(|time| time < limits.min_respawn_time) {
+            println!("[grid:{}][{}] item spawner: {prop} below {}s, raising..", ctx.grid, ctx.chunk, limits.min_respawn_time);
+            component.set_prop(prop, BrdbValue::F32(limits.min_respawn_time));
+            modified = true;
+        }
+    }
+
+    if let Some(max_items) = limits.max_items
+        && ctx.i32(component, "MaxSpawnedItems").is_some_and(|items| items > max_items)
+    {
+        println!("[grid:{}][{}] item spawner: spawns more than {max_items} items, forcing down..", ctx.grid, ctx.chunk);
+        component.set_prop("MaxSpawnedItems", BrdbValue::I32(max_items));
+        modified = true;
+    }
+
+    modified
+}
+
+// caps how many particles emitters spawn, how long they live and how big they get
+pub fn limit_emitter(component: &mut BrdbStruct, limits: &ParticleConfig, ctx: &mut Ctx) -> bool {
+    if ctx.name != "BrickComponentData_ParticleEmitter" {
+        return false;
+    }
+    let mut modified = false;
+
+    for (prop, max) in [("SpawnRate", limits.max_rate), ("Lifetime", limits.max_lifetime), ("Scale", limits.max_scale)] {
+        if ctx.f32(component, prop).is_some_and(|value| value > max) {
+            println!("[grid:{}][{}] particle emitter: {prop} exceeds {max}, forcing down..", ctx.grid, ctx.chunk);
+            component.set_prop(prop, BrdbValue::F32(max));
+            modified = true;
+        }
+    }
+
+    modified
+}
+
+/*
+ * resets float properties that are NaN or infinite to 0, which crash the game when the world loads.
+ * this runs before the other rules, so properties with a minimum get raised to it afterwards
+ */
+pub fn sanitize_floats(component: &mut BrdbStruct, ctx: &mut Ctx) -> bool {
+    let mut modified = false;
+
+    for prop in FLOAT_PROPS {
+        // no component has all of these, so a missing property isn't worth a warning here
+        let value = component.prop(*prop).and_then(|v| v.as_brdb_f32()).ok();
+        if let Some(value) = value
+            && !value.is_finite()
+        {
+            println!("[grid:{}][{}] {}: {prop} is {value}, resetting it to 0..", ctx.grid, ctx.chunk, ctx.name);
+            component.set_prop(*prop, BrdbValue::F32(0.0));
+            modified = true;
+        }
+    }
+
+    modified
+}
+
+// tames motors with extreme speed, torque or force, which make the physics solver freak out
+pub fn limit_motor(component: &mut BrdbStruct, limits: &MotorConfig, ctx: &mut Ctx) -> bool {
+    if !ctx.kinds.motor(ctx.name) {
+        return false;
+    }
+    let mut modified = false;
+
+    for (prop, max) in [("TargetSpeed", limits.max_speed), ("MaxTorque", limits.max_torque), ("MaxForce", limits.max_force)] {
+        // motors can turn both ways, so keep the sign
+        if let Some(value) = ctx.f32(component, prop)
+            && value.abs() > max
+        {
+            println!("[grid:{}][{}] motor: {prop} exceeds {max}, forcing down..", ctx.grid, ctx.chunk);
+            component.set_prop(prop, BrdbValue::F32(max.copysign(value)));
+            modified = true;
+        }
+    }
+
+    modified
+}
+
+// cuts down huge texts, which bloat the chunk they're in and take ages to render
+pub fn limit_text(component: &mut BrdbStruct, limits: &TextConfig, ctx: &mut Ctx) -> bool {
+    if !ctx.kinds.text(ctx.name) {
+        return false;
+    }
+    let mut modified = false;
+
+    if let Some(text) = ctx.string(component, "Text") {
+        let mut new_text = text.clone();
+        if limits.strip_control_chars {
+            new_text.retain(|c| !c.is_control() || c == '\n');
+        }
+        if let Some((cut, _)) = new_text.char_indices().nth(limits.max_length) {
+            println!("[grid:{}][{}] text: longer than {} characters, cutting it off..", ctx.grid, ctx.chunk, limits.max_length);
+            new_text.truncate(cut);
+        }
+
+        if new_text != text {
+            component.set_prop("Text", BrdbValue::String(new_text));
+            modified = true;
+        }
+    }
+
+    if ctx.f32(component, "FontSize").is_some_and(|size| size > limits.max_font_size) {
+        println!("[grid:{}][{}] text: font size exceeds {}, forcing down..", ctx.grid, ctx.chunk, limits.max_font_size);
+        component.set_prop("FontSize", BrdbValue::F32(limits.max_font_size));
+        modified = true;
+    }
+
+    modified
+}
+
+// like the light brightness cap, but for anything that glows
+pub fn limit_emissive(component: &mut BrdbStruct, limits: &LightConfig, ctx: &mut Ctx) -> bool {
+    let Some(max) = limits.max_emissive else {
+        return false;
+    };
+    let mut modified = false;
+
+    for prop in EMISSIVE_PROPS {
+        // most components don't glow at all, so a missing property isn't worth a warning here
+        let intensity = component.prop(*prop).and_then(|v| v.as_brdb_f32()).ok();
+        if intensity.is_some_and(|intensity| intensity > max) && !ctx.clamped(prop) {
+            println!("[grid:{}][{}] {}: {prop} exceeds {max}, forcing down..", ctx.grid, ctx.chunk, ctx.name);
+            component.set_prop(*prop, BrdbValue::F32(max));
+            modified = true;
+        }
+    }
+
+    modified
+}
+
+// makes sensors check less often, every check costs server time even if the build is frozen
+pub fn throttle_sensor(component: &mut BrdbStruct, limits: &SensorConfig, ctx: &mut Ctx) -> bool {
+    if !ctx.kinds.sensor(ctx.name) {
+        return false;
+    }
+
+    if ctx.f32(component, "PollInterval").is_some_and(|interval| interval < limits.min_interval) {
+        println!("[grid:{}][{}] sensor: polls more often than every {}s, slowing down..", ctx.grid, ctx.chunk, limits.min_interval);
+        component.set_prop("PollInterval", BrdbValue::F32(limits.min_interval));
+        return true;
+    }
+    false
+}
+
+/*
+ * tames gravity wells and other physics modifiers, a badly configured one
+ * flings everything around it across the map.
+ * `exempt` are the owners whose modifiers are left alone
+ */
+pub fn limit_modifier(component: &mut BrdbStruct, limits: &ModifierConfig, exempt: &HashSet<u32>, ctx: &mut Ctx) -> bool {
+    if !ctx.kinds.modifier(ctx.name) || limits.mode == ModifierMode::Off {
+        return false;
+    }
+    if ctx.owner.is_some_and(|owner| exempt.contains(&owner)) {
+        return false;
+    }
+    let mut modified = false;
+
+    if limits.mode == ModifierMode::Neutralize {
+        if ctx.f32(component, "Strength").is_some_and(|strength| strength != 0.0) {
+            println!("[grid:{}][{}] physics modifier neutralized", ctx.grid, ctx.chunk);
+            component.set_prop("Strength", BrdbValue::F32(0.0));
+            modified = true;
+        }
+        return modified;
+    }
+
+    // strength can be negative (pulling instead of pushing), so keep the sign
+    if let Some(strength) = ctx.f32(component, "Strength")
+        && strength.abs() > limits.max_strength
+    {
+        println!("[grid:{}][{}] physics modifier: strength exceeds {}, forcing down..", ctx.grid, ctx.chunk, limits.max_strength);
+        component.set_prop("Strength", BrdbValue::F32(limits.max_strength.copysign(strength)));
+        modified = true;
+    }
+    if ctx.f32(component, "Radius").is_some_and(|radius| radius > limits.max_radius) {
+        println!("[grid:{}][{}] physics modifier: radius exceeds {}, forcing down..", ctx.grid, ctx.chunk, limits.max_radius);
+        component.set_prop("Radius", BrdbValue::F32(limits.max_radius));
+        modified = true;
+    }
+
+    modified
+}
+
+// tames thrusters and rockets, a contraption full of them at full power launches itself (and everything nearby) into orbit
+pub fn limit_thruster(component: &mut BrdbStruct, limits: &ThrusterConfig, ctx: &mut Ctx) -> bool {
+    if !ctx.kinds.thruster(ctx.name) {
+        return false;
+    }
+    let mut modified = false;
+
+    // every type has only one of these, so the others missing isn't worth a warning, all of them missing is
+    let props: Vec<&str> = THRUST_PROPS.iter().copied().filter(|prop| component.prop(prop).is_ok()).collect();
+    if props.is_empty() {
+        ctx.props.get::<(), _>(ctx.name, &THRUST_PROPS.join("/"), Err("it has none of them"));
+    }
+
+    for prop in props {
+        // thrusters can push both ways, so keep the sign
+        if let Some(thrust) = ctx.f32(component, prop)
+            && thrust.abs() > limits.max_thrust
+        {
+            println!("[grid:{}][{}] thruster: {prop} exceeds {}, forcing down..", ctx.grid, ctx.chunk, limits.max_thrust);
+            component.set_prop(prop, BrdbValue::F32(limits.max_thrust.copysign(thrust)));
+            modified = true;
+        }
+    }
+
+    modified
+}
@@ -0,0 +1,314 @@
+/*
+ * rulesets: component rules read from files, so communities can share them
+ *
+ * a ruleset is a json or yaml file (going by its extension) with a list of rules.
+ * every rule matches components by their type, and optionally by their properties,
+ * and then does one thing to them:
+ *  - clamp: keeps a number property between `min` and/or `max`
+ *  - set: sets a property to `value`
+ *  - remove: removes the component from its brick
+ *
+ * in yaml, that looks like:
+ *   name: tame lights
+ *   rules:
+ *     - component: BrickComponentData_PointLight
+ *       when: [{ prop: Brightness, above: 1000 }]
+ *       action: clamp
+ *       prop: Radius
+ *       max: 2000
+ *     - component: BrickComponentData_SpotLight
+ *       action: set
+ *       prop: bCastShadows
+ *       value: false
+ *     - component: BrickComponentData_Fireworks
+ *       action: remove
+ *
+ * `component` is a regular expression, so one rule can cover several types
+ * (like BrickComponentData_.*Light), including ones a game update adds later.
+ * every condition under `when` has to hold: `above` and `below` for numbers, `equals` for anything.
+ * ruleset rules run after the built-in ones (see rules.rs), so they get the last word:
+ * a ruleset can loosen a built-in limit too, and verify.rs doesn't hold the properties it touches to those limits.
+ *
+ * `[clamp."<component>".<property>]` entries in the config (with `min` and/or `max`)
+ * become clamp rules too, in a ruleset of their own that runs before the files.
+ * those replace the built-in limit for their property: a [clamp] on the Radius of lights
+ * means max_radius under [lights] isn't applied to them anymore, so it can be raised as well as lowered
+ */
+
+use std::{collections::BTreeMap, fs, path::Path};
+use brdb::{BrdbComponent, ChunkIndex, ComponentChunkSoA, schema::{BrdbStruct, BrdbValue}};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{components::{self, JOINT_COMPONENTS}, config::Clamp, props::Props, rules::{self, Ctx}};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Ruleset {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Rule {
+    // the component type, like BrickComponentData_PointLight, or a regular expression matching types
+    pub component: String,
+    // `component`, compiled by load()
+    #[serde(skip)]
+    pub pattern: Option<Regex>,
+    #[serde(default)]
+    pub when: Vec<Condition>,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Condition {
+    pub prop: String,
+    pub above: Option<f64>,
+    pub below: Option<f64>,
+    pub equals: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Action {
+    Clamp { prop: String, min: Option<f64>, max: Option<f64> },
+    Set { prop: String, value: Value },
+    Remove,
+}
+
+// reads the ruleset at `path`, as yaml if it ends in .yaml or .yml and as json otherwise
+pub fn load(path: &Path) -> Result<Ruleset, Box<dyn std::error::Error>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("can't read ruleset {:?}: {e}", path))?;
+    let mut ruleset: Ruleset = match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml" | "yml") => serde_yaml::from_str(&text).map_err(|e| format!("invalid ruleset {:?}: {e}", path))?,
+        _ => serde_json::from_str(&text).map_err(|e| format!("invalid ruleset {:?}: {e}", path))?,
+    };
+
+    prepare(&mut ruleset).map_err(|e| format!("invalid ruleset {:?}: {e}", path))?;
+
+    let name = ruleset.name.clone().unwrap_or_else(|| path.display().to_string());
+    println!("Reading ruleset {name:?} ({} rules)", ruleset.rules.len());
+    Ok(ruleset)
+}
+
+// the [clamp."<component>".<property>] entries of the config, as a ruleset
+pub fn from_clamps(clamps: &BTreeMap<String, BTreeMap<String, Clamp>>) -> Result<Ruleset, Box<dyn std::error::Error>> {
+    let rules = clamps.iter()
+        .flat_map(|(component, props)| {
+            props.iter().map(|(prop, clamp)| Rule {
+                component: component.clone(),
+                pattern: None,
+                when: vec![],
+                action: Action::Clamp { prop: prop.clone(), min: clamp.min, max: clamp.max },
+            })
+        })
+        .collect();
+
+    let mut ruleset = Ruleset { name: Some("[clamp] in the config".to_string()), rules };
+    prepare(&mut ruleset).map_err(|e| format!("invalid [clamp] in the config: {e}"))?;
+    Ok(ruleset)
+}
+
+// compiles the component patterns, and checks every rule makes sense
+fn prepare(ruleset: &mut Ruleset) -> Result<(), String> {
+    for rule in &mut ruleset.rules {
+        rule.pattern = rules::pattern(std::slice::from_ref(&rule.component))
+            .map_err(|e| format!("{:?} isn't a valid pattern: {e}", rule.component))?;
+        if let Action::Clamp { prop, min: None, max: None } = &rule.action {
+            return Err(format!("clamping {prop} of {} needs a min or a max", rule.component));
+        }
+    }
+    Ok(())
+}
+
+// whether any of the rulesets removes components, which breaks wires pointing at them
+pub fn removes(rulesets: &[Ruleset]) -> bool {
+    rulesets.iter().flat_map(|r| &r.rules).any(|rule| matches!(rule.action, Action::Remove))
+}
+
+/*
+ * whether any clamp or set rule in the rulesets is for this property of this component type.
+ * conditions aren't looked at, a rule that only sometimes applies still decides the value
+ */
+pub fn touches(rulesets: &[Ruleset], component: &str, prop: &str) -> bool {
+    rulesets.iter().flat_map(|r| &r.rules).any(|rule| {
+        let touched = match &rule.action {
+            Action::Clamp { prop: p, .. } | Action::Set { prop: p, .. } => p == prop,
+            Action::Remove => false,
+        };
+        touched && matches(rule, component)
+    })
+}
+
+// runs the clamp and set rules on one component, like the rules in rules.rs
+pub fn apply(component: &mut BrdbStruct, rulesets: &[Ruleset], ctx: &mut Ctx) -> bool {
+    let mut modified = false;
+
+    for rule in rulesets.iter().flat_map(|r| &r.rules) {
+        if !applies(component, rule, ctx.name, ctx.props) {
+            continue;
+        }
+
+        match &rule.action {
+            Action::Clamp { prop, min, max } => {
+                let Some(value) = ctx.props.get(ctx.name, prop, component.prop(prop)) else {
+                    continue;
+                };
+                let Some(number) = number(value) else {
+                    println!("[WARNING] ruleset: {prop} of {} isn't a number, can't clamp it", ctx.name);
+                    continue;
+                };
+                let clamped = min.map_or(number, |min| number.max(min));
+                let clamped = max.map_or(clamped, |max| clamped.min(max));
+                if clamped != number {
+                    let new = with_number(value, clamped);
+                    println!("[grid:{}][{}] {}: {prop} is {number}, clamping it to {clamped}..", ctx.grid, ctx.chunk, ctx.name);
+                    component.set_prop(prop, new);
+                    modified = true;
+                }
+            }
+            Action::Set { prop, value: wanted } => {
+                let Some(value) = ctx.props.get(ctx.name, prop, component.prop(prop)) else {
+                    continue;
+                };
+                if equals(value, wanted) {
+                    continue;
+                }
+                let Some(new) = from_json(value, wanted) else {
+                    println!("[WARNING] ruleset: can't set {prop} of {} to {wanted}, it's a different kind of value", ctx.name);
+                    continue;
+                };
+                println!("[grid:{}][{}] {}: setting {prop} to {wanted}..", ctx.grid, ctx.chunk, ctx.name);
+                component.set_prop(prop, new);
+                modified = true;
+            }
+            Action::Remove => {}
+        }
+    }
+
+    modified
+}
+
+/*
+ * removes the components the remove rules match from the chunk, returns how many were removed.
+ * joint components are left alone, their joint data would have to go with them (see components.rs),
+ * and so are chunks with wires, which could point at a removed component
+ */
+pub fn remove(
+    grid: usize,
+    chunk: ChunkIndex,
+    wired: bool,
+    soa: &mut ComponentChunkSoA,
+    components: &mut Vec<BrdbStruct>,
+    rulesets: &[Ruleset],
+    props: &mut Props,
+) -> usize {
+    let removed: Vec<usize> = components.iter()
+        .enumerate()
+        .filter(|(_, component)| {
+            let name = component.get_name();
+            !JOINT_COMPONENTS.contains(&name)
+                && rulesets.iter()
+                    .flat_map(|r| &r.rules)
+                    .any(|rule| matches!(rule.action, Action::Remove) && applies(component, rule, name, props))
+        })
+        .map(|(i, _)| i)
+        .collect();
+    if removed.is_empty() {
+        return 0;
+    }
+
+    if wired {
+        println!("[grid:{grid}][{chunk}] has {} components to remove by rulesets, but also wires, leaving it alone", removed.len());
+        return 0;
+    }
+
+    println!("[grid:{grid}][{chunk}] removing {} components by rulesets..", removed.len());
+    components::remove(soa, components, &removed);
+    removed.len()
+}
+
+// whether `rule` is for this component, and all its conditions hold
+fn applies(component: &BrdbStruct, rule: &Rule, name: &str, props: &mut Props) -> bool {
+    if !matches(rule, name) {
+        return false;
+    }
+
+    rule.when.iter().all(|condition| {
+        let Some(value) = props.get(name, &condition.prop, component.prop(&condition.prop)) else {
+            return false;
+        };
+        let number = number(value);
+        condition.above.is_none_or(|above| number.is_some_and(|n| n > above))
+            && condition.below.is_none_or(|below| number.is_some_and(|n| n < below))
+            && condition.equals.as_ref().is_none_or(|wanted| equals(value, wanted))
+    })
+}
+
+// whether `rule` is for this component type
+fn matches(rule: &Rule, name: &str) -> bool {
+    match &rule.pattern {
+        Some(pattern) => pattern.is_match(name),
+        None => rule.component == name,
+    }
+}
+
+// any kind of number as an f64, None if it isn't a number
+fn number(value: &BrdbValue) -> Option<f64> {
+    Some(match value {
+        BrdbValue::U8(v) => *v as f64,
+        BrdbValue::U16(v) => *v as f64,
+        BrdbValue::U32(v) => *v as f64,
+        BrdbValue::U64(v) => *v as f64,
+        BrdbValue::I8(v) => *v as f64,
+        BrdbValue::I16(v) => *v as f64,
+        BrdbValue::I32(v) => *v as f64,
+        BrdbValue::I64(v) => *v as f64,
+        BrdbValue::F32(v) => *v as f64,
+        BrdbValue::F64(v) => *v,
+        _ => return None,
+    })
+}
+
+// `number` as the same kind of number as `value`, rounded for whole numbers
+fn with_number(value: &BrdbValue, number: f64) -> BrdbValue {
+    let whole = number.round();
+    match value {
+        BrdbValue::U8(_) => BrdbValue::U8(whole as u8),
+        BrdbValue::U16(_) => BrdbValue::U16(whole as u16),
+        BrdbValue::U32(_) => BrdbValue::U32(whole as u32),
+        BrdbValue::U64(_) => BrdbValue::U64(whole as u64),
+        BrdbValue::I8(_) => BrdbValue::I8(whole as i8),
+        BrdbValue::I16(_) => BrdbValue::I16(whole as i16),
+        BrdbValue::I32(_) => BrdbValue::I32(whole as i32),
+        BrdbValue::I64(_) => BrdbValue::I64(whole as i64),
+        BrdbValue::F64(_) => BrdbValue::F64(number),
+        _ => BrdbValue::F32(number as f32),
+    }
+}
+
+// `wanted` as the same kind of value as `value`, None if it can't be
+fn from_json(value: &BrdbValue, wanted: &Value) -> Option<BrdbValue> {
+    match value {
+        BrdbValue::Bool(_) => wanted.as_bool().map(BrdbValue::Bool),
+        BrdbValue::String(_) => wanted.as_str().map(|s| BrdbValue::String(s.to_string())),
+        _ if number(value).is_some() => wanted.as_f64().map(|n| with_number(value, n)),
+        _ => None,
+    }
+}
+
+// whether `value` is `wanted`. numbers are compared as the kind of number `value` is
+fn equals(value: &BrdbValue, wanted: &Value) -> bool {
+    match (value, from_json(value, wanted)) {
+        (BrdbValue::Bool(a), Some(BrdbValue::Bool(b))) => *a == b,
+        (BrdbValue::String(a), Some(BrdbValue::String(b))) => *a == b,
+        (_, Some(new)) => number(value) == number(&new),
+        (_, None) => false,
+    }
+}
@@ -0,0 +1,263 @@
+/*
+ * an http api, for web control panels
+ *
+ * `brdb_optimize serve --listen 127.0.0.1:9185` keeps running and takes jobs:
+ * - POST /jobs with {"world": "<path>", "options": ["--freeze-all", ..]} queues an optimization
+ *   (options can be left out) and answers with the job, including its id.
+ *   only the optimize options in OPTIONS are taken, anything else (subcommands, --daemon,
+ *   options that read or write other files) is refused
+ * - GET /jobs lists every job, GET /jobs/<id> gets one: its status (queued, running,
+ *   optimized, unchanged when there was nothing to do, or failed), the log of the run,
+ *   and what it changed
+ * - GET /jobs/<id>/report gets the analysis of the optimized world, like `analyze --json` prints it
+ *
+ * jobs run one at a time, every one in a process of its own like daemon mode does,
+ * and the optimized world is written next to the original as usual.
+ * requests are answered on a thread each, so reading a big report doesn't hold up the rest.
+ * there's no authentication, so only listen on addresses you trust
+ */
+
+use std::{
+    env,
+    fs,
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process,
+    sync::{Arc, Mutex, MutexGuard, mpsc},
+    thread,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{analyze, archive, config::Config, http, metrics::RunStats};
+
+// the options a job may use, and whether they take a value
+const OPTIONS: &[(&str, bool)] = &[
+    ("--force", false),
+    ("--repair", false),
+    ("--max-lights-per-chunk", true),
+    ("--max-lights-per-owner", true),
+    ("--strip-hidden-bricks", false),
+    ("--merge-bricks", false),
+    ("--consolidate-microbricks", false),
+    ("--freeze-all", false),
+    ("--unfreeze", false),
+    ("--max-active-grids", true),
+    ("--vacuum", false),
+    ("--no-vacuum", false),
+    ("--archive", true),
+    ("--compression", true),
+    ("--manifest", false),
+    ("--keep-revisions-newer-than", true),
+    ("--skip", true),
+    ("--purge-owner", true),
+    ("--enforce-brick-budget", true),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Queued,
+    Running,
+    Optimized,
+    Unchanged,
+    // only the archive was kept (--archive-only), so there's no optimized world to report on
+    #[serde(rename = "archive only")]
+    ArchiveOnly,
+    Failed,
+}
+
+#[derive(Serialize)]
+struct Job {
+    id: usize,
+    world: PathBuf,
+    options: Vec<String>,
+    status: Status,
+    // everything the run printed
+    log: String,
+    stats: Option<RunStats>,
+}
+
+// what POST /jobs takes
+#[derive(Deserialize)]
+struct Submit {
+    world: PathBuf,
+    #[serde(default)]
+    options: Vec<String>,
+}
+
+pub fn run(listen: &str, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(listen).map_err(|e| format!("can't listen on {listen}: {e}"))?;
+    println!("listening on http://{listen}, POST a world to /jobs to optimize it");
+
+    let jobs: Arc<Mutex<Vec<Job>>> = Arc::new(Mutex::new(vec![]));
+    let (queue, queued) = mpsc::channel::<usize>();
+    let worker_jobs = jobs.clone();
+    thread::spawn(move || {
+        for id in queued {
+            work(&worker_jobs, id);
+        }
+    });
+
+    // every connection gets a thread of its own, so a slow client or a big report doesn't hold up the others
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let (jobs, queue) = (&jobs, queue.clone());
+            scope.spawn(move || answer(&stream, jobs, &queue, config));
+        }
+    });
+
+    Ok(())
+}
+
+fn answer(stream: &TcpStream, jobs: &Mutex<Vec<Job>>, queue: &mpsc::Sender<usize>, config: &Config) {
+    let Some(request) = http::read(stream) else {
+        http::respond(stream, "400 Bad Request", "text/plain", "couldn't read the request\n");
+        return;
+    };
+
+    let parts: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let response = match (request.method.as_str(), parts.as_slice()) {
+        ("POST", ["jobs"]) => submit(jobs, queue, &request.body),
+        ("GET", ["jobs"]) => lock(jobs).map(|jobs| ("200 OK", json(&*jobs))),
+        ("GET", ["jobs", id]) => lock(jobs).and_then(|jobs| Ok(("200 OK", json(find(&jobs, id)?)))),
+        ("GET", ["jobs", id, "report"]) => report(jobs, id, config),
+        _ => Err(("404 Not Found", "try POST /jobs, GET /jobs, GET /jobs/<id> or GET /jobs/<id>/report".to_string())),
+    };
+
+    match response {
+        Ok((status, body)) => http::respond(stream, status, "application/json", &body),
+        Err((status, error)) => {
+            let body = serde_json::json!({ "error": error }).to_string();
+            http::respond(stream, status, "application/json", &body);
+        }
+    }
+}
+
+// (status, body) or (status, error)
+type Response = Result<(&'static str, String), (&'static str, String)>;
+
+fn lock(jobs: &Mutex<Vec<Job>>) -> Result<MutexGuard<'_, Vec<Job>>, (&'static str, String)> {
+    jobs.lock().map_err(|_| ("500 Internal Server Error", "jobs are unavailable".to_string()))
+}
+
+fn find<'a>(jobs: &'a [Job], id: &str) -> Result<&'a Job, (&'static str, String)> {
+    id.parse::<usize>().ok()
+        .and_then(|id| jobs.get(id))
+        .ok_or_else(|| ("404 Not Found", format!("there's no job {id}")))
+}
+
+fn json(value: &impl Serialize) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_default()
+}
+
+fn submit(jobs: &Mutex<Vec<Job>>, queue: &mpsc::Sender<usize>, body: &[u8]) -> Response {
+    let mut submit: Submit = serde_json::from_slice(body)
+        .map_err(|e| ("400 Bad Request", format!("expected {{\"world\": \"<path>\", \"options\": [..]}}: {e}")))?;
+    if !submit.world.is_file() {
+        return Err(("400 Bad Request", format!("world file {:?} does not exist", submit.world)));
+    }
+    // an absolute path can't be taken for a subcommand or an option
+    submit.world = fs::canonicalize(&submit.world)
+        .map_err(|e| ("400 Bad Request", format!("can't find world file {:?}: {e}", submit.world)))?;
+    check_options(&submit.options)?;
+
+    let mut jobs = lock(jobs)?;
+    let id = jobs.len();
+    jobs.push(Job {
+        id,
+        world: submit.world,
+        options: submit.options,
+        status: Status::Queued,
+        log: String::new(),
+        stats: None,
+    });
+    queue.send(id).map_err(|_| ("500 Internal Server Error", "the job queue is gone".to_string()))?;
+
+    Ok(("202 Accepted", json(&jobs[id])))
+}
+
+// refuses anything that isn't in OPTIONS, and options that are missing their value
+fn check_options(options: &[String]) -> Result<(), (&'static str, String)> {
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let Some((_, takes_value)) = OPTIONS.iter().find(|(name, _)| name == option) else {
+            return Err(("400 Bad Request", format!("option {option:?} isn't allowed in jobs")));
+        };
+        if *takes_value && options.next().is_none() {
+            return Err(("400 Bad Request", format!("option {option} needs a value")));
+        }
+    }
+    Ok(())
+}
+
+fn report(jobs: &Mutex<Vec<Job>>, id: &str, config: &Config) -> Response {
+    let world = {
+        let jobs = lock(jobs)?;
+        let job = find(&jobs, id)?;
+        if job.status != Status::Optimized {
+            return Err(("404 Not Found", format!("job {id} didn't write an optimized world (yet)")));
+        }
+        optimized_path(&job.world)
+    };
+
+    let analysis = analyze::analyze(&world, config).map_err(|e| ("500 Internal Server Error", e.to_string()))?;
+    Ok(("200 OK", json(&analysis)))
+}
+
+// runs job `id`, and keeps its status up to date
+fn work(jobs: &Mutex<Vec<Job>>, id: usize) {
+    let Some((world, options)) = jobs.lock().ok().map(|mut jobs| {
+        jobs[id].status = Status::Running;
+        (jobs[id].world.clone(), jobs[id].options.clone())
+    }) else {
+        return;
+    };
+    println!("[job:{id}] optimizing {:?}..", world);
+
+    let optimized = optimized_path(&world);
+    let stats_path = env::temp_dir().join(format!("brdb_optimize_{}_job_{id}.json", process::id()));
+    // leftovers of an earlier run would look like this run's results
+    fs::remove_file(&optimized).ok();
+    fs::remove_file(&stats_path).ok();
+
+    let output = env::current_exe().and_then(|exe| {
+        process::Command::new(exe)
+            .args(&options)
+            .arg("--stats")
+            .arg(&stats_path)
+            .arg(&world)
+            .output()
+    });
+    let stats: Option<RunStats> = fs::read_to_string(&stats_path).ok().and_then(|text| serde_json::from_str(&text).ok());
+    fs::remove_file(&stats_path).ok();
+
+    let (status, log) = match output {
+        Ok(output) => {
+            let log = String::from_utf8_lossy(&output.stdout).to_string() + &String::from_utf8_lossy(&output.stderr);
+            let status = match (output.status.success(), optimized.exists()) {
+                (false, _) => Status::Failed,
+                (true, true) => Status::Optimized,
+                (true, false) if archive::only(&optimized).is_some() => Status::ArchiveOnly,
+                // already optimized with the same settings, nothing got written
+                (true, false) => Status::Unchanged,
+            };
+            (status, log)
+        }
+        Err(e) => (Status::Failed, format!("couldn't start the optimizer: {e}")),
+    };
+    println!("[job:{id}] {}", format!("{status:?}").to_lowercase());
+
+    if let Ok(mut jobs) = jobs.lock() {
+        jobs[id].status = status;
+        jobs[id].log = log;
+        jobs[id].stats = stats;
+    }
+}
+
+fn optimized_path(world: &Path) -> PathBuf {
+    let stem = world.file_stem().unwrap().to_string_lossy();
+    world.with_file_name(format!("{stem}.optimized.brdb"))
+}
@@ -0,0 +1,160 @@
+/*
+ * splitting a world that's too big to load into pieces
+ *
+ * `brdb_optimize split --tiles 3x2 <world.brdb>` cuts the main grid into 3 by 2 tiles (along X and Y),
+ * `brdb_optimize split --regions regions.txt <world.brdb>` cuts out the boxes listed in a file instead.
+ * every piece is the world trimmed down to its box (see trim.rs) and gets written as a world of its own,
+ * next to the original: <world>.x0_y1.brdb for tiles, <world>.<name>.brdb for regions.
+ *
+ * a region file has a name and a box (two corners, like --box of trim) on every line:
+ *   spawn -5000,-5000,-1000,5000,5000,10000
+ *   # lines starting with # are skipped
+ *   city 5000,-20000,-1000,40000,20000,10000
+ *
+ * tiles cover everything, the outer ones reach out forever. pieces of the main grid
+ * that have no bricks in them aren't written
+ */
+
+use std::{fs, path::{Path, PathBuf}, process};
+use brdb::{BrReader, Brdb, IntoReader, pending::BrPendingFs};
+
+use crate::{grids, lock::Lock, trim::{self, Region}, welds::CHUNK_SIZE, worlds};
+
+// how to cut the world up
+#[derive(Debug, Clone)]
+pub enum Split {
+    // this many tiles along X and Y
+    Tiles(usize, usize),
+    // the named boxes in this file
+    Regions(PathBuf),
+}
+
+// reads a tile count like 3x2
+pub fn parse_tiles(text: &str) -> Option<(usize, usize)> {
+    let (x, y) = text.split_once('x')?;
+    let (x, y) = (x.trim().parse().ok()?, y.trim().parse().ok()?);
+    (x > 0 && y > 0).then_some((x, y))
+}
+
+pub fn run(src: PathBuf, split: &Split) -> Result<(), Box<dyn std::error::Error>> {
+    if !src.exists() {
+        println!("world file {:?} does not exist", src);
+        process::exit(1);
+    }
+
+    let Some(_lock) = Lock::acquire(&src)? else {
+        process::exit(1);
+    };
+
+    println!("Reading file {:?}", src);
+    let db = Brdb::open(&src)?.into_reader();
+    let source = db.to_pending()?;
+
+    let regions = match split {
+        Split::Tiles(x, y) => tiles(&db, &source, &src, *x, *y)?,
+        Split::Regions(path) => read_regions(path)?,
+    };
+
+    let stem = src.file_stem().unwrap().to_string_lossy();
+    let mut written = 0;
+    for (name, region) in &regions {
+        let dst = src.with_file_name(format!("{stem}.{name}.brdb"));
+        println!("---SEP---");
+        println!("cutting out {name} ({:?} to {:?})..", region.min, region.max);
+
+        let (patch, trimmed) = trim::patch(&db, &source, region, false, &dst)?;
+        if trimmed.left == 0 {
+            println!("{name} has no bricks in it, skipping it.");
+            continue;
+        }
+        println!("{}", trimmed.summary());
+        trim::write(&src, &dst, &format!("Split World ({name})"), patch)?;
+        println!("{name} written to {:?}", dst);
+        written += 1;
+    }
+
+    println!("---SEP---");
+    println!("split the world into {written} pieces.");
+    Ok(())
+}
+
+/*
+ * `x` by `y` tiles over the main grid of the first world, named like x0_y1.
+ * they split the chunks the main grid has evenly, the outer ones reach out forever
+ */
+fn tiles(
+    db: &BrReader<Brdb>,
+    source: &BrPendingFs,
+    near: &Path,
+    x: usize,
+    y: usize,
+) -> Result<Vec<(String, Region)>, Box<dyn std::error::Error>> {
+    let Some(world) = worlds::indices(source).into_iter().next() else {
+        return Err("the world file has no worlds in it".into());
+    };
+
+    // the chunks the main grid spans, from the lowest to the highest along X and Y
+    let (min, max) = worlds::read_world(db, source, &world, near, |db| {
+        let grids = grids::find(db, source, &world)?;
+        let mut min = [i32::MAX; 2];
+        let mut max = [i32::MIN; 2];
+        for grid in &grids.root {
+            for chunk in db.brick_chunk_index(*grid)? {
+                min = [min[0].min(chunk.x as i32), min[1].min(chunk.y as i32)];
+                max = [max[0].max(chunk.x as i32 + 1), max[1].max(chunk.y as i32 + 1)];
+            }
+        }
+        Ok((min, max))
+    })?;
+    if min[0] > max[0] {
+        return Err("the main grid has no bricks, there's nothing to split".into());
+    }
+
+    // where the edges between tiles go along one axis, in world units
+    let edges = |axis: usize, count: usize| -> Vec<f32> {
+        let (start, end) = ((min[axis] * CHUNK_SIZE) as f32, (max[axis] * CHUNK_SIZE) as f32);
+        (0..=count)
+            .map(|i| match i {
+                0 => f32::NEG_INFINITY,
+                i if i == count => f32::INFINITY,
+                i => start + (end - start) * i as f32 / count as f32,
+            })
+            .collect()
+    };
+    let (xs, ys) = (edges(0, x), edges(1, y));
+
+    let mut tiles = vec![];
+    for i in 0..x {
+        for j in 0..y {
+            tiles.push((format!("x{i}_y{j}"), Region {
+                min: [xs[i], ys[j], f32::NEG_INFINITY],
+                max: [xs[i + 1], ys[j + 1], f32::INFINITY],
+            }));
+        }
+    }
+    Ok(tiles)
+}
+
+// the named boxes in a region file, see the top of this file
+fn read_regions(path: &Path) -> Result<Vec<(String, Region)>, Box<dyn std::error::Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("can't read region file {:?}: {e}", path))?;
+
+    let mut regions = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let region = line.split_once(char::is_whitespace)
+            .and_then(|(name, region)| Some((name.to_string(), Region::parse(region.trim())?)));
+        match region {
+            Some(region) => regions.push(region),
+            None => return Err(format!("{:?} line {}: expected a name and a box like x1,y1,z1,x2,y2,z2", path, number + 1).into()),
+        }
+    }
+
+    if regions.is_empty() {
+        return Err(format!("region file {:?} has no regions in it", path).into());
+    }
+    Ok(regions)
+}
@@ -0,0 +1,359 @@
+/*
+ * cutting a world down to the part that's still used
+ *
+ * `brdb_optimize trim --box x1,y1,z1,x2,y2,z2 [--invert] <world.brdb>`
+ * removes every brick, component and entity outside of the box (or inside of it, with --invert)
+ * and writes what's left to <world>.trimmed.brdb, as a new revision on top of a copy of the world.
+ * the box is in world units, the same as entity locations (see `export entities`).
+ *
+ * dynamic grids (vehicles) go or stay as a whole, going by where their entity is.
+ * chunks with wires, bricks with joints and the entities jointed to something stay where they are,
+ * removing those would break whatever is wired or jointed to them
+ */
+
+use std::{collections::{HashMap, HashSet}, fs, path::{Path, PathBuf}, process};
+use brdb::{BrReader, Brdb, Entity, EntityChunkSoA, IntoReader, pending::BrPendingFs};
+
+use crate::{
+    bricks::{self, IndexChanges},
+    components,
+    grids::{self, Grids},
+    lock::Lock,
+    patch::{self, Entry},
+    welds::CHUNK_SIZE,
+    worlds,
+};
+
+// a box in world units, min and max along every axis
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Region {
+    // reads a box like x1,y1,z1,x2,y2,z2, the corners can be in any order
+    pub fn parse(text: &str) -> Option<Self> {
+        let numbers: Vec<f32> = text.split(',').map(|n| n.trim().parse().ok()).collect::<Option<_>>()?;
+        let [x1, y1, z1, x2, y2, z2] = numbers[..] else {
+            return None;
+        };
+        Some(Self {
+            min: [x1.min(x2), y1.min(y2), z1.min(z2)],
+            max: [x1.max(x2), y1.max(y2), z1.max(z2)],
+        })
+    }
+
+    // the max side is left out, so boxes that touch (see split.rs) never both have the same brick
+    pub fn contains(&self, point: [f32; 3]) -> bool {
+        (0..3).all(|axis| point[axis] >= self.min[axis] && point[axis] < self.max[axis])
+    }
+}
+
+// what got removed, across all worlds in the file
+#[derive(Default)]
+pub struct Trimmed {
+    pub bricks: usize,
+    pub chunks: usize,
+    pub components: usize,
+    pub entities: usize,
+    pub grids: usize,
+    // bricks of the main grid that are left
+    pub left: usize,
+}
+
+impl Trimmed {
+    pub fn is_empty(&self) -> bool {
+        self.bricks + self.components + self.entities + self.grids == 0
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "removed {} bricks ({} whole chunks), {} components, {} entities and {} dynamic grids.",
+            self.bricks, self.chunks, self.components, self.entities, self.grids
+        )
+    }
+}
+
+pub fn run(src: PathBuf, region: &Region, invert: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if !src.exists() {
+        println!("world file {:?} does not exist", src);
+        process::exit(1);
+    }
+
+    let Some(_lock) = Lock::acquire(&src)? else {
+        process::exit(1);
+    };
+
+    let stem = src.file_stem().unwrap().to_string_lossy();
+    let dst = src.with_file_name(format!("{stem}.trimmed.brdb"));
+
+    println!("Reading file {:?}", src);
+    let db = Brdb::open(&src)?.into_reader();
+    let source = db.to_pending()?;
+
+    let (combined_patch, trimmed) = patch(&db, &source, region, invert, &dst)?;
+    if trimmed.is_empty() {
+        println!("nothing to trim, everything is {} the box.", if invert { "outside of" } else { "inside of" });
+        return Ok(());
+    }
+
+    println!();
+    println!("{}", trimmed.summary());
+    println!("writing to world file..");
+    write(&src, &dst, "Trim World", combined_patch)?;
+    println!("trimmed world written to {:?}", dst);
+
+    Ok(())
+}
+
+/*
+ * the patch that trims every world in the file down to `region` (or removes it, with `invert`).
+ * `near` is where other worlds get mounted next to (see worlds.rs)
+ */
+pub fn patch(
+    db: &BrReader<Brdb>,
+    source: &BrPendingFs,
+    region: &Region,
+    invert: bool,
+    near: &Path,
+) -> Result<(BrPendingFs, Trimmed), Box<dyn std::error::Error>> {
+    let mut trimmed = Trimmed::default();
+    let mut combined_patch = BrPendingFs::Root(vec![]);
+    for world in worlds::indices(source) {
+        println!("trimming world {world}..");
+        let world_patch = worlds::read_world(db, source, &world, near, |world_db| {
+            trim_world(world_db, source, &world, region, invert, &mut trimmed)
+        })?;
+        combined_patch = combined_patch.with_patch(world_patch)?;
+    }
+    Ok((combined_patch, trimmed))
+}
+
+// writes `patch` on top of a copy of `src` at `dst`, as a new revision
+pub fn write(src: &Path, dst: &Path, description: &str, patch: BrPendingFs) -> Result<(), Box<dyn std::error::Error>> {
+    if dst.exists() {
+        fs::remove_file(dst)?;
+    }
+    fs::copy(src, dst)?;
+    Brdb::open(dst)?.write_pending(description, patch)?;
+    Ok(())
+}
+
+fn trim_world(
+    db: &BrReader<Brdb>,
+    source: &BrPendingFs,
+    world: &str,
+    region: &Region,
+    invert: bool,
+    trimmed: &mut Trimmed,
+) -> Result<BrPendingFs, Box<dyn std::error::Error>> {
+    let grids = grids::find(db, source, world)?;
+    // whether something at `point` gets removed
+    let goes = |point: [f32; 3]| region.contains(point) == invert;
+
+    let mut locations = HashMap::new();
+    for chunk in db.entity_chunk_index()? {
+        for entity in db.entity_chunk(chunk)? {
+            if let Some(id) = entity.id {
+                locations.insert(id, [entity.location.x, entity.location.y, entity.location.z]);
+            }
+        }
+    }
+
+    // vehicles go as a whole, unless the main grid has a joint to them
+    let mut jointed = joint_references(db, &grids.root)?;
+    let kept_grids: Vec<usize> = grids.dynamic.iter()
+        .filter(|grid| !locations.get(grid).is_some_and(|l| goes(*l)) || jointed.contains(grid))
+        .copied()
+        .collect();
+    jointed.extend(joint_references(db, &kept_grids)?);
+    let removed_grids: Vec<usize> = grids.dynamic.iter()
+        .filter(|grid| !kept_grids.contains(grid) && !jointed.contains(grid))
+        .copied()
+        .collect();
+    for grid in &removed_grids {
+        println!("[grid:{grid}] dynamic grid is {} the box, removing it..", if invert { "inside of" } else { "outside of" });
+    }
+    trimmed.grids += removed_grids.len();
+    let (grids_patch, _) = grids::delete(source, world, &removed_grids);
+
+    let entities_patch = trim_entities(db, world, &removed_grids, &jointed, &goes, trimmed)?;
+    let bricks_patch = trim_bricks(db, world, &grids, &goes, trimmed)?;
+
+    Ok(entities_patch.with_patch(grids_patch)?.with_patch(bricks_patch)?)
+}
+
+// the entities the components of these grids have a joint to
+pub fn joint_references(db: &BrReader<Brdb>, grids: &[usize]) -> Result<HashSet<usize>, Box<dyn std::error::Error>> {
+    let mut jointed = HashSet::new();
+    for grid in grids {
+        for chunk in db.brick_chunk_index(*grid)? {
+            if chunk.num_components == 0 {
+                continue;
+            }
+            if let Ok((soa, _)) = db.component_chunk(*grid, *chunk) {
+                jointed.extend(soa.joint_entity_references.iter().map(|e| *e as usize));
+            }
+        }
+    }
+    Ok(jointed)
+}
+
+// the entity chunks without the entities that go, only the ones that changed
+fn trim_entities(
+    db: &BrReader<Brdb>,
+    world: &str,
+    removed_grids: &[usize],
+    jointed: &HashSet<usize>,
+    goes: &impl Fn([f32; 3]) -> bool,
+    trimmed: &mut Trimmed,
+) -> Result<BrPendingFs, Box<dyn std::error::Error>> {
+    let global_data = db.global_data()?;
+    let entity_schema = db.entities_schema()?;
+
+    let mut entity_chunk_files = vec![];
+    for chunk in db.entity_chunk_index()? {
+        let mut soa = EntityChunkSoA::default();
+        let mut chunk_modified = false;
+        for entity in db.entity_chunk(chunk)? {
+            let id = entity.id.unwrap();
+            if removed_grids.contains(&id) {
+                chunk_modified = true;
+                continue;
+            }
+            let location = [entity.location.x, entity.location.y, entity.location.z];
+            if goes(location) && !jointed.contains(&id) && !is_grid(&entity) {
+                trimmed.entities += 1;
+                chunk_modified = true;
+                continue;
+            }
+            soa.add_entity(&global_data, &entity, id as u32);
+        }
+
+        if chunk_modified {
+            entity_chunk_files.push(patch::chunk_file(chunk, soa.to_bytes(&entity_schema)?));
+        }
+    }
+
+    Ok(patch::nested(&["World", world, "Entities", "Chunks"], entity_chunk_files))
+}
+
+// the entities of dynamic grids that stay, which only go along with their grid
+fn is_grid(entity: &Entity) -> bool {
+    entity.data.get_schema_struct().is_some_and(|(name, _)| name.as_ref() == "Entity_DynamicBrickGrid")
+}
+
+/*
+ * the chunks of the main grid without the bricks that go, and their components.
+ * chunks with nothing left in them get deleted
+ */
+fn trim_bricks(
+    db: &BrReader<Brdb>,
+    world: &str,
+    grids: &Grids,
+    goes: &impl Fn([f32; 3]) -> bool,
+    trimmed: &mut Trimmed,
+) -> Result<BrPendingFs, Box<dyn std::error::Error>> {
+    let brick_schema = db.bricks_schema()?;
+    let component_schema = db.components_schema()?;
+    let wired = bricks::wired_chunks(db, &grids.root)?;
+
+    let mut brick_grids_folder = vec![];
+    for grid in &grids.root {
+        let mut index_changes = IndexChanges::default();
+        let mut files: Vec<(&str, Entry)> = vec![];
+
+        for chunk in db.brick_chunk_index(*grid)? {
+            if chunk.num_bricks == 0 {
+                continue;
+            }
+            let bricks = db.brick_chunk_soa(*grid, *chunk)?;
+            let offset = [chunk.x, chunk.y, chunk.z].map(|c| (c as i32 * CHUNK_SIZE) as f32);
+            let mut keep: Vec<bool> = bricks.relative_positions.iter()
+                .map(|p| !goes([offset[0] + p.x as f32, offset[1] + p.y as f32, offset[2] + p.z as f32]))
+                .collect();
+            trimmed.left += keep.len();
+            if keep.iter().all(|k| *k) {
+                continue;
+            }
+            if wired.contains(&(*grid, *chunk)) {
+                println!("[grid:{grid}][{}] has wires, leaving it alone", *chunk);
+                continue;
+            }
+
+            let (mut soa, mut components) = if chunk.num_components == 0 {
+                Default::default()
+            } else {
+                match db.component_chunk(*grid, *chunk) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        println!("[grid:{grid}][{}] can't read its components ({e}), leaving it alone", *chunk);
+                        continue;
+                    }
+                }
+            };
+            // bricks with joints stay, along with whatever they're jointed to
+            for brick in &soa.joint_brick_indices {
+                if let Some(keep) = keep.get_mut(*brick as usize) {
+                    *keep = true;
+                }
+            }
+            let num_removed = keep.iter().filter(|k| !**k).count();
+            if num_removed == 0 {
+                continue;
+            }
+            trimmed.bricks += num_removed;
+            trimmed.left -= num_removed;
+
+            // nothing left, so the whole chunk goes
+            if num_removed == keep.len() {
+                println!("[grid:{grid}][{}] removing the whole chunk..", *chunk);
+                for (folder, tombstone, _) in bricks::delete_chunk(db, *grid, *chunk) {
+                    files.push((folder, tombstone));
+                }
+                index_changes.removed.push(*chunk);
+                trimmed.chunks += 1;
+                trimmed.components += components.len();
+                continue;
+            }
+
+            println!("[grid:{grid}][{}] removing {num_removed} bricks..", *chunk);
+            let (retained, map) = bricks::retain(&bricks, &keep);
+            files.push(("Chunks", patch::chunk_file(*chunk, retained.to_bytes(&brick_schema)?)));
+            index_changes.bricks.push((*chunk, retained.brick_type_indices.len() as u32));
+
+            if !components.is_empty() {
+                // components of a brick that goes, go with it
+                let removed: Vec<usize> = soa.component_brick_indices.iter()
+                    .enumerate()
+                    .filter(|(_, brick)| !keep.get(**brick as usize).copied().unwrap_or(true))
+                    .map(|(i, _)| i)
+                    .collect();
+                components::remove(&mut soa, &mut components, &removed);
+                components::remap_bricks(&mut soa, &map);
+                if !removed.is_empty() {
+                    index_changes.components.push((*chunk, components.len() as u32));
+                    trimmed.components += removed.len();
+                }
+                for component in components {
+                    soa.unwritten_struct_data.push(Box::new(component));
+                }
+                files.push(("Components", patch::chunk_file(*chunk, soa.to_bytes(&component_schema)?)));
+            }
+        }
+
+        if files.is_empty() {
+            continue;
+        }
+        let mut grid_folder = vec![];
+        for (folder, file) in files {
+            patch::push_into(&mut grid_folder, folder, file);
+        }
+        let (index_file, _) = bricks::chunk_index_files(db, *grid, &index_changes)?;
+        grid_folder.push(index_file);
+        brick_grids_folder.push((grid.to_string(), BrPendingFs::Folder(Some(grid_folder))));
+    }
+
+    Ok(patch::nested(&["World", world, "Bricks", "Grids"], brick_grids_folder))
+}
@@ -0,0 +1,65 @@
+/*
+ * rolls back an optimization
+ *
+ * whenever the optimizer modifies a chunk, it stores an unmodified copy
+ * of that chunk in an undo file (world.undo.brdb) next to the world.
+ * that undo file is itself a tiny brdb database containing only those chunks,
+ * so undoing is just a matter of patching them back over the optimized world.
+ *
+ * NOTE: any changes made to those chunks after optimizing
+ * (for example by server autosaves) get overwritten too!
+ */
+
+use std::{
+    path::{Path, PathBuf},
+    process,
+};
+use brdb::{Brdb, IntoReader};
+
+use crate::lock::Lock;
+
+// world.brdb and world.optimized.brdb both share world.undo.brdb
+pub fn undo_path_for(world: &Path) -> PathBuf {
+    let stem = world.file_stem().unwrap().to_string_lossy();
+    let stem = stem.strip_suffix(".optimized").unwrap_or(&stem);
+    world.with_file_name(format!("{stem}.undo.brdb"))
+}
+
+pub fn run(src: PathBuf, undo_file: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let undo_file = undo_file.unwrap_or_else(|| undo_path_for(&src));
+
+    if !src.exists() {
+        println!("world file {:?} does not exist", src);
+        process::exit(1);
+    }
+    if !undo_file.exists() {
+        println!("no undo file found at {:?}", undo_file);
+        process::exit(1);
+    }
+
+    let Some(_lock) = Lock::acquire(&src)? else {
+        process::exit(1);
+    };
+
+    let stem = src.file_stem().unwrap().to_string_lossy();
+    let dst = src.with_file_name(format!("{stem}.restored.brdb"));
+
+    println!("Reading file {:?}", src);
+    let db = Brdb::open(&src)?.into_reader();
+    println!("Reading undo file {:?}", undo_file);
+    let undo = Brdb::open(&undo_file)?.into_reader();
+
+    // the undo file only contains original copies of the chunks we changed,
+    // so patching it over the world restores exactly those chunks
+    let pending = db.to_pending()?.with_patch(undo.to_pending()?)?;
+
+    println!("writing to world file..");
+    if dst.exists() {
+        std::fs::remove_file(&dst)?;
+    }
+    Brdb::new(&dst)?.write_pending("Undo Optimize World", pending)?;
+
+    println!("world restored to {:?}", dst);
+
+    Ok(())
+}
@@ -0,0 +1,22 @@
+/*
+ * shrinking the written file on disk
+ *
+ * sqlite doesn't give space back to the filesystem when data gets deleted,
+ * it keeps the empty pages around for later. VACUUM rebuilds the database
+ * without them, which is what actually makes the file smaller
+ */
+
+use std::{fs, path::Path};
+use rusqlite::Connection;
+
+// vacuums the database at `path`, returns its size (in bytes) before and after
+pub fn run(path: &Path) -> Result<(u64, u64), Box<dyn std::error::Error>> {
+    let before = fs::metadata(path)?.len();
+
+    let conn = Connection::open(path)?;
+    conn.execute_batch("VACUUM")?;
+    drop(conn);
+
+    let after = fs::metadata(path)?.len();
+    Ok((before, after))
+}
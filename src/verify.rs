@@ -0,0 +1,427 @@
+/*
+ * checks that a world we just wrote actually contains what we meant to write
+ *
+ * after writing, the destination gets reopened and every chunk we patched
+ * is decoded again, to make sure that:
+ * - no entities or components went missing
+ * - the values we changed actually stuck
+ *
+ * the `verify` subcommand is the other half: it decodes everything in a world
+ * without optimizing anything, to find corrupt chunks before they get in the way
+ */
+
+use std::{collections::BTreeSet, path::{Path, PathBuf}, process};
+use brdb::{AsBrdbValue, BrReader, Brdb, BrdbComponent, ChunkIndex, IntoReader, pending::BrPendingFs};
+
+use crate::{
+    config::{Config, ModifierMode},
+    environment,
+    grids,
+    rules::{
+        EMISSIVE_PROPS, FLOAT_PROPS, MODIFIER_COMPONENTS, MOTOR_COMPONENTS, SENSOR_COMPONENTS, TEXT_COMPONENTS, THRUSTER_COMPONENTS,
+        THRUST_PROPS,
+    },
+    rulesets,
+    worlds,
+};
+
+// what the patched chunks should look like after writing
+#[derive(Default)]
+pub struct Expected {
+    // (world, chunk, number of entities, number of frozen entities)
+    pub entity_chunks: Vec<(String, ChunkIndex, usize, usize)>,
+    // (world, grid, chunk, number of components)
+    pub component_chunks: Vec<(String, usize, ChunkIndex, usize)>,
+    // (world, grid) of every root grid, where weights get neutralized
+    pub root_grids: Vec<(String, usize)>,
+    // (world, grid, chunk, number of bricks) of every brick chunk that lost bricks
+    pub brick_chunks: Vec<(String, usize, ChunkIndex, usize)>,
+    // (world, grid, chunk, number of wires) of every wire chunk that lost wires
+    pub wire_chunks: Vec<(String, usize, ChunkIndex, usize)>,
+    // (world, grid, chunk) of every chunk that got deleted
+    pub removed_chunks: Vec<(String, usize, ChunkIndex)>,
+    // (world, grid) of every grid that got deleted
+    pub removed_grids: Vec<(String, usize)>,
+    // (world, number of basic brick, procedural brick and material names) of every world whose name tables got compacted
+    pub global_names: Vec<(String, usize, usize, usize)>,
+    // (world, number of owners) of every world whose owner table got pruned
+    pub owners: Vec<(String, usize)>,
+    // every world whose environment settings got changed
+    pub environments: Vec<String>,
+}
+
+// returns a list of everything that didn't match, empty if all is well
+pub fn written(dst: &Path, expected: &Expected, config: &Config) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let db = Brdb::open(dst)?.into_reader();
+    let mut problems = vec![];
+
+    let mut world_indices: Vec<&str> = expected.entity_chunks.iter().map(|c| c.0.as_str())
+        .chain(expected.component_chunks.iter().map(|c| c.0.as_str()))
+        .chain(expected.brick_chunks.iter().map(|c| c.0.as_str()))
+        .chain(expected.wire_chunks.iter().map(|c| c.0.as_str()))
+        .chain(expected.removed_chunks.iter().map(|c| c.0.as_str()))
+        .chain(expected.removed_grids.iter().map(|g| g.0.as_str()))
+        .chain(expected.global_names.iter().map(|g| g.0.as_str()))
+        .chain(expected.owners.iter().map(|o| o.0.as_str()))
+        .chain(expected.environments.iter().map(String::as_str))
+        .collect();
+    world_indices.sort();
+    world_indices.dedup();
+
+    // only read the whole file into memory if there are worlds that need mounting
+    let pending = if world_indices.iter().any(|w| *w != "0") {
+        db.to_pending()?
+    } else {
+        brdb::pending::BrPendingFs::Root(vec![])
+    };
+
+    for world in world_indices {
+        worlds::read_world(&db, &pending, world, dst, |world_db| {
+            check_world(world_db, world, expected, config, &mut problems)
+        })?;
+    }
+
+    Ok(problems)
+}
+
+fn check_world(
+    db: &BrReader<Brdb>,
+    world: &str,
+    expected: &Expected,
+    config: &Config,
+    problems: &mut Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let entity_chunks = expected.entity_chunks.iter().filter(|c| c.0 == world);
+    for (_, chunk, num_entities, num_frozen) in entity_chunks {
+        let entities = match db.entity_chunk(*chunk) {
+            Ok(entities) => entities,
+            Err(e) => {
+                problems.push(format!("[world:{world}][entities][{chunk}] can't be read back: {e}"));
+                continue;
+            }
+        };
+
+        if entities.len() != *num_entities {
+            problems.push(format!(
+                "[world:{world}][entities][{chunk}] expected {num_entities} entities, found {}",
+                entities.len()
+            ));
+        }
+        for entity in &entities {
+            let l = entity.location;
+            let r = entity.rotation;
+            if ![l.x, l.y, l.z, r.x, r.y, r.z, r.w].iter().all(|v| v.is_finite()) {
+                problems.push(format!("[world:{world}][entities][{chunk}] entity {:?} has a NaN or infinite transform", entity.id));
+            }
+        }
+        let frozen = entities.iter().filter(|e| e.frozen).count();
+        if frozen != *num_frozen {
+            problems.push(format!("[world:{world}][entities][{chunk}] expected {num_frozen} frozen entities, found {frozen}"));
+        }
+    }
+
+    let component_chunks = expected.component_chunks.iter().filter(|c| c.0 == world);
+    for (_, grid, chunk, num_components) in component_chunks {
+        let (_, components) = match db.component_chunk(*grid, *chunk) {
+            Ok(value) => value,
+            Err(e) => {
+                problems.push(format!("[world:{world}][grid:{grid}][{chunk}] can't be read back: {e}"));
+                continue;
+            }
+        };
+
+        if components.len() != *num_components {
+            problems.push(format!(
+                "[world:{world}][grid:{grid}][{chunk}] expected {num_components} components, found {}",
+                components.len()
+            ));
+        }
+
+        let root_grid = expected.root_grids.iter().any(|(w, g)| w == world && g == grid);
+        for component in &components {
+            let name = component.get_name();
+            for prop in FLOAT_PROPS {
+                // no ruleset can make these NaN or infinite, so this holds for every property
+                if let Ok(value) = component.prop(*prop).and_then(|v| v.as_brdb_f32())
+                    && !value.is_finite()
+                {
+                    problems.push(format!("[world:{world}][grid:{grid}][{chunk}] {name}: {prop} is {value}"));
+                }
+            }
+
+            // [clamp] and the rulesets replace or loosen the built-in limits, so the properties they touch are theirs to decide
+            let rulesets = &config.rulesets;
+            let mut check = |prop: &str, ok: bool| {
+                let touched = rulesets::touches(rulesets.clamps.as_slice(), name, prop) || rulesets::touches(&rulesets.loaded, name, prop);
+                if !ok && !touched {
+                    problems.push(format!("[world:{world}][grid:{grid}][{chunk}] {name}: {prop} was not optimized"));
+                }
+            };
+
+            // properties the component doesn't have were skipped while optimizing, so they're skipped here too
+            let f32_prop = |prop: &str| component.prop(prop).and_then(|v| v.as_brdb_f32()).ok();
+
+            let limits = &config.lights;
+            if name == "BrickComponentData_PointLight" || name == "BrickComponentData_SpotLight" {
+                check("Radius", f32_prop("Radius").is_none_or(|r| r <= limits.max_radius));
+                check("Brightness", f32_prop("Brightness").is_none_or(|b| b <= limits.max_brightness));
+                let cast_shadows = component.prop("bCastShadows").and_then(|v| v.as_brdb_bool()).ok();
+                check("bCastShadows", limits.cast_shadows || cast_shadows != Some(true));
+            }
+            if name == "BrickComponentData_SpotLight" {
+                for prop in ["InnerConeAngle", "OuterConeAngle"] {
+                    check(prop, f32_prop(prop).is_none_or(|a| a <= limits.spot_max_cone_angle));
+                }
+                if let Some(max) = limits.spot_max_attenuation {
+                    check("Attenuation", f32_prop("Attenuation").is_none_or(|a| a <= max));
+                }
+            }
+            if let Some(max) = limits.max_emissive {
+                for prop in EMISSIVE_PROPS {
+                    check(prop, f32_prop(prop).is_none_or(|i| i <= max));
+                }
+            }
+            if name == "BrickComponentData_ItemSpawn" {
+                let limits = &config.spawners;
+                for prop in ["PickupMinSpawnTime", "PickupMaxSpawnTime"] {
+                    check(prop, f32_prop(prop).is_none_or(|t| t >= limits.min_respawn_time));
+                }
+                if let Some(max) = limits.max_items {
+                    let items = component.prop("MaxSpawnedItems").and_then(|v| v.as_brdb_i32()).ok();
+                    check("MaxSpawnedItems", items.is_none_or(|i| i <= max));
+                }
+            }
+            if name == "BrickComponentData_ParticleEmitter" {
+                let limits = &config.particles;
+                for (prop, max) in [("SpawnRate", limits.max_rate), ("Lifetime", limits.max_lifetime), ("Scale", limits.max_scale)] {
+                    check(prop, f32_prop(prop).is_none_or(|v| v <= max));
+                }
+            }
+            if MOTOR_COMPONENTS.contains(&name) {
+                let limits = &config.motors;
+                for (prop, max) in [("TargetSpeed", limits.max_speed), ("MaxTorque", limits.max_torque), ("MaxForce", limits.max_force)] {
+                    check(prop, f32_prop(prop).is_none_or(|v| v.abs() <= max));
+                }
+            }
+            if THRUSTER_COMPONENTS.contains(&name) {
+                for prop in THRUST_PROPS {
+                    check(prop, f32_prop(prop).is_none_or(|t| t.abs() <= config.thrusters.max_thrust));
+                }
+            }
+            if TEXT_COMPONENTS.contains(&name) {
+                let limits = &config.text;
+                let text = component.prop("Text").and_then(|v| v.as_brdb_str()).ok();
+                check("Text", text.is_none_or(|t| t.chars().count() <= limits.max_length));
+                check("FontSize", f32_prop("FontSize").is_none_or(|s| s <= limits.max_font_size));
+            }
+            if SENSOR_COMPONENTS.contains(&name) {
+                check("PollInterval", f32_prop("PollInterval").is_none_or(|i| i >= config.sensors.min_interval));
+            }
+            // with exemptions we'd need to know who owns what, so those are only checked without any
+            let modifiers = &config.modifiers;
+            if MODIFIER_COMPONENTS.contains(&name) && modifiers.exempt_owners.is_empty() {
+                match modifiers.mode {
+                    ModifierMode::Off => {}
+                    ModifierMode::Clamp => {
+                        check("Strength", f32_prop("Strength").is_none_or(|s| s.abs() <= modifiers.max_strength));
+                        check("Radius", f32_prop("Radius").is_none_or(|r| r <= modifiers.max_radius));
+                    }
+                    ModifierMode::Neutralize => check("Strength", f32_prop("Strength").is_none_or(|s| s == 0.0)),
+                }
+            }
+            if root_grid && name == "BrickComponentData_WeightBrick" {
+                check("Mass", f32_prop("Mass").is_none_or(|m| m <= 0.0));
+            }
+            if root_grid && name == "BrickComponentData_WheelEngine" {
+                check("CustomMass", f32_prop("CustomMass").is_none_or(|m| m <= 0.0));
+            }
+        }
+    }
+
+    let brick_chunks = expected.brick_chunks.iter().filter(|c| c.0 == world);
+    for (_, grid, chunk, num_bricks) in brick_chunks {
+        match db.brick_chunk_soa(*grid, *chunk) {
+            Ok(bricks) if bricks.brick_type_indices.len() != *num_bricks => problems.push(format!(
+                "[world:{world}][grid:{grid}][{chunk}] expected {num_bricks} bricks, found {}",
+                bricks.brick_type_indices.len()
+            )),
+            Ok(_) => {}
+            Err(e) => problems.push(format!("[world:{world}][grid:{grid}][{chunk}] bricks can't be read back: {e}")),
+        }
+    }
+
+    let wire_chunks = expected.wire_chunks.iter().filter(|c| c.0 == world);
+    for (_, grid, chunk, num_wires) in wire_chunks {
+        match db.wire_chunk_soa(*grid, *chunk) {
+            Ok(wires) => {
+                let found = wires.local_wire_sources.len() + wires.remote_wire_sources.len();
+                if found != *num_wires {
+                    problems.push(format!("[world:{world}][grid:{grid}][{chunk}] expected {num_wires} wires, found {found}"));
+                }
+            }
+            Err(e) => problems.push(format!("[world:{world}][grid:{grid}][{chunk}] wires can't be read back: {e}")),
+        }
+    }
+
+    // removed chunks shouldn't be in their grid's chunk index anymore
+    let removed_chunks: Vec<_> = expected.removed_chunks.iter().filter(|c| c.0 == world).collect();
+    for grid in removed_chunks.iter().map(|c| c.1).collect::<BTreeSet<_>>() {
+        let index = db.brick_chunk_index(grid)?;
+        for (_, _, chunk) in removed_chunks.iter().filter(|c| c.1 == grid) {
+            if index.iter().any(|c| **c == *chunk) {
+                problems.push(format!("[world:{world}][grid:{grid}][{chunk}] was removed, but is still in the chunk index"));
+            }
+        }
+    }
+
+    // removed grids shouldn't have any chunks left
+    for (_, grid) in expected.removed_grids.iter().filter(|g| g.0 == world) {
+        if db.brick_chunk_index(*grid).is_ok_and(|chunks| !chunks.is_empty()) {
+            problems.push(format!("[world:{world}][grid:{grid}] was removed, but still has chunks"));
+        }
+    }
+
+    for (_, basic, procedural, materials) in expected.global_names.iter().filter(|g| g.0 == world) {
+        match db.global_data() {
+            Ok(global) => {
+                let found = (
+                    global.basic_brick_asset_names.len(),
+                    global.procedural_brick_asset_names.len(),
+                    global.material_asset_names.len(),
+                );
+                if found != (*basic, *procedural, *materials) {
+                    problems.push(format!(
+                        "[world:{world}] expected {basic}/{procedural}/{materials} basic brick/procedural brick/material names, found {}/{}/{}",
+                        found.0, found.1, found.2
+                    ));
+                }
+            }
+            Err(e) => problems.push(format!("[world:{world}] global data can't be read back: {e}")),
+        }
+    }
+
+    if expected.environments.iter().any(|w| w == world) {
+        match environment::check(db, &config.environment) {
+            Ok(settings) => {
+                for setting in settings {
+                    problems.push(format!("[world:{world}][environment] {setting} was not optimized"));
+                }
+            }
+            Err(e) => problems.push(format!("[world:{world}][environment] can't be read back: {e}")),
+        }
+    }
+
+    for (_, num_owners) in expected.owners.iter().filter(|o| o.0 == world) {
+        match db.owners_soa() {
+            Ok(owners) if owners.user_ids.len() != *num_owners => problems.push(format!(
+                "[world:{world}] expected {num_owners} owners, found {}",
+                owners.user_ids.len()
+            )),
+            Ok(_) => {}
+            Err(e) => problems.push(format!("[world:{world}] owner table can't be read back: {e}")),
+        }
+    }
+
+    Ok(())
+}
+
+// `brdb_optimize verify <world.brdb>`, exits with 1 if anything in the world is corrupt
+pub fn run(src: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    if !src.exists() {
+        println!("world file {:?} does not exist", src);
+        process::exit(1);
+    }
+
+    println!("Reading file {:?}", src);
+    let db = Brdb::open(&src)?.into_reader();
+    let source = db.to_pending()?;
+
+    let mut problems = vec![];
+    let mut chunks = 0;
+    for world in worlds::indices(&source) {
+        println!("checking world {world}..");
+        let (world_problems, world_chunks) = worlds::read_world(&db, &source, &world, &src, |world_db| {
+            Ok(integrity(world_db, &source, &world))
+        })?;
+        problems.extend(world_problems);
+        chunks += world_chunks;
+    }
+
+    for problem in &problems {
+        println!("[verify]{problem}");
+    }
+    if !problems.is_empty() {
+        println!("[ERROR] found {} problems in {chunks} chunks.", problems.len());
+        println!("optimizing with --repair drops the components of corrupt component chunks.");
+        process::exit(1);
+    }
+    println!("all {chunks} chunks decoded fine.");
+
+    Ok(())
+}
+
+/*
+ * decodes everything in the world `db` reads as World/0.
+ * returns (what couldn't be decoded, how many chunks were checked)
+ */
+fn integrity(db: &BrReader<Brdb>, source: &BrPendingFs, world: &str) -> (Vec<String>, usize) {
+    let mut problems = vec![];
+    let mut chunks = 0;
+
+    if let Err(e) = db.global_data() {
+        problems.push(format!("[world:{world}] name tables: {e}"));
+    }
+    if let Err(e) = db.owners_soa() {
+        problems.push(format!("[world:{world}] owner table: {e}"));
+    }
+
+    match db.entity_chunk_index() {
+        Ok(index) => {
+            for chunk in index {
+                chunks += 1;
+                if let Err(e) = db.entity_chunk(chunk) {
+                    problems.push(format!("[world:{world}][entities][{chunk}] {e}"));
+                }
+            }
+        }
+        Err(e) => problems.push(format!("[world:{world}][entities] chunk index: {e}")),
+    }
+
+    // finding the grids needs every entity chunk, so a broken one stops the check here
+    let grids = match grids::find(db, source, world) {
+        Ok(grids) => grids,
+        Err(e) => {
+            problems.push(format!("[world:{world}] can't list the grids, not checking them: {e}"));
+            return (problems, chunks);
+        }
+    };
+    for grid in grids.all() {
+        let index = match db.brick_chunk_index(grid) {
+            Ok(index) => index,
+            Err(e) => {
+                problems.push(format!("[world:{world}][grid:{grid}] chunk index: {e}"));
+                continue;
+            }
+        };
+        for chunk in index {
+            chunks += 1;
+            if chunk.num_bricks > 0
+                && let Err(e) = db.brick_chunk_soa(grid, *chunk)
+            {
+                problems.push(format!("[world:{world}][grid:{grid}][{}] bricks: {e}", *chunk));
+            }
+            if chunk.num_components > 0
+                && let Err(e) = db.component_chunk(grid, *chunk)
+            {
+                problems.push(format!("[world:{world}][grid:{grid}][{}] components: {e}", *chunk));
+            }
+            if chunk.num_wires > 0
+                && let Err(e) = db.wire_chunk_soa(grid, *chunk)
+            {
+                problems.push(format!("[world:{world}][grid:{grid}][{}] wires: {e}", *chunk));
+            }
+        }
+    }
+
+    (problems, chunks)
+}
@@ -0,0 +1,147 @@
+/*
+ * welding small physics grids into the main grid
+ *
+ * decorations are often built as tiny physics objects (a lamp, a sign, a crate)
+ * that then get frozen in place. every one of those is still a whole grid with an entity,
+ * while they might as well be part of the static world.
+ * this copies their bricks over to the main grid and gets rid of the grid and its entity.
+ *
+ * to keep it safe, only grids that are:
+ *  - frozen, and not rotated (so their bricks still line up with the main grid)
+ *  - small (at most max_bricks bricks)
+ *  - just bricks: no components, no wires, not attached to anything with a joint
+ * get welded
+ */
+
+use std::collections::{BTreeMap, HashSet, btree_map::Entry};
+use brdb::{BrReader, Brdb, BrickChunkSoA, ChunkIndex, RelativePosition};
+
+use crate::{bricks, config::WeldConfig, grids::Grids};
+
+// how many brick units one chunk spans along each axis
+pub const CHUNK_SIZE: i32 = 2048;
+
+#[derive(Default)]
+pub struct Welded {
+    // the grid everything got welded into
+    pub target: usize,
+    // the grids that got welded (and need deleting)
+    pub grids: Vec<usize>,
+    // every chunk of the target grid that got bricks, with all of its bricks
+    pub chunks: BTreeMap<ChunkIndex, BrickChunkSoA>,
+}
+
+pub fn weld(db: &BrReader<Brdb>, grids: &Grids, config: &WeldConfig) -> Result<Welded, Box<dyn std::error::Error>> {
+    let mut welded = Welded {
+        target: grids.root[0],
+        ..Default::default()
+    };
+
+    let wired = bricks::wired_chunks(db, &grids.all())?;
+    let jointed = jointed_entities(db, &grids.all())?;
+    let target_chunks: HashSet<ChunkIndex> = db.brick_chunk_index(welded.target)?.iter().map(|c| **c).collect();
+
+    for chunk in db.entity_chunk_index()? {
+        for entity in db.entity_chunk(chunk)? {
+            let Some(grid) = entity.id else {
+                continue;
+            };
+            if !grids.dynamic.contains(&grid)
+                || !entity.frozen
+                || entity.data.get_schema_struct().is_none_or(|s| s.0.as_ref() != "Entity_DynamicBrickGrid")
+            {
+                continue;
+            }
+
+            // rotated grids would need every brick rotated too, which isn't worth it for a decoration
+            let r = entity.rotation;
+            if r.x.abs() > 0.001 || r.y.abs() > 0.001 || r.z.abs() > 0.001 {
+                continue;
+            }
+            // and grids that sit between brick positions can't be lined up with the main grid
+            let l = entity.location;
+            let offset = [l.x, l.y, l.z].map(|v| v.round() as i32);
+            if [l.x, l.y, l.z].iter().zip(offset).any(|(v, o)| (v - o as f32).abs() > 0.01) {
+                continue;
+            }
+
+            let chunks = db.brick_chunk_index(grid)?;
+            let num_bricks: u32 = chunks.iter().map(|c| c.num_bricks).sum();
+            if num_bricks == 0
+                || num_bricks as usize > config.max_bricks
+                || chunks.iter().any(|c| c.num_components > 0 || c.num_wires > 0 || wired.contains(&(grid, **c)))
+                || jointed.contains(&(grid as u32))
+            {
+                continue;
+            }
+
+            // copy all bricks over, onto a copy of the target chunks so a grid that fails halfway doesn't leave anything behind
+            let mut target = welded.chunks.clone();
+            let mut ok = true;
+            for chunk in &chunks {
+                let source = db.brick_chunk_soa(grid, **chunk)?;
+                for (i, position) in source.relative_positions.iter().enumerate() {
+                    let position = [
+                        offset[0] + chunk.x as i32 * CHUNK_SIZE + position.x as i32,
+                        offset[1] + chunk.y as i32 * CHUNK_SIZE + position.y as i32,
+                        offset[2] + chunk.z as i32 * CHUNK_SIZE + position.z as i32,
+                    ];
+                    let target_chunk = ChunkIndex {
+                        x: position[0].div_euclid(CHUNK_SIZE) as i16,
+                        y: position[1].div_euclid(CHUNK_SIZE) as i16,
+                        z: position[2].div_euclid(CHUNK_SIZE) as i16,
+                    };
+
+                    let to = match target.entry(target_chunk) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) if target_chunks.contains(&target_chunk) => {
+                            entry.insert(db.brick_chunk_soa(welded.target, target_chunk)?)
+                        }
+                        Entry::Vacant(entry) => entry.insert(BrickChunkSoA {
+                            procedural_brick_starting_index: source.procedural_brick_starting_index,
+                            ..Default::default()
+                        }),
+                    };
+                    if to.procedural_brick_starting_index != source.procedural_brick_starting_index {
+                        ok = false;
+                        break;
+                    }
+
+                    let relative = RelativePosition {
+                        x: position[0].rem_euclid(CHUNK_SIZE) as i16,
+                        y: position[1].rem_euclid(CHUNK_SIZE) as i16,
+                        z: position[2].rem_euclid(CHUNK_SIZE) as i16,
+                    };
+                    bricks::push(to, &source, i, relative);
+                }
+            }
+            if !ok {
+                println!("[grid:{grid}] bricks don't line up with the main grid's chunks, not welding it");
+                continue;
+            }
+
+            println!("[grid:{grid}] welding {num_bricks} bricks into grid {}..", welded.target);
+            welded.chunks = target;
+            welded.grids.push(grid);
+        }
+    }
+
+    Ok(welded)
+}
+
+// ids of every entity that's attached to a joint (bearing, slider, ..) somewhere
+fn jointed_entities(db: &BrReader<Brdb>, grids: &[usize]) -> Result<HashSet<u32>, Box<dyn std::error::Error>> {
+    let mut jointed = HashSet::new();
+    for grid in grids {
+        for chunk in db.brick_chunk_index(*grid)? {
+            if chunk.num_components == 0 {
+                continue;
+            }
+            // unreadable chunks get reported by the component pass
+            if let Ok((soa, _)) = db.component_chunk(*grid, *chunk) {
+                jointed.extend(soa.joint_entity_references);
+            }
+        }
+    }
+    Ok(jointed)
+}
@@ -0,0 +1,98 @@
+/*
+ * cleaning up wires
+ *
+ * a wire chunk stores every wire that ends in that chunk, in two lists:
+ *  - local wires, that start in the same chunk: local_wire_sources + local_wire_targets
+ *  - remote wires, that start somewhere else: remote_wire_sources + remote_wire_targets
+ * the source and target lists are paired up, wire N is source N -> target N.
+ *
+ * NOTE: chains of relays aren't collapsed into a single wire,
+ * every relay delays the signal by a tick and builds can depend on that timing
+ */
+
+use std::collections::HashSet;
+use brdb::{ChunkIndex, WireChunkSoA};
+
+/*
+ * removes wires that connect the exact same ports as another wire.
+ * those do nothing except get evaluated again every tick.
+ * returns how many were removed
+ */
+pub fn dedupe(grid: usize, chunk: ChunkIndex, soa: &mut WireChunkSoA) -> usize {
+    let num_wires = soa.local_wire_sources.len() + soa.remote_wire_sources.len();
+
+    let mut seen = HashSet::new();
+    let (sources, targets): (Vec<_>, Vec<_>) = soa.local_wire_sources.iter()
+        .zip(&soa.local_wire_targets)
+        .filter(|wire| seen.insert(*wire))
+        .map(|(source, target)| (*source, *target))
+        .unzip();
+    soa.local_wire_sources = sources;
+    soa.local_wire_targets = targets;
+
+    let mut seen = HashSet::new();
+    let (sources, targets): (Vec<_>, Vec<_>) = soa.remote_wire_sources.iter()
+        .zip(&soa.remote_wire_targets)
+        .filter(|wire| seen.insert(*wire))
+        .map(|(source, target)| (*source, *target))
+        .unzip();
+    soa.remote_wire_sources = sources;
+    soa.remote_wire_targets = targets;
+
+    let removed = num_wires - soa.local_wire_sources.len() - soa.remote_wire_sources.len();
+    if removed > 0 {
+        println!("[grid:{grid}][{chunk}] removing {removed} duplicate wires..");
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use brdb::{LocalWirePortSource, RemoteWirePortSource, WirePortTarget};
+
+    use super::*;
+
+    const CHUNK: ChunkIndex = ChunkIndex { x: 0, y: 0, z: 0 };
+
+    fn local(brick: u32) -> LocalWirePortSource {
+        LocalWirePortSource { brick_index_in_chunk: brick, component_type_index: 0, port_index: 0 }
+    }
+
+    fn remote(brick: u32) -> RemoteWirePortSource {
+        RemoteWirePortSource { grid_persistent_index: 1, chunk_index: CHUNK, brick_index_in_chunk: brick, component_type_index: 0, port_index: 0 }
+    }
+
+    fn target(brick: u32, port: u16) -> WirePortTarget {
+        WirePortTarget { brick_index_in_chunk: brick, component_type_index: 0, port_index: port }
+    }
+
+    #[test]
+    fn removes_wires_between_the_same_ports() {
+        let mut soa = WireChunkSoA {
+            local_wire_sources: vec![local(0), local(0), local(1), local(0)],
+            local_wire_targets: vec![target(5, 0), target(5, 0), target(5, 0), target(5, 1)],
+            remote_wire_sources: vec![remote(0), remote(0)],
+            remote_wire_targets: vec![target(6, 0), target(6, 0)],
+        };
+        assert_eq!(dedupe(1, CHUNK, &mut soa), 2);
+
+        // the first of every wire is kept, in order, with its own target
+        assert!(soa.local_wire_sources == [local(0), local(1), local(0)]);
+        assert!(soa.local_wire_targets == [target(5, 0), target(5, 0), target(5, 1)]);
+        assert!(soa.remote_wire_sources == [remote(0)]);
+        assert!(soa.remote_wire_targets == [target(6, 0)]);
+    }
+
+    #[test]
+    fn keeps_local_and_remote_wires_apart() {
+        // the same ports, but one wire comes from another chunk
+        let mut soa = WireChunkSoA {
+            local_wire_sources: vec![local(0)],
+            local_wire_targets: vec![target(5, 0)],
+            remote_wire_sources: vec![remote(0)],
+            remote_wire_targets: vec![target(5, 0)],
+        };
+        assert_eq!(dedupe(1, CHUNK, &mut soa), 0);
+        assert_eq!(soa.local_wire_sources.len() + soa.remote_wire_sources.len(), 2);
+    }
+}
@@ -0,0 +1,75 @@
+/*
+ * a brdb file can hold more than one world (World/0, World/1, ..)
+ *
+ * the brdb reader only ever reads World/0 though,
+ * so to read any other world we copy it into a temporary database
+ * where it sits at World/0 instead ("mounting" it)
+ */
+
+use std::{fs, path::Path};
+use brdb::{BrReader, Brdb, IntoReader, pending::BrPendingFs};
+
+use crate::patch;
+
+// names of all world folders in the file ("0", "1", ..)
+pub fn indices(pending: &BrPendingFs) -> Vec<String> {
+    patch::folder(pending, &["World"])
+        .map(|worlds| worlds.iter().map(|(name, _)| name.clone()).collect())
+        .unwrap_or_default()
+}
+
+/*
+ * runs `f` with a reader that sees world `index` as World/0.
+ * world 0 is read straight from `db`, any other world gets mounted
+ * into a temporary file next to `near` for as long as `f` runs
+ */
+pub fn read_world<T>(
+    db: &BrReader<Brdb>,
+    pending: &BrPendingFs,
+    index: &str,
+    near: &Path,
+    f: impl FnOnce(&BrReader<Brdb>) -> Result<T, Box<dyn std::error::Error>>,
+) -> Result<T, Box<dyn std::error::Error>> {
+    if index == "0" {
+        return f(db);
+    }
+
+    let BrPendingFs::Root(root) = pending else {
+        return Err("world file has no root folder".into());
+    };
+
+    // everything outside of /World is kept as is, and only the one world is copied over as /World/0
+    let mut mounted = vec![];
+    for (name, node) in root {
+        if name != "World" {
+            mounted.push((name.clone(), node.clone()));
+            continue;
+        }
+
+        let BrPendingFs::Folder(Some(worlds)) = node else {
+            continue;
+        };
+        let Some((_, world)) = worlds.iter().find(|(name, _)| name == index) else {
+            return Err(format!("world {index} does not exist").into());
+        };
+        mounted.push((
+            "World".to_string(),
+            BrPendingFs::Folder(Some(vec![("0".to_string(), world.clone())])),
+        ));
+    }
+
+    let stem = near.file_stem().unwrap().to_string_lossy();
+    let tmp = near.with_file_name(format!("{stem}.world{index}.tmp.brdb"));
+    if tmp.exists() {
+        fs::remove_file(&tmp)?;
+    }
+    Brdb::new(&tmp)?.write_pending(format!("Mount World {index}"), BrPendingFs::Root(mounted))?;
+
+    let result = {
+        let mounted_db = Brdb::open(&tmp)?.into_reader();
+        f(&mounted_db)
+    };
+    fs::remove_file(&tmp)?;
+
+    result
+}